@@ -8,14 +8,14 @@ pub enum BencodeType {
     String(Vec<u8>),
     Integer(i64),
     List(Vec<BencodeType>),
-    Dictionary(HashMap<String, BencodeType>),
+    Dictionary(HashMap<Vec<u8>, BencodeType>),
     End,
 }
 
 impl BencodeType {
     pub fn get_value_from_dict(&self, key: &str) -> Result<BencodeType, Error> {
         if let BencodeType::Dictionary(dict) = self {
-            if let Some(value) = dict.get(key) {
+            if let Some(value) = dict.get(key.as_bytes()) {
                 return Ok(value.clone());
             }
         }
@@ -0,0 +1,419 @@
+use crate::bencode_type::BencodeType;
+use crate::error::Error;
+use crate::MAX_DEPTH;
+
+use serde::de::{self, Deserialize, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Deserializes a value from bencoded data. Byte strings are borrowed from the data,
+/// so `&[u8]` and `&str` fields don't copy it.
+/// On error (invalid data, or bytes after the value), returns Error.
+pub fn from_bytes<'de, T: Deserialize<'de>>(data: &'de [u8]) -> Result<T, Error> {
+    let mut deserializer = Deserializer::new(data);
+    let value = T::deserialize(&mut deserializer)?;
+    if deserializer.position() != data.len() {
+        return Err(Error::TrailingBytes);
+    }
+    Ok(value)
+}
+
+/// # struct Deserializer
+/// Serde deserializer that reads the bencoded data directly, without building
+/// BencodeType values first.
+///     - data
+///     - index -> position of the next byte to read
+///     - depth -> number of lists and dictionaries that contain the next value
+pub struct Deserializer<'de> {
+    data: &'de [u8],
+    index: usize,
+    depth: usize,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn new(data: &'de [u8]) -> Deserializer<'de> {
+        Deserializer {
+            data,
+            index: 0,
+            depth: 0,
+        }
+    }
+
+    /// Returns the amount of bytes that have been read. After deserializing a value
+    /// from the beginning of a message, the rest of the message starts here.
+    pub fn position(&self) -> usize {
+        self.index
+    }
+
+    fn peek(&self) -> Result<u8, Error> {
+        self.data.get(self.index).copied().ok_or(Error::Eof)
+    }
+
+    fn next(&mut self) -> Result<u8, Error> {
+        let byte = self.peek()?;
+        self.index += 1;
+        Ok(byte)
+    }
+
+    /// Reads the digits until the end byte, and returns them as a number.
+    fn read_number(&mut self, end: u8) -> Result<i64, Error> {
+        let begin = self.index;
+        while self.peek()? != end {
+            self.index += 1;
+        }
+        let digits =
+            std::str::from_utf8(&self.data[begin..self.index]).map_err(|_| Error::InvalidFormat)?;
+        self.index += 1;
+        digits.parse().map_err(|_| Error::InvalidFormat)
+    }
+
+    fn read_integer(&mut self) -> Result<i64, Error> {
+        if self.next()? != b'i' {
+            return Err(Error::InvalidFormat);
+        }
+        self.read_number(b'e')
+    }
+
+    fn read_bytes(&mut self) -> Result<&'de [u8], Error> {
+        if !self.peek()?.is_ascii_digit() {
+            return Err(Error::InvalidFormat);
+        }
+        let length = usize::try_from(self.read_number(b':')?).map_err(|_| Error::InvalidFormat)?;
+        let end = self.index.checked_add(length).ok_or(Error::InvalidFormat)?;
+        let bytes = self.data.get(self.index..end).ok_or(Error::Eof)?;
+        self.index = end;
+        Ok(bytes)
+    }
+
+    /// Skips the byte that starts a list or dictionary.
+    /// On error (it is nested more than MAX_DEPTH levels), returns Error.
+    fn enter_container(&mut self) -> Result<(), Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::TooDeep);
+        }
+        self.depth += 1;
+        self.index += 1;
+        Ok(())
+    }
+
+    /// Skips the 'e' that ends a list or dictionary, if it is the next byte.
+    fn end_of_container(&mut self) -> Result<bool, Error> {
+        if self.peek()? == b'e' {
+            self.index += 1;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.peek()? {
+            b'i' => visitor.visit_i64(self.read_integer()?),
+            b'l' => {
+                self.enter_container()?;
+                let value = visitor.visit_seq(Access { de: self });
+                self.depth -= 1;
+                value
+            }
+            b'd' => {
+                self.enter_container()?;
+                let value = visitor.visit_map(Access { de: self });
+                self.depth -= 1;
+                value
+            }
+            b'0'..=b'9' => visitor.visit_borrowed_bytes(self.read_bytes()?),
+            _ => Err(Error::InvalidFormat),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_bool(self.read_integer()? != 0)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let bytes = self.read_bytes()?;
+        match std::str::from_utf8(bytes) {
+            Ok(s) => visitor.visit_borrowed_str(s),
+            Err(_) => visitor.visit_borrowed_bytes(bytes),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_borrowed_bytes(self.read_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// Bencode has not null values: a missing key of a dictionary is None.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Unit variants are strings, and other variants are dictionaries with the
+    /// variant name as their only key.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        if self.peek()? == b'd' {
+            self.enter_container()?;
+            let value = visitor.visit_enum(Enum { de: self })?;
+            self.depth -= 1;
+            if !self.end_of_container()? {
+                return Err(Error::InvalidFormat);
+            }
+            return Ok(value);
+        }
+        visitor.visit_enum(Enum { de: self })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char
+        unit unit_struct seq tuple tuple_struct map struct ignored_any
+    }
+}
+
+/// Reads the elements of a list, or the entries of a dictionary, until its end.
+struct Access<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> SeqAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.de.end_of_container()? {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de> MapAccess<'de> for Access<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.de.end_of_container()? {
+            return Ok(None);
+        }
+        if !self.de.peek()?.is_ascii_digit() {
+            return Err(Error::KeyMustBeString);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+struct Enum<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum<'_, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Enum<'_, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for BencodeType {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(BencodeTypeVisitor)
+    }
+}
+
+struct BencodeTypeVisitor;
+
+impl<'de> Visitor<'de> for BencodeTypeVisitor {
+    type Value = BencodeType;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a bencode value")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<BencodeType, E> {
+        Ok(BencodeType::Integer(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<BencodeType, E> {
+        i64::try_from(v)
+            .map(BencodeType::Integer)
+            .map_err(|_| E::custom("integer out of range"))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<BencodeType, E> {
+        Ok(BencodeType::String(v.to_vec()))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<BencodeType, E> {
+        Ok(BencodeType::String(v.as_bytes().to_vec()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BencodeType, A::Error> {
+        let mut list = vec![];
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+        Ok(BencodeType::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<BencodeType, A::Error> {
+        let mut dict = HashMap::new();
+        while let Some((key, value)) = map.next_entry::<crate::bytes::ByteBuf, BencodeType>()? {
+            dict.insert(key.0, value);
+        }
+        Ok(BencodeType::Dictionary(dict))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::ByteBuf;
+    use crate::ser::to_bytes;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Response<'a> {
+        interval: u32,
+        #[serde(rename = "failure reason")]
+        failure_reason: Option<String>,
+        #[serde(borrow)]
+        peers: Peers<'a>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(untagged)]
+    enum Peers<'a> {
+        Compact(ByteBuf),
+        List(#[serde(borrow)] Vec<PeerEntry<'a>>),
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct PeerEntry<'a> {
+        ip: &'a str,
+        port: u16,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Started,
+        Stopped,
+    }
+
+    #[test]
+    fn deserializing_struct_borrowing_strings() {
+        let data = b"d8:intervali1800e5:peersld2:ip9:127.0.0.14:porti6881e3:fooi1eeee";
+        let response: Response = from_bytes(data).expect("It shouldn't fail");
+        assert_eq!(
+            response,
+            Response {
+                interval: 1800,
+                failure_reason: None,
+                peers: Peers::List(vec![PeerEntry {
+                    ip: "127.0.0.1",
+                    port: 6881
+                }]),
+            }
+        );
+
+        let compact = b"d8:intervali60e5:peers6:\x7f\x00\x00\x01\x1a\xe1e";
+        let response: Response = from_bytes(compact).expect("It shouldn't fail");
+        assert_eq!(
+            response.peers,
+            Peers::Compact(ByteBuf(vec![127, 0, 0, 1, 0x1a, 0xe1]))
+        );
+    }
+
+    #[test]
+    fn deserializing_invalid_data() {
+        assert_eq!(from_bytes::<u32>(b"i1eXX"), Err(Error::TrailingBytes));
+        assert_eq!(from_bytes::<u32>(b"i1"), Err(Error::Eof));
+        assert_eq!(from_bytes::<String>(b"5:ab"), Err(Error::Eof));
+        assert!(from_bytes::<Response>(b"d8:intervali1ee").is_err());
+    }
+
+    #[test]
+    fn deeply_nested_data_is_rejected() {
+        let nested = |depth: usize| [vec![b'l'; depth], vec![b'e'; depth]].concat();
+        assert!(from_bytes::<BencodeType>(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            from_bytes::<BencodeType>(&nested(MAX_DEPTH + 1)),
+            Err(Error::TooDeep)
+        );
+        assert_eq!(
+            from_bytes::<BencodeType>(&nested(100_000)),
+            Err(Error::TooDeep)
+        );
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let data = b"d1:ai-3e1:bl2:xyd1:c0:eee".to_vec();
+        let value: BencodeType = from_bytes(&data).expect("It shouldn't fail");
+        assert_eq!(to_bytes(&value), Ok(data));
+
+        let events = vec![Event::Started, Event::Stopped];
+        let bencoded = to_bytes(&events).expect("It shouldn't fail");
+        assert_eq!(bencoded, b"l7:Started7:Stoppede".to_vec());
+        assert_eq!(from_bytes::<Vec<Event>>(&bencoded), Ok(events));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::bencode_type::BencodeType;
+use std::collections::HashMap;
+
+/// # struct Bencode Encoder
+/// Encodes BencodeType values with the canonical Bencode format: dictionary keys
+/// are sorted as raw byte strings, so the same value is always encoded with the
+/// same bytes.
+pub struct BencodeEncoder;
+
+impl BencodeEncoder {
+    /// Receives a decoded BencodeType element and encodes it with Bencode format.
+    /// Then, returns it as vec<u8>
+    pub fn bencode(&self, decoded: &BencodeType) -> Vec<u8> {
+        let mut bencoded_data = Vec::<u8>::new();
+        self.bencode_type(decoded, &mut bencoded_data);
+        bencoded_data
+    }
+
+    /// Receives a BencodeType element and a vec<u8>, and adds the encoded element to the vec
+    fn bencode_type(&self, ben_type: &BencodeType, vec: &mut Vec<u8>) {
+        match ben_type {
+            BencodeType::String(s) => self.bencode_string(s, vec),
+            BencodeType::Integer(i) => self.bencode_integer(*i, vec),
+            BencodeType::List(l) => self.bencode_list(l.as_slice(), vec),
+            BencodeType::Dictionary(d) => self.bencode_dictionary(d, vec),
+            _ => (),
+        }
+    }
+
+    /// Encodes an integer with Bencode format
+    fn bencode_integer(&self, integer: i64, vec: &mut Vec<u8>) {
+        vec.push(b'i');
+
+        let int_str = integer.to_string();
+        vec.append(&mut int_str.into_bytes());
+        vec.push(b'e');
+    }
+
+    /// Encodes a string with Bencode format
+    fn bencode_string(&self, string: &[u8], vec: &mut Vec<u8>) {
+        let mut length = string.len().to_string();
+        length.push(':');
+        *vec = [vec, length.as_bytes(), string].concat();
+    }
+
+    /// Encodes a list with Bencode format
+    fn bencode_list(&self, list: &[BencodeType], vec: &mut Vec<u8>) {
+        vec.push(b'l');
+        for item in list {
+            self.bencode_type(item, vec);
+        }
+        vec.push(b'e');
+    }
+
+    /// Encodes a dictionary with Bencode format.
+    /// Keys are written sorted as raw byte strings, as the specification requires.
+    fn bencode_dictionary(&self, dic: &HashMap<Vec<u8>, BencodeType>, vec: &mut Vec<u8>) {
+        vec.push(b'd');
+        let mut sorted_keys: Vec<&Vec<u8>> = dic.keys().collect();
+        sorted_keys.sort();
+        for key in sorted_keys {
+            if let Some(v) = dic.get(key) {
+                self.bencode_string(key, vec);
+                self.bencode_type(v, vec);
+            }
+        }
+        vec.push(b'e');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::BencodeParser;
+
+    #[test]
+    fn bencode_integer() {
+        let integer_parsed = BencodeType::Integer(12345);
+        let integer_bencoded = BencodeEncoder.bencode(&integer_parsed);
+
+        let expected_vec = "i12345e".as_bytes().to_vec();
+        assert_eq!(integer_bencoded, expected_vec);
+    }
+
+    #[test]
+    fn bencode_string() {
+        let string_parsed = BencodeType::String("hello bittorrent".as_bytes().to_vec());
+        let string_bencoded = BencodeEncoder.bencode(&string_parsed);
+
+        let expected_vec = "16:hello bittorrent".as_bytes().to_vec();
+        assert_eq!(string_bencoded, expected_vec);
+    }
+
+    #[test]
+    fn bencode_list() {
+        let list_parsed = BencodeType::List(vec![
+            BencodeType::Integer(1),
+            BencodeType::Integer(2),
+            BencodeType::Integer(3),
+        ]);
+        let list_bencoded = BencodeEncoder.bencode(&list_parsed);
+
+        let expected_vec = "li1ei2ei3ee".as_bytes().to_vec();
+        assert_eq!(list_bencoded, expected_vec);
+    }
+
+    #[test]
+    fn bencode_info_dic() {
+        let file_parsed =
+            BencodeParser.parse_file("files_for_testing/bencoded_files_testing/dictionary.txt");
+        let info_value = file_parsed
+            .expect("It shouldn't fail")
+            .get_value_from_dict("info")
+            .expect("It shouldn't fail");
+        let bencoded_info = BencodeEncoder.bencode(&info_value);
+        let expected_vec= "d6:lengthi3379068928e4:name32:ubuntu-20.04.4-desktop-amd64.iso12:piece lengthi262144e6:pieces3:xyze".as_bytes().to_vec();
+        assert_eq!(bencoded_info, expected_vec);
+    }
+
+    #[test]
+    fn bencode_dic_with_sorted_keys() {
+        let mut file = HashMap::new();
+        file.insert(b"path".to_vec(), BencodeType::List(vec![]));
+        file.insert(b"length".to_vec(), BencodeType::Integer(10));
+
+        let mut dic = HashMap::new();
+        dic.insert(b"private".to_vec(), BencodeType::Integer(1));
+        dic.insert(
+            b"files".to_vec(),
+            BencodeType::List(vec![BencodeType::Dictionary(file)]),
+        );
+        dic.insert(b"name".to_vec(), BencodeType::String(b"dir".to_vec()));
+
+        let bencoded_dic = BencodeEncoder.bencode(&BencodeType::Dictionary(dic));
+        let expected_vec = "d5:filesld6:lengthi10e4:pathleee4:name3:dir7:privatei1ee"
+            .as_bytes()
+            .to_vec();
+        assert_eq!(bencoded_dic, expected_vec);
+    }
+
+    #[test]
+    fn bencode_dic_with_raw_byte_keys() {
+        let mut dic = HashMap::new();
+        dic.insert(b"a".to_vec(), BencodeType::Integer(1));
+        dic.insert(vec![0xff, 0x01], BencodeType::Integer(2));
+        dic.insert(b"Z".to_vec(), BencodeType::Integer(3));
+
+        let bencoded_dic = BencodeEncoder.bencode(&BencodeType::Dictionary(dic));
+        let expected_vec = [b"d1:Zi3e1:ai1e2:".as_slice(), &[0xff, 0x01], b"i2ee"].concat();
+        assert_eq!(bencoded_dic, expected_vec);
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{de, ser};
+use std::fmt::{self, Display};
+use std::num::ParseIntError;
+use std::string::FromUtf8Error;
+
+#[derive(Debug)]
+pub enum ParseError {
+    EmptyFilePath,
+    NoSuchFile(std::io::Error),
+    InvalidFormat,
+    EmptyVector,
+    ReadingFileError(std::io::Error),
+    IntConvertionError(ParseIntError),
+    StrConvertionError(FromUtf8Error),
+    TooDeep,
+}
+
+/// # enum Error
+/// Errors of the serde support:
+///     - Message -> custom error (e.g. a missing field of a struct)
+///     - Eof -> the data ended in the middle of a value
+///     - InvalidFormat -> the data is not valid bencode
+///     - TrailingBytes -> there are bytes after the value
+///     - UnsupportedType -> the type cannot be represented in bencode (e.g. floats)
+///     - KeyMustBeString -> dictionary keys have to be byte strings
+///     - TooDeep -> lists and dictionaries are nested more than MAX_DEPTH levels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    Message(String),
+    Eof,
+    InvalidFormat,
+    TrailingBytes,
+    UnsupportedType,
+    KeyMustBeString,
+    TooDeep,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Message(msg) => write!(f, "{}", msg),
+            Error::Eof => write!(f, "unexpected end of data"),
+            Error::InvalidFormat => write!(f, "invalid bencode"),
+            Error::TrailingBytes => write!(f, "trailing bytes after the value"),
+            Error::UnsupportedType => write!(f, "type not supported by bencode"),
+            Error::KeyMustBeString => write!(f, "dictionary keys must be strings"),
+            Error::TooDeep => write!(f, "lists and dictionaries are nested too deep"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
@@ -0,0 +1,18 @@
+pub mod bencode_type;
+pub mod bytes;
+pub mod de;
+pub mod encoder;
+pub mod error;
+pub mod parser;
+pub mod ser;
+
+/// Maximum nesting of lists and dictionaries. Deeper data is rejected, because
+/// parsing it could overflow the stack.
+pub const MAX_DEPTH: usize = 64;
+
+pub use bencode_type::BencodeType;
+pub use de::{from_bytes, Deserializer};
+pub use encoder::BencodeEncoder;
+pub use error::{Error, ParseError};
+pub use parser::BencodeParser;
+pub use ser::{to_bytes, to_value, Serializer};
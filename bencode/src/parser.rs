@@ -1,5 +1,6 @@
 use crate::bencode_type::BencodeType;
-use crate::errors::ParseError;
+use crate::error::ParseError;
+use crate::MAX_DEPTH;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
@@ -30,7 +31,7 @@ impl BencodeParser {
             return Err(ParseError::InvalidFormat);
         }
         let mut index = 0;
-        self.parse(&bencoded_data, &mut index)
+        self.parse(&bencoded_data, &mut index, 0)
     }
 
     /// Receives the Vec<u8> to be parsed.
@@ -41,7 +42,47 @@ impl BencodeParser {
             return Err(ParseError::EmptyVector);
         }
         let mut index = 0;
-        self.parse(bencoded_data, &mut index)
+        self.parse(bencoded_data, &mut index, 0)
+    }
+
+    /// Receives a Vec<u8> that starts with a bencoded element followed by other bytes.
+    /// On success, returns the parsed element and the amount of bytes it takes.
+    /// Otherwise, returns ParseError.
+    pub fn parse_prefix(&self, bencoded_data: &[u8]) -> Result<(BencodeType, usize), ParseError> {
+        if bencoded_data.is_empty() {
+            return Err(ParseError::EmptyVector);
+        }
+        let mut index = 0;
+        let parsed = self.parse(bencoded_data, &mut index, 0)?;
+        Ok((parsed, index))
+    }
+
+    /// Receives a bencoded dictionary and returns the exact bytes of the value of a key.
+    /// The value is not encoded again, so its hash is the same one of the original data
+    /// (e.g. the info hash of a torrent).
+    /// On error (it's not a dictionary, or it has not the key), returns ParseError.
+    pub fn parse_raw_value<'a>(
+        &self,
+        bencoded_data: &'a [u8],
+        key: &str,
+    ) -> Result<&'a [u8], ParseError> {
+        let mut index = 0;
+        if self.next_byte(bencoded_data, &mut index)? != b'd' {
+            return Err(ParseError::InvalidFormat);
+        }
+        loop {
+            let current_key = match self.parse(bencoded_data, &mut index, 1)? {
+                BencodeType::String(s) => s,
+                _ => return Err(ParseError::InvalidFormat),
+            };
+            let begin = index;
+            if let BencodeType::End = self.parse(bencoded_data, &mut index, 1)? {
+                return Err(ParseError::InvalidFormat);
+            }
+            if current_key == key.as_bytes() {
+                return Ok(&bencoded_data[begin..index]);
+            }
+        }
     }
 
     ///Reads a byte from the file, and then decides what to do according to the byte reading.
@@ -49,14 +90,21 @@ impl BencodeParser {
     /// If the byte is a 'l', it proceeds to read a list.
     /// If the byte is a 'i', it proceeds to read an integer.
     /// If the byte is a numeric char, it proceeds to read a string.
-    /// Otherwise, returns ParseError (invalid format)  
-    fn parse(&self, bencoded_data: &[u8], i: &mut usize) -> Result<BencodeType, ParseError> {
+    /// The depth is the number of lists and dictionaries that contain the element.
+    /// Otherwise, returns ParseError (invalid format, or nested more than MAX_DEPTH levels)
+    fn parse(
+        &self,
+        bencoded_data: &[u8],
+        i: &mut usize,
+        depth: usize,
+    ) -> Result<BencodeType, ParseError> {
         let current_byte = self.next_byte(bencoded_data, i)?;
 
         let current_char = current_byte as char;
         match current_char {
-            'd' => self.read_dictionary(bencoded_data, i),
-            'l' => self.read_list(bencoded_data, i),
+            'd' | 'l' if depth >= MAX_DEPTH => Err(ParseError::TooDeep),
+            'd' => self.read_dictionary(bencoded_data, i, depth + 1),
+            'l' => self.read_list(bencoded_data, i, depth + 1),
             'i' => self.read_integer(bencoded_data, i),
             'e' => Ok(BencodeType::End),
             _ if current_char.is_numeric() => self.read_string(current_char, bencoded_data, i),
@@ -129,10 +177,15 @@ impl BencodeParser {
     /// Reads a bencoded list from the file.
     /// On success, returns BencodeType::List that contains the list as vec<BencodeType>
     /// Otherwise, returns ParseError.
-    fn read_list(&self, bencoded_data: &[u8], i: &mut usize) -> Result<BencodeType, ParseError> {
+    fn read_list(
+        &self,
+        bencoded_data: &[u8],
+        i: &mut usize,
+        depth: usize,
+    ) -> Result<BencodeType, ParseError> {
         let mut list = Vec::<BencodeType>::new();
         loop {
-            let current_element = self.parse(bencoded_data, i)?;
+            let current_element = self.parse(bencoded_data, i, depth)?;
             if let BencodeType::End = current_element {
                 break;
             }
@@ -142,29 +195,28 @@ impl BencodeParser {
     }
 
     /// Reads a bencoded dictionary from the file.
-    /// On success, returns BencodeType::Dictionary that contains the dictionary as HashMap<String,BencodeType>
+    /// On success, returns BencodeType::Dictionary that contains the dictionary as HashMap<Vec<u8>, BencodeType>
     /// Otherwise, returns ParseError.
     fn read_dictionary(
         &self,
         bencoded_data: &[u8],
         i: &mut usize,
+        depth: usize,
     ) -> Result<BencodeType, ParseError> {
         let mut dic = HashMap::new();
 
         loop {
-            let key_aux = self.parse(bencoded_data, i)?;
+            let key_aux = self.parse(bencoded_data, i, depth)?;
             if let BencodeType::End = key_aux {
                 break;
             }
 
-            let value = self.parse(bencoded_data, i)?;
-            let key: String = match (key_aux, &value) {
+            let value = self.parse(bencoded_data, i, depth)?;
+            let key = match (key_aux, &value) {
                 (_, BencodeType::End) => {
                     return Err(ParseError::InvalidFormat);
                 }
-                (BencodeType::String(s), _) => {
-                    String::from_utf8(s).map_err(ParseError::StrConvertionError)?
-                }
+                (BencodeType::String(s), _) => s,
                 _ => {
                     return Err(ParseError::InvalidFormat);
                 }
@@ -175,7 +227,7 @@ impl BencodeParser {
     }
 
     fn next_byte(&self, vec: &[u8], i: &mut usize) -> Result<u8, ParseError> {
-        if *i < vec.len() as usize {
+        if *i < vec.len() {
             let current_byte = vec[*i];
             *i += 1;
             return Ok(current_byte);
@@ -191,116 +243,129 @@ mod tests {
     #[test]
     fn empty_file_path() {
         let integer_parsed = BencodeParser.parse_file("");
-        match integer_parsed {
-            Err(ParseError::EmptyFilePath) => assert!(true),
-            _ => assert!(false),
-        }
+        assert!(matches!(integer_parsed, Err(ParseError::EmptyFilePath)));
     }
 
     #[test]
     fn no_such_file() {
         let integer_parsed =
             BencodeParser.parse_file("files_for_testing/bencoded_files_testing/not_exist.txt");
-        match integer_parsed {
-            Err(ParseError::NoSuchFile(_)) => assert!(true),
-            _ => assert!(false),
-        }
+        assert!(matches!(integer_parsed, Err(ParseError::NoSuchFile(_))));
     }
 
     #[test]
     fn file_empty() {
         let integer_parsed =
             BencodeParser.parse_file("files_for_testing/bencoded_files_testing/empty.txt");
-        match integer_parsed {
-            Err(ParseError::InvalidFormat) => assert!(true),
-            _ => assert!(false),
-        }
+        assert!(matches!(integer_parsed, Err(ParseError::InvalidFormat)));
     }
 
     #[test]
     fn file_invalid_format() {
         let integer_parsed =
             BencodeParser.parse_file("files_for_testing/bencoded_files_testing/invalid_format.txt");
-        match integer_parsed {
-            Err(ParseError::InvalidFormat) => assert!(true),
-            _ => assert!(false),
-        }
+        assert!(matches!(integer_parsed, Err(ParseError::InvalidFormat)));
     }
 
     #[test]
     fn reading_integer() {
         let path = "files_for_testing/bencoded_files_testing/integer.txt";
-        if let Ok(integer_parsed) = BencodeParser.parse_file(path) {
-            let expected_value = BencodeType::Integer(12345);
-            assert_eq!(integer_parsed, expected_value);
-        } else {
-            assert!(false);
-        }
+        let integer_parsed = BencodeParser.parse_file(path).expect("It shouldn't fail");
+        let expected_value = BencodeType::Integer(12345);
+        assert_eq!(integer_parsed, expected_value);
     }
 
     #[test]
     fn reading_string() {
         let path = "files_for_testing/bencoded_files_testing/string.txt";
-        if let Ok(integer_parsed) = BencodeParser.parse_file(path) {
-            let expected_value = BencodeType::String("hello bittorrent".as_bytes().to_vec());
-            assert_eq!(integer_parsed, expected_value);
-        } else {
-            assert!(false);
-        }
+        let integer_parsed = BencodeParser.parse_file(path).expect("It shouldn't fail");
+        let expected_value = BencodeType::String("hello bittorrent".as_bytes().to_vec());
+        assert_eq!(integer_parsed, expected_value);
     }
 
     #[test]
     fn reading_list() {
         let path = "files_for_testing/bencoded_files_testing/list.txt";
-        if let Ok(integer_parsed) = BencodeParser.parse_file(path) {
-            let list = vec![
-                BencodeType::Integer(1),
-                BencodeType::Integer(2),
-                BencodeType::Integer(3),
-            ];
-            let expected_value = BencodeType::List(list);
-            assert_eq!(integer_parsed, expected_value);
-        } else {
-            assert!(false);
-        }
+        let integer_parsed = BencodeParser.parse_file(path).expect("It shouldn't fail");
+        let list = vec![
+            BencodeType::Integer(1),
+            BencodeType::Integer(2),
+            BencodeType::Integer(3),
+        ];
+        let expected_value = BencodeType::List(list);
+        assert_eq!(integer_parsed, expected_value);
     }
 
     #[test]
     fn reading_dictionary() {
         let path = "files_for_testing/bencoded_files_testing/dictionary.txt";
-        if let Ok(integer_parsed) = BencodeParser.parse_file(path) {
-            let mut dic = HashMap::new();
-            dic.insert(
-                "announce".to_string(),
-                BencodeType::String("https://torrent.ubuntu.com/announce".as_bytes().to_vec()),
-            );
-            dic.insert(
-                "created by".to_string(),
-                BencodeType::String("mktorrent 1.1".as_bytes().to_vec()),
-            );
-            dic.insert(
-                "creation date".to_string(),
-                BencodeType::Integer(1645734650),
-            );
-
-            let mut info = HashMap::new();
-            info.insert("length".to_string(), BencodeType::Integer(3379068928));
-            info.insert(
-                "name".to_string(),
-                BencodeType::String("ubuntu-20.04.4-desktop-amd64.iso".as_bytes().to_vec()),
-            );
-            info.insert("piece length".to_string(), BencodeType::Integer(262144));
-            info.insert(
-                "pieces".to_string(),
-                BencodeType::String("xyz".as_bytes().to_vec()),
-            );
-
-            dic.insert("info".to_string(), BencodeType::Dictionary(info));
-
-            let expected_value = BencodeType::Dictionary(dic);
-            assert_eq!(integer_parsed, expected_value);
-        } else {
-            assert!(false);
-        }
+        let integer_parsed = BencodeParser.parse_file(path).expect("It shouldn't fail");
+        let mut dic = HashMap::new();
+        dic.insert(
+            b"announce".to_vec(),
+            BencodeType::String("https://torrent.ubuntu.com/announce".as_bytes().to_vec()),
+        );
+        dic.insert(
+            b"created by".to_vec(),
+            BencodeType::String("mktorrent 1.1".as_bytes().to_vec()),
+        );
+        dic.insert(b"creation date".to_vec(), BencodeType::Integer(1645734650));
+
+        let mut info = HashMap::new();
+        info.insert(b"length".to_vec(), BencodeType::Integer(3379068928));
+        info.insert(
+            b"name".to_vec(),
+            BencodeType::String("ubuntu-20.04.4-desktop-amd64.iso".as_bytes().to_vec()),
+        );
+        info.insert(b"piece length".to_vec(), BencodeType::Integer(262144));
+        info.insert(
+            b"pieces".to_vec(),
+            BencodeType::String("xyz".as_bytes().to_vec()),
+        );
+
+        dic.insert(b"info".to_vec(), BencodeType::Dictionary(info));
+
+        let expected_value = BencodeType::Dictionary(dic);
+        assert_eq!(integer_parsed, expected_value);
+    }
+
+    #[test]
+    fn parse_prefix_of_vec() {
+        let data = "d8:msg_typei1e5:piecei0ee1234".as_bytes();
+        let (parsed, length) = BencodeParser.parse_prefix(data).expect("It shouldn't fail");
+        assert_eq!(length, 25);
+        assert_eq!(&data[length..], "1234".as_bytes());
+        let msg_type = parsed
+            .get_value_from_dict("msg_type")
+            .expect("It shouldn't fail");
+        assert_eq!(msg_type, BencodeType::Integer(1));
+    }
+
+    #[test]
+    fn dictionary_keys_are_byte_strings() {
+        let data = b"d2:\xff\x01i1e4:infod1:ai1e1:b2:xyee";
+        let parsed = BencodeParser.parse_vec(data).expect("It shouldn't fail");
+        assert!(matches!(
+            parsed,
+            BencodeType::Dictionary(dic) if dic.get(b"\xff\x01".as_slice()) == Some(&BencodeType::Integer(1))
+        ));
+
+        let info = BencodeParser.parse_raw_value(data, "info");
+        assert_eq!(info.expect("It shouldn't fail"), b"d1:ai1e1:b2:xye");
+        assert!(BencodeParser.parse_raw_value(data, "announce").is_err());
+    }
+
+    #[test]
+    fn deeply_nested_data_is_rejected() {
+        let nested = |depth: usize| [vec![b'l'; depth], vec![b'e'; depth]].concat();
+        assert!(BencodeParser.parse_vec(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(
+            BencodeParser.parse_vec(&nested(MAX_DEPTH + 1)),
+            Err(ParseError::TooDeep)
+        ));
+        assert!(matches!(
+            BencodeParser.parse_vec(&nested(100_000)),
+            Err(ParseError::TooDeep)
+        ));
     }
 }
@@ -0,0 +1,444 @@
+use crate::bencode_type::BencodeType;
+use crate::encoder::BencodeEncoder;
+use crate::error::Error;
+
+use serde::ser::{self, Serialize};
+use std::collections::HashMap;
+
+/// Serializes a value and returns it encoded with the canonical Bencode format.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    Ok(BencodeEncoder.bencode(&to_value(value)?))
+}
+
+/// Serializes a value into a BencodeType.
+/// None values (and unit values) can only be fields of structs or values of maps,
+/// where they are skipped.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<BencodeType, Error> {
+    value.serialize(Serializer)?.ok_or(Error::UnsupportedType)
+}
+
+/// # struct Serializer
+/// Serde serializer that builds BencodeType values.
+/// Booleans are integers (0 or 1), and byte slices, strings, chars and unit variants
+/// are byte strings. A value that is skipped (None or unit) is returned as None.
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Error> {
+        Ok(Some(BencodeType::Integer(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Error> {
+        let v = i64::try_from(v).map_err(|_| Error::UnsupportedType)?;
+        self.serialize_i64(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Error> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Error> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Error> {
+        self.serialize_str(&v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Error> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Error> {
+        Ok(Some(BencodeType::String(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        value.serialize(self)
+    }
+
+    /// A newtype variant is a dictionary with the variant name as its only key.
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Error> {
+        let mut dict = HashMap::new();
+        if let Some(value) = value.serialize(Serializer)? {
+            dict.insert(variant.as_bytes().to_vec(), value);
+        }
+        Ok(Some(BencodeType::Dictionary(dict)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Ok(SeqSerializer {
+            list: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Ok(MapSerializer {
+            dict: HashMap::new(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+/// Builds a list. Its elements cannot be skipped.
+pub struct SeqSerializer {
+    list: Vec<BencodeType>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let value = value.serialize(Serializer)?.ok_or(Error::UnsupportedType)?;
+        self.list.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> Option<BencodeType> {
+        Some(BencodeType::List(self.list))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.finish())
+    }
+}
+
+/// Builds a dictionary. Keys have to be strings (or bytes), and skipped values are
+/// not added.
+pub struct MapSerializer {
+    dict: HashMap<Vec<u8>, BencodeType>,
+    next_key: Option<Vec<u8>>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Vec<u8>, value: &T) -> Result<(), Error> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.dict.insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Option<BencodeType> {
+        Some(BencodeType::Dictionary(self.dict))
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(Serializer)? {
+            Some(BencodeType::String(key)) => {
+                self.next_key = Some(key);
+                Ok(())
+            }
+            _ => Err(Error::KeyMustBeString),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.next_key.take().ok_or(Error::KeyMustBeString)?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(self.finish())
+    }
+}
+
+/// Wraps the value of a tuple or struct variant in a dictionary with the variant
+/// name as its only key.
+pub struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn finish(variant: &'static str, value: Option<BencodeType>) -> Option<BencodeType> {
+        let mut dict = HashMap::new();
+        if let Some(value) = value {
+            dict.insert(variant.as_bytes().to_vec(), value);
+        }
+        Some(BencodeType::Dictionary(dict))
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Self::finish(self.variant, self.inner.finish()))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Option<BencodeType>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.inner.insert(key.as_bytes().to_vec(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Error> {
+        Ok(Self::finish(self.variant, self.inner.finish()))
+    }
+}
+
+impl Serialize for BencodeType {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use ser::{SerializeMap, SerializeSeq};
+        match self {
+            BencodeType::String(s) => serializer.serialize_bytes(s),
+            BencodeType::Integer(i) => serializer.serialize_i64(*i),
+            BencodeType::List(list) => {
+                let mut seq = serializer.serialize_seq(Some(list.len()))?;
+                for item in list {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            BencodeType::Dictionary(dict) => {
+                let mut map = serializer.serialize_map(Some(dict.len()))?;
+                for (key, value) in dict {
+                    map.serialize_entry(&crate::bytes::Bytes(key), value)?;
+                }
+                map.end()
+            }
+            BencodeType::End => serializer.serialize_unit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct File {
+        length: u64,
+        path: Vec<String>,
+    }
+
+    #[derive(Serialize)]
+    struct Info {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u32,
+        #[serde(with = "crate::bytes")]
+        pieces: Vec<u8>,
+        files: Vec<File>,
+        private: Option<bool>,
+    }
+
+    #[test]
+    fn serializing_struct_with_sorted_keys() {
+        let info = Info {
+            name: "dir".to_string(),
+            piece_length: 16384,
+            pieces: vec![0xff; 2],
+            files: vec![File {
+                length: 10,
+                path: vec!["a.txt".to_string()],
+            }],
+            private: None,
+        };
+        let bencoded = to_bytes(&info).expect("It shouldn't fail");
+        let expected = [
+            b"d5:filesld6:lengthi10e4:pathl5:a.txteee4:name3:dir12:piece lengthi16384e6:pieces2:"
+                .as_slice(),
+            &[0xff, 0xff],
+            b"e",
+        ]
+        .concat();
+        assert_eq!(bencoded, expected);
+    }
+
+    #[test]
+    fn serializing_unsupported_values() {
+        assert_eq!(to_bytes(&1.5), Err(Error::UnsupportedType));
+        assert_eq!(to_bytes(&vec![None::<u32>]), Err(Error::UnsupportedType));
+        let mut map = HashMap::new();
+        map.insert(1, 2);
+        assert_eq!(to_bytes(&map), Err(Error::KeyMustBeString));
+    }
+}
@@ -0,0 +1,78 @@
+use c122_albatros_rustico::constants::DEFAULT_PIECE_LENGTH_EXP;
+use c122_albatros_rustico::errors::HandleError;
+use c122_albatros_rustico::torrent_creator::TorrentCreator;
+use std::env;
+use std::process::exit;
+
+const USAGE: &str = "Usage: mktorrent [OPTIONS] <file or directory>
+Options:
+    -a <url[,url...]>   add a tier of trackers (can be used several times)
+    -c <comment>        add a comment
+    -d                  don't write the creation date
+    -l <n>              piece length as 2^n bytes (default: 18, 256 KiB)
+    -o <file>           output file (default: <name>.torrent)
+    -p                  set the private flag";
+
+/// Creates a torrent file from a local file or directory.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut tiers = vec![];
+    let mut comment = None;
+    let mut creation_date = true;
+    let mut piece_length_exp = DEFAULT_PIECE_LENGTH_EXP;
+    let mut output = None;
+    let mut private = false;
+    let mut path = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-a" => tiers.push(option_value(args.next())),
+            "-c" => comment = Some(option_value(args.next())),
+            "-d" => creation_date = false,
+            "-l" => {
+                piece_length_exp = option_value(args.next())
+                    .parse()
+                    .unwrap_or_else(|_| usage_error())
+            }
+            "-o" => output = Some(option_value(args.next())),
+            "-p" => private = true,
+            "-h" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => usage_error(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage_error());
+    if piece_length_exp >= u32::BITS {
+        usage_error();
+    }
+
+    let mut creator = TorrentCreator::new(&path, 1 << piece_length_exp);
+    for tier in tiers {
+        creator.add_tier(tier.split(',').map(|url| url.to_string()).collect());
+    }
+    creator.set_comment(comment);
+    if !creation_date {
+        creator.set_creation_date(None);
+    }
+    creator.set_private(private);
+
+    let output = output.unwrap_or_else(|| {
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        format!("{}.torrent", name)
+    });
+    creator.write(&output).handle_error();
+    println!("Torrent created: {}", output);
+}
+
+fn option_value(value: Option<String>) -> String {
+    value.unwrap_or_else(|| usage_error())
+}
+
+fn usage_error() -> ! {
+    println!("{}", USAGE);
+    exit(-1);
+}
@@ -0,0 +1,88 @@
+use c122_albatros_rustico::constants::CONTROL_DEFAULT_ADDRESS;
+use c122_albatros_rustico::control::control_client::ControlClient;
+use c122_albatros_rustico::control::rpc::*;
+use c122_albatros_rustico::errors::HandleError;
+use c122_albatros_rustico::magnet::MagnetLink;
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::process::exit;
+
+const USAGE: &str = "Usage: torrentctl [-s <address>] <command> [arguments]
+Commands:
+    add <torrent file or magnet>    add a torrent
+    remove [-d] <id>                remove a torrent (-d also deletes its data)
+    pause <id>                      pause a torrent
+    resume <id>                     resume a paused torrent
+    recheck <id>                    hash the data of a torrent again
+    list                            list the torrents with their progress and peers
+    stats                           show the totals of the session
+Options:
+    -s <address>    control address of the client, host:port or unix:<socket path>
+                    (default: 127.0.0.1:7070)";
+
+/// Manages the torrents of a running client through its control API.
+/// The results are printed as JSON.
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let mut address = CONTROL_DEFAULT_ADDRESS.to_string();
+    if args.peek().map(|arg| arg.as_str()) == Some("-s") {
+        args.next();
+        address = option_value(args.next());
+    }
+    let command = option_value(args.next());
+    let args: Vec<String> = args.collect();
+
+    let (method, params) = match (command.as_str(), args.as_slice()) {
+        ("add", [source]) => (TORRENT_ADD, json!({ "source": torrent_source(source) })),
+        ("remove", [id]) => (TORRENT_REMOVE, json!({ "id": id, "delete_data": false })),
+        ("remove", [flag, id]) if flag == "-d" => {
+            (TORRENT_REMOVE, json!({ "id": id, "delete_data": true }))
+        }
+        ("pause", [id]) => (TORRENT_PAUSE, json!({ "id": id })),
+        ("resume", [id]) => (TORRENT_RESUME, json!({ "id": id })),
+        ("recheck", [id]) => (TORRENT_RECHECK, json!({ "id": id })),
+        ("list", []) => (TORRENT_LIST, Value::Null),
+        ("stats", []) => (SESSION_STATS, Value::Null),
+        ("-h", []) => {
+            println!("{}", USAGE);
+            return;
+        }
+        _ => usage_error(),
+    };
+
+    let mut client = ControlClient::new(&address).handle_error();
+    let response = client.call(method, params).handle_error();
+    if let Some(error) = response.error {
+        println!("ERROR: {}!", error.message);
+        exit(-1);
+    }
+    match response.result {
+        Some(Value::Null) | None => println!("OK"),
+        Some(result) => match serde_json::to_string_pretty(&result) {
+            Ok(result) => println!("{}", result),
+            Err(_) => println!("{}", result),
+        },
+    }
+}
+
+/// Torrent files are sent with their absolute path, because the client
+/// can run in another directory.
+fn torrent_source(source: &str) -> String {
+    if MagnetLink::is_magnet(source) {
+        return source.to_string();
+    }
+    match fs::canonicalize(source) {
+        Ok(path) => path.to_string_lossy().to_string(),
+        Err(_) => source.to_string(),
+    }
+}
+
+fn option_value(value: Option<String>) -> String {
+    value.unwrap_or_else(|| usage_error())
+}
+
+fn usage_error() -> ! {
+    println!("{}", USAGE);
+    exit(-1);
+}
@@ -32,6 +32,16 @@ impl PieceBitfield {
         }
     }
 
+    /// Receives a piece index and mark this one with a 0.
+    pub fn remove_a_piece(&mut self, piece_idx: u32) {
+        let n_shift = 7 - (piece_idx % 8);
+        let mask: u8 = 1 << n_shift;
+        let idx: usize = (piece_idx / 8) as usize;
+        if idx < self.bitfield.len() {
+            self.bitfield[idx] &= !mask;
+        }
+    }
+
     /// Receives a vector with pieces and adds them to the current bitfield.
     pub fn add_multiple_pieces(&mut self, pieces: Vec<u8>) {
         for (i, item) in pieces.iter().enumerate().take(self.bitfield.len()) {
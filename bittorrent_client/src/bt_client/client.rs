@@ -1,19 +1,27 @@
 use super::piece_queue::PieceQueue;
-use crate::bencode_type::BencodeType;
 use crate::bitfield::PieceBitfield;
+use crate::bt_client::metadata_fetcher::MetadataFetcher;
 use crate::bt_client::peer::Peer;
 use crate::bt_client::peer_connection::PeerConnection;
+use crate::bt_client::tracker_list::TrackerList;
 use crate::bt_client::tracker_request::TrackerRequest;
+use crate::choker::Choker;
 use crate::constants::*;
+use crate::dht::dht_node::DhtNode;
 use crate::errors::*;
-use crate::event_messages::NewEvent;
+use crate::event_messages::{EventBus, NewEvent};
 use crate::logging::msg_coder::MsgCoder;
-use crate::piece::Piece;
-use crate::piece_merger::PieceMerger;
+use crate::resume::ResumeData;
 use crate::settings::Settings;
+use crate::storage::Storage;
+use crate::swarm::PeerSwarm;
+use crate::torrent_finder::TorrentFinder;
 use crate::torrent_info::TorrentInfo;
+use crate::torrent_registry::StopSignal;
+use bencode::BencodeType;
 
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::{SocketAddr, SocketAddrV4};
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
@@ -22,8 +30,11 @@ use std::sync::Mutex;
 use std::sync::RwLock;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 use std::vec;
 
+type TorrentCombo = (TorrentInfo, Arc<RwLock<PieceBitfield>>);
+
 /// # struct PeerConnection
 /// Represents the BitTorrent client.
 /// Fields:
@@ -32,6 +43,11 @@ use std::vec;
 ///     - torrent -> parsed torrent file
 ///     - downloaded_pieces: bitfield with out pieces
 ///     - tx_logger
+///     - events -> bus where the events of the torrent are published
+///     - trackers -> tiers of trackers of the torrent
+///     - dht -> our DHT node, shared by all clients (if it could be started)
+///     - known_peers -> peers we connected to (saved in the resume file)
+///     - stop -> signal to stop the download (the torrent was paused or removed)
 #[derive(Debug, Clone)]
 pub struct Client {
     settings: Arc<Settings>,
@@ -39,21 +55,29 @@ pub struct Client {
     torrent: TorrentInfo,
     downloaded_pieces: Arc<RwLock<PieceBitfield>>,
     tx_logger: Sender<String>,
-    tx_gui: glib::Sender<NewEvent>,
+    events: EventBus,
+    trackers: TrackerList,
+    dht: Option<Arc<DhtNode>>,
+    known_peers: Vec<SocketAddrV4>,
+    stop: Arc<RwLock<StopSignal>>,
 }
 
 impl Client {
-    /// Creates and runs a client.
+    /// Creates and runs a client, until the torrent is downloaded or the stop signal is set.
     pub fn init(
         settings: Arc<Settings>,
-        torrent: (TorrentInfo, Arc<RwLock<PieceBitfield>>),
+        torrent: TorrentCombo,
         tx_logger: Sender<String>,
-        rx_gui: Arc<Mutex<Receiver<glib::Sender<NewEvent>>>>,
-    ) -> Result<(), (TorrentInfo, Arc<RwLock<PieceBitfield>>)> {
-        let mut client = Client::new(settings, torrent.0, torrent.1, tx_logger, rx_gui);
-        let _ = client.tx_gui.send(NewEvent::DownloadingTorrent(
-            client.get_torrent_info().get_name(),
-        ));
+        events: EventBus,
+        dht: Option<Arc<DhtNode>>,
+        stop: Arc<RwLock<StopSignal>>,
+    ) -> Result<(), ClientError> {
+        let mut client = Client::new(settings, torrent.0, torrent.1, tx_logger, events, dht);
+        client.stop = stop;
+        let torrent_id = client.get_torrent_info().get_id();
+        client
+            .events
+            .publish(NewEvent::DownloadingTorrent(torrent_id));
 
         match client.run_client() {
             Ok(_) => {
@@ -61,14 +85,18 @@ impl Client {
                     "Torrent: {} has been downloaded successfully",
                     client.get_torrent_info().get_name()
                 );
+                client
+                    .events
+                    .publish(NewEvent::TorrentDownloaded(torrent_id));
                 Ok(())
             }
+            Err(ClientError::TorrentStopped) => Err(ClientError::TorrentStopped),
             Err(error) => {
                 error.print_error();
-                let _ = client.tx_gui.send(NewEvent::TorrentDownloadFailed(
-                    client.get_torrent_info().get_name(),
-                ));
-                Err((client.get_torrent_info(), client.get_dl_pieces()))
+                client
+                    .events
+                    .publish(NewEvent::TorrentDownloadFailed(torrent_id));
+                Err(error)
             }
         }
     }
@@ -80,45 +108,76 @@ impl Client {
         torrent: TorrentInfo,
         downloaded_pieces: Arc<RwLock<PieceBitfield>>,
         tx_logger: Sender<String>,
-        rx_gui: Arc<Mutex<Receiver<glib::Sender<NewEvent>>>>,
+        events: EventBus,
+        dht: Option<Arc<DhtNode>>,
     ) -> Client {
-        let tx_gui = rx_gui.lock().unwrap().recv().unwrap();
+        let trackers = TrackerList::new(torrent.get_announce_list());
 
         Client {
             settings,
             our_id: CLIENT_ID.as_bytes().to_vec(),
             torrent,
+            trackers,
             downloaded_pieces,
             tx_logger,
-            tx_gui,
+            events,
+            dht,
+            known_peers: vec![],
+            stop: Arc::new(RwLock::new(StopSignal::Run)),
         }
     }
 
     /// The client runs. It implies:
+    ///     - The resume file of the torrent is loaded (known peers, totals and partial pieces)
     ///     - Client connects to tracker, sends the request and gets the tracker response.
-    ///     - Gets peer list
+    ///     - Gets peer list, adding the peers found in the DHT
+    ///     - If the torrent was created from a magnet link, its metadata is fetched from the peers
     ///     - Chooses one peer, then connects to it
+    ///     - The files of the torrent are preallocated
     ///     - A PeerConnection is created
-    ///     - Download starts.
-    ///     - On success, all pieces are joined.
+    ///     - Download starts. Every piece is written in the files when it is downloaded.
+    ///     - The peers received from other peers (ut_pex) are connected during the download.
+    ///     - The resume file is saved periodically and when the download ends.
     ///
-    /// On error, it returns ClientError::DownloadError
+    /// If the stop signal is set, the connections are dropped and it returns
+    /// ClientError::TorrentStopped. On error, it returns ClientError::DownloadError
     pub fn run_client(&mut self) -> Result<(), ClientError> {
+        let resume = self.load_resume_data();
         if self.file_is_downloaded() {
+            self.save_resume_data(None);
             let _ = self.connect_to_tracker(self.torrent.get_n_pieces());
+            // We announce ourselves in the DHT, so leechers can find us
+            let _ = self.get_dht_peers();
             return Ok(());
         }
 
-        let piece_queue = PieceQueue::new(&self.torrent, &self.downloaded_pieces);
-        let response =
-            self.connect_to_tracker(self.torrent.get_n_pieces() - piece_queue.length())?;
-        let peer_list = self.get_peer_list(&response)?;
+        let peer_list = self.find_peers()?;
         self.notify_no_of_peers(peer_list.len() as u32);
+        self.check_stop()?;
+
+        if !self.torrent.has_metadata() {
+            self.fetch_metadata(&peer_list)?;
+            self.check_stop()?;
+            if self.file_is_downloaded() {
+                let _ = self.connect_to_tracker(self.torrent.get_n_pieces());
+                return Ok(());
+            }
+        }
 
+        if self.get_storage().preallocate().is_err() {
+            return Err(ClientError::StoringPieceError);
+        }
+
+        let mut piece_queue = PieceQueue::new(&self.torrent, &self.downloaded_pieces);
+        if let Some(resume) = resume {
+            piece_queue.restore_partial_pieces(resume.get_partial_pieces());
+        }
         let mut vec_threads: Vec<JoinHandle<()>> = vec![];
         let (tx, rx) = mpsc::channel();
         let dl_finished = Arc::new(RwLock::new(false));
         let sh_piece_queue = Arc::new(RwLock::new(piece_queue));
+        let mut tried_peers: HashSet<SocketAddr> =
+            peer_list.iter().map(|peer| peer.socket_addr()).collect();
         for peer in peer_list {
             let thread = self.handle_connection(
                 self.clone(),
@@ -129,108 +188,273 @@ impl Client {
             );
             vec_threads.push(thread);
         }
-        self.listen_for_new_events(rx, dl_finished);
+        self.listen_for_new_events(
+            rx,
+            &tx,
+            dl_finished,
+            &sh_piece_queue,
+            &mut vec_threads,
+            &mut tried_peers,
+        );
         self.join_peer_conn_threads(vec_threads)?;
+        self.save_resume_data(Some(&sh_piece_queue));
 
-        if self.file_is_downloaded() && self.merge_pieces().is_ok() {
+        if self.file_is_downloaded() {
+            self.log_downloaded_file();
             let _ = self.connect_to_tracker(self.torrent.get_n_pieces());
             return Ok(());
         }
 
+        self.check_stop()?;
         Err(ClientError::DownloadError)
     }
 
+    /// Returns true if the torrent was paused or removed.
+    pub fn is_stopped(&self) -> bool {
+        match self.stop.read() {
+            Ok(stop) => *stop != StopSignal::Run,
+            Err(_) => false,
+        }
+    }
+
+    fn is_removed(&self) -> bool {
+        match self.stop.read() {
+            Ok(stop) => *stop == StopSignal::Remove,
+            Err(_) => false,
+        }
+    }
+
+    fn check_stop(&self) -> Result<(), ClientError> {
+        if self.is_stopped() {
+            return Err(ClientError::TorrentStopped);
+        }
+        Ok(())
+    }
+
+    /// Gets the peers from the tracker and the DHT.
+    /// If the tracker fails, the download can continue with the peers of the DHT.
+    /// On error (no peer from any of them), returns the error of the tracker.
+    fn find_peers(&mut self) -> Result<Vec<Peer>, ClientError> {
+        let tracker_peers = self.connect_to_tracker(self.number_of_downloaded_pieces());
+        let dht_peers = self.get_dht_peers();
+
+        let known_peers: Vec<Peer> = self
+            .known_peers
+            .iter()
+            .map(|addr| Peer::from_socket_addr(SocketAddr::V4(*addr)))
+            .collect();
+
+        let mut peer_list = match tracker_peers {
+            Ok(peer_list) => peer_list,
+            Err(error) if dht_peers.is_empty() && known_peers.is_empty() => return Err(error),
+            Err(_) => vec![],
+        };
+        Client::merge_peers(&mut peer_list, dht_peers);
+        Client::merge_peers(&mut peer_list, known_peers);
+        Ok(peer_list)
+    }
+
+    /// Loads the resume file of the torrent: the known peers are added to the client,
+    /// and the totals are restored in the choker of the torrent.
+    fn load_resume_data(&mut self) -> Option<ResumeData> {
+        let resume = ResumeData::load(&self.torrent, &self.settings.get_downloads_dir()).ok()?;
+        self.known_peers = resume.get_peers();
+        if let Ok(mut choker) = self.get_choker().lock() {
+            choker.restore_totals(resume.get_uploaded(), resume.get_downloaded());
+        }
+        Some(resume)
+    }
+
+    /// Saves the state of the torrent in its resume file.
+    /// The partially downloaded pieces are taken from the piece queue.
+    /// Removed torrents are not saved, because their data may be deleted.
+    fn save_resume_data(&self, piece_queue: Option<&Arc<RwLock<PieceQueue>>>) {
+        if !self.torrent.has_metadata() || self.is_removed() {
+            return;
+        }
+        let dl_dir = self.settings.get_downloads_dir();
+        let bitfield = match self.downloaded_pieces.read() {
+            Ok(bitfield) => bitfield.clone(),
+            Err(_) => return,
+        };
+
+        let mut resume = ResumeData::new(&self.torrent, &dl_dir, &bitfield);
+        if let Ok(choker) = self.get_choker().lock() {
+            let (uploaded, downloaded) = choker.get_totals();
+            resume.set_totals(uploaded, downloaded);
+        }
+        resume.set_peers(self.known_peers.clone());
+        if let Some(Ok(piece_queue)) = piece_queue.map(|pq| pq.read()) {
+            resume.set_partial_pieces(piece_queue.get_partial_pieces());
+        }
+
+        if resume.save(&self.torrent, &dl_dir).is_err() {
+            let _ = self.tx_logger.send(MsgCoder::generate_message(
+                ERROR_LOG_TYPE,
+                CLIENT_MODE_LOG,
+                format!(
+                    "Torrent: {} - Cannot save the resume file\n",
+                    self.torrent.get_name()
+                ),
+            ));
+        }
+    }
+
+    fn get_choker(&self) -> Arc<Mutex<Choker>> {
+        Choker::for_torrent(&self.torrent.get_info_hash(), self.get_dl_pieces())
+    }
+
+    /// Adds the new peers that are not in the list yet.
+    fn merge_peers(peer_list: &mut Vec<Peer>, new_peers: Vec<Peer>) {
+        for peer in new_peers {
+            let known = peer_list
+                .iter()
+                .any(|p| p.socket_addr() == peer.socket_addr());
+            if !known {
+                peer_list.push(peer);
+            }
+        }
+    }
+
+    /// Looks for peers in the DHT, announcing the port of our server.
+    /// Private torrents are not looked for, they only use their trackers.
+    fn get_dht_peers(&self) -> Vec<Peer> {
+        let dht = match &self.dht {
+            Some(dht) if !self.torrent.is_private() => dht,
+            _ => return vec![],
+        };
+        let port = self.get_port().parse().ok();
+        let peers: Vec<Peer> = dht
+            .get_peers(&self.torrent.get_info_hash(), port)
+            .into_iter()
+            .map(|addr| Peer::from_socket_addr(SocketAddr::V4(addr)))
+            .collect();
+        self.log_dht_peers(peers.len());
+        peers
+    }
+
     /// Checks if the file has been already downloaded
     fn file_is_downloaded(&self) -> bool {
         if let Ok(dl_pieces) = self.downloaded_pieces.read() {
-            return dl_pieces.has_all_pieces();
+            return self.torrent.has_metadata() && dl_pieces.has_all_pieces();
         }
         false
     }
 
-    /// The client connects to tracker, sends the request and receives the response.
-    /// On success, returns the response.
-    /// Otherwise, return an error.
-    fn connect_to_tracker(&mut self, n_dl_pieces: u32) -> Result<BencodeType, ClientError> {
+    fn number_of_downloaded_pieces(&self) -> u32 {
         if let Ok(dl_pieces) = self.downloaded_pieces.read() {
-            let piece_length = self.torrent.get_piece_length();
-            let mut downloaded_bytes: u64 = (n_dl_pieces * piece_length) as u64;
-            let last_piece = self.torrent.get_n_pieces() - 1;
-            if dl_pieces.has_piece(last_piece) {
-                downloaded_bytes -= piece_length as u64;
-                downloaded_bytes += self.torrent.length_of_piece_n(last_piece) as u64;
+            return dl_pieces.number_of_downloaded_pieces();
+        }
+        0
+    }
+
+    /// Gets the metadata of a torrent created from a magnet link.
+    /// Peers are asked one by one until one of them sends the info dictionary.
+    /// Then, the torrent information and the bitfield are updated, and GUI is notified.
+    fn fetch_metadata(&mut self, peer_list: &[Peer]) -> Result<(), ClientError> {
+        for peer in peer_list {
+            if self.is_stopped() {
+                break;
             }
+            let metadata =
+                MetadataFetcher::fetch(peer, self.torrent.get_info_hash(), self.get_peer_id());
+            if let Ok(metadata) = metadata {
+                let new_torrent =
+                    TorrentInfo::from_metadata(&metadata, self.torrent.get_announce_list());
+                if let Ok(torrent) = new_torrent {
+                    let bitfield =
+                        TorrentFinder::build_bitfield(&self.settings.get_downloads_dir(), &torrent);
+                    let n_dl_pieces = bitfield.number_of_downloaded_pieces();
+                    if let Ok(mut lock_dl) = self.downloaded_pieces.write() {
+                        *lock_dl = bitfield;
+                    }
 
-            let request = TrackerRequest::new(self, downloaded_bytes);
-            match request.make_request() {
-                Ok(response) => {
-                    println!(
-                        "\nConnected to the tracker. The response has been obtained successfully :)"
-                    );
-                    self.log_tracker_connection();
-                    return Ok(response);
+                    self.torrent = torrent;
+                    self.log_metadata_received(peer);
+                    self.notify_metadata_received(n_dl_pieces);
+                    return Ok(());
                 }
-                Err(_error) => return Err(ClientError::TrackerConnectionError),
             }
         }
-        Err(ClientError::TrackerConnectionError)
+        Err(ClientError::CannotFetchMetadata)
     }
 
-    /// Gets the peer list from the tracker response.
-    fn get_peer_list(&self, response: &BencodeType) -> Result<Vec<Peer>, ClientError> {
-        if let Ok(peers_benc) = response.get_value_from_dict("peers") {
-            let mut peer_list = vec![];
-            if let Ok(peer_list_aux) = peers_benc.get_list() {
-                peer_list = peer_list_aux;
-            } else if let Ok(peer_list_aux) = peers_benc.get_string() {
-                if let Ok(peer_compacted_list) = self.parse_compacted_peers(peer_list_aux) {
-                    peer_list = peer_compacted_list;
-                }
-            }
-            if !peer_list.is_empty() {
-                let mut peers = vec![];
-                for peer in peer_list {
-                    if let Ok(new_peer) = Peer::new(peer) {
-                        peers.push(new_peer);
+    /// The client connects to the trackers, sends the request and receives the responses.
+    /// The trackers of each tier are tried in order until one of them answers with peers,
+    /// which is moved to the front of its tier (BEP 12). The peers of all tiers are merged.
+    /// On success, returns the peers.
+    /// Otherwise (no tracker worked), return an error.
+    fn connect_to_tracker(&mut self, n_dl_pieces: u32) -> Result<Vec<Peer>, ClientError> {
+        let downloaded_bytes = self.downloaded_bytes(n_dl_pieces);
+        let mut peer_list = vec![];
+        let mut connected = false;
+        let mut error = ClientError::TrackerConnectionError;
+
+        for (tier_idx, tier) in self.trackers.get_tiers().iter().enumerate() {
+            for (tracker_idx, url) in tier.iter().enumerate() {
+                let request = TrackerRequest::new(self, url, downloaded_bytes);
+                let response = match request.make_request() {
+                    Ok(response) => response,
+                    Err(_) => continue,
+                };
+                match self.get_peer_list(&response) {
+                    Ok(peers) => {
+                        self.log_tracker_connection(url);
+                        self.trackers.promote(tier_idx, tracker_idx);
+                        Client::merge_peers(&mut peer_list, peers);
+                        connected = true;
+                        break;
                     }
+                    Err(invalid_response) => error = invalid_response,
                 }
-                return Ok(peers);
             }
         }
-        println!("The tracker response is invalid. Cannot continue :(");
-        Err(ClientError::InvalidTrackerResponse)
+
+        if connected {
+            return Ok(peer_list);
+        }
+        Err(error)
     }
 
-    /// Parses a compacted peer list
-    fn parse_compacted_peers(
-        &self,
-        peers_compact: Vec<u8>,
-    ) -> Result<Vec<BencodeType>, ClientError> {
-        let mut list = vec![];
-        let mut idx = 0;
-        loop {
-            if idx + 5 >= peers_compact.len() {
-                return Ok(list);
+    /// Returns the number of bytes of the downloaded pieces (the last piece may be shorter).
+    fn downloaded_bytes(&self, n_dl_pieces: u32) -> u64 {
+        let piece_length = self.torrent.get_piece_length();
+        let mut downloaded_bytes: u64 = n_dl_pieces as u64 * piece_length as u64;
+        if let Ok(dl_pieces) = self.downloaded_pieces.read() {
+            let last_piece = self.torrent.get_n_pieces().saturating_sub(1);
+            if self.torrent.has_metadata() && dl_pieces.has_piece(last_piece) {
+                downloaded_bytes -= piece_length as u64;
+                downloaded_bytes += self.torrent.length_of_piece_n(last_piece) as u64;
             }
+        }
+        downloaded_bytes
+    }
 
-            let ip_result: Result<[u8; 4], _> = peers_compact[idx..idx + 4].try_into();
-            let port_result: Result<[u8; 2], _> = peers_compact[idx + 4..idx + 6].try_into();
-
-            if let (Ok(ip_aux), Ok(port_aux)) = (ip_result, port_result) {
-                let port = u16::from_be_bytes(port_aux);
-                let ip = format!("{}.{}.{}.{}", ip_aux[0], ip_aux[1], ip_aux[2], ip_aux[3]);
-                idx += 6;
-
-                let mut peer = HashMap::new();
-                let peer_ip = BencodeType::String(ip.into_bytes());
-                let peer_port = BencodeType::Integer(port as i64);
-
-                peer.insert("ip".to_string(), peer_ip);
-                peer.insert("port".to_string(), peer_port);
-
-                list.push(BencodeType::Dictionary(peer));
+    /// Gets the peer list from the tracker response.
+    /// The 'peers' can be a list of dictionaries or a compact string of IPv4 peers (BEP 23),
+    /// and the IPv6 peers can be in 'peers6' (BEP 7).
+    fn get_peer_list(&self, response: &BencodeType) -> Result<Vec<Peer>, ClientError> {
+        let mut peers = vec![];
+        if let Ok(peers_benc) = response.get_value_from_dict("peers") {
+            if let Ok(peer_list) = peers_benc.get_list() {
+                peers.extend(
+                    peer_list
+                        .into_iter()
+                        .filter_map(|peer| Peer::new(peer).ok()),
+                );
+            } else if let Ok(peers_compact) = peers_benc.get_string() {
+                peers.extend(Peer::from_compact(&peers_compact, false));
             }
         }
+        if let Ok(BencodeType::String(peers6_compact)) = response.get_value_from_dict("peers6") {
+            peers.extend(Peer::from_compact(&peers6_compact, true));
+        }
+
+        if peers.is_empty() {
+            println!("The tracker response is invalid. Cannot continue :(");
+            return Err(ClientError::InvalidTrackerResponse);
+        }
+        Ok(peers)
     }
 
     /// Receives a peer and spawns a thread for each of these. Then, it tries to connect to the peer.
@@ -260,30 +484,45 @@ impl Client {
     ///     - New connection
     ///     - A connection was dropped
     /// The client makes a decision according to the received event.
-    /// The client stops listening for new events when it receives
-    /// all pieces of the file or the connections counterreachs zero.
-    fn listen_for_new_events(&mut self, rx: Receiver<NewEvent>, dl_finished: Arc<RwLock<bool>>) {
+    /// Meanwhile, the peers discovered with ut_pex are connected (until the file is downloaded),
+    /// and the resume file is saved periodically.
+    /// The client stops listening for new events when the connections counter reaches zero
+    /// and there are not any connection threads running.
+    fn listen_for_new_events(
+        &mut self,
+        rx: Receiver<NewEvent>,
+        tx: &Sender<NewEvent>,
+        dl_finished: Arc<RwLock<bool>>,
+        piece_queue: &Arc<RwLock<PieceQueue>>,
+        vec_threads: &mut Vec<JoinHandle<()>>,
+        tried_peers: &mut HashSet<SocketAddr>,
+    ) {
         let mut connection_counter = 0;
         let mut dl_pieces_counter = 0;
+        let save_interval = Duration::from_secs(RESUME_SAVE_INTERVAL);
+        let mut last_save = Instant::now();
+        let mut known_pieces = match self.downloaded_pieces.read() {
+            Ok(bitfield) => bitfield.clone(),
+            Err(_) => PieceBitfield::new(self.torrent.get_n_pieces()),
+        };
 
         loop {
-            println!("Active connections: {}", connection_counter);
-            if let Ok(new_event_msg) = rx.recv() {
-                match new_event_msg {
-                    NewEvent::NewConnection(torrent_name, peer) => {
-                        self.handle_new_conn_msg(&mut connection_counter, torrent_name, peer);
-                    }
-                    NewEvent::NewDownloadedPiece(torrent_name, piece, peer) => {
-                        dl_pieces_counter += 1;
-                        self.handle_new_dl_piece_msg(torrent_name, piece, peer);
-                    }
-                    NewEvent::ConnectionDropped(torrent_name, peer) => {
-                        self.handle_conn_dropped_msg(&mut connection_counter, torrent_name, peer);
-                    }
-                    NewEvent::OurStatus(status, peer) => {
-                        self.handle_status_msg(status, peer);
-                    }
-                    _ => (),
+            if last_save.elapsed() >= save_interval {
+                self.save_resume_data(Some(piece_queue));
+                last_save = Instant::now();
+            }
+            if let Ok(new_event_msg) = rx.recv_timeout(save_interval) {
+                self.handle_new_event(
+                    new_event_msg,
+                    &mut connection_counter,
+                    &mut dl_pieces_counter,
+                );
+            }
+            let requeued = self.requeue_rechecked_pieces(piece_queue, &mut known_pieces);
+            if requeued > 0 {
+                dl_pieces_counter = dl_pieces_counter.saturating_sub(requeued);
+                if let Ok(mut lock_dl) = dl_finished.write() {
+                    *lock_dl = false;
                 }
             }
 
@@ -293,110 +532,226 @@ impl Client {
                 }
             }
 
-            if connection_counter == 0 {
+            if !self.is_stopped() && !dl_finished.read().map_or(true, |lock_dl| *lock_dl) {
+                let active = vec_threads.iter().filter(|t| !t.is_finished()).count();
+                let free_slots = MAX_ACTIVE_CONNECTIONS.saturating_sub(active);
+                for peer in self.take_discovered_peers(tried_peers, free_slots) {
+                    let thread = self.handle_connection(
+                        self.clone(),
+                        peer,
+                        Sender::clone(tx),
+                        piece_queue.clone(),
+                        dl_finished.clone(),
+                    );
+                    vec_threads.push(thread);
+                }
+            }
+
+            if connection_counter == 0 && vec_threads.iter().all(|thread| thread.is_finished()) {
+                // The connections that ended may have sent events that were not read yet
+                while let Ok(new_event_msg) = rx.try_recv() {
+                    self.handle_new_event(
+                        new_event_msg,
+                        &mut connection_counter,
+                        &mut dl_pieces_counter,
+                    );
+                }
                 break;
             }
         }
     }
 
+    /// A force recheck removes the corrupted pieces from the bitfield. The pieces that
+    /// were downloaded and are not in the bitfield anymore are queued again, so the
+    /// connections download them.
+    /// Returns the number of queued pieces.
+    fn requeue_rechecked_pieces(
+        &self,
+        piece_queue: &Arc<RwLock<PieceQueue>>,
+        known_pieces: &mut PieceBitfield,
+    ) -> u32 {
+        let dl_pieces = match self.downloaded_pieces.read() {
+            Ok(bitfield) => bitfield.clone(),
+            Err(_) => return 0,
+        };
+        let lost: Vec<u32> = (0..self.torrent.get_n_pieces())
+            .filter(|idx| known_pieces.has_piece(*idx) && !dl_pieces.has_piece(*idx))
+            .collect();
+        *known_pieces = dl_pieces;
+        if lost.is_empty() {
+            return 0;
+        }
+
+        let mut requeued = 0;
+        if let Ok(mut pq_lock) = piece_queue.write() {
+            for idx in lost {
+                if pq_lock.requeue_piece(&self.torrent, idx) {
+                    requeued += 1;
+                }
+            }
+        }
+        requeued
+    }
+
+    fn handle_new_event(
+        &mut self,
+        new_event_msg: NewEvent,
+        connection_counter: &mut u32,
+        dl_pieces_counter: &mut u32,
+    ) {
+        match new_event_msg {
+            NewEvent::NewConnection(_, peer) => {
+                self.handle_new_conn_msg(connection_counter, peer);
+            }
+            NewEvent::NewDownloadedPiece(_, piece_idx, piece_length, peer) => {
+                *dl_pieces_counter += 1;
+                self.handle_new_dl_piece_msg(piece_idx, piece_length, peer);
+            }
+            NewEvent::ConnectionDropped(_, peer) => {
+                self.handle_conn_dropped_msg(connection_counter, peer);
+            }
+            NewEvent::OurStatus(_, status, peer) => {
+                self.handle_status_msg(status, peer);
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the peers received from other peers (ut_pex) that were not tried yet.
+    /// At most max peers are taken, so the active connections are limited. The other
+    /// ones are taken when some connections end.
+    fn take_discovered_peers(
+        &self,
+        tried_peers: &mut HashSet<SocketAddr>,
+        max: usize,
+    ) -> Vec<Peer> {
+        if max == 0 {
+            return vec![];
+        }
+        let discovered = match PeerSwarm::for_torrent(&self.torrent.get_info_hash()).lock() {
+            Ok(mut swarm) => swarm.take_discovered(max),
+            Err(_) => return vec![],
+        };
+        let new_peers: Vec<Peer> = discovered
+            .into_iter()
+            .filter(|addr| tried_peers.insert(*addr))
+            .map(Peer::from_socket_addr)
+            .collect();
+        if !new_peers.is_empty() {
+            self.log_pex_peers(new_peers.len());
+        }
+        new_peers
+    }
+
     /// When receives a new connection message, it increases the active connections counter
-    /// and notify GUI about this event.
+    /// and publishes this event.
     /// Also, this event is logged.
-    fn handle_new_conn_msg(&self, conn_counter: &mut u32, torrent_name: String, peer: Peer) {
+    fn handle_new_conn_msg(&mut self, conn_counter: &mut u32, peer: Peer) {
         *conn_counter += 1;
-        self.log_peer_connection(&peer);
-        if self
-            .tx_gui
-            .send(NewEvent::NewConnection(torrent_name, peer))
-            .is_err()
-        {
-            let _ = self.tx_logger.send(MsgCoder::generate_message(
-                ERROR_LOG_TYPE,
-                CLIENT_MODE_LOG,
-                "Failed to notify GUI about a new peer connection".to_string(),
-            ));
+        // The resume file only has IPv4 peers
+        if let SocketAddr::V4(addr) = peer.socket_addr() {
+            if !self.known_peers.contains(&addr) {
+                self.known_peers.push(addr);
+            }
         }
+        self.log_peer_connection(&peer);
+        self.events
+            .publish(NewEvent::NewConnection(self.torrent.get_id(), peer));
     }
 
     /// When receives a new downloaded piece message, it updates the bitfield
-    /// and notify GUI about this event.
+    /// and publishes this event.
     /// Also, this event is logged.
-    fn handle_new_dl_piece_msg(&self, torrent_name: String, piece: Piece, peer: Peer) {
+    fn handle_new_dl_piece_msg(&self, piece_idx: u32, piece_length: u32, peer: Peer) {
         if let Ok(mut lock_dl) = self.downloaded_pieces.write() {
-            lock_dl.add_a_piece(piece.get_idx());
+            lock_dl.add_a_piece(piece_idx);
         }
-        self.log_downloaded_piece(piece.get_idx());
+        self.log_downloaded_piece(piece_idx);
 
-        if self
-            .tx_gui
-            .send(NewEvent::NewDownloadedPiece(torrent_name, piece, peer))
-            .is_err()
-        {
-            let _ = self.tx_logger.send(MsgCoder::generate_message(
-                ERROR_LOG_TYPE,
-                CLIENT_MODE_LOG,
-                "Failed to notify GUI about a new downloaded piece".to_string(),
-            ));
-        }
+        self.events.publish(NewEvent::NewDownloadedPiece(
+            self.torrent.get_id(),
+            piece_idx,
+            piece_length,
+            peer,
+        ));
     }
 
     /// When receives a new connection message, it decreases the active connections counter
-    /// and notify GUI about this event.
-    fn handle_conn_dropped_msg(&self, conn_counter: &mut u32, torrent_name: String, peer: Peer) {
+    /// and publishes this event.
+    fn handle_conn_dropped_msg(&self, conn_counter: &mut u32, peer: Peer) {
         *conn_counter -= 1;
-        if self
-            .tx_gui
-            .send(NewEvent::ConnectionDropped(torrent_name, peer))
-            .is_err()
-        {
-            let _ = self.tx_logger.send(MsgCoder::generate_message(
-                ERROR_LOG_TYPE,
-                CLIENT_MODE_LOG,
-                "Failed to notify GUI about peer connection drop".to_string(),
-            ));
-        }
+        self.events
+            .publish(NewEvent::ConnectionDropped(self.torrent.get_id(), peer));
     }
 
     fn handle_status_msg(&self, status: String, peer: Peer) {
-        if self.tx_gui.send(NewEvent::OurStatus(status, peer)).is_err() {
-            let _ = self.tx_logger.send(MsgCoder::generate_message(
-                ERROR_LOG_TYPE,
-                CLIENT_MODE_LOG,
-                "Failed to notify GUI about new status".to_string(),
-            ));
-        }
+        self.events
+            .publish(NewEvent::OurStatus(self.torrent.get_id(), status, peer));
     }
 
     fn notify_no_of_peers(&self, no_of_peers: u32) {
-        let torrent_name = self.get_torrent_info().get_name();
+        self.events
+            .publish(NewEvent::NumberOfPeers(self.torrent.get_id(), no_of_peers));
+    }
+
+    fn notify_metadata_received(&self, n_dl_pieces: u32) {
+        self.events.publish(NewEvent::MetadataReceived(
+            self.torrent.get_id(),
+            self.get_torrent_info(),
+            n_dl_pieces,
+        ));
+    }
+
+    /// Logs tracker connection
+    fn log_tracker_connection(&self, url: &str) {
         if self
-            .tx_gui
-            .send(NewEvent::NumberOfPeers(torrent_name, no_of_peers))
+            .tx_logger
+            .send(MsgCoder::generate_message(
+                START_LOG_TYPE,
+                CLIENT_MODE_LOG,
+                format!("Connected to tracker: {} successfully\n", url),
+            ))
             .is_err()
         {
-            let _ = self.tx_logger.send(MsgCoder::generate_message(
-                ERROR_LOG_TYPE,
+            println!("Failed to log tracker connection");
+        }
+    }
+
+    /// Logs the number of peers found in the DHT.
+    fn log_dht_peers(&self, n_peers: usize) {
+        if self
+            .tx_logger
+            .send(MsgCoder::generate_message(
+                GENERIC_LOG_TYPE,
                 CLIENT_MODE_LOG,
-                "Failed to notify GUI about number of peers provided by the tracker".to_string(),
-            ));
+                format!(
+                    "Torrent: {} - {} peers found in the DHT\n",
+                    self.torrent.get_name(),
+                    n_peers
+                ),
+            ))
+            .is_err()
+        {
+            println!("Failed to log DHT peers");
         }
     }
 
-    /// Logs tracker connection
-    fn log_tracker_connection(&self) {
+    /// Logs the number of new peers received from other peers.
+    fn log_pex_peers(&self, n_peers: usize) {
         if self
             .tx_logger
             .send(MsgCoder::generate_message(
-                START_LOG_TYPE,
+                GENERIC_LOG_TYPE,
                 CLIENT_MODE_LOG,
                 format!(
-                    "Connected to tracker: {} successfully\n",
-                    self.torrent.get_announce()
+                    "Torrent: {} - {} new peers received from other peers\n",
+                    self.torrent.get_name(),
+                    n_peers
                 ),
             ))
             .is_err()
         {
-            println!("Failed to log tracker connection");
+            println!("Failed to log peers received from other peers");
         }
     }
 
@@ -420,6 +775,26 @@ impl Client {
         }
     }
 
+    /// Logs the reception of the torrent metadata.
+    fn log_metadata_received(&self, peer: &Peer) {
+        if self
+            .tx_logger
+            .send(MsgCoder::generate_message(
+                GENERIC_LOG_TYPE,
+                CLIENT_MODE_LOG,
+                format!(
+                    "Torrent: {} - Metadata received from peer - {}:{}\n",
+                    self.torrent.get_name(),
+                    peer.ip(),
+                    peer.port()
+                ),
+            ))
+            .is_err()
+        {
+            println!("Failed to log metadata reception");
+        }
+    }
+
     /// Logs piece downloading.
     fn log_downloaded_piece(&self, idx: u32) {
         if self
@@ -457,14 +832,9 @@ impl Client {
         }
     }
 
-    fn merge_pieces(&self) -> Result<(), String> {
-        self.log_downloaded_file();
-
-        PieceMerger::merge_pieces(
-            &self.torrent.get_name(),
-            &self.settings.get_downloads_dir(),
-            self.torrent.get_n_pieces(),
-        )
+    /// Returns the storage of the torrent files in the downloads directory.
+    pub fn get_storage(&self) -> Storage {
+        Storage::new(self.torrent.clone(), &self.settings.get_downloads_dir())
     }
 
     fn join_peer_conn_threads(&self, vec_threads: Vec<JoinHandle<()>>) -> Result<(), ClientError> {
@@ -495,6 +865,10 @@ impl Client {
     pub fn get_port(&self) -> String {
         self.settings.get_tcp_port()
     }
+
+    pub fn get_request_queue_depth(&self) -> u32 {
+        self.settings.get_request_queue_depth()
+    }
 }
 
 // Testing:
@@ -508,8 +882,6 @@ impl Client {
 /*
 #[cfg(test)]
 mod tests {
-    use glib::{MainContext, PRIORITY_DEFAULT};
-
     use crate::piece::Piece;
     use crate::{settings::Settings, torrent_finder::TorrentFinder};
     use std::sync::mpsc::channel;
@@ -524,13 +896,9 @@ mod tests {
             Settings::new("files_for_testing/settings_files_testing/settings.txt").handle_error(),
         );
         let (tx_logger, _rx) = channel();
-        let (tx, rx) = channel();
-        let (tx_gtk, _rx_gtk) = MainContext::channel(PRIORITY_DEFAULT);
-        let _ = tx.send(tx_gtk.clone());
-        let _ = tx.send(tx_gtk);
-        let sh_rx = Arc::new(Mutex::new(rx));
+        let events = EventBus::new();
 
-        if let Ok(vec) = TorrentFinder::find(torrent_path, "files_for_testing/downloaded_files2", sh_rx.clone()) {
+        if let Ok(vec) = TorrentFinder::find(torrent_path, "files_for_testing/downloaded_files2", &events) {
             let mut piece = Piece::new(0, vec[0].0.get_piece_length(), vec[0].0.get_hash(0));
             let piece_queue = Arc::new(RwLock::new(PieceQueue::new(&vec[0].0, &vec[0].1)));
             let mut client = Client::new(
@@ -538,7 +906,7 @@ mod tests {
                 vec[0].0.clone(),
                 vec[0].1.clone(),
                 tx_logger,
-                sh_rx.clone(),
+                events.clone(),
             );
             let (tx_peer_conn_to_client, _rx) = channel();
 
@@ -0,0 +1,237 @@
+use crate::bt_client::peer::Peer;
+use crate::constants::*;
+use crate::errors::DownloadError;
+use crate::extensions::extension_registry::ExtensionRegistry;
+use crate::extensions::ut_metadata::{UtMetadata, UT_METADATA};
+use crate::p2p_messages::handshake::Handshake;
+use crate::p2p_messages::message_builder::{MessageBuilder, P2PMessage};
+use crate::p2p_messages::message_trait::Message;
+
+use std::net::TcpStream;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// # struct MetadataFetcher
+/// Downloads the info dictionary of a torrent from a peer, using the
+/// ut_metadata extension (BEP 9).
+/// Fields:
+///     - stream
+///     - info_hash -> the downloaded metadata is verified against it
+///     - extensions -> registry that only contains ut_metadata
+///     - metadata -> it is set by ut_metadata when the download ends
+#[derive(Debug)]
+pub struct MetadataFetcher {
+    stream: TcpStream,
+    info_hash: Vec<u8>,
+    extensions: ExtensionRegistry,
+    metadata: Arc<RwLock<Option<Vec<u8>>>>,
+}
+
+impl MetadataFetcher {
+    /// Connects to the peer and downloads the metadata of the torrent.
+    /// On success, returns the bencoded info dictionary (its hash matches the info hash).
+    /// Otherwise, returns error.
+    pub fn fetch(
+        peer: &Peer,
+        info_hash: Vec<u8>,
+        peer_id: Vec<u8>,
+    ) -> Result<Vec<u8>, DownloadError> {
+        let stream = peer
+            .connect()
+            .map_err(|_| DownloadError::ConnectionFailed)?;
+        if stream.set_read_timeout(Some(Duration::new(5, 0))).is_err() {
+            return Err(DownloadError::ConnectionFailed);
+        }
+
+        let metadata = Arc::new(RwLock::new(None));
+        let mut extensions = ExtensionRegistry::new(None);
+        extensions.register(Box::new(UtMetadata::new(
+            info_hash.clone(),
+            metadata.clone(),
+        )));
+
+        let mut fetcher = MetadataFetcher {
+            stream,
+            info_hash,
+            extensions,
+            metadata,
+        };
+        fetcher.exchange_handshake(peer_id)?;
+        fetcher.download_metadata()
+    }
+
+    /// Sends a handshake with the extension protocol bit set.
+    /// The peer's handshake has to be valid and support the extension protocol too.
+    fn exchange_handshake(&mut self, peer_id: Vec<u8>) -> Result<(), DownloadError> {
+        let handshake =
+            Handshake::new_from_param("BitTorrent protocol", self.info_hash.clone(), peer_id);
+        if handshake.send_msg(&mut self.stream).is_ok() {
+            if let Ok(handshake_res) = Handshake::read_msg(&mut self.stream) {
+                if !handshake_res.is_valid(self.info_hash.clone()) {
+                    return Err(DownloadError::HandshakeError);
+                }
+                if !handshake_res.supports_extension_protocol() {
+                    return Err(DownloadError::MetadataNotSupported);
+                }
+                return Ok(());
+            }
+        }
+        Err(DownloadError::HandshakeError)
+    }
+
+    /// Sends our extension handshake, then it handles the extended messages of the peer
+    /// until ut_metadata has received and verified the whole metadata.
+    fn download_metadata(&mut self) -> Result<Vec<u8>, DownloadError> {
+        self.send_extended_msgs(vec![self.extensions.handshake_msg()])?;
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(METADATA_TIMEOUT) {
+            let msg = MessageBuilder::build(&mut self.stream)
+                .map_err(|_| DownloadError::CannotReadPeerMessage)?;
+            if let P2PMessage::Extended(msg) = msg {
+                let responses = self
+                    .extensions
+                    .handle_msg(msg)
+                    .map_err(|_| DownloadError::InvalidMetadata)?;
+                self.send_extended_msgs(responses)?;
+            }
+
+            if let Some(handshake) = self.extensions.get_peer_handshake() {
+                if handshake.get_value("metadata_size").is_none()
+                    || !self.extensions.peer_supports(UT_METADATA)
+                {
+                    return Err(DownloadError::MetadataNotSupported);
+                }
+            }
+            let requests = self.extensions.poll();
+            self.send_extended_msgs(requests)?;
+
+            if let Ok(lock_metadata) = self.metadata.read() {
+                if let Some(metadata) = &*lock_metadata {
+                    return Ok(metadata.clone());
+                }
+            }
+        }
+        Err(DownloadError::CannotReadPeerMessage)
+    }
+
+    fn send_extended_msgs<T: Message>(&mut self, msgs: Vec<T>) -> Result<(), DownloadError> {
+        for msg in msgs {
+            if msg.send_msg(&mut self.stream).is_err() {
+                return Err(DownloadError::ConnectionFailed);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p_messages::extended::ExtendedMsg;
+    use crate::torrent_info::TorrentInfo;
+    use bencode::BencodeEncoder;
+    use bencode::BencodeParser;
+    use bencode::BencodeType;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn get_metadata() -> Vec<u8> {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let parsed = BencodeParser.parse_file(path).expect("It shouldn't fail");
+        let info = parsed
+            .get_value_from_dict("info")
+            .expect("It shouldn't fail");
+        BencodeEncoder.bencode(&info)
+    }
+
+    fn get_peer(port: u16) -> Peer {
+        let mut dict = HashMap::new();
+        dict.insert(
+            b"ip".to_vec(),
+            BencodeType::String("127.0.0.1".as_bytes().to_vec()),
+        );
+        dict.insert(b"port".to_vec(), BencodeType::Integer(port as i64));
+        Peer::new(BencodeType::Dictionary(dict)).expect("It shouldn't fail")
+    }
+
+    /// Behaves like a peer that has the metadata and sends it in pieces of 16 KiB.
+    fn run_seeder(listener: TcpListener, metadata: Vec<u8>) {
+        let (mut stream, _) = listener.accept().expect("It shouldn't fail");
+        let handshake = Handshake::read_msg(&mut stream).expect("It shouldn't fail");
+        let our_handshake = Handshake::new_from_param(
+            "BitTorrent protocol",
+            handshake.get_info_hash(),
+            vec![1; 20],
+        );
+        let _ = our_handshake.send_msg(&mut stream);
+
+        let ext_handshake = format!(
+            "d1:md11:ut_metadatai3ee13:metadata_sizei{}ee",
+            metadata.len()
+        );
+        let _ = ExtendedMsg::new(0, ext_handshake.into_bytes()).send_msg(&mut stream);
+
+        while let Ok(msg) = MessageBuilder::build(&mut stream) {
+            if let P2PMessage::Extended(msg) = msg {
+                if msg.get_ext_id() != 3 {
+                    continue;
+                }
+                let request = BencodeParser
+                    .parse_vec(&msg.get_payload())
+                    .expect("It shouldn't fail");
+                let piece = request
+                    .get_value_from_dict("piece")
+                    .and_then(|p| p.get_integer())
+                    .expect("It shouldn't fail") as usize;
+                let begin = piece * METADATA_PIECE_LENGTH;
+                let end = metadata.len().min(begin + METADATA_PIECE_LENGTH);
+                let mut payload = format!(
+                    "d8:msg_typei1e5:piecei{}e10:total_sizei{}ee",
+                    piece,
+                    metadata.len()
+                )
+                .into_bytes();
+                payload.extend(&metadata[begin..end]);
+                // We are the first (and only) extension of the fetcher registry
+                let _ = ExtendedMsg::new(1, payload).send_msg(&mut stream);
+            }
+        }
+    }
+
+    #[test]
+    fn fetch_metadata_from_peer() {
+        let metadata = get_metadata();
+        let torrent = TorrentInfo::from_metadata(&metadata, vec![]).expect("It shouldn't fail");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("It shouldn't fail");
+        let port = listener.local_addr().expect("It shouldn't fail").port();
+        let metadata_aux = metadata.clone();
+        let seeder = thread::spawn(move || run_seeder(listener, metadata_aux));
+
+        let fetched = MetadataFetcher::fetch(
+            &get_peer(port),
+            torrent.get_info_hash(),
+            CLIENT_ID.as_bytes().to_vec(),
+        );
+        let _ = seeder.join();
+
+        match fetched {
+            Ok(fetched_metadata) => assert_eq!(fetched_metadata, metadata),
+            Err(_) => assert!(false),
+        }
+    }
+
+    #[test]
+    fn error_fetch_metadata_with_other_info_hash() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("It shouldn't fail");
+        let port = listener.local_addr().expect("It shouldn't fail").port();
+        let seeder = thread::spawn(move || run_seeder(listener, get_metadata()));
+
+        let fetched =
+            MetadataFetcher::fetch(&get_peer(port), vec![7; 20], CLIENT_ID.as_bytes().to_vec());
+        let _ = seeder.join();
+
+        assert!(matches!(fetched, Err(DownloadError::InvalidMetadata)));
+    }
+}
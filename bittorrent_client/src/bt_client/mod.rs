@@ -1,5 +1,9 @@
 pub mod client;
+pub mod metadata_fetcher;
 pub mod peer;
 pub mod peer_connection;
 pub mod piece_queue;
+pub mod request_window;
+pub mod tracker_list;
 pub mod tracker_request;
+pub mod udp_tracker;
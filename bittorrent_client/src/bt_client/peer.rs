@@ -1,32 +1,58 @@
-use crate::bencode_type::BencodeType;
+use crate::dht::node_info::COMPACT_PEER_LENGTH;
 use crate::errors::ClientError;
+use bencode::BencodeType;
 use std::io::Error;
-use std::net::TcpStream;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs};
 use std::vec;
 
+pub const COMPACT_PEER6_LENGTH: usize = 18;
+
 /// # struct Peer
 /// Represents a peer.
+///     - id -> peer id (zeros while it is unknown)
+///     - addr -> listening address of the peer (IPv4 or IPv6)
 #[derive(Debug, Clone)]
 pub struct Peer {
     id: Vec<u8>,
-    ip: String,
-    port: u32,
+    addr: SocketAddr,
 }
 
 impl Peer {
-    /// Receives a list of peers and gets the information of the last peer.
+    /// Receives a peer of a tracker response (dictionary with ip, port and peer id).
+    /// The ip can also be a domain name, which is resolved.
     /// On success, returns a Peer.
-    /// Otherwise, returns ClientError (CannotFindAnyPeer or InvalidTrackerResponse if
-    /// the list is not valid)-
+    /// Otherwise, returns ClientError (InvalidTrackerResponse if the peer is not valid).
     pub fn new(peer: BencodeType) -> Result<Peer, ClientError> {
         let ip = get_peer_ip(&peer)?;
         let port = get_peer_port(&peer)?;
+        let addr = (ip.as_str(), port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or(ClientError::InvalidTrackerResponse)?;
         let id = match get_peer_id(&peer) {
             Ok(peer_id) => peer_id,
             Err(_) => vec![0u8; 20],
         };
 
-        Ok(Peer { id, ip, port })
+        Ok(Peer { id, addr })
+    }
+
+    /// Creates a peer whose id is unknown (e.g. found in the DHT).
+    pub fn from_socket_addr(addr: SocketAddr) -> Peer {
+        Peer {
+            id: vec![0u8; 20],
+            addr,
+        }
+    }
+
+    /// Parses a list of peers in compact format: 'peers' of IPv4 peers (BEP 23)
+    /// or 'peers6' of IPv6 peers (BEP 7).
+    pub fn from_compact(compact: &[u8], ipv6: bool) -> Vec<Peer> {
+        decode_compact_addrs(compact, ipv6)
+            .into_iter()
+            .map(Peer::from_socket_addr)
+            .collect()
     }
 
     pub fn id(&self) -> Vec<u8> {
@@ -34,15 +60,19 @@ impl Peer {
     }
 
     pub fn ip(&self) -> String {
-        self.ip.clone()
+        self.addr.ip().to_string()
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
     }
 
-    pub fn port(&self) -> u32 {
-        self.port
+    pub fn socket_addr(&self) -> SocketAddr {
+        self.addr
     }
 
     pub fn connect(&self) -> Result<TcpStream, Error> {
-        TcpStream::connect(format!("{}:{}", self.ip, self.port))
+        TcpStream::connect(self.addr)
     }
 
     pub fn update_id(&mut self, id: Vec<u8>) {
@@ -50,6 +80,36 @@ impl Peer {
     }
 }
 
+/// Returns the address in compact format (4 or 16 bytes of ip and 2 of port).
+pub fn encode_compact_addr(addr: &SocketAddr) -> Vec<u8> {
+    let mut compact = match addr.ip() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    compact.extend(addr.port().to_be_bytes());
+    compact
+}
+
+/// Parses a list of addresses in compact format. The incomplete entries are ignored.
+pub fn decode_compact_addrs(compact: &[u8], ipv6: bool) -> Vec<SocketAddr> {
+    let length = if ipv6 {
+        COMPACT_PEER6_LENGTH
+    } else {
+        COMPACT_PEER_LENGTH
+    };
+    compact
+        .chunks_exact(length)
+        .map(|entry| {
+            let (ip, port) = entry.split_at(length - 2);
+            let ip = match <[u8; 16]>::try_from(ip) {
+                Ok(octets) => IpAddr::V6(Ipv6Addr::from(octets)),
+                Err(_) => IpAddr::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])),
+            };
+            SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]]))
+        })
+        .collect()
+}
+
 fn get_peer_id(peer: &BencodeType) -> Result<Vec<u8>, ClientError> {
     if let Ok(value1) = peer.get_value_from_dict("peer id") {
         if let Ok(value2) = value1.get_string() {
@@ -70,11 +130,72 @@ fn get_peer_ip(peer: &BencodeType) -> Result<String, ClientError> {
     Err(ClientError::InvalidTrackerResponse)
 }
 
-fn get_peer_port(peer: &BencodeType) -> Result<u32, ClientError> {
+fn get_peer_port(peer: &BencodeType) -> Result<u16, ClientError> {
     if let Ok(value1) = peer.get_value_from_dict("port") {
         if let Ok(value2) = value1.get_integer() {
-            return Ok(value2 as u32);
+            if let Ok(port) = u16::try_from(value2) {
+                return Ok(port);
+            }
         }
     }
     Err(ClientError::InvalidTrackerResponse)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn compact_peers_are_parsed() {
+        // Two peers and an incomplete entry
+        let compact = [127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x1a, 0xe2, 10, 0, 0];
+        let peers = Peer::from_compact(&compact, false);
+
+        assert_eq!(peers.len(), 2);
+        assert_eq!(
+            peers[0].socket_addr(),
+            "127.0.0.1:6881".parse().expect("It shouldn't fail")
+        );
+        assert_eq!(
+            peers[1].socket_addr(),
+            "10.0.0.2:6882".parse().expect("It shouldn't fail")
+        );
+    }
+
+    #[test]
+    fn compact_ipv6_peers_to_bytes_and_back() {
+        let addr: SocketAddr = "[2001:db8::1]:6881".parse().expect("It shouldn't fail");
+        let compact = encode_compact_addr(&addr);
+
+        assert_eq!(compact.len(), COMPACT_PEER6_LENGTH);
+        assert_eq!(decode_compact_addrs(&compact, true), vec![addr]);
+        assert_eq!(decode_compact_addrs(&compact, false).len(), 3);
+    }
+
+    #[test]
+    fn peer_of_a_dictionary_with_ipv6_address() {
+        let mut dict = HashMap::new();
+        dict.insert(b"ip".to_vec(), BencodeType::String(b"::1".to_vec()));
+        dict.insert(b"port".to_vec(), BencodeType::Integer(6881));
+
+        if let Ok(peer) = Peer::new(BencodeType::Dictionary(dict)) {
+            assert_eq!(
+                peer.socket_addr(),
+                "[::1]:6881".parse().expect("It shouldn't fail")
+            );
+            assert_eq!(peer.id(), vec![0u8; 20]);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn error_peer_with_invalid_port() {
+        let mut dict = HashMap::new();
+        dict.insert(b"ip".to_vec(), BencodeType::String(b"127.0.0.1".to_vec()));
+        dict.insert(b"port".to_vec(), BencodeType::Integer(70000));
+
+        assert!(Peer::new(BencodeType::Dictionary(dict)).is_err());
+    }
+}
@@ -1,9 +1,17 @@
 use crate::bitfield::PieceBitfield;
 use crate::bt_client::client::Client;
 use crate::bt_client::peer::Peer;
-use crate::bt_client::piece_queue::PieceQueue;
+use crate::bt_client::piece_queue::{BlockRequester, PieceQueue};
+use crate::bt_client::request_window::RequestWindow;
+use crate::choker::Choker;
+use crate::constants::BLOCK_REQUEST_TIMEOUT;
 use crate::errors::*;
 use crate::event_messages::*;
+use crate::extensions::extension_registry::ExtensionRegistry;
+use crate::extensions::ut_metadata::UtMetadata;
+use crate::extensions::ut_pex::UtPex;
+use crate::p2p_messages::cancel::CancelMsg;
+use crate::p2p_messages::extended::ExtendedMsg;
 use crate::p2p_messages::handshake::Handshake;
 use crate::p2p_messages::interested::InterestedMsg;
 use crate::p2p_messages::keep_alive::KeepAliveMsg;
@@ -11,17 +19,18 @@ use crate::p2p_messages::message_builder::MessageBuilder;
 use crate::p2p_messages::message_builder::P2PMessage;
 use crate::p2p_messages::message_trait::Message;
 use crate::p2p_messages::piece::PieceMsg;
+use crate::p2p_messages::reject_request::RejectRequestMsg;
 use crate::p2p_messages::request::RequestMsg;
 use crate::piece::Piece;
+use crate::storage::Storage;
+use crate::swarm::PeerSwarm;
 
-use std::fs::{self, File};
-use std::io::Write;
+use std::collections::HashSet;
+use std::io::ErrorKind;
 use std::net::TcpStream;
-use std::path::Path;
-use std::sync::mpsc::SendError;
-use std::sync::{mpsc::Sender, Arc, RwLock};
+use std::sync::{mpsc::Sender, Arc, Mutex, RwLock};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::vec;
 
 /// # struct PeerConnection (client)
@@ -39,9 +48,26 @@ use std::vec;
 ///     - status 1: downloading  -> waiting piece message
 ///              0: not downloading -> we've received all requested blocks and can request the next one.
 ///     - piece: downloaded piece
+///     - extensions -> extensions of the extension protocol (BEP 10)
+///     - supports_extensions -> peer supports the extension protocol
+///     - choker -> shared by all connections of the torrent. Outbound connections only
+///       report the bytes the peer sends us: they never unchoke the peer, because they don't
+///       upload (the server connections do).
+///     - storage -> files where the downloaded pieces are written
+///     - swarm -> peers of the torrent, exchanged with ut_pex (shared by all connections of the torrent)
+///     - supports_fast -> both peers support the Fast extension (BEP 6)
+///     - allowed_fast -> pieces we can request while the peer chokes us
+///     - suggested -> pieces the peer suggested, they are downloaded first
+///     - downloading -> pieces whose blocks are being requested to the peer
+///     - requests -> blocks requested to the peer that have not been received yet
+///     - stalled -> pieces whose requests timed out, the peer is not asked for them again
+///     - last_block -> when the last requested block was received
+///     - writer -> stream used to send messages. It is shared with the piece queue, so other
+///       connections can cancel our requests of the blocks they received (endgame mode).
 #[derive(Debug)]
 pub struct PeerConnection {
     stream: TcpStream,
+    writer: Arc<Mutex<TcpStream>>,
     client: Client,
     peer: Peer,
     am_choked: bool,
@@ -49,6 +75,18 @@ pub struct PeerConnection {
     pieces: PieceBitfield,
     piece_queue: Arc<RwLock<PieceQueue>>,
     tx_client: Sender<NewEvent>,
+    extensions: ExtensionRegistry,
+    supports_extensions: bool,
+    choker: Arc<Mutex<Choker>>,
+    storage: Storage,
+    swarm: Arc<Mutex<PeerSwarm>>,
+    supports_fast: bool,
+    allowed_fast: HashSet<u32>,
+    suggested: HashSet<u32>,
+    downloading: Vec<Piece>,
+    requests: RequestWindow,
+    stalled: HashSet<u32>,
+    last_block: Instant,
 }
 
 impl PeerConnection {
@@ -62,11 +100,32 @@ impl PeerConnection {
         tx_client: Sender<NewEvent>,
     ) -> Result<PeerConnection, ClientError> {
         if let Ok(stream) = peer.connect() {
-            let number_of_pieces = client.get_torrent_info().get_n_pieces();
+            let torrent = client.get_torrent_info();
+            let number_of_pieces = torrent.get_n_pieces();
             let bitfield = vec![0; (number_of_pieces as f32 / 8.0).ceil() as usize];
-            if stream.set_read_timeout(Some(Duration::new(5, 0))).is_ok() {
+
+            let mut extensions = ExtensionRegistry::new(client.get_port().parse().ok());
+            let metadata = Arc::new(RwLock::new(Some(torrent.get_metadata())));
+            extensions.register(Box::new(UtMetadata::new(torrent.get_info_hash(), metadata)));
+            let swarm = PeerSwarm::for_torrent(&torrent.get_info_hash());
+            // Peers of private torrents are not exchanged
+            if !torrent.is_private() {
+                extensions.register(Box::new(UtPex::new(
+                    swarm.clone(),
+                    Some(peer.socket_addr()),
+                )));
+            }
+            let choker = Choker::for_torrent(&torrent.get_info_hash(), client.get_dl_pieces());
+            let storage = client.get_storage();
+            let requests = RequestWindow::new(client.get_request_queue_depth());
+
+            if let (Ok(writer), Ok(())) = (
+                stream.try_clone(),
+                stream.set_read_timeout(Some(Duration::new(5, 0))),
+            ) {
                 return Ok(PeerConnection {
                     stream,
+                    writer: Arc::new(Mutex::new(writer)),
                     client,
                     peer,
                     am_choked: true,
@@ -74,6 +133,18 @@ impl PeerConnection {
                     pieces: PieceBitfield::new_from_vec(bitfield, number_of_pieces),
                     piece_queue,
                     tx_client,
+                    extensions,
+                    supports_extensions: false,
+                    choker,
+                    storage,
+                    swarm,
+                    supports_fast: false,
+                    allowed_fast: HashSet::new(),
+                    suggested: HashSet::new(),
+                    downloading: vec![],
+                    requests,
+                    stalled: HashSet::new(),
+                    last_block: Instant::now(),
                 });
             }
         }
@@ -81,6 +152,8 @@ impl PeerConnection {
     }
 
     /// Sends a handshake to a connected peer and tries to receive it from this one.
+    /// If both peers support the extension protocol, our extension handshake is sent.
+    /// The peer is registered in the choker and the swarm of the torrent.
     /// On error, returns CannotConnectToPeer
     pub fn exchange_handshake(&mut self) -> Result<(), DownloadError> {
         let handshake = Handshake::new(&self.client, "BitTorrent protocol");
@@ -88,6 +161,15 @@ impl PeerConnection {
             if let Ok(handshake_res) = Handshake::read_msg(&mut self.stream) {
                 if handshake_res.is_valid(self.client.get_torrent_info().get_info_hash()) {
                     self.peer.update_id(handshake_res.get_peer_id());
+                    if let Ok(mut choker) = self.choker.lock() {
+                        choker.register(&self.peer.id());
+                    }
+                    self.update_swarm(true);
+                    self.supports_extensions = handshake_res.supports_extension_protocol();
+                    self.supports_fast = handshake_res.supports_fast_extension();
+                    if self.supports_extensions {
+                        let _ = self.extensions.handshake_msg().send_msg(&mut self.stream);
+                    }
                     return Ok(());
                 }
             }
@@ -95,19 +177,25 @@ impl PeerConnection {
         Err(DownloadError::HandshakeError)
     }
 
-    fn announce_new_connection(&self) -> Result<(), SendError<NewEvent>> {
-        let torrent_name = self.client.get_torrent_info().get_name();
+    fn announce_new_connection(&self) -> Result<(), DownloadError> {
+        let torrent_id = self.client.get_torrent_info().get_id();
         self.tx_client
-            .send(NewEvent::NewConnection(torrent_name, self.peer.clone()))
+            .send(NewEvent::NewConnection(torrent_id, self.peer.clone()))
+            .map_err(|_| DownloadError::ConnectionFailed)
     }
 
     /// The download starts. First there is an exchange of handshakes
     /// If handshake fails, the download will end.
-    /// Then, it pops a piece from the piece queue. If the piece queue is not empty,
-    /// this piece will be downloaded.
-    /// If the piece queue is empty, we check:
+    /// Then, we wait for the pieces of the peer (Bitfield or Have messages).
+    /// After that, blocks are requested while the request window has room, so several
+    /// blocks (of one or more pieces) are on their way at the same time. When the pieces
+    /// being downloaded have no blocks left to request, the rarest piece that the peer has
+    /// is taken from the piece queue.
+    /// If there is nothing to request, we check:
     ///     - If the download finished, the connection will be dropped
+    ///     - If the client was stopped, the connection will be dropped (checked before every message)
     ///     - If the peer has not any piece that we need, the connection will be dropped.
+    ///     - If the peer chokes us, we wait until it unchokes us.
     ///     - Otherwise, calls yield_now() and then, starts another loop iteration
     pub fn start_download(
         &mut self,
@@ -117,30 +205,56 @@ impl PeerConnection {
         if self.exchange_handshake().is_err() || self.announce_new_connection().is_err() {
             return;
         }
+        if self.receive_peer_pieces().is_err() {
+            return self.drop_connection();
+        }
 
         loop {
-            if let Ok(mut piece) = self.fetch_piece() {
-                match self.download_piece(&mut piece) {
-                    Ok(_) => self.handle_new_piece(piece),
+            if self.client.is_stopped() {
+                return self.drop_connection();
+            }
+            self.send_extension_msgs();
+            if !self.am_interested {
+                self.interested_in_piece();
+            }
+            if self.request_blocks().is_err() {
+                return self.drop_connection();
+            }
 
-                    Err(DownloadError::InvalidPiece) => self.return_piece(piece),
+            if self.requests.is_empty() {
+                if self.download_finished(&dl_finished) || !self.has_any_wanted_piece(&bf_pieces) {
+                    return self.drop_connection();
+                }
+                if !self.am_choked {
+                    thread::yield_now();
+                    continue;
+                }
+            }
+            self.keep_connection_alive();
+            if self.receive_message().is_err() || self.return_timed_out_pieces().is_err() {
+                return self.drop_connection();
+            }
+            self.sync_shared_pieces();
+            self.return_blocked_pieces();
+        }
+    }
 
-                    Err(DownloadError::CannotReadPeerMessage) => {
-                        return self.drop_connection(Some(piece));
-                    }
-                    Err(DownloadError::PeerChokedUs) => {
-                        return self.drop_connection(Some(piece));
-                    }
-                    _ => (),
+    /// Reads messages until the peer tells us which pieces it has.
+    /// If the peer sends nothing for a while (or Have None), we continue without its pieces.
+    fn receive_peer_pieces(&mut self) -> Result<(), DownloadError> {
+        while self.pieces.number_of_downloaded_pieces() == 0 {
+            match MessageBuilder::build(&mut self.stream) {
+                Ok(P2PMessage::HaveNone(_)) if self.supports_fast => return Ok(()),
+                Ok(msg) => self.handle_msg(msg),
+                Err(MessageError::ReadingError(err))
+                    if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut =>
+                {
+                    return Ok(());
                 }
-                continue;
-            } else if self.download_finished(&dl_finished) || !self.has_any_wanted_piece(&bf_pieces)
-            {
-                return self.drop_connection(None);
-            } else {
-                thread::yield_now();
+                Err(_) => return Err(DownloadError::CannotReadPeerMessage),
             }
         }
+        Ok(())
     }
 
     fn download_finished(&self, dl_pieces: &Arc<RwLock<bool>>) -> bool {
@@ -158,91 +272,353 @@ impl PeerConnection {
         false
     }
 
+    /// Stores the piece. In endgame mode, if another connection finished the piece first,
+    /// it is discarded. If it cannot be stored, it is downloaded again.
     fn handle_new_piece(&mut self, mut piece: Piece) {
         if self.store_piece_in_file(&mut piece).is_ok() {
-            println!(
-                "DOWNLOADED PIECE_N {} - from peer: {:?}\n",
-                piece.get_idx(),
-                self.peer.id()
-            );
-            let torrent_name = self.client.get_torrent_info().get_name();
+            if !self.mark_piece_downloaded(piece.get_idx()) {
+                return;
+            }
+            let torrent_id = self.client.get_torrent_info().get_id();
             let _ = self.tx_client.send(NewEvent::NewDownloadedPiece(
-                torrent_name,
-                piece,
+                torrent_id,
+                piece.get_idx(),
+                piece.get_tl(),
                 self.peer.clone(),
             ));
         } else {
-            self.return_piece(piece);
+            self.discard_piece(piece);
         }
     }
 
-    fn drop_connection(&mut self, curr_piece: Option<Piece>) {
-        if let Some(piece) = curr_piece {
+    /// The pieces being downloaded are returned to the queue.
+    fn drop_connection(&mut self) {
+        for piece in std::mem::take(&mut self.downloading) {
             self.return_piece(piece);
         }
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            pq_lock.remove_peer_pieces(&self.pieces);
+            pq_lock.remove_peer_requests(self.peer.socket_addr(), None);
+        }
+        if let Ok(mut choker) = self.choker.lock() {
+            choker.unregister(&self.peer.id());
+        }
+        self.update_swarm(false);
 
-        let torrent_name = self.client.get_torrent_info().get_name();
+        let torrent_id = self.client.get_torrent_info().get_id();
         let _ = self
             .tx_client
-            .send(NewEvent::ConnectionDropped(torrent_name, self.peer.clone()));
+            .send(NewEvent::ConnectionDropped(torrent_id, self.peer.clone()));
     }
 
-    /// Carries out the exchange of messages following the BitTorrent protocol to download a piece.
-    /// Finally, on success, it returns the downloaded piece.
-    /// Otherwise, it returns an error.
-    ///
-    /// -> Note that if the other peer chokes us, the message exchange will end, otherwise,
-    /// it will continue until we download the piece or some error arises.
-    pub fn download_piece(&mut self, piece: &mut Piece) -> Result<(), DownloadError> {
-        while piece.get_dl() < piece.get_tl() {
-            self.keep_connection_alive();
-
-            if !self.am_interested {
-                self.interested_in_piece();
-            }
+    /// Checks the piece once all its blocks were received. If it is valid, it is stored.
+    /// Otherwise, it is returned to the queue to be downloaded again.
+    fn finish_piece(&mut self, mut piece: Piece) {
+        if piece.piece_is_valid() {
+            self.handle_new_piece(piece);
+        } else {
+            self.discard_piece(piece);
+        }
+    }
 
-            if !self.am_choked && self.am_interested {
-                self.request_a_piece(piece);
-            }
-            self.receive_message(piece)?;
+    /// Discards the data of the piece (it is not valid, or it cannot be stored), and
+    /// returns it to the queue. The copy shared with other connections is discarded too,
+    /// otherwise the returned piece would take its blocks and never be requested again.
+    fn discard_piece(&mut self, mut piece: Piece) {
+        piece.reset_info();
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            pq_lock.piece_is_invalid(piece.get_idx());
         }
-        if piece.piece_is_valid() {
+        self.return_piece(piece);
+    }
+
+    /// Requests blocks while the request window has room. The blocks of the pieces being
+    /// downloaded are requested first, then new pieces are taken from the queue.
+    /// If a request cannot be sent, returns an error because the connection is broken.
+    fn request_blocks(&mut self) -> Result<(), DownloadError> {
+        if !self.am_interested {
             return Ok(());
         }
-        Err(DownloadError::InvalidPiece)
+        while self.requests.has_room() {
+            let position = self
+                .downloading
+                .iter()
+                .position(|piece| piece.has_free_blocks() && self.can_request(piece.get_idx()));
+            let position = match position {
+                Some(position) => position,
+                None => match self.fetch_piece() {
+                    Ok(piece) => {
+                        self.downloading.push(piece);
+                        self.downloading.len() - 1
+                    }
+                    Err(_) => return Ok(()),
+                },
+            };
+
+            let piece = &mut self.downloading[position];
+            let piece_idx = piece.get_idx();
+            let (begin, block_length) = match piece.next_free_block() {
+                Some(block) => block,
+                None => return Ok(()),
+            };
+            piece.mark_requested(begin);
+            let request_msg = RequestMsg::new(piece_idx, begin, block_length)
+                .map_err(|_| DownloadError::ConnectionFailed)?;
+            self.send_message(request_msg)
+                .map_err(|_| DownloadError::ConnectionFailed)?;
+            self.requests.add(piece_idx, begin, block_length);
+            let requester =
+                BlockRequester::new(self.peer.socket_addr(), block_length, self.writer.clone());
+            if let Ok(mut pq_lock) = self.piece_queue.write() {
+                pq_lock.add_request(piece_idx, begin, requester);
+            }
+        }
+        Ok(())
     }
 
-    /// Receives a message from the peer.
-    /// If the peer choked us, it returns an error because the download has to end.
-    /// Else, it handles the message.
-    fn receive_message(&mut self, piece: &mut Piece) -> Result<(), DownloadError> {
-        if let Ok(msg) = MessageBuilder::build(&mut self.stream) {
-            if let P2PMessage::Choke(_) = msg {
-                self.am_choked = true;
-                return Err(DownloadError::PeerChokedUs);
-            }
-            self.handle_msg(msg, piece);
-            return Ok(());
+    /// Receives a message from the peer and handles it.
+    /// If the peer sends nothing for a while, it is not an error while we are waiting for
+    /// blocks: the requests that are not answered time out instead.
+    fn receive_message(&mut self) -> Result<(), DownloadError> {
+        match MessageBuilder::build(&mut self.stream) {
+            Ok(msg) => self.handle_msg(msg),
+            Err(MessageError::ReadingError(err))
+                if (err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut)
+                    && !self.requests.is_empty() => {}
+            Err(_) => return Err(DownloadError::CannotReadPeerMessage),
         }
-        Err(DownloadError::CannotReadPeerMessage)
+        Ok(())
     }
 
     /// According to the received message, it makes some decission.
     /// Bitfield -> initializes peer's piece vector
     /// Have -> updates peer's piece vector
-    /// Unchoke -> sets am_choked = 0
-    /// Piece -> handle piece msg
-    fn handle_msg(&mut self, message: P2PMessage, piece: &mut Piece) {
+    /// Choke / Unchoke -> updates am_choked
+    /// Piece -> adds the block to the piece it belongs to
+    /// Fast extension messages (only if both peers support it):
+    ///     Have All -> the peer has every piece
+    ///     Suggest Piece / Allowed Fast -> the piece is added to suggested / allowed_fast
+    ///     Reject Request -> the block is requested again
+    ///     Request -> it is rejected, because we don't upload on outbound connections
+    fn handle_msg(&mut self, message: P2PMessage) {
         match message {
-            P2PMessage::Bitfield(msg) => self.pieces.add_multiple_pieces(msg.get_pieces()),
-            P2PMessage::Have(msg) => self.pieces.add_a_piece(msg.get_piece_index()),
-            P2PMessage::Unchoke(_msg) => self.handle_choke_msg(),
-            P2PMessage::Piece(msg) => self.handle_piece_msg(msg, piece),
+            P2PMessage::Bitfield(msg) => self.handle_bitfield_msg(msg.get_pieces()),
+            P2PMessage::Have(msg) => self.add_peer_piece(msg.get_piece_index()),
+            P2PMessage::Choke(_msg) => self.handle_choke_msg(),
+            P2PMessage::Unchoke(_msg) => self.handle_unchoke_msg(),
+            P2PMessage::Piece(msg) => self.handle_piece_msg(msg),
+            P2PMessage::Extended(msg) => self.handle_extended_msg(msg),
+            msg if self.supports_fast => self.handle_fast_msg(msg),
             _ => (),
         }
     }
 
+    fn handle_fast_msg(&mut self, message: P2PMessage) {
+        let n_pieces = self.client.get_torrent_info().get_n_pieces();
+        match message {
+            P2PMessage::HaveAll(_msg) => {
+                for idx in 0..n_pieces {
+                    self.add_peer_piece(idx);
+                }
+            }
+            P2PMessage::SuggestPiece(msg) if msg.get_piece_index() < n_pieces => {
+                self.suggested.insert(msg.get_piece_index());
+            }
+            P2PMessage::AllowedFast(msg) if msg.get_piece_index() < n_pieces => {
+                self.allowed_fast.insert(msg.get_piece_index());
+            }
+            P2PMessage::RejectRequest(msg) => self.handle_reject_msg(msg),
+            P2PMessage::Request(msg) => {
+                let reject_msg = RejectRequestMsg::new(
+                    msg.get_piece_index(),
+                    msg.get_begin(),
+                    msg.get_block_length(),
+                );
+                let _ = self.send_message(reject_msg);
+            }
+            _ => (),
+        }
+    }
+
+    /// The rejected block is returned to its piece, so it is requested again
+    /// (when the peer unchokes us, or now if the piece is allowed fast).
+    fn handle_reject_msg(&mut self, msg: RejectRequestMsg) {
+        let (piece_idx, begin) = (msg.get_piece_index(), msg.get_begin());
+        if self.requests.remove(piece_idx, begin).is_none() {
+            return;
+        }
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            pq_lock.remove_request(piece_idx, begin, self.peer.socket_addr());
+        }
+        if let Some(piece) = self
+            .downloading
+            .iter_mut()
+            .find(|piece| piece.get_idx() == piece_idx)
+        {
+            piece.return_block(begin);
+        }
+    }
+
+    /// We can request blocks of the piece if the peer unchoked us, or if the
+    /// piece is allowed fast.
+    fn can_request(&self, piece_idx: u32) -> bool {
+        !self.am_choked || (self.supports_fast && self.allowed_fast.contains(&piece_idx))
+    }
+
+    /// The peer choked us. Without the Fast extension, our pending requests are
+    /// discarded, so the pieces are returned to the queue. With it, the peer rejects
+    /// the requests it will not serve.
     fn handle_choke_msg(&mut self) {
+        self.am_choked = true;
+        if self.supports_fast {
+            return;
+        }
+        self.requests.clear();
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            pq_lock.remove_peer_requests(self.peer.socket_addr(), None);
+        }
+        for piece in std::mem::take(&mut self.downloading) {
+            self.return_piece(piece);
+        }
+    }
+
+    /// Returns to the queue the pieces we cannot request now (the peer chokes us) and
+    /// that have not any pending request, so other connections can download them.
+    fn return_blocked_pieces(&mut self) {
+        let (blocked, downloading): (Vec<Piece>, Vec<Piece>) =
+            std::mem::take(&mut self.downloading)
+                .into_iter()
+                .partition(|piece| {
+                    !self.can_request(piece.get_idx())
+                        && !self.requests.has_requests_of(piece.get_idx())
+                });
+        self.downloading = downloading;
+        for piece in blocked {
+            self.return_piece(piece);
+        }
+    }
+
+    /// The pieces with requests that were not answered in time are returned to the queue,
+    /// so other connections download them, and this peer is not asked for them again.
+    /// If the peer has not sent any block in that time, returns an error.
+    fn return_timed_out_pieces(&mut self) -> Result<(), DownloadError> {
+        let now = Instant::now();
+        let timed_out = self.requests.timed_out_pieces(now);
+        if timed_out.is_empty() {
+            return Ok(());
+        }
+        for piece_idx in timed_out {
+            self.cancel_requests(piece_idx);
+            self.stalled.insert(piece_idx);
+            if let Some(position) = self
+                .downloading
+                .iter()
+                .position(|piece| piece.get_idx() == piece_idx)
+            {
+                let piece = self.downloading.remove(position);
+                self.return_piece(piece);
+            }
+        }
+        if now.duration_since(self.last_block) >= Duration::from_secs(BLOCK_REQUEST_TIMEOUT) {
+            return Err(DownloadError::CannotReadPeerMessage);
+        }
+        Ok(())
+    }
+
+    /// In endgame mode, the blocks that other connections received are copied in our
+    /// pieces (they already cancelled our requests of those blocks), and the pieces that
+    /// another connection finished are discarded, cancelling their pending requests.
+    fn sync_shared_pieces(&mut self) {
+        let mut finished = vec![];
+        if let Ok(pq_lock) = self.piece_queue.read() {
+            for piece in self.downloading.iter_mut() {
+                let piece_idx = piece.get_idx();
+                if !pq_lock.is_downloading(piece_idx) {
+                    finished.push(piece_idx);
+                    continue;
+                }
+                for begin in pq_lock.sync_piece(piece) {
+                    self.requests.remove(piece_idx, begin);
+                }
+            }
+        }
+        for piece_idx in finished {
+            self.cancel_requests(piece_idx);
+            self.downloading
+                .retain(|piece| piece.get_idx() != piece_idx);
+        }
+    }
+
+    fn handle_bitfield_msg(&mut self, pieces: Vec<u8>) {
+        let n_pieces = self.client.get_torrent_info().get_n_pieces();
+        let peer_pieces = PieceBitfield::new_from_vec(pieces, n_pieces);
+        for idx in 0..n_pieces {
+            if peer_pieces.has_piece(idx) {
+                self.add_peer_piece(idx);
+            }
+        }
+    }
+
+    /// Marks the piece as owned by the peer, and updates its availability in the piece queue.
+    fn add_peer_piece(&mut self, piece_idx: u32) {
+        if piece_idx >= self.client.get_torrent_info().get_n_pieces()
+            || self.pieces.has_piece(piece_idx)
+        {
+            return;
+        }
+        self.pieces.add_a_piece(piece_idx);
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            pq_lock.add_peer_piece(piece_idx);
+        }
+    }
+
+    /// The message is handled by the extension it belongs to, then
+    /// the responses and the pending messages of the extensions are sent.
+    fn handle_extended_msg(&mut self, msg: ExtendedMsg) {
+        if !self.supports_extensions {
+            return;
+        }
+        let msgs = self.extensions.handle_msg(msg).unwrap_or_default();
+        if let Some(reqq) = self
+            .extensions
+            .get_peer_handshake()
+            .and_then(|handshake| handshake.get_reqq())
+        {
+            self.requests.set_peer_reqq(reqq);
+        }
+        for msg in msgs {
+            if self.send_message(msg).is_err() {
+                return;
+            }
+        }
+        self.send_extension_msgs();
+    }
+
+    /// Sends the messages the extensions want to send on their own (e.g. ut_pex updates).
+    fn send_extension_msgs(&mut self) {
+        if !self.supports_extensions {
+            return;
+        }
+        for msg in self.extensions.poll() {
+            if self.send_message(msg).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Adds the peer to the swarm of the torrent, or removes it when the connection ends.
+    fn update_swarm(&self, connected: bool) {
+        let addr = self.peer.socket_addr();
+        if let Ok(mut swarm) = self.swarm.lock() {
+            if connected {
+                swarm.add_connected(addr);
+            } else {
+                swarm.remove_connected(addr);
+            }
+        }
+    }
+
+    fn handle_unchoke_msg(&mut self) {
         self.am_choked = false;
 
         let int_st = if self.am_interested {
@@ -252,54 +628,78 @@ impl PeerConnection {
         };
 
         let _ = self.tx_client.send(NewEvent::OurStatus(
+            self.client.get_torrent_info().get_id(),
             format!("unchoked / {}", int_st),
             self.peer.clone(),
         ));
     }
 
-    /// Sets status as NOT_DOWNLOADING (0), the checks if the received block is valid.
-    /// Finally, updates the value of the downloaded byte and appends the received block to self.piece
-    fn handle_piece_msg(&mut self, msg: PieceMsg, piece: &mut Piece) {
-        if (msg.get_begin() == piece.get_dl()) && (msg.get_piece_index() == piece.get_idx()) {
-            let block = msg.get_block();
-            piece.add_to_dl(block.len() as u32);
-            piece.add_block(block);
+    /// Writes the block in its piece, if it was requested. The blocks can arrive in any order.
+    /// The block is shared with the other connections downloading the piece (endgame mode),
+    /// and their requests of the same block are cancelled right away.
+    /// The connection whose block completes the piece finishes it.
+    fn handle_piece_msg(&mut self, msg: PieceMsg) {
+        let (piece_idx, begin) = (msg.get_piece_index(), msg.get_begin());
+        let block = msg.get_block();
+        let now = Instant::now();
+        // Truncated or oversized blocks are ignored, so their requests time out
+        if !self
+            .requests
+            .received(piece_idx, begin, block.len() as u32, now)
+        {
+            return;
+        }
+        self.last_block = now;
+        let position = match self
+            .downloading
+            .iter()
+            .position(|piece| piece.get_idx() == piece_idx)
+        {
+            Some(position) => position,
+            None => return,
+        };
+
+        if let Ok(mut choker) = self.choker.lock() {
+            choker.add_downloaded(&self.peer.id(), block.len() as u64);
+        }
+        let (requesters, completed) = match self.piece_queue.write() {
+            Ok(mut pq_lock) => {
+                let received =
+                    pq_lock.block_received(piece_idx, begin, &block, self.peer.socket_addr());
+                for copied in pq_lock.sync_piece(&mut self.downloading[position]) {
+                    self.requests.remove(piece_idx, copied);
+                }
+                received
+            }
+            Err(_) => return,
+        };
+        for requester in requesters {
+            let cancel_msg = CancelMsg::new(piece_idx, begin, requester.get_length());
+            if let Ok(mut writer) = requester.get_writer().lock() {
+                let _ = cancel_msg.send_msg(&mut *writer);
+            }
+        }
+
+        if completed {
+            let piece = self.downloading.remove(position);
+            self.finish_piece(piece);
         }
     }
 
     /// Receives a message and tries to send it to the connected peer.
     /// Tries to send it 10 times. If all sendings fail, returns an error.
     fn send_message<T: Message>(&mut self, msg: T) -> Result<(), ClientError> {
-        if let Ok(()) = msg.send_msg(&mut self.stream) {
+        let mut writer = self.writer.lock().map_err(|_| ClientError::ProtocolError)?;
+        if let Ok(()) = msg.send_msg(&mut *writer) {
             Ok(())
         } else {
             Err(ClientError::ProtocolError)
         }
     }
 
-    /// Writes bytes of the downloaded piece in a file.
-    fn store_piece_in_file(&self, piece: &mut Piece) -> Result<(), ()> {
-        let download_dir_path = self.client.get_download_dir();
-        let torrent_name = self.client.get_torrent_info().get_name();
-
-        if !Path::new(&download_dir_path).exists()
-            && fs::create_dir_all(&download_dir_path).is_err()
-        {
-            return Err(());
-        }
-
-        let path = format!(
-            "{}/{}_piece_{}",
-            download_dir_path,
-            torrent_name,
-            piece.get_idx(),
-        );
-        if let Ok(mut file) = File::create(path) {
-            if file.write_all(&piece.get_data()).is_ok() {
-                return Ok(());
-            }
-        }
-        Err(())
+    /// Writes the downloaded piece in the files of the torrent.
+    fn store_piece_in_file(&self, piece: &mut Piece) -> Result<(), StorageError> {
+        self.storage.write_piece(piece.get_idx(), &piece.get_data())
     }
 
     /// Sends a KeepAlive message
@@ -308,17 +708,15 @@ impl PeerConnection {
         let _ = self.send_message(keep_alive_msg);
     }
 
-    /// Sends a Request message with the current block and sets status = DOWNLOADING (1)
-    /// If sending failes, returns an error.
-    fn request_a_piece(&mut self, piece: &mut Piece) {
-        while piece.get_rq() < piece.get_tl() {
-            let begin = piece.get_rq();
-            let block_length = piece.next_block_length();
-
-            if let Ok(request_msg) = RequestMsg::new(piece.get_idx(), begin, block_length) {
-                if request_msg.send_msg(&mut self.stream).is_ok() {
-                    piece.add_to_rq(block_length);
-                }
+    /// Sends a Cancel message for every pending request of the piece.
+    fn cancel_requests(&mut self, piece_idx: u32) {
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            pq_lock.remove_peer_requests(self.peer.socket_addr(), Some(piece_idx));
+        }
+        for request in self.requests.remove_piece(piece_idx) {
+            let cancel_msg = CancelMsg::new(piece_idx, request.get_begin(), request.get_length());
+            if self.send_message(cancel_msg).is_err() {
+                return;
             }
         }
     }
@@ -330,15 +728,35 @@ impl PeerConnection {
             self.am_interested = true;
             let choke_st = if self.am_choked { "choked" } else { "unchoked" };
             let _ = self.tx_client.send(NewEvent::OurStatus(
+                self.client.get_torrent_info().get_id(),
                 format!("{} | interested", choke_st),
                 self.peer.clone(),
             ));
         }
     }
 
+    /// Takes a piece of the peer from the queue, the suggested ones first.
+    /// While the peer chokes us, only allowed fast pieces are taken (Fast extension).
+    /// The pieces being downloaded and the stalled ones are not taken.
     fn fetch_piece(&mut self) -> Result<Piece, ()> {
+        let mut pieces = if self.am_choked {
+            self.peer_pieces_among(&self.pieces, &self.allowed_fast)
+        } else {
+            self.pieces.clone()
+        };
+        for piece in &self.downloading {
+            pieces.remove_a_piece(piece.get_idx());
+        }
+        for piece_idx in &self.stalled {
+            pieces.remove_a_piece(*piece_idx);
+        }
+        let suggested = self.peer_pieces_among(&pieces, &self.suggested);
+
         if let Ok(mut pq_lock) = self.piece_queue.write() {
-            if let Some(option_piece) = pq_lock.get_next_piece() {
+            if let Some(option_piece) = pq_lock.get_next_piece(&suggested) {
+                return Ok(option_piece);
+            }
+            if let Some(option_piece) = pq_lock.get_next_piece(&pieces) {
                 return Ok(option_piece);
             }
         }
@@ -346,8 +764,29 @@ impl PeerConnection {
         Err(())
     }
 
+    /// Returns the pieces of the bitfield whose index is in the set.
+    fn peer_pieces_among(&self, pieces: &PieceBitfield, indexes: &HashSet<u32>) -> PieceBitfield {
+        let n_pieces = self.client.get_torrent_info().get_n_pieces();
+        let mut filtered = PieceBitfield::new(n_pieces);
+        for idx in indexes {
+            if pieces.has_piece(*idx) {
+                filtered.add_a_piece(*idx);
+            }
+        }
+        filtered
+    }
+
+    fn mark_piece_downloaded(&mut self, piece_idx: u32) -> bool {
+        if let Ok(mut pq_lock) = self.piece_queue.write() {
+            return pq_lock.piece_downloaded(piece_idx);
+        }
+        true
+    }
+
+    /// Returns the piece to the queue. The blocks that were already received are kept,
+    /// so the next connection only requests the missing ones.
     fn return_piece(&mut self, mut piece: Piece) {
-        piece.reset_info();
+        piece.discard_requests();
 
         if let Ok(mut pq_lock) = self.piece_queue.write() {
             pq_lock.push_back(piece);
@@ -3,14 +3,66 @@ use crate::piece::Piece;
 use crate::torrent_info::TorrentInfo;
 
 use std::{
-    collections::VecDeque,
-    sync::{Arc, RwLock},
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    net::{SocketAddr, TcpStream},
+    sync::{Arc, Mutex, RwLock},
 };
 
+/// # struct BlockRequester
+/// A connection that requested a block and is waiting for it:
+///     - addr -> address of the peer
+///     - length -> length of the requested block
+///     - writer -> stream used to send messages to the peer (shared with its connection)
+#[derive(Debug, Clone)]
+pub struct BlockRequester {
+    addr: SocketAddr,
+    length: u32,
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+impl BlockRequester {
+    pub fn new(addr: SocketAddr, length: u32, writer: Arc<Mutex<TcpStream>>) -> BlockRequester {
+        BlockRequester {
+            addr,
+            length,
+            writer,
+        }
+    }
+
+    pub fn get_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn get_length(&self) -> u32 {
+        self.length
+    }
+
+    pub fn get_writer(&self) -> Arc<Mutex<TcpStream>> {
+        self.writer.clone()
+    }
+}
+
 /// # struct Piece Queue
-/// Represents a queue containing the pieces that have not yet been downloaded
+/// Contains the pieces that have not yet been downloaded, and the availability of every
+/// piece (how many connected peers have it).
+/// Each connection only gets pieces that its peer has, choosing the rarest ones first.
+/// When every pending piece is being downloaded, the queue enters endgame mode: the
+/// pieces being downloaded are handed to other connections too, so the same block can be
+/// requested to several peers. The first block that arrives is shared with the other
+/// connections, and the other requests of that block are cancelled.
+///     - pieces -> pending pieces by index
+///     - availability
+///     - downloading -> pieces being downloaded (with the blocks received by all the
+///       connections), and the number of connections downloading them
+///     - requests -> connections waiting for each block (piece index, begin)
 #[derive(Debug)]
-pub struct PieceQueue(VecDeque<Piece>);
+pub struct PieceQueue {
+    pieces: HashMap<u32, Piece>,
+    availability: Vec<u32>,
+    downloading: HashMap<u32, (Piece, u32)>,
+    requests: HashMap<(u32, u32), Vec<BlockRequester>>,
+}
 
 impl PieceQueue {
     /// Creates the queue using the information of the torrent and a bitfield that contains the
@@ -18,46 +70,443 @@ impl PieceQueue {
     pub fn new(torrent_info: &TorrentInfo, bitfield: &Arc<RwLock<PieceBitfield>>) -> PieceQueue {
         let piece_bytes = torrent_info.get_piece_length();
         let n_pieces = torrent_info.get_n_pieces();
-        let mut pieces: VecDeque<Piece> = VecDeque::new();
+        let mut pieces: HashMap<u32, Piece> = HashMap::new();
         let bitfield_lock = match bitfield.read() {
-            Ok(bf) => (&*bf).clone(),
+            Ok(bf) => (*bf).clone(),
             Err(_) => PieceBitfield::new(n_pieces),
         };
-        // We create all pieces except the last one, then we push them into the queue
+        // We create all pieces except the last one
         for idx in 0..n_pieces - 1 {
             if bitfield_lock.has_piece(idx) {
                 continue;
             }
             let piece = Piece::new(idx, piece_bytes, torrent_info.get_hash(idx));
-            pieces.push_back(piece);
+            pieces.insert(idx, piece);
         }
 
-        // We create the last piece with the correct length and  then we push it into the queue
+        // We create the last piece with the correct length
         if !bitfield_lock.has_piece(n_pieces - 1) {
-            let l_piece_bytes = match torrent_info.get_length() % piece_bytes {
-                0 => piece_bytes,
-                piece_size => piece_size,
-            };
             let l_piece = Piece::new(
                 n_pieces - 1,
-                l_piece_bytes,
+                torrent_info.length_of_piece_n(n_pieces - 1),
                 torrent_info.get_hash(n_pieces - 1),
             );
-            pieces.push_back(l_piece);
+            pieces.insert(n_pieces - 1, l_piece);
+        }
+        PieceQueue {
+            pieces,
+            availability: vec![0; n_pieces as usize],
+            downloading: HashMap::new(),
+            requests: HashMap::new(),
+        }
+    }
+
+    /// Returns the rarest pending piece that the peer has.
+    /// Ties are broken randomly, so connections don't ask for the same pieces.
+    /// In endgame mode, returns a copy of a piece that is being downloaded by other connections.
+    /// If the peer has not any pending piece, returns None.
+    pub fn get_next_piece(&mut self, peer_pieces: &PieceBitfield) -> Option<Piece> {
+        if self.is_endgame() {
+            return self.get_endgame_piece(peer_pieces);
+        }
+
+        let mut candidates: Vec<u32> = vec![];
+        let mut min_availability = u32::MAX;
+        for idx in self.pieces.keys() {
+            if !peer_pieces.has_piece(*idx) {
+                continue;
+            }
+            let availability = self.get_availability(*idx);
+            if availability < min_availability {
+                min_availability = availability;
+                candidates.clear();
+            }
+            if availability == min_availability {
+                candidates.push(*idx);
+            }
+        }
+        if candidates.is_empty() {
+            return None;
+        }
+        let random = RandomState::new().build_hasher().finish() as usize;
+        let idx = candidates[random % candidates.len()];
+        let piece = self.pieces.remove(&idx)?;
+        self.downloading.insert(idx, (piece.clone(), 1));
+        Some(piece)
+    }
+
+    /// Returns the piece being downloaded by the fewest connections, among the ones the peer has.
+    fn get_endgame_piece(&mut self, peer_pieces: &PieceBitfield) -> Option<Piece> {
+        let (piece, downloaders) = self
+            .downloading
+            .iter_mut()
+            .filter(|(idx, _)| peer_pieces.has_piece(**idx))
+            .min_by_key(|(_, (_, downloaders))| *downloaders)
+            .map(|(_, entry)| entry)?;
+        *downloaders += 1;
+        Some(piece.clone())
+    }
+
+    /// Returns true if all the pending pieces are being downloaded.
+    pub fn is_endgame(&self) -> bool {
+        self.pieces.is_empty() && !self.downloading.is_empty()
+    }
+
+    /// Returns true if the piece is still being downloaded, so nobody has finished it.
+    pub fn is_downloading(&self, piece_idx: u32) -> bool {
+        self.downloading.contains_key(&piece_idx)
+    }
+
+    /// A connection finished the piece, so the other ones have to cancel it.
+    /// Returns false if another connection had already finished it.
+    pub fn piece_downloaded(&mut self, piece_idx: u32) -> bool {
+        self.requests.retain(|(idx, _), _| *idx != piece_idx);
+        self.downloading.remove(&piece_idx).is_some()
+    }
+
+    /// The downloaded piece was not valid, so all its blocks are downloaded again.
+    pub fn piece_is_invalid(&mut self, piece_idx: u32) {
+        if let Some((piece, _)) = self.downloading.get_mut(&piece_idx) {
+            piece.reset_info();
+        }
+    }
+
+    /// A connection requested a block of a piece being downloaded.
+    pub fn add_request(&mut self, piece_idx: u32, begin: u32, requester: BlockRequester) {
+        self.requests
+            .entry((piece_idx, begin))
+            .or_default()
+            .push(requester);
+    }
+
+    /// The request of a block is not pending anymore (e.g. it was rejected or cancelled).
+    pub fn remove_request(&mut self, piece_idx: u32, begin: u32, addr: SocketAddr) {
+        if let Some(requesters) = self.requests.get_mut(&(piece_idx, begin)) {
+            requesters.retain(|requester| requester.addr != addr);
+            if requesters.is_empty() {
+                self.requests.remove(&(piece_idx, begin));
+            }
+        }
+    }
+
+    /// Removes the pending requests of a connection. If piece_idx is given, only the
+    /// requests of that piece are removed.
+    pub fn remove_peer_requests(&mut self, addr: SocketAddr, piece_idx: Option<u32>) {
+        for ((idx, _), requesters) in self.requests.iter_mut() {
+            if piece_idx.is_none() || piece_idx == Some(*idx) {
+                requesters.retain(|requester| requester.addr != addr);
+            }
         }
-        PieceQueue(pieces)
+        self.requests.retain(|_, requesters| !requesters.is_empty());
     }
 
-    pub fn get_next_piece(&mut self) -> Option<Piece> {
-        self.0.pop_front()
+    /// A connection received a block. It is written in the piece, so the other
+    /// connections that download it don't request it again.
+    /// Returns the other connections that requested the block, so their requests can
+    /// be cancelled, and true if this block completed the piece.
+    pub fn block_received(
+        &mut self,
+        piece_idx: u32,
+        begin: u32,
+        block: &[u8],
+        addr: SocketAddr,
+    ) -> (Vec<BlockRequester>, bool) {
+        let mut requesters = self
+            .requests
+            .remove(&(piece_idx, begin))
+            .unwrap_or_default();
+        requesters.retain(|requester| requester.addr != addr);
+        let completed = match self.downloading.get_mut(&piece_idx) {
+            Some((piece, _)) => piece.add_block(begin, block.to_vec()) && piece.is_complete(),
+            None => false,
+        };
+        (requesters, completed)
+    }
+
+    /// Copies in the piece the blocks that other connections received.
+    /// Returns where the copied blocks begin.
+    pub fn sync_piece(&self, piece: &mut Piece) -> Vec<u32> {
+        match self.downloading.get(&piece.get_idx()) {
+            Some((shared, _)) => piece.sync_with(shared),
+            None => vec![],
+        }
     }
 
+    /// Returns a piece that could not be downloaded, so it can be fetched again.
+    /// The blocks it already has (and the ones other connections received) are kept.
+    /// If other connections are still downloading it, it stays with them.
+    /// If it was already downloaded by another connection, it is discarded.
     pub fn push_back(&mut self, mut piece: Piece) {
-        piece.reset_info();
-        self.0.push_back(piece)
+        let idx = piece.get_idx();
+        if let Some((shared, downloaders)) = self.downloading.get_mut(&idx) {
+            *downloaders -= 1;
+            if *downloaders > 0 {
+                return;
+            }
+            piece.sync_with(shared);
+            self.downloading.remove(&idx);
+            self.pieces.insert(idx, piece);
+        }
+    }
+
+    /// Queues again a piece that was downloaded, but its data is no longer valid
+    /// (a force recheck found it corrupted).
+    /// Returns false if the piece is unknown or it is already pending.
+    pub fn requeue_piece(&mut self, torrent_info: &TorrentInfo, piece_idx: u32) -> bool {
+        if piece_idx >= torrent_info.get_n_pieces()
+            || self.pieces.contains_key(&piece_idx)
+            || self.downloading.contains_key(&piece_idx)
+        {
+            return false;
+        }
+        let piece = Piece::new(
+            piece_idx,
+            torrent_info.length_of_piece_n(piece_idx),
+            torrent_info.get_hash(piece_idx),
+        );
+        self.pieces.insert(piece_idx, piece);
+        true
+    }
+
+    /// Returns the received blocks of the pending pieces that were partially downloaded
+    /// (from the start of every piece until its first missing block).
+    pub fn get_partial_pieces(&self) -> HashMap<u32, Vec<u8>> {
+        self.pieces
+            .iter()
+            .map(|(idx, piece)| (*idx, piece.get_contiguous_data()))
+            .filter(|(_, data)| !data.is_empty())
+            .collect()
+    }
+
+    /// Restores the blocks of partially downloaded pieces (e.g. from the resume file).
+    pub fn restore_partial_pieces(&mut self, partial_pieces: HashMap<u32, Vec<u8>>) {
+        for (idx, data) in partial_pieces {
+            if let Some(piece) = self.pieces.get_mut(&idx) {
+                piece.restore_blocks(data);
+            }
+        }
     }
 
     pub fn length(&self) -> u32 {
-        self.0.len() as u32
+        self.pieces.len() as u32
+    }
+
+    /// A peer has a new piece (Have message, or a piece of its Bitfield).
+    pub fn add_peer_piece(&mut self, piece_idx: u32) {
+        if let Some(availability) = self.availability.get_mut(piece_idx as usize) {
+            *availability += 1;
+        }
+    }
+
+    /// A peer disconnected, so its pieces are no longer available from it.
+    pub fn remove_peer_pieces(&mut self, peer_pieces: &PieceBitfield) {
+        for (idx, availability) in self.availability.iter_mut().enumerate() {
+            if peer_pieces.has_piece(idx as u32) {
+                *availability = availability.saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn get_availability(&self, piece_idx: u32) -> u32 {
+        self.availability
+            .get(piece_idx as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::BLOCK_LENGTH;
+    use std::net::TcpListener;
+
+    fn new_queue() -> PieceQueue {
+        let torrent = TorrentInfo::new(
+            "files_for_testing/torrents_testing/debian-11.3.0-amd64-netinst.iso.torrent",
+        )
+        .expect("It shouldn't fail");
+        let bitfield = Arc::new(RwLock::new(PieceBitfield::new(torrent.get_n_pieces())));
+        PieceQueue::new(&torrent, &bitfield)
+    }
+
+    fn peer_with(pieces: &[u32], n_pieces: u32) -> PieceBitfield {
+        let mut bitfield = PieceBitfield::new(n_pieces);
+        for idx in pieces {
+            bitfield.add_a_piece(*idx);
+        }
+        bitfield
+    }
+
+    #[test]
+    fn only_pieces_of_the_peer_are_returned() {
+        let mut queue = new_queue();
+        let n_pieces = queue.length();
+        let peer = peer_with(&[3, 7], n_pieces);
+
+        let first = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        let second = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        let mut idxs = vec![first.get_idx(), second.get_idx()];
+        idxs.sort();
+        assert_eq!(idxs, vec![3, 7]);
+        assert!(queue.get_next_piece(&peer).is_none());
+        assert_eq!(queue.length(), n_pieces - 2);
+
+        queue.push_back(first);
+        assert_eq!(queue.length(), n_pieces - 1);
+    }
+
+    #[test]
+    fn corrupted_pieces_are_queued_again() {
+        let torrent = TorrentInfo::new(
+            "files_for_testing/torrents_testing/debian-11.3.0-amd64-netinst.iso.torrent",
+        )
+        .expect("It shouldn't fail");
+        let n_pieces = torrent.get_n_pieces();
+        let bitfield = Arc::new(RwLock::new(peer_with(&[2, n_pieces - 1], n_pieces)));
+        let mut queue = PieceQueue::new(&torrent, &bitfield);
+        assert_eq!(queue.length(), n_pieces - 2);
+
+        assert!(queue.requeue_piece(&torrent, n_pieces - 1));
+        assert!(!queue.requeue_piece(&torrent, n_pieces - 1));
+        assert!(!queue.requeue_piece(&torrent, n_pieces));
+        let peer = peer_with(&[n_pieces - 1], n_pieces);
+        let piece = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        assert_eq!(piece.get_tl(), torrent.length_of_piece_n(n_pieces - 1));
+        assert!(!queue.requeue_piece(&torrent, n_pieces - 1));
+    }
+
+    #[test]
+    fn partial_pieces_are_restored() {
+        let mut queue = new_queue();
+        let block = vec![7u8; BLOCK_LENGTH as usize];
+        // Only the complete blocks are restored
+        let partial = HashMap::from([(5, [block.clone(), vec![7u8; 100]].concat()), (6, vec![])]);
+        queue.restore_partial_pieces(partial);
+
+        assert_eq!(
+            queue.get_partial_pieces(),
+            HashMap::from([(5, block.clone())])
+        );
+        let peer = peer_with(&[5], queue.length());
+        let piece = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        assert_eq!(piece.get_dl(), BLOCK_LENGTH);
+        assert_eq!(piece.next_free_block(), Some((BLOCK_LENGTH, BLOCK_LENGTH)));
+    }
+
+    #[test]
+    fn endgame_shares_pieces_until_one_connection_finishes() {
+        let mut queue = new_queue();
+        let n_pieces = queue.length();
+        let all_pieces = PieceBitfield::get_completed_bitfield(n_pieces);
+        let mut pieces = vec![];
+        while let Some(piece) = queue.get_next_piece(&all_pieces) {
+            if queue.is_endgame() {
+                pieces.push(piece);
+                break;
+            }
+            assert!(queue.piece_downloaded(piece.get_idx()));
+        }
+        assert!(queue.is_endgame());
+        let idx = pieces[0].get_idx();
+
+        // Other connections get the same piece
+        let copy = queue
+            .get_next_piece(&all_pieces)
+            .expect("It shouldn't fail");
+        assert_eq!(copy.get_idx(), idx);
+        let other_peer = PieceBitfield::new(n_pieces);
+        assert!(queue.get_next_piece(&other_peer).is_none());
+
+        // One connection gives up, but the other one keeps downloading it
+        queue.push_back(copy);
+        assert!(queue.is_downloading(idx));
+        assert_eq!(queue.length(), 0);
+
+        assert!(queue.piece_downloaded(idx));
+        assert!(!queue.piece_downloaded(idx));
+        assert!(!queue.is_endgame());
+        assert!(queue.get_next_piece(&all_pieces).is_none());
+    }
+
+    #[test]
+    fn endgame_blocks_are_shared_and_duplicated_requests_cancelled() {
+        let mut queue = new_queue();
+        let peer = peer_with(&[4], queue.length());
+        let mut piece = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        let listener = TcpListener::bind("127.0.0.1:0").expect("It shouldn't fail");
+        let stream = TcpStream::connect(listener.local_addr().expect("It shouldn't fail"))
+            .expect("It shouldn't fail");
+        let writer = Arc::new(Mutex::new(stream));
+        let addr_a: SocketAddr = "10.0.0.1:6881".parse().expect("It shouldn't fail");
+        let addr_b: SocketAddr = "10.0.0.2:6881".parse().expect("It shouldn't fail");
+        for addr in [addr_a, addr_b] {
+            let requester = BlockRequester::new(addr, BLOCK_LENGTH, writer.clone());
+            queue.add_request(4, 0, requester);
+        }
+
+        // The block arrives from A, so the request to B is cancelled
+        let block = vec![1u8; BLOCK_LENGTH as usize];
+        let (requesters, completed) = queue.block_received(4, 0, &block, addr_a);
+        assert_eq!(requesters.len(), 1);
+        assert_eq!(requesters[0].get_addr(), addr_b);
+        assert!(!completed);
+        assert!(queue.block_received(4, 0, &block, addr_b).0.is_empty());
+
+        assert_eq!(queue.sync_piece(&mut piece), vec![0]);
+        queue.push_back(piece);
+        let piece = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        assert_eq!(piece.get_dl(), BLOCK_LENGTH);
+
+        queue.add_request(4, BLOCK_LENGTH, BlockRequester::new(addr_a, 1, writer));
+        queue.remove_peer_requests(addr_a, None);
+        assert!(queue.requests.is_empty());
+    }
+
+    #[test]
+    fn piece_that_cannot_be_stored_is_downloaded_again() {
+        let mut queue = new_queue();
+        let peer = peer_with(&[4], queue.length());
+        let mut piece = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        let addr: SocketAddr = "10.0.0.1:6881".parse().expect("It shouldn't fail");
+        let mut completed = false;
+        for begin in (0..piece.get_tl()).step_by(BLOCK_LENGTH as usize) {
+            let length = BLOCK_LENGTH.min(piece.get_tl() - begin);
+            let block = vec![1u8; length as usize];
+            completed = queue.block_received(4, begin, &block, addr).1;
+            piece.add_block(begin, block);
+        }
+        assert!(completed);
+
+        // Writing the piece failed, so it is discarded as an invalid piece
+        piece.reset_info();
+        queue.piece_is_invalid(4);
+        queue.push_back(piece);
+        let piece = queue.get_next_piece(&peer).expect("It shouldn't fail");
+        assert_eq!(piece.get_dl(), 0);
+        assert_eq!(piece.next_free_block(), Some((0, BLOCK_LENGTH)));
+    }
+
+    #[test]
+    fn rarest_piece_is_returned_first() {
+        let mut queue = new_queue();
+        let n_pieces = queue.length();
+        let common_peer = peer_with(&[0, 1, 2], n_pieces);
+        for idx in [0, 1, 2] {
+            queue.add_peer_piece(idx);
+        }
+        let other_peer = peer_with(&[0, 2], n_pieces);
+        for idx in [0, 2] {
+            queue.add_peer_piece(idx);
+        }
+
+        let piece = queue
+            .get_next_piece(&common_peer)
+            .expect("It shouldn't fail");
+        assert_eq!(piece.get_idx(), 1);
+
+        queue.remove_peer_pieces(&other_peer);
+        assert_eq!(queue.get_availability(0), 1);
+        assert_eq!(queue.get_availability(1), 1);
     }
 }
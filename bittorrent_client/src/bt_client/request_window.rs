@@ -0,0 +1,231 @@
+use crate::constants::{BLOCK_REQUEST_TIMEOUT, MIN_REQUEST_WINDOW, REQUEST_QUEUE_TIME};
+use crate::piece::BLOCK_LENGTH;
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// # struct BlockRequest
+/// A block requested to the peer that has not been received yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRequest {
+    piece_idx: u32,
+    begin: u32,
+    length: u32,
+    sent_at: Instant,
+}
+
+impl BlockRequest {
+    pub fn get_piece_idx(&self) -> u32 {
+        self.piece_idx
+    }
+
+    pub fn get_begin(&self) -> u32 {
+        self.begin
+    }
+
+    pub fn get_length(&self) -> u32 {
+        self.length
+    }
+}
+
+/// # struct RequestWindow
+/// Outstanding block requests of a connection. The number of requests sent to the peer
+/// before receiving the blocks adapts to its download rate, so the window holds about
+/// REQUEST_QUEUE_TIME seconds of blocks. It never exceeds the queue depth of the settings,
+/// nor the number of requests the peer supports (reqq of its extension handshake).
+///     - pending -> requests in the order they were sent
+///     - size -> current number of requests that can be outstanding
+///     - max_size
+///     - received_bytes -> bytes received since rate_start, used to measure the rate
+///     - rate_start
+#[derive(Debug)]
+pub struct RequestWindow {
+    pending: Vec<BlockRequest>,
+    size: u32,
+    max_size: u32,
+    received_bytes: u64,
+    rate_start: Instant,
+}
+
+impl RequestWindow {
+    pub fn new(max_size: u32) -> RequestWindow {
+        let max_size = max_size.max(1);
+        RequestWindow {
+            pending: vec![],
+            size: MIN_REQUEST_WINDOW.min(max_size),
+            max_size,
+            received_bytes: 0,
+            rate_start: Instant::now(),
+        }
+    }
+
+    /// The peer told us how many outstanding requests it supports.
+    pub fn set_peer_reqq(&mut self, reqq: u32) {
+        self.max_size = self.max_size.min(reqq.max(1));
+        self.size = self.size.min(self.max_size);
+    }
+
+    /// Returns true if another request can be sent.
+    pub fn has_room(&self) -> bool {
+        (self.pending.len() as u32) < self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn add(&mut self, piece_idx: u32, begin: u32, length: u32) {
+        self.pending.push(BlockRequest {
+            piece_idx,
+            begin,
+            length,
+            sent_at: Instant::now(),
+        });
+    }
+
+    /// A block arrived. Returns false if it was not requested (or it was cancelled).
+    /// A block whose length is not the requested one is not valid, so its request is
+    /// kept (it times out if the block never arrives).
+    /// Every second, the size of the window is updated with the measured rate.
+    pub fn received(&mut self, piece_idx: u32, begin: u32, length: u32, now: Instant) -> bool {
+        let is_requested = |request: &BlockRequest| {
+            request.piece_idx == piece_idx && request.begin == begin && request.length == length
+        };
+        let request = match self.pending.iter().position(is_requested) {
+            Some(position) => self.pending.remove(position),
+            None => return false,
+        };
+        self.received_bytes += request.length as u64;
+
+        let elapsed = now.saturating_duration_since(self.rate_start);
+        if elapsed >= Duration::from_secs(1) {
+            let rate = self.received_bytes as f64 / elapsed.as_secs_f64();
+            let size = (rate * REQUEST_QUEUE_TIME as f64 / BLOCK_LENGTH as f64) as u32;
+            self.size = size.clamp(MIN_REQUEST_WINDOW.min(self.max_size), self.max_size);
+            self.received_bytes = 0;
+            self.rate_start = now;
+        }
+        true
+    }
+
+    /// Removes a request (e.g. the peer rejected it). Returns it if it was pending.
+    pub fn remove(&mut self, piece_idx: u32, begin: u32) -> Option<BlockRequest> {
+        let position = self
+            .pending
+            .iter()
+            .position(|request| request.piece_idx == piece_idx && request.begin == begin)?;
+        Some(self.pending.remove(position))
+    }
+
+    /// Removes the requests of a piece, so they can be cancelled.
+    pub fn remove_piece(&mut self, piece_idx: u32) -> Vec<BlockRequest> {
+        let (removed, pending) = self
+            .pending
+            .drain(..)
+            .partition(|request| request.piece_idx == piece_idx);
+        self.pending = pending;
+        removed
+    }
+
+    pub fn has_requests_of(&self, piece_idx: u32) -> bool {
+        self.pending
+            .iter()
+            .any(|request| request.piece_idx == piece_idx)
+    }
+
+    /// Returns the pieces with a request that was not answered in BLOCK_REQUEST_TIMEOUT
+    /// seconds. The window goes back to its minimum size, because the peer is slower
+    /// than we thought.
+    pub fn timed_out_pieces(&mut self, now: Instant) -> HashSet<u32> {
+        let timeout = Duration::from_secs(BLOCK_REQUEST_TIMEOUT);
+        let timed_out: HashSet<u32> = self
+            .pending
+            .iter()
+            .filter(|request| now.saturating_duration_since(request.sent_at) >= timeout)
+            .map(|request| request.piece_idx)
+            .collect();
+        if !timed_out.is_empty() {
+            self.size = MIN_REQUEST_WINDOW.min(self.max_size);
+            self.received_bytes = 0;
+            self.rate_start = now;
+        }
+        timed_out
+    }
+
+    /// Forgets every request (e.g. the peer choked us without the Fast extension).
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_grows_with_the_rate_of_the_peer() {
+        let mut window = RequestWindow::new(250);
+        let start = window.rate_start;
+        for begin in 0..MIN_REQUEST_WINDOW {
+            window.add(0, begin * BLOCK_LENGTH, BLOCK_LENGTH);
+        }
+        assert!(!window.has_room());
+
+        // The window holds the blocks received in REQUEST_QUEUE_TIME seconds
+        for begin in 0..MIN_REQUEST_WINDOW - 1 {
+            assert!(window.received(0, begin * BLOCK_LENGTH, BLOCK_LENGTH, start));
+        }
+        let last = (MIN_REQUEST_WINDOW - 1) * BLOCK_LENGTH;
+        assert!(window.received(0, last, BLOCK_LENGTH, start + Duration::from_secs(1)));
+        assert_eq!(
+            window.get_size(),
+            MIN_REQUEST_WINDOW * REQUEST_QUEUE_TIME as u32
+        );
+        assert!(window.has_room());
+        assert!(!window.received(0, 0, BLOCK_LENGTH, start));
+    }
+
+    #[test]
+    fn blocks_with_another_length_are_not_accepted() {
+        let mut window = RequestWindow::new(250);
+        let start = window.rate_start;
+        window.add(1, 0, BLOCK_LENGTH);
+
+        assert!(!window.received(1, 0, BLOCK_LENGTH - 1, start));
+        assert!(!window.received(1, 0, BLOCK_LENGTH + 1, start));
+        assert!(window.has_requests_of(1));
+        let timeout = start + Duration::from_secs(BLOCK_REQUEST_TIMEOUT + 1);
+        assert_eq!(window.timed_out_pieces(timeout), HashSet::from([1]));
+    }
+
+    #[test]
+    fn window_is_limited_by_the_reqq_of_the_peer() {
+        let mut window = RequestWindow::new(250);
+        window.set_peer_reqq(2);
+        window.add(0, 0, BLOCK_LENGTH);
+        window.add(0, BLOCK_LENGTH, BLOCK_LENGTH);
+
+        assert_eq!(window.get_size(), 2);
+        assert!(!window.has_room());
+    }
+
+    #[test]
+    fn requests_not_answered_time_out() {
+        let mut window = RequestWindow::new(250);
+        window.add(3, 0, BLOCK_LENGTH);
+        window.add(5, 0, BLOCK_LENGTH);
+        let now = Instant::now();
+
+        assert!(window.timed_out_pieces(now).is_empty());
+        let later = now + Duration::from_secs(BLOCK_REQUEST_TIMEOUT);
+        assert_eq!(window.timed_out_pieces(later), HashSet::from([3, 5]));
+
+        assert_eq!(window.remove_piece(3).len(), 1);
+        assert!(!window.has_requests_of(3));
+        assert!(window.has_requests_of(5));
+    }
+}
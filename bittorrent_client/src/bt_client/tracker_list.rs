@@ -0,0 +1,84 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// # struct TrackerList
+/// Tiers of trackers used by a client (BEP 12).
+/// The trackers of each tier are shuffled when the list is created. Trackers are
+/// tried in tier order, and when one of them works, it is moved to the front of its tier.
+///     - tiers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackerList {
+    tiers: Vec<Vec<String>>,
+}
+
+impl TrackerList {
+    pub fn new(announce_list: Vec<Vec<String>>) -> TrackerList {
+        let mut tiers = announce_list;
+        for tier in tiers.iter_mut() {
+            shuffle(tier);
+        }
+        TrackerList { tiers }
+    }
+
+    pub fn get_tiers(&self) -> Vec<Vec<String>> {
+        self.tiers.clone()
+    }
+
+    /// The tracker worked, so it is moved to the front of its tier.
+    pub fn promote(&mut self, tier_idx: usize, tracker_idx: usize) {
+        if let Some(tier) = self.tiers.get_mut(tier_idx) {
+            if tracker_idx < tier.len() {
+                let tracker = tier.remove(tracker_idx);
+                tier.insert(0, tracker);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiers.is_empty()
+    }
+}
+
+/// Fisher-Yates shuffle (every RandomState has random keys).
+fn shuffle(tier: &mut [String]) {
+    for i in (1..tier.len()).rev() {
+        let random = RandomState::new().build_hasher().finish() as usize;
+        tier.swap(i, random % (i + 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn tiers_are_shuffled_but_keep_their_trackers() {
+        let announce_list = vec![urls(&["a", "b", "c", "d"]), urls(&["e"])];
+        let list = TrackerList::new(announce_list);
+        let tiers = list.get_tiers();
+
+        assert_eq!(tiers.len(), 2);
+        let mut first_tier = tiers[0].clone();
+        first_tier.sort();
+        assert_eq!(first_tier, urls(&["a", "b", "c", "d"]));
+        assert_eq!(tiers[1], urls(&["e"]));
+    }
+
+    #[test]
+    fn working_tracker_is_promoted() {
+        let mut list = TrackerList {
+            tiers: vec![urls(&["a", "b", "c"])],
+        };
+        list.promote(0, 2);
+        assert_eq!(list.get_tiers(), vec![urls(&["c", "a", "b"])]);
+
+        // Invalid positions are ignored
+        list.promote(1, 0);
+        list.promote(0, 3);
+        assert_eq!(list.get_tiers(), vec![urls(&["c", "a", "b"])]);
+    }
+}
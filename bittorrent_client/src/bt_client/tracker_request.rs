@@ -1,9 +1,10 @@
-use crate::bencode_type::BencodeType;
 use crate::bt_client::client::Client;
+use crate::bt_client::udp_tracker::UdpTracker;
 use crate::constants::*;
-use crate::encoding_decoding::bencode_parser::BencodeParser;
 use crate::encoding_decoding::encoder::Encoder;
 use crate::errors::*;
+use bencode::BencodeParser;
+use bencode::BencodeType;
 
 use native_tls::TlsConnector;
 use native_tls::TlsStream;
@@ -21,20 +22,18 @@ pub struct TrackerRequest {
     port: String,
     uploaded: u64,
     downloaded: u64,
-    left: u32,
+    left: u64,
     event: String,
 }
 
 impl TrackerRequest {
-    /// Creates a new request from a decoded torrent file and a client.
-    /// On success, returns the request.
-    /// Otherwise, returns error.
-    pub fn new(client: &Client, downloaded: u64) -> TrackerRequest {
+    /// Creates a new request to a tracker of the torrent, from a client.
+    pub fn new(client: &Client, url: &str, downloaded: u64) -> TrackerRequest {
         let torrent_info = client.get_torrent_info();
         let length = torrent_info.get_length();
 
         let event = {
-            if downloaded as u32 == length {
+            if downloaded == length {
                 "completed".to_string()
             } else {
                 "started".to_string()
@@ -42,22 +41,28 @@ impl TrackerRequest {
         };
 
         TrackerRequest {
-            url: torrent_info.get_announce(),
+            url: url.to_string(),
             info_hash: torrent_info.get_info_hash(),
             peer_id: client.get_peer_id(),
             port: client.get_port(),
             uploaded: 0,
             downloaded,
-            left: length - downloaded as u32,
+            left: length.saturating_sub(downloaded),
             event,
         }
     }
 
-    /// Sends the http request to the tracker.
-    /// If the tracker url starts with "https", we use TlsStream, otherwise we use TcpStream.
+    /// Sends the request to the tracker.
+    /// If the tracker url starts with "udp", we use the UDP tracker protocol.
+    /// If it starts with "https", we use TlsStream, otherwise we use TcpStream.
     /// On success, returns the tracker response (decoded).
     /// Otherwise, returns error
     pub fn make_request(&self) -> Result<BencodeType, RequestError> {
+        if self.url.starts_with("udp://") {
+            let tracker = UdpTracker::new(&self.url)?;
+            return tracker.announce(&self.build_udp_announce());
+        }
+
         let (domain, port) = self.get_domain_and_port(&self.url);
         if self.url.contains("https://") {
             let mut stream = self.connect_to_https_tracker(&domain, HTTPS_TRACKER_PORT)?;
@@ -132,13 +137,38 @@ impl TrackerRequest {
 
     fn build_request(&self, domain: &str, info_hash: &str, peer_id: &str) -> String {
         let params = format!(
-            "/announce?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&event={}",
+            "/announce?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&event={}&compact=1",
             info_hash, peer_id, self.port, self.uploaded, self.downloaded, self.left, self.event
         );
 
         format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", params, domain)
     }
 
+    /// Builds the announce request of the UDP tracker protocol, without the header
+    /// (connection id, action and transaction id) that is added by the UdpTracker.
+    fn build_udp_announce(&self) -> Vec<u8> {
+        let event: u32 = match self.event.as_str() {
+            "completed" => 1,
+            "started" => 2,
+            "stopped" => 3,
+            _ => 0,
+        };
+        let port: u16 = self.port.parse().unwrap_or_default();
+
+        let mut payload = self.info_hash.clone();
+        payload.extend(&self.peer_id);
+        payload.extend(self.downloaded.to_be_bytes());
+        payload.extend(self.left.to_be_bytes());
+        payload.extend(self.uploaded.to_be_bytes());
+        payload.extend(event.to_be_bytes());
+        // ip (0 = the sender address), key (not used), and num_want (-1 = default)
+        payload.extend(0u32.to_be_bytes());
+        payload.extend(0u32.to_be_bytes());
+        payload.extend((-1i32).to_be_bytes());
+        payload.extend(port.to_be_bytes());
+        payload
+    }
+
     fn get_response(&self, response: Vec<u8>) -> Result<BencodeType, RequestError> {
         // We need to skip the first lines because the response contains
         // information that we don't need (Request code, Date, etc.)
@@ -175,13 +205,12 @@ impl TrackerRequest {
 #[cfg(test)]
 mod tests {
     use std::sync::mpsc::channel;
-    use std::sync::{Arc, Mutex, RwLock};
-
-    use glib::PRIORITY_DEFAULT;
+    use std::sync::{Arc, RwLock};
 
     use super::*;
     use crate::bitfield::PieceBitfield;
     use crate::errors::ClientError;
+    use crate::event_messages::EventBus;
     use crate::settings::Settings;
     use crate::torrent_info::TorrentInfo;
 
@@ -212,17 +241,7 @@ mod tests {
             let dl_pieces = Arc::new(RwLock::new(PieceBitfield::new(t.get_n_pieces())));
             let (tx_logger, _rx) = channel();
 
-            let (tx, rx) = channel();
-            let (tx_gtk, _rx_gtk) = glib::MainContext::channel(PRIORITY_DEFAULT);
-            let _ = tx.send(tx_gtk);
-
-            let client = Client::new(
-                Arc::new(s),
-                t,
-                dl_pieces,
-                tx_logger,
-                Arc::new(Mutex::new(rx)),
-            );
+            let client = Client::new(Arc::new(s), t, dl_pieces, tx_logger, EventBus::new(), None);
 
             return Ok(client);
         }
@@ -233,8 +252,9 @@ mod tests {
     fn check_request_creation() {
         let torrent_path =
             "files_for_testing/torrents_tracker_request_test/ubuntu-20.04.4-desktop-amd64.iso.torrent";
-        if let Ok(client) = create_client(&torrent_path) {
-            let request = TrackerRequest::new(&client, 0);
+        if let Ok(client) = create_client(torrent_path) {
+            let request =
+                TrackerRequest::new(&client, &client.get_torrent_info().get_announce(), 0);
 
             let expected_req = TrackerRequest {
                 url: "https://torrent.ubuntu.com/announce".to_string(),
@@ -253,12 +273,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn udp_announce_payload() {
+        let torrent_path =
+            "files_for_testing/torrents_tracker_request_test/ubuntu-20.04.4-desktop-amd64.iso.torrent";
+        if let Ok(client) = create_client(torrent_path) {
+            let request =
+                TrackerRequest::new(&client, &client.get_torrent_info().get_announce(), 0);
+            let payload = request.build_udp_announce();
+
+            assert_eq!(payload.len(), 82);
+            assert_eq!(&payload[..20], request.info_hash.as_slice());
+            assert_eq!(&payload[20..40], CLIENT_ID.as_bytes());
+            assert_eq!(&payload[48..56], &3379068928u64.to_be_bytes());
+            // Event: started
+            assert_eq!(&payload[64..68], &[0, 0, 0, 2]);
+            assert_eq!(&payload[80..], &8080u16.to_be_bytes());
+        } else {
+            assert!(false);
+        }
+    }
+
     #[test]
     fn send_request() {
         let torrent_path =
             "files_for_testing/torrents_tracker_request_test/ubuntu-20.04.4-desktop-amd64.iso.torrent";
-        if let Ok(client) = create_client(&torrent_path) {
-            let request = TrackerRequest::new(&client, 0);
+        if let Ok(client) = create_client(torrent_path) {
+            let request =
+                TrackerRequest::new(&client, &client.get_torrent_info().get_announce(), 0);
             if let Ok(response) = request.make_request() {
                 let x1 = response.get_value_from_dict("interval");
                 let x2 = response.get_value_from_dict("complete");
@@ -283,8 +325,9 @@ mod tests {
     #[test]
     fn error_send_request_invalid_url() {
         let torrent_path = "files_for_testing/torrents_tracker_request_test/invalid_url.torrent";
-        if let Ok(client) = create_client(&torrent_path) {
-            let request = TrackerRequest::new(&client, 0);
+        if let Ok(client) = create_client(torrent_path) {
+            let request =
+                TrackerRequest::new(&client, &client.get_torrent_info().get_announce(), 0);
             let response = request.make_request();
 
             match response {
@@ -299,8 +342,9 @@ mod tests {
     #[test]
     fn error_send_request_invalid_info() {
         let torrent_path = "files_for_testing/torrents_tracker_request_test/invalid_info.torrent";
-        if let Ok(client) = create_client(&torrent_path) {
-            let request = TrackerRequest::new(&client, 0);
+        if let Ok(client) = create_client(torrent_path) {
+            let request =
+                TrackerRequest::new(&client, &client.get_torrent_info().get_announce(), 0);
             if let Ok(response) = request.make_request() {
                 let x = response.get_value_from_dict("failure reason");
                 match x {
@@ -0,0 +1,383 @@
+use crate::constants::*;
+use crate::errors::RequestError;
+use bencode::BencodeType;
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const CONNECT: u32 = 0;
+const ANNOUNCE: u32 = 1;
+const SCRAPE: u32 = 2;
+const ERROR: u32 = 3;
+const CONNECTION_ID_LIFETIME: u64 = 60;
+const MAX_PACKET_SIZE: usize = 65535;
+
+type ConnectionIds = Mutex<HashMap<SocketAddr, (u64, Instant)>>;
+
+/// Connection ids given by the trackers. They can be used for one minute,
+/// so they are shared by all the requests sent to the same tracker.
+static CONNECTION_IDS: OnceLock<ConnectionIds> = OnceLock::new();
+
+/// # struct ScrapeStats
+/// Information of a torrent returned by a scrape request.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ScrapeStats {
+    pub seeders: u32,
+    pub completed: u32,
+    pub leechers: u32,
+}
+
+/// # struct UdpTracker
+/// Client of the UDP tracker protocol (BEP 15).
+/// Before announcing or scraping, a connection id is requested to the tracker.
+/// If the tracker does not answer, requests are sent again after 15 * 2 ^ n seconds.
+///     - socket -> connected to the tracker
+///     - addr -> address of the tracker
+///     - base_timeout -> timeout of the first try
+#[derive(Debug)]
+pub struct UdpTracker {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    base_timeout: Duration,
+}
+
+impl UdpTracker {
+    /// Receives the url of the tracker (udp://host:port/announce) and creates a socket to talk to it.
+    pub fn new(url: &str) -> Result<UdpTracker, RequestError> {
+        let host = url.trim_start_matches("udp://");
+        let host = host.split('/').next().unwrap_or(host);
+        let addr = host
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or(RequestError::CannotConnectToTracker)?;
+
+        let bind_addr = match addr {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket =
+            UdpSocket::bind(bind_addr).map_err(|_| RequestError::CannotConnectToTracker)?;
+        socket
+            .connect(addr)
+            .map_err(|_| RequestError::CannotConnectToTracker)?;
+
+        Ok(UdpTracker {
+            socket,
+            addr,
+            base_timeout: Duration::from_secs(UDP_TRACKER_TIMEOUT),
+        })
+    }
+
+    /// Changes the timeout of the first try (it is doubled on every retry).
+    pub fn set_base_timeout(&mut self, timeout: Duration) {
+        self.base_timeout = timeout;
+    }
+
+    /// Sends an announce request. The payload is the request without the header
+    /// (connection id, action and transaction id), built by the TrackerRequest.
+    /// On success, returns a dictionary like the one sent by HTTP trackers
+    /// (interval, complete, incomplete and compact peers).
+    /// If the tracker is reached over IPv6, the peers are IPv6 peers, so they are returned as 'peers6'.
+    /// If the tracker answers with an error, the dictionary has the 'failure reason'.
+    pub fn announce(&self, payload: &[u8]) -> Result<BencodeType, RequestError> {
+        let response = match self.send_request(ANNOUNCE, payload)? {
+            Ok(response) => response,
+            Err(message) => return Ok(failure_response(message)),
+        };
+        if response.len() < 12 {
+            return Err(RequestError::CannotGetResponse);
+        }
+
+        let mut dict = HashMap::new();
+        let interval = read_u32(&response, 0) as i64;
+        let leechers = read_u32(&response, 4) as i64;
+        let seeders = read_u32(&response, 8) as i64;
+        dict.insert(b"interval".to_vec(), BencodeType::Integer(interval));
+        dict.insert(b"incomplete".to_vec(), BencodeType::Integer(leechers));
+        dict.insert(b"complete".to_vec(), BencodeType::Integer(seeders));
+        let peers_key = match self.addr {
+            SocketAddr::V4(_) => b"peers".to_vec(),
+            SocketAddr::V6(_) => b"peers6".to_vec(),
+        };
+        dict.insert(peers_key, BencodeType::String(response[12..].to_vec()));
+        Ok(BencodeType::Dictionary(dict))
+    }
+
+    /// Sends a scrape request. Returns the information of every torrent, in the same order.
+    pub fn scrape(&self, info_hashes: &[Vec<u8>]) -> Result<Vec<ScrapeStats>, RequestError> {
+        let payload: Vec<u8> = info_hashes.iter().flatten().copied().collect();
+        let response = self
+            .send_request(SCRAPE, &payload)?
+            .map_err(|_| RequestError::CannotGetResponse)?;
+        if response.len() < 12 * info_hashes.len() {
+            return Err(RequestError::CannotGetResponse);
+        }
+
+        Ok(response
+            .chunks_exact(12)
+            .take(info_hashes.len())
+            .map(|stats| ScrapeStats {
+                seeders: read_u32(stats, 0),
+                completed: read_u32(stats, 4),
+                leechers: read_u32(stats, 8),
+            })
+            .collect())
+    }
+
+    /// Sends a request (with a connection id) and waits for its response, retrying on timeout.
+    /// Returns the response without its header, or the error message sent by the tracker.
+    fn send_request(
+        &self,
+        action: u32,
+        payload: &[u8],
+    ) -> Result<Result<Vec<u8>, String>, RequestError> {
+        for n in 0..=UDP_TRACKER_MAX_RETRIES {
+            let timeout = self.base_timeout * 2u32.pow(n);
+            let connection_id = match self.get_connection_id(timeout)? {
+                Some(connection_id) => connection_id,
+                None => continue,
+            };
+
+            let transaction_id = new_transaction_id();
+            let mut packet = connection_id.to_be_bytes().to_vec();
+            packet.extend(action.to_be_bytes());
+            packet.extend(transaction_id.to_be_bytes());
+            packet.extend(payload);
+
+            match self.exchange(&packet, transaction_id, timeout)? {
+                Some((resp_action, response)) if resp_action == action => return Ok(Ok(response)),
+                Some((ERROR, message)) => {
+                    // The connection id may have expired
+                    self.forget_connection_id();
+                    return Ok(Err(String::from_utf8_lossy(&message).to_string()));
+                }
+                _ => self.forget_connection_id(),
+            }
+        }
+        Err(RequestError::CannotGetResponse)
+    }
+
+    /// Returns the cached connection id of the tracker, or requests a new one.
+    /// Returns None if the tracker did not answer in time.
+    fn get_connection_id(&self, timeout: Duration) -> Result<Option<u64>, RequestError> {
+        let connection_ids = CONNECTION_IDS.get_or_init(|| Mutex::new(HashMap::new()));
+        if let Ok(ids) = connection_ids.lock() {
+            if let Some((connection_id, received)) = ids.get(&self.addr) {
+                if received.elapsed().as_secs() < CONNECTION_ID_LIFETIME {
+                    return Ok(Some(*connection_id));
+                }
+            }
+        }
+
+        let transaction_id = new_transaction_id();
+        let mut packet = UDP_TRACKER_PROTOCOL_ID.to_be_bytes().to_vec();
+        packet.extend(CONNECT.to_be_bytes());
+        packet.extend(transaction_id.to_be_bytes());
+
+        match self.exchange(&packet, transaction_id, timeout)? {
+            Some((CONNECT, response)) if response.len() >= 8 => {
+                let connection_id = u64::from_be_bytes(
+                    response[..8]
+                        .try_into()
+                        .map_err(|_| RequestError::CannotGetResponse)?,
+                );
+                if let Ok(mut ids) = connection_ids.lock() {
+                    ids.insert(self.addr, (connection_id, Instant::now()));
+                }
+                Ok(Some(connection_id))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn forget_connection_id(&self) {
+        if let Some(connection_ids) = CONNECTION_IDS.get() {
+            if let Ok(mut ids) = connection_ids.lock() {
+                ids.remove(&self.addr);
+            }
+        }
+    }
+
+    /// Sends a packet and waits for the response with the same transaction id.
+    /// Returns the action and the rest of the response, or None on timeout.
+    /// If the tracker is unreachable, returns error.
+    fn exchange(
+        &self,
+        packet: &[u8],
+        transaction_id: u32,
+        timeout: Duration,
+    ) -> Result<Option<(u32, Vec<u8>)>, RequestError> {
+        self.socket
+            .send(packet)
+            .map_err(|_| RequestError::CannotConnectToTracker)?;
+
+        let deadline = Instant::now() + timeout;
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || self.socket.set_read_timeout(Some(remaining)).is_err() {
+                return Ok(None);
+            }
+            match self.socket.recv(&mut buffer) {
+                Ok(size) if size >= 8 && read_u32(&buffer, 4) == transaction_id => {
+                    return Ok(Some((read_u32(&buffer, 0), buffer[8..size].to_vec())));
+                }
+                Ok(_) => (),
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => {
+                    return Err(RequestError::CannotConnectToTracker)
+                }
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+}
+
+fn failure_response(message: String) -> BencodeType {
+    let mut dict = HashMap::new();
+    dict.insert(
+        b"failure reason".to_vec(),
+        BencodeType::String(message.into_bytes()),
+    );
+    BencodeType::Dictionary(dict)
+}
+
+fn read_u32(bytes: &[u8], idx: usize) -> u32 {
+    u32::from_be_bytes([bytes[idx], bytes[idx + 1], bytes[idx + 2], bytes[idx + 3]])
+}
+
+/// Returns a random transaction id (every RandomState has random keys).
+fn new_transaction_id() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Fake tracker that answers every request.
+    /// The first `drop` announces are ignored, so the client has to retry.
+    fn start_fake_tracker(drop: u32, connects: Arc<AtomicU32>) -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("It shouldn't fail");
+        let addr = socket.local_addr().expect("It shouldn't fail");
+        thread::spawn(move || {
+            let mut dropped = 0;
+            let mut buffer = [0; 1024];
+            let connection_id: u64 = 0x1234;
+            while let Ok((size, from)) = socket.recv_from(&mut buffer) {
+                let action = read_u32(&buffer, 8);
+                let mut response = action.to_be_bytes().to_vec();
+                response.extend(&buffer[12..16]);
+                match action {
+                    CONNECT => {
+                        connects.fetch_add(1, Ordering::Relaxed);
+                        response.extend(connection_id.to_be_bytes());
+                    }
+                    _ if buffer[..8] != connection_id.to_be_bytes() => {
+                        response = ERROR.to_be_bytes().to_vec();
+                        response.extend(&buffer[12..16]);
+                        response.extend(b"Invalid connection id");
+                    }
+                    ANNOUNCE if dropped < drop => {
+                        dropped += 1;
+                        continue;
+                    }
+                    ANNOUNCE => {
+                        // interval, leechers, seeders and two peers
+                        for value in [1800u32, 3, 5] {
+                            response.extend(value.to_be_bytes());
+                        }
+                        response.extend([127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0x1a, 0xe2]);
+                    }
+                    _ => {
+                        for _ in (16..size).step_by(20) {
+                            for value in [5u32, 10, 3] {
+                                response.extend(value.to_be_bytes());
+                            }
+                        }
+                    }
+                }
+                let _ = socket.send_to(&response, from);
+            }
+        });
+        format!("udp://{}/announce", addr)
+    }
+
+    fn fast_tracker(url: &str) -> UdpTracker {
+        let mut tracker = UdpTracker::new(url).expect("It shouldn't fail");
+        tracker.set_base_timeout(Duration::from_millis(100));
+        tracker
+    }
+
+    #[test]
+    fn announce_returns_compact_peers() {
+        let connects = Arc::new(AtomicU32::new(0));
+        let tracker = fast_tracker(&start_fake_tracker(0, connects.clone()));
+
+        if let Ok(response) = tracker.announce(&[0; 82]) {
+            let peers = response.get_value_from_dict("peers");
+            let seeders = response.get_value_from_dict("complete");
+            if let (Ok(BencodeType::String(peers)), Ok(BencodeType::Integer(seeders))) =
+                (peers, seeders)
+            {
+                assert_eq!(peers.len(), 12);
+                assert_eq!(&peers[..6], &[127, 0, 0, 1, 0x1a, 0xe1]);
+                assert_eq!(seeders, 5);
+            } else {
+                assert!(false);
+            }
+        } else {
+            assert!(false);
+        }
+
+        // The connection id is reused
+        assert!(tracker.announce(&[0; 82]).is_ok());
+        assert_eq!(connects.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn announce_is_sent_again_after_timeout() {
+        let connects = Arc::new(AtomicU32::new(0));
+        let tracker = fast_tracker(&start_fake_tracker(2, connects.clone()));
+
+        assert!(tracker.announce(&[0; 82]).is_ok());
+        // A new connection id is requested after every timeout
+        assert_eq!(connects.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn scrape_returns_stats_of_every_torrent() {
+        let tracker = fast_tracker(&start_fake_tracker(0, Arc::new(AtomicU32::new(0))));
+        let stats = ScrapeStats {
+            seeders: 5,
+            completed: 10,
+            leechers: 3,
+        };
+        if let Ok(response) = tracker.scrape(&[vec![1; 20], vec![2; 20]]) {
+            assert_eq!(response, vec![stats, stats]);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn error_tracker_does_not_answer() {
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("It shouldn't fail");
+        let url = format!("udp://{}", socket.local_addr().expect("It shouldn't fail"));
+        let mut tracker = UdpTracker::new(&url).expect("It shouldn't fail");
+        tracker.set_base_timeout(Duration::from_millis(10));
+
+        match tracker.announce(&[0; 82]) {
+            Err(RequestError::CannotGetResponse) => assert!(true),
+            _ => assert!(false),
+        }
+    }
+}
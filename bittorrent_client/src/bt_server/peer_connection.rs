@@ -1,30 +1,38 @@
-use std::fs::DirEntry;
-use std::io::Seek;
-use std::net::TcpStream;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr, TcpStream};
 use std::sync::mpsc::Sender;
 
 use crate::bitfield::PieceBitfield;
+use crate::choker::Choker;
 use crate::constants::*;
 use crate::encoding_decoding::encoder::Encoder;
-use crate::errors::ServerError;
+use crate::errors::{MessageError, ServerError};
+use crate::extensions::extension_registry::ExtensionRegistry;
+use crate::extensions::ut_metadata::UtMetadata;
+use crate::extensions::ut_pex::UtPex;
 use crate::logging::msg_coder::MsgCoder;
+use crate::p2p_messages::allowed_fast::{generate_allowed_fast_set, AllowedFastMsg};
 use crate::p2p_messages::bitfield::BitfieldMsg;
+use crate::p2p_messages::choke::ChokeMsg;
+use crate::p2p_messages::extended::ExtendedMsg;
 use crate::p2p_messages::handshake::Handshake;
+use crate::p2p_messages::have_all::HaveAllMsg;
+use crate::p2p_messages::have_none::HaveNoneMsg;
 use crate::p2p_messages::message_builder::MessageBuilder;
 use crate::p2p_messages::message_builder::P2PMessage;
 use crate::p2p_messages::message_trait::Message;
 use crate::p2p_messages::piece::PieceMsg;
+use crate::p2p_messages::reject_request::RejectRequestMsg;
 use crate::p2p_messages::request::RequestMsg;
 use crate::p2p_messages::unchoke::UnchokeMsg;
 use crate::piece::Piece;
+use crate::storage::Storage;
+use crate::swarm::PeerSwarm;
 use crate::torrent_info::TorrentInfo;
 
-use std::fs;
-use std::fs::File;
-use std::io::Read;
-use std::io::SeekFrom;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 /// # struct PeerConnection (server)
 /// Contains all information about the connection.
@@ -38,6 +46,15 @@ use std::time::Duration;
 ///     - download_path
 ///     - piece -> piece requested by the peer.
 ///     - tx_logger
+///     - extensions -> extensions of the extension protocol (BEP 10)
+///     - supports_extensions -> peer supports the extension protocol
+///     - choker -> decides if the peer is unchoked (shared by all connections of the torrent)
+///     - swarm -> peers of the torrent, exchanged with ut_pex (shared by all connections of the torrent)
+///     - peer_addr -> address of the peer. The port is the one of the connection until
+///       the peer tells us its listening port in the extension handshake.
+///     - in_swarm -> the peer was added to the swarm (its listening port is known)
+///     - supports_fast -> both peers support the Fast extension (BEP 6)
+///     - allowed_fast -> pieces the peer can request while it is choked
 pub struct PeerConnection {
     stream: TcpStream,
     peer_id: Vec<u8>,
@@ -48,6 +65,14 @@ pub struct PeerConnection {
     download_path: String,
     piece: Option<Piece>,
     tx_logger: Sender<String>,
+    extensions: ExtensionRegistry,
+    supports_extensions: bool,
+    choker: Arc<Mutex<Choker>>,
+    swarm: Arc<Mutex<PeerSwarm>>,
+    peer_addr: Option<SocketAddr>,
+    in_swarm: bool,
+    supports_fast: bool,
+    allowed_fast: HashSet<u32>,
 }
 
 impl PeerConnection {
@@ -62,13 +87,28 @@ impl PeerConnection {
     ) -> Result<PeerConnection, ServerError> {
         if let Ok(handshake) = Handshake::read_msg(&mut stream) {
             if stream
-                .set_read_timeout(Some(Duration::new(TWO_MINUTES, 0)))
+                .set_read_timeout(Some(Duration::new(SERVER_READ_TIMEOUT, 0)))
                 .is_ok()
             {
                 let info_hash = handshake.get_info_hash();
                 let peer_id = handshake.get_peer_id();
                 let (torrent_info, our_pieces) = get_torrent_info(&info_hash, torrents)?;
-                PeerConnection::send_handshake(info_hash, &mut stream)?;
+                PeerConnection::send_handshake(info_hash.clone(), &mut stream)?;
+
+                let mut extensions = ExtensionRegistry::new(None);
+                let metadata = Arc::new(RwLock::new(Some(torrent_info.get_metadata())));
+                extensions.register(Box::new(UtMetadata::new(info_hash.clone(), metadata)));
+                let peer_addr = stream.peer_addr().ok();
+                let swarm = PeerSwarm::for_torrent(&info_hash);
+                // Peers of private torrents are not exchanged
+                if !torrent_info.is_private() {
+                    extensions.register(Box::new(UtPex::new(swarm.clone(), peer_addr)));
+                }
+
+                let choker = Choker::for_torrent(&info_hash, our_pieces.clone());
+                if let Ok(mut choker) = choker.lock() {
+                    choker.register(&peer_id);
+                }
 
                 let peer_conn = PeerConnection {
                     stream,
@@ -80,6 +120,14 @@ impl PeerConnection {
                     download_path,
                     piece: None,
                     tx_logger,
+                    extensions,
+                    supports_extensions: handshake.supports_extension_protocol(),
+                    choker,
+                    swarm,
+                    peer_addr,
+                    in_swarm: false,
+                    supports_fast: handshake.supports_fast_extension(),
+                    allowed_fast: HashSet::new(),
                 };
                 peer_conn.announce_new_connection();
                 return Ok(peer_conn);
@@ -100,72 +148,239 @@ impl PeerConnection {
         }
     }
 
-    /// Sends the Bitfield message as the first message, then it listening for new messages
+    /// Sends our pieces as the first message, then it listening for new messages
+    /// If the peer supports the extension protocol, our extension handshake is sent too.
+    /// If it supports the Fast extension, its allowed fast pieces are sent too.
     /// When a new message from the other peer arrives, it is handled.
+    /// Meanwhile, the peer is choked or unchoked as the choker decides.
+    /// The extensions can send their own messages too (e.g. ut_pex updates).
+    /// If the peer does not send anything for two minutes, the connection ends.
     pub fn handle_connection(&mut self) {
-        if let Ok(pieces) = self.our_pieces.read() {
-            if let Ok(bf_msg) = BitfieldMsg::new(pieces.get_vec()) {
-                let _ = bf_msg.send_msg(&mut self.stream);
+        self.send_our_pieces();
+        if self.supports_extensions {
+            let _ = self.extensions.handshake_msg().send_msg(&mut self.stream);
+        }
+        self.send_allowed_fast();
+
+        let mut last_msg = Instant::now();
+        while last_msg.elapsed().as_secs() < TWO_MINUTES {
+            match MessageBuilder::build(&mut self.stream) {
+                Ok(msg) => {
+                    self.handle_msg(msg);
+                    last_msg = Instant::now();
+                }
+                Err(MessageError::ReadingError(err))
+                    if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => {
+                }
+                Err(_) => break,
+            }
+            if self.update_choke_state().is_err() || self.send_extension_msgs().is_err() {
+                break;
             }
         }
+        if let Ok(mut choker) = self.choker.lock() {
+            choker.unregister(&self.peer_id);
+        }
+        self.remove_from_swarm();
+    }
+
+    /// Sends the Bitfield message. With the Fast extension, Have All is sent instead if
+    /// we are seeding, and Have None if we have not any piece.
+    fn send_our_pieces(&mut self) {
+        let pieces = match self.our_pieces.read() {
+            Ok(pieces) => pieces.clone(),
+            Err(_) => return,
+        };
+        if self.supports_fast && pieces.has_all_pieces() {
+            let _ = HaveAllMsg::new().send_msg(&mut self.stream);
+        } else if self.supports_fast && pieces.number_of_downloaded_pieces() == 0 {
+            let _ = HaveNoneMsg::new().send_msg(&mut self.stream);
+        } else if let Ok(bf_msg) = BitfieldMsg::new(pieces.get_vec()) {
+            let _ = bf_msg.send_msg(&mut self.stream);
+        }
+    }
 
-        while let Ok(msg) = MessageBuilder::build(&mut self.stream) {
-            self.handle_msg(msg);
+    /// Computes the allowed fast set of the peer (only defined for IPv4 peers), and
+    /// sends the pieces of the set that we have.
+    fn send_allowed_fast(&mut self) {
+        if !self.supports_fast {
+            return;
+        }
+        let ip = match self.peer_addr.map(|addr| addr.ip().to_canonical()) {
+            Some(IpAddr::V4(ip)) => ip,
+            _ => return,
+        };
+        let allowed_fast = generate_allowed_fast_set(
+            ip,
+            &self.torrent_info.get_info_hash(),
+            self.torrent_info.get_n_pieces(),
+            ALLOWED_FAST_SET_SIZE,
+        );
+        for piece_idx in allowed_fast {
+            if !self.have_the_piece(piece_idx) {
+                continue;
+            }
+            if AllowedFastMsg::new(piece_idx)
+                .send_msg(&mut self.stream)
+                .is_err()
+            {
+                return;
+            }
+            self.allowed_fast.insert(piece_idx);
         }
     }
 
     /// According to the received message, it makes some decission.
     fn handle_msg(&mut self, message: P2PMessage) {
         match message {
-            P2PMessage::Interested(_msg) => self.handle_interested_msg(),
-            P2PMessage::NotInterested(_msg) => self.is_interested = NOT_INTERESTED,
+            P2PMessage::Interested(_msg) => self.set_interested(INTERESTED),
+            P2PMessage::NotInterested(_msg) => self.set_interested(NOT_INTERESTED),
             P2PMessage::Request(msg) => self.handle_request(msg),
+            P2PMessage::Extended(msg) => self.handle_extended_msg(msg),
             _ => (),
         }
     }
 
-    fn handle_interested_msg(&mut self) {
-        self.is_interested = INTERESTED;
-        if UnchokeMsg::new().send_msg(&mut self.stream).is_ok() {
+    /// The message is handled by the extension it belongs to, then
+    /// the responses and the pending messages of the extensions are sent.
+    fn handle_extended_msg(&mut self, msg: ExtendedMsg) {
+        if !self.supports_extensions {
+            return;
+        }
+        let msgs = self.extensions.handle_msg(msg).unwrap_or_default();
+        self.add_to_swarm();
+        for msg in msgs {
+            if msg.send_msg(&mut self.stream).is_err() {
+                return;
+            }
+        }
+        let _ = self.send_extension_msgs();
+    }
+
+    /// Sends the messages the extensions want to send on their own.
+    fn send_extension_msgs(&mut self) -> Result<(), ServerError> {
+        if !self.supports_extensions {
+            return Ok(());
+        }
+        for msg in self.extensions.poll() {
+            msg.send_msg(&mut self.stream)
+                .map_err(|_| ServerError::ProtocolError)?;
+        }
+        Ok(())
+    }
+
+    /// When the peer tells us its listening port, it is added to the swarm of the torrent,
+    /// so other peers can connect to it.
+    fn add_to_swarm(&mut self) {
+        if self.in_swarm {
+            return;
+        }
+        let port = self
+            .extensions
+            .get_peer_handshake()
+            .and_then(|handshake| handshake.get_port());
+        if let (Some(addr), Some(port)) = (self.peer_addr, port) {
+            let addr = SocketAddr::new(addr.ip(), port);
+            if let Ok(mut swarm) = self.swarm.lock() {
+                swarm.add_connected(addr);
+                self.peer_addr = Some(addr);
+                self.in_swarm = true;
+            }
+        }
+    }
+
+    fn remove_from_swarm(&mut self) {
+        if let (true, Some(addr)) = (self.in_swarm, self.peer_addr) {
+            if let Ok(mut swarm) = self.swarm.lock() {
+                swarm.remove_connected(addr);
+            }
+            self.in_swarm = false;
+        }
+    }
+
+    fn set_interested(&mut self, is_interested: u8) {
+        self.is_interested = is_interested;
+        if let Ok(mut choker) = self.choker.lock() {
+            choker.set_interested(&self.peer_id, is_interested == INTERESTED);
+        }
+    }
+
+    /// Asks the choker if the peer has to be unchoked, and sends Choke or Unchoke
+    /// if the decision changed.
+    fn update_choke_state(&mut self) -> Result<(), ServerError> {
+        let unchoked = match self.choker.lock() {
+            Ok(mut choker) => choker.update(&self.peer_id, Instant::now()),
+            Err(_) => return Ok(()),
+        };
+        if unchoked && self.is_choked == CHOKED {
+            UnchokeMsg::new()
+                .send_msg(&mut self.stream)
+                .map_err(|_| ServerError::ProtocolError)?;
             self.is_choked = UNCHOKED;
+        } else if !unchoked && self.is_choked == UNCHOKED {
+            ChokeMsg::new()
+                .send_msg(&mut self.stream)
+                .map_err(|_| ServerError::ProtocolError)?;
+            self.is_choked = CHOKED;
+            self.piece = None;
         }
+        Ok(())
     }
 
     /// Receives a Request Message, loads the piece in self.piece
     /// and then gets the correct block of bytes from this piece.
     /// The whole piece is loaded because the peer probably keeps requesting blocks
     /// of the same piece, so by doing this we avoid reading the same piece many times.
+    /// With the Fast extension, allowed fast pieces are served while the peer is choked,
+    /// and the requests we do not serve are rejected.
     fn handle_request(&mut self, msg: RequestMsg) {
         let piece_idx = msg.get_piece_index();
-        if self.is_interested == NOT_INTERESTED
-            || self.is_choked == CHOKED
-            || !self.have_the_piece(piece_idx)
-        {
-            return;
+        let allowed = (self.is_interested == INTERESTED && self.is_choked == UNCHOKED)
+            || (self.supports_fast && self.allowed_fast.contains(&piece_idx));
+        if !allowed || !self.have_the_piece(piece_idx) {
+            return self.reject_request(&msg);
         }
 
         if let Some(piece) = &self.piece {
             if piece.get_idx() != piece_idx {
                 match self.load_piece(piece_idx) {
                     Ok(piece) => self.piece = piece,
-                    Err(_) => return,
+                    Err(_) => return self.reject_request(&msg),
                 }
             }
         } else if self.piece.is_none() {
             match self.load_piece(piece_idx) {
                 Ok(piece) => self.piece = piece,
-                Err(_) => return,
+                Err(_) => return self.reject_request(&msg),
             }
         }
 
-        let block = self.get_block(msg.get_begin(), msg.get_block_length());
+        let block = match self.get_block(msg.get_begin(), msg.get_block_length()) {
+            Some(block) => block,
+            None => return self.reject_request(&msg),
+        };
         if let Ok(msg) = PieceMsg::new(piece_idx, msg.get_begin(), block) {
             if msg.send_msg(&mut self.stream).is_ok() {
+                if let Ok(mut choker) = self.choker.lock() {
+                    choker.add_uploaded(&self.peer_id, msg.get_block().len() as u64);
+                }
                 self.announce_piece_served(msg);
             }
         }
     }
 
+    /// Tells the peer that the block will not be sent (only with the Fast extension).
+    fn reject_request(&mut self, msg: &RequestMsg) {
+        if self.supports_fast {
+            let reject_msg = RejectRequestMsg::new(
+                msg.get_piece_index(),
+                msg.get_begin(),
+                msg.get_block_length(),
+            );
+            let _ = reject_msg.send_msg(&mut self.stream);
+        }
+    }
+
     fn have_the_piece(&self, piece_idx: u32) -> bool {
         if let Ok(pieces) = self.our_pieces.read() {
             return pieces.has_piece(piece_idx);
@@ -173,65 +388,22 @@ impl PeerConnection {
         false
     }
 
+    /// Loads a piece from the files of the torrent.
     fn load_piece(&self, piece_idx: u32) -> Result<Option<Piece>, ServerError> {
-        if let Ok(files) = fs::read_dir(&self.download_path) {
-            for file in files.flatten() {
-                let file_name = file.file_name().to_string_lossy().to_string();
-                let piece_name = format!("{}_piece_{}", self.torrent_info.get_name(), piece_idx);
-
-                // Get piece from directory
-                if file_name == piece_name {
-                    if let Ok(loaded_piece) = self.piece_from_dir(&file, piece_idx) {
-                        return Ok(loaded_piece);
-                    }
-                }
-                // Get piece from a entire downloaded file
-                else if file_name == self.torrent_info.get_name() {
-                    if let Ok(loaded_piece) = self.piece_from_file(&file, piece_idx) {
-                        return Ok(loaded_piece);
-                    }
-                }
-            }
-        }
-        Err(ServerError::NoSuchDirectory)
-    }
+        let buffer = Storage::new(self.torrent_info.clone(), &self.download_path)
+            .read_piece(piece_idx)
+            .map_err(|_| ServerError::PieceError)?;
 
-    fn piece_from_dir(&self, file: &DirEntry, idx: u32) -> Result<Option<Piece>, ServerError> {
-        if let Ok(mut piece_file) = File::open(file.path()) {
-            let mut piece = Piece::new(idx, 0, vec![0u8; 20]);
-            let mut buffer = Vec::new();
-            if piece_file.read_to_end(&mut buffer).is_ok() {
-                piece.add_block(buffer);
-                return Ok(Some(piece));
-            }
-        }
-        Err(ServerError::NoSuchDirectory)
+        Ok(Some(Piece::with_data(piece_idx, buffer, vec![0u8; 20])))
     }
 
-    fn piece_from_file(&self, file: &DirEntry, idx: u32) -> Result<Option<Piece>, ServerError> {
-        if let Ok(mut downloaded_file) = File::open(file.path()) {
-            let pos = idx * self.torrent_info.get_piece_length();
-            if downloaded_file.seek(SeekFrom::Start(pos as u64)).is_ok() {
-                let mut buffer = vec![0u8; self.torrent_info.length_of_piece_n(idx) as usize];
-                if downloaded_file.read_exact(&mut buffer).is_ok() {
-                    let mut piece = Piece::new(idx, 0, vec![0u8; 20]);
-                    piece.add_block(buffer);
-                    return Ok(Some(piece));
-                }
-            }
-        }
-        Err(ServerError::NoSuchDirectory)
-    }
-
-    fn get_block(&self, begin: u32, block_length: u32) -> Vec<u8> {
-        let mut block = Vec::new();
-        if let Some(piece) = &self.piece {
-            let piece_data = piece.get_data();
-            for i in begin..begin + block_length {
-                block.push(piece_data[i as usize]);
-            }
-        }
-        block
+    /// Gets a block of the loaded piece.
+    /// Returns None if the requested block is outside the piece.
+    fn get_block(&self, begin: u32, block_length: u32) -> Option<Vec<u8>> {
+        let piece_data = self.piece.as_ref()?.get_data();
+        let begin = begin as usize;
+        let end = begin.checked_add(block_length as usize)?;
+        piece_data.get(begin..end).map(|block| block.to_vec())
     }
 
     fn announce_new_connection(&self) {
@@ -278,12 +450,14 @@ impl PeerConnection {
     }
 }
 
+/// Finds the torrent with the received info hash.
+/// Torrents without metadata (started from a magnet link) cannot be served.
 fn get_torrent_info(
     info_hash: &[u8],
     torrents: Vec<(TorrentInfo, Arc<RwLock<PieceBitfield>>)>,
 ) -> Result<(TorrentInfo, Arc<RwLock<PieceBitfield>>), ServerError> {
     for torrent in torrents {
-        if torrent.0.get_info_hash() == *info_hash {
+        if torrent.0.get_info_hash() == *info_hash && torrent.0.has_metadata() {
             return Ok((torrent.0, torrent.1));
         }
     }
@@ -293,74 +467,22 @@ fn get_torrent_info(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::build_testing_downloads;
     use crate::torrent_info::TorrentInfo;
     use sha1::{Digest, Sha1};
 
-    fn load_piece_for_unit_test(piece_idx: u32) -> Result<Option<Piece>, ServerError> {
-        let torrent_name = "ubuntu-20.04.4-desktop-amd64.iso";
-        let download_dir = "files_for_testing/downloaded_files";
-        let length = 262144;
-
-        if let Ok(files) = fs::read_dir(download_dir) {
-            for file in files.flatten() {
-                let file_name = file.file_name().to_string_lossy().to_string();
-                let piece_name = format!("{}_piece_{}", torrent_name, piece_idx);
-
-                if file_name == piece_name {
-                    if let Ok(loaded_piece) = piece_from_dir_unit_test(&file, piece_idx) {
-                        return Ok(loaded_piece);
-                    }
-                } else if file_name == torrent_name {
-                    if let Ok(loaded_piece) = piece_from_file_unit_test(&file, piece_idx, length) {
-                        return Ok(loaded_piece);
-                    }
-                }
-            }
-        }
-        Err(ServerError::NoSuchDirectory)
-    }
-
-    fn piece_from_dir_unit_test(file: &DirEntry, idx: u32) -> Result<Option<Piece>, ServerError> {
-        if let Ok(mut piece_file) = File::open(file.path()) {
-            let mut piece = Piece::new(idx, 0, vec![0u8; 20]);
-            let mut buffer = Vec::new();
-            if piece_file.read_to_end(&mut buffer).is_ok() {
-                piece.add_block(buffer);
-                return Ok(Some(piece));
-            }
-        }
-        Err(ServerError::NoSuchDirectory)
-    }
-
-    fn piece_from_file_unit_test(
-        file: &DirEntry,
-        idx: u32,
-        length: u32,
-    ) -> Result<Option<Piece>, ServerError> {
-        if let Ok(mut downloaded_file) = File::open(file.path()) {
-            let pos = idx * length;
-            if downloaded_file.seek(SeekFrom::Start(pos as u64)).is_ok() {
-                let mut buffer = vec![0u8; length as usize];
-                if downloaded_file.read_exact(&mut buffer).is_ok() {
-                    let mut piece = Piece::new(idx, 0, vec![0u8; 20]);
-                    piece.add_block(buffer);
-                    return Ok(Some(piece));
-                }
-            }
-        }
-        Err(ServerError::NoSuchDirectory)
-    }
-
     #[test]
     fn loading_correct_piece() {
+        build_testing_downloads();
         if let Ok(torrent) = TorrentInfo::new(
             "files_for_testing/torrents_testing/ubuntu-20.04.4-desktop-amd64.iso.torrent",
         ) {
-            let exp_piece_hash = torrent.get_hash(0);
+            let exp_piece_hash = torrent.get_hash(10);
+            let storage = Storage::new(torrent, "files_for_testing/downloaded_files");
 
-            if let Ok(Some(piece0)) = load_piece_for_unit_test(0) {
+            if let Ok(piece10) = storage.read_piece(10) {
                 let mut hasher = Sha1::new();
-                hasher.update(piece0.get_data());
+                hasher.update(piece10);
                 let piece_hash = hasher.finalize();
 
                 assert_eq!(exp_piece_hash, piece_hash.to_vec());
@@ -1,11 +1,10 @@
-use crate::bitfield::PieceBitfield;
 use crate::settings::Settings;
-use crate::torrent_info::TorrentInfo;
+use crate::torrent_registry::TorrentRegistry;
 
 use std::io::Error;
 use std::net::TcpListener;
 use std::sync::mpsc::Sender;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 use std::thread;
 
 use super::peer_connection::PeerConnection;
@@ -13,7 +12,7 @@ use super::peer_connection::PeerConnection;
 #[derive(Debug, Clone)]
 pub struct Server {
     settings: Arc<Settings>,
-    torrents: Vec<(TorrentInfo, Arc<RwLock<PieceBitfield>>)>,
+    torrents: TorrentRegistry,
 }
 
 /// # struct PeerConnection
@@ -21,24 +20,17 @@ pub struct Server {
 /// Fields:
 ///     - settings
 ///     - tx_logger
-///     - torrents -> All torrents and Bitfield
+///     - torrents -> Torrents of the session (the paused ones are not shared)
 impl Server {
     /// Creates and runs a server.
-    pub fn init(
-        settings: Arc<Settings>,
-        tx_logger: Sender<String>,
-        torrents: Vec<(TorrentInfo, Arc<RwLock<PieceBitfield>>)>,
-    ) {
+    pub fn init(settings: Arc<Settings>, tx_logger: Sender<String>, torrents: TorrentRegistry) {
         let server = Server::new(settings, torrents);
         let _ = server.run_server(tx_logger);
     }
 
-    /// Receives the settings and the information of all torrents.
+    /// Receives the settings and the torrents of the session.
     /// Returns a server which is correctly initialized
-    pub fn new(
-        settings: Arc<Settings>,
-        torrents: Vec<(TorrentInfo, Arc<RwLock<PieceBitfield>>)>,
-    ) -> Server {
+    pub fn new(settings: Arc<Settings>, torrents: TorrentRegistry) -> Server {
         Server { settings, torrents }
     }
 
@@ -49,7 +41,7 @@ impl Server {
         let listener = TcpListener::bind("127.0.0.1:".to_string() + &self.settings.get_tcp_port())?;
         for new_stream in listener.incoming().flatten() {
             let dl_dir = self.settings.get_downloads_dir();
-            let sh_torrents = self.torrents.clone();
+            let sh_torrents = self.torrents.shared_torrents();
             let sh_tx_logger = Sender::clone(&tx_logger);
             thread::spawn(move || {
                 if let Ok(mut peer_connection) =
@@ -66,28 +58,27 @@ impl Server {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::event_messages::EventBus;
     use crate::p2p_messages::handshake::Handshake;
     use crate::p2p_messages::interested::InterestedMsg;
     use crate::p2p_messages::message_builder::{MessageBuilder, P2PMessage};
     use crate::p2p_messages::message_trait::Message;
     use crate::p2p_messages::request::RequestMsg;
     use crate::piece::Piece;
-    use glib::{MainContext, PRIORITY_DEFAULT};
+    use crate::test_support::build_testing_downloads;
     use sha1::{Digest, Sha1};
     use std::net::TcpStream;
-    use std::sync::Mutex;
     use std::{sync::mpsc::channel, vec};
 
+    use crate::bitfield::PieceBitfield;
+    use crate::torrent_info::TorrentInfo;
     use crate::{errors::HandleError, settings::Settings, torrent_finder::TorrentFinder};
 
     fn request_a_piece(stream: &mut TcpStream, piece: &mut Piece) {
-        while piece.get_rq() < piece.get_tl() {
-            let begin = piece.get_rq();
-            let block_length = piece.next_block_length();
-
+        while let Some((begin, block_length)) = piece.next_free_block() {
             if let Ok(request_msg) = RequestMsg::new(piece.get_idx(), begin, block_length) {
                 if request_msg.send_msg(stream).is_ok() {
-                    piece.add_to_rq(block_length);
+                    piece.mark_requested(begin);
                 }
             }
         }
@@ -101,6 +92,7 @@ mod tests {
     //      - Response requests with a valid piece
     #[test]
     fn integration_test_send_piece() {
+        build_testing_downloads();
         let settings = Arc::new(
             Settings::new("files_for_testing/settings_files_testing/settings.txt").handle_error(),
         );
@@ -111,16 +103,17 @@ mod tests {
         let mut requested_piece = Piece::new(0, torrent.get_piece_length(), torrent.get_hash(0));
         // Running the server
         let _thread = thread::spawn(move || {
-            let (tx, rx) = channel();
-            let (tx_gtk, _rx_gtk) = MainContext::channel(PRIORITY_DEFAULT);
-            let _ = tx.send(tx_gtk);
             if let Ok(vec) = TorrentFinder::find(
                 torrent_path,
                 "files_for_testing/downloaded_files",
-                Arc::new(Mutex::new(rx)),
+                &EventBus::new(),
             ) {
                 let (tx, _rx) = channel();
-                Server::init(settings.clone(), tx, vec);
+                let torrents = TorrentRegistry::new();
+                for torrent in vec {
+                    torrents.add(torrent);
+                }
+                Server::init(settings.clone(), tx, torrents);
             }
         });
 
@@ -155,13 +148,23 @@ mod tests {
                 expected_bf.add_a_piece(10);
                 assert_eq!(expected_bf.get_vec(), bf);
 
+                // Receiving the extension handshake (both handshakes have the extension bit)
+                match MessageBuilder::build(&mut stream) {
+                    Ok(P2PMessage::Extended(msg)) => assert_eq!(msg.get_ext_id(), 0),
+                    _ => {
+                        assert!(false);
+                        return;
+                    }
+                }
+
                 // Sending Interested Message
                 let _ = InterestedMsg::new().send_msg(&mut stream);
 
-                // Receiving unchoke
-                if let Ok(p2p_msg) = MessageBuilder::build(&mut stream) {
-                    match p2p_msg {
-                        P2PMessage::Unchoke(_msg) => assert!(true),
+                // Receiving unchoke (our allowed fast pieces may be sent before)
+                loop {
+                    match MessageBuilder::build(&mut stream) {
+                        Ok(P2PMessage::Unchoke(_msg)) => break,
+                        Ok(P2PMessage::AllowedFast(_msg)) => continue,
                         _ => {
                             assert!(false);
                             return;
@@ -172,12 +175,11 @@ mod tests {
                 request_a_piece(&mut stream, &mut requested_piece);
 
                 // Receiving the piece
-                while requested_piece.get_dl() < requested_piece.get_tl() {
+                while !requested_piece.is_complete() {
                     if let Ok(p2p_msg) = MessageBuilder::build(&mut stream) {
                         match p2p_msg {
                             P2PMessage::Piece(msg) => {
-                                requested_piece.add_block(msg.get_block());
-                                requested_piece.add_to_dl(msg.get_block().len() as u32);
+                                requested_piece.add_block(msg.get_begin(), msg.get_block());
                             }
                             _ => {
                                 assert!(false);
@@ -0,0 +1,410 @@
+use crate::bitfield::PieceBitfield;
+use crate::constants::{CHOKE_INTERVAL, OPTIMISTIC_UNCHOKE_INTERVAL, UPLOAD_SLOTS};
+
+use std::collections::{hash_map::RandomState, HashMap};
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+type Chokers = Mutex<HashMap<Vec<u8>, Arc<Mutex<Choker>>>>;
+
+/// Chokers of every torrent, by info hash. They are shared by the inbound (server)
+/// and outbound (client) connections of the torrent. Only inbound connections upload,
+/// so only their peers become interested and get unchoked; outbound connections
+/// report the bytes their peers send us.
+static CHOKERS: OnceLock<Chokers> = OnceLock::new();
+
+/// # struct Choker
+/// Decides which peers of a torrent we upload to (tit-for-tat).
+///     - Every 10 seconds, the interested peers with the best transfer rate get the
+///       regular upload slots. While downloading, peers are ranked by how fast they
+///       upload to us. While seeding, by how fast we upload to them.
+///     - Every 30 seconds, another interested peer is unchoked optimistically, so new
+///       peers get a chance to show their rate.
+///     - While there are free slots, interested peers are unchoked right away.
+///
+/// Fields:
+///     - peers -> by peer id
+///     - our_pieces -> used to know if we are seeding
+///     - optimistic -> peer unchoked optimistically
+///     - last_round
+///     - last_optimistic
+///     - uploaded / downloaded -> total bytes transferred with all the peers of the torrent
+#[derive(Debug)]
+pub struct Choker {
+    peers: HashMap<Vec<u8>, ChokerPeer>,
+    our_pieces: Arc<RwLock<PieceBitfield>>,
+    optimistic: Option<Vec<u8>>,
+    last_round: Option<Instant>,
+    last_optimistic: Option<Instant>,
+    uploaded: u64,
+    downloaded: u64,
+}
+
+/// # struct ChokerPeer
+/// Transfer statistics of a peer, added up from all its connections.
+#[derive(Debug, Default)]
+struct ChokerPeer {
+    connections: u32,
+    interested: bool,
+    unchoked: bool,
+    downloaded: u64,
+    uploaded: u64,
+    round_downloaded: u64,
+    round_uploaded: u64,
+    download_rate: f64,
+    upload_rate: f64,
+}
+
+impl Choker {
+    pub fn new(our_pieces: Arc<RwLock<PieceBitfield>>) -> Choker {
+        Choker {
+            peers: HashMap::new(),
+            our_pieces,
+            optimistic: None,
+            last_round: None,
+            last_optimistic: None,
+            uploaded: 0,
+            downloaded: 0,
+        }
+    }
+
+    /// Returns the choker of the torrent, creating it if it does not exist yet.
+    /// If the torrent was added again, its choker takes the new bitfield.
+    pub fn for_torrent(
+        info_hash: &[u8],
+        our_pieces: Arc<RwLock<PieceBitfield>>,
+    ) -> Arc<Mutex<Choker>> {
+        let chokers = CHOKERS.get_or_init(|| Mutex::new(HashMap::new()));
+        let choker = match chokers.lock() {
+            Ok(mut chokers) => chokers
+                .entry(info_hash.to_vec())
+                .or_insert_with(|| Arc::new(Mutex::new(Choker::new(our_pieces.clone()))))
+                .clone(),
+            Err(_) => return Arc::new(Mutex::new(Choker::new(our_pieces))),
+        };
+        if let Ok(mut lock_choker) = choker.lock() {
+            if !Arc::ptr_eq(&lock_choker.our_pieces, &our_pieces) {
+                lock_choker.our_pieces = our_pieces;
+            }
+        }
+        choker
+    }
+
+    /// Returns the choker of the torrent, or None if none of its connections created it.
+    pub fn get(info_hash: &[u8]) -> Option<Arc<Mutex<Choker>>> {
+        let chokers = CHOKERS.get()?;
+        match chokers.lock() {
+            Ok(chokers) => chokers.get(info_hash).cloned(),
+            Err(_) => None,
+        }
+    }
+
+    /// Forgets the choker of a removed torrent.
+    pub fn remove(info_hash: &[u8]) {
+        if let Some(chokers) = CHOKERS.get() {
+            if let Ok(mut chokers) = chokers.lock() {
+                chokers.remove(info_hash);
+            }
+        }
+    }
+
+    /// A new connection with the peer was established.
+    pub fn register(&mut self, peer_id: &[u8]) {
+        self.peers.entry(peer_id.to_vec()).or_default().connections += 1;
+    }
+
+    /// A connection with the peer was closed. When the peer has not any connection left,
+    /// it is forgotten.
+    pub fn unregister(&mut self, peer_id: &[u8]) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.connections = peer.connections.saturating_sub(1);
+            if peer.connections == 0 {
+                self.peers.remove(peer_id);
+            }
+        }
+    }
+
+    pub fn set_interested(&mut self, peer_id: &[u8], interested: bool) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.interested = interested;
+        }
+    }
+
+    /// Bytes received from the peer.
+    pub fn add_downloaded(&mut self, peer_id: &[u8], bytes: u64) {
+        self.downloaded += bytes;
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.downloaded += bytes;
+        }
+    }
+
+    /// Bytes sent to the peer.
+    pub fn add_uploaded(&mut self, peer_id: &[u8], bytes: u64) {
+        self.uploaded += bytes;
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.uploaded += bytes;
+        }
+    }
+
+    /// Returns the total bytes (uploaded, downloaded) of the torrent.
+    pub fn get_totals(&self) -> (u64, u64) {
+        (self.uploaded, self.downloaded)
+    }
+
+    /// Restores the totals of previous sessions. If something was already transferred
+    /// in this session, the current totals are kept.
+    pub fn restore_totals(&mut self, uploaded: u64, downloaded: u64) {
+        if self.uploaded == 0 && self.downloaded == 0 {
+            self.uploaded = uploaded;
+            self.downloaded = downloaded;
+        }
+    }
+
+    /// Runs a choking round if it is time to, or if there is a free slot for a
+    /// waiting peer. Then returns true if the peer is unchoked.
+    pub fn update(&mut self, peer_id: &[u8], now: Instant) -> bool {
+        let round_due = match self.last_round {
+            Some(last_round) => now.duration_since(last_round) >= secs(CHOKE_INTERVAL),
+            None => true,
+        };
+        if round_due {
+            self.update_rates(now);
+            self.run_round(now);
+        } else if self.has_free_slot() {
+            self.run_round(now);
+        }
+        self.is_unchoked(peer_id)
+    }
+
+    pub fn is_unchoked(&self, peer_id: &[u8]) -> bool {
+        self.peers
+            .get(peer_id)
+            .map(|peer| peer.unchoked)
+            .unwrap_or_default()
+    }
+
+    /// There are free slots if every interested peer can be unchoked.
+    fn has_free_slot(&self) -> bool {
+        let interested = self.peers.values().filter(|peer| peer.interested);
+        let waiting = interested.clone().any(|peer| !peer.unchoked);
+        waiting && interested.count() <= UPLOAD_SLOTS + 1
+    }
+
+    /// Updates the rate of every peer with the bytes transferred since the last round.
+    fn update_rates(&mut self, now: Instant) {
+        let elapsed = match self.last_round {
+            Some(last_round) => now.duration_since(last_round).as_secs_f64(),
+            None => 0.0,
+        };
+        for peer in self.peers.values_mut() {
+            if elapsed > 0.0 {
+                peer.download_rate = (peer.downloaded - peer.round_downloaded) as f64 / elapsed;
+                peer.upload_rate = (peer.uploaded - peer.round_uploaded) as f64 / elapsed;
+            }
+            peer.round_downloaded = peer.downloaded;
+            peer.round_uploaded = peer.uploaded;
+        }
+        self.last_round = Some(now);
+    }
+
+    /// Chooses the peers of the regular slots and the optimistic one. The rest are choked.
+    fn run_round(&mut self, now: Instant) {
+        let seeding = match self.our_pieces.read() {
+            Ok(pieces) => pieces.has_all_pieces(),
+            Err(_) => false,
+        };
+        let mut interested: Vec<(&Vec<u8>, f64)> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| peer.interested)
+            .map(|(id, peer)| {
+                let rate = if seeding {
+                    peer.upload_rate
+                } else {
+                    peer.download_rate
+                };
+                (id, rate)
+            })
+            .collect();
+        interested.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let regular: Vec<Vec<u8>> = interested
+            .iter()
+            .take(UPLOAD_SLOTS)
+            .map(|(id, _)| (*id).clone())
+            .collect();
+        let candidates: Vec<Vec<u8>> = interested
+            .iter()
+            .skip(UPLOAD_SLOTS)
+            .map(|(id, _)| (*id).clone())
+            .collect();
+
+        let rotation_due = match self.last_optimistic {
+            Some(last) => now.duration_since(last) >= secs(OPTIMISTIC_UNCHOKE_INTERVAL),
+            None => true,
+        };
+        let optimistic_is_valid = match &self.optimistic {
+            Some(id) => candidates.contains(id),
+            None => false,
+        };
+        if rotation_due || !optimistic_is_valid {
+            self.optimistic = random_peer(&candidates);
+            self.last_optimistic = Some(now);
+        }
+
+        for (id, peer) in self.peers.iter_mut() {
+            peer.unchoked = regular.contains(id) || self.optimistic.as_ref() == Some(id);
+        }
+    }
+}
+
+fn secs(n: u64) -> Duration {
+    Duration::from_secs(n)
+}
+
+fn random_peer(peers: &[Vec<u8>]) -> Option<Vec<u8>> {
+    if peers.is_empty() {
+        return None;
+    }
+    let random = RandomState::new().build_hasher().finish() as usize;
+    Some(peers[random % peers.len()].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_choker(seeding: bool) -> Choker {
+        let pieces = if seeding {
+            PieceBitfield::get_completed_bitfield(8)
+        } else {
+            PieceBitfield::new(8)
+        };
+        Choker::new(Arc::new(RwLock::new(pieces)))
+    }
+
+    fn peer_id(n: u8) -> Vec<u8> {
+        vec![n; 20]
+    }
+
+    fn add_interested_peers(choker: &mut Choker, n: u8) {
+        for i in 0..n {
+            choker.register(&peer_id(i));
+            choker.set_interested(&peer_id(i), true);
+        }
+    }
+
+    fn unchoked_peers(choker: &Choker, n: u8) -> Vec<u8> {
+        (0..n)
+            .filter(|i| choker.is_unchoked(&peer_id(*i)))
+            .collect()
+    }
+
+    #[test]
+    fn choker_of_a_torrent_added_again_takes_the_new_bitfield() {
+        let info_hash = vec![0xc1; 20];
+        let old_pieces = Arc::new(RwLock::new(PieceBitfield::new(8)));
+        let new_pieces = Arc::new(RwLock::new(PieceBitfield::get_completed_bitfield(8)));
+
+        let choker = Choker::for_torrent(&info_hash, old_pieces);
+        let same_choker = Choker::for_torrent(&info_hash, new_pieces.clone());
+        assert!(Arc::ptr_eq(&choker, &same_choker));
+        let lock_choker = choker.lock().expect("It shouldn't fail");
+        assert!(Arc::ptr_eq(&lock_choker.our_pieces, &new_pieces));
+    }
+
+    #[test]
+    fn removed_chokers_are_forgotten() {
+        let info_hash = vec![0xc2; 20];
+        assert!(Choker::get(&info_hash).is_none());
+        let pieces = Arc::new(RwLock::new(PieceBitfield::new(8)));
+        Choker::for_torrent(&info_hash, pieces);
+        assert!(Choker::get(&info_hash).is_some());
+
+        Choker::remove(&info_hash);
+        assert!(Choker::get(&info_hash).is_none());
+    }
+
+    #[test]
+    fn interested_peers_are_unchoked_while_there_are_free_slots() {
+        let mut choker = new_choker(false);
+        let now = Instant::now();
+        add_interested_peers(&mut choker, 2);
+        assert!(choker.update(&peer_id(0), now));
+        assert!(choker.is_unchoked(&peer_id(1)));
+
+        // Not interested peers are not unchoked
+        choker.register(&peer_id(9));
+        assert!(!choker.update(&peer_id(9), now));
+    }
+
+    #[test]
+    fn fastest_uploaders_get_the_regular_slots() {
+        let mut choker = new_choker(false);
+        let start = Instant::now();
+        let n = UPLOAD_SLOTS as u8 + 4;
+        add_interested_peers(&mut choker, n);
+        choker.update(&peer_id(0), start);
+
+        // The last peers upload to us faster
+        for i in 0..n {
+            choker.add_downloaded(&peer_id(i), i as u64 * 1000);
+        }
+        choker.update(&peer_id(0), start + secs(CHOKE_INTERVAL));
+
+        let unchoked = unchoked_peers(&choker, n);
+        assert_eq!(unchoked.len(), UPLOAD_SLOTS + 1);
+        for i in n - UPLOAD_SLOTS as u8..n {
+            assert!(unchoked.contains(&i));
+        }
+    }
+
+    #[test]
+    fn seeding_ranks_peers_by_upload_rate() {
+        let mut choker = new_choker(true);
+        let start = Instant::now();
+        let n = UPLOAD_SLOTS as u8 + 2;
+        add_interested_peers(&mut choker, n);
+        choker.update(&peer_id(0), start);
+
+        // We download nothing while seeding, but the first peers take more from us
+        for i in 0..n {
+            choker.add_uploaded(&peer_id(i), (n - i) as u64 * 1000);
+            choker.add_downloaded(&peer_id(i), i as u64 * 1000);
+        }
+        choker.update(&peer_id(0), start + secs(CHOKE_INTERVAL));
+
+        for i in 0..UPLOAD_SLOTS as u8 {
+            assert!(choker.is_unchoked(&peer_id(i)));
+        }
+    }
+
+    #[test]
+    fn optimistic_unchoke_rotates_among_choked_peers() {
+        let mut choker = new_choker(false);
+        let start = Instant::now();
+        let n = UPLOAD_SLOTS as u8 + 1;
+        add_interested_peers(&mut choker, n);
+        choker.update(&peer_id(0), start);
+        for i in 0..n {
+            choker.add_downloaded(&peer_id(i), i as u64 * 1000);
+        }
+        choker.update(&peer_id(0), start + secs(CHOKE_INTERVAL));
+        // The slowest peer can only be unchoked optimistically
+        assert_eq!(choker.optimistic, Some(peer_id(0)));
+
+        // A new peer arrives. After 30 seconds, one of the two slow peers is chosen.
+        choker.register(&peer_id(n));
+        choker.set_interested(&peer_id(n), true);
+        for i in 0..n {
+            choker.add_downloaded(&peer_id(i), i as u64 * 1000);
+        }
+        choker.update(&peer_id(0), start + secs(OPTIMISTIC_UNCHOKE_INTERVAL));
+        let optimistic = choker.optimistic.clone().expect("It shouldn't fail");
+        assert!(optimistic == peer_id(0) || optimistic == peer_id(n));
+        assert_eq!(unchoked_peers(&choker, n + 1).len(), UPLOAD_SLOTS + 1);
+
+        choker.unregister(&optimistic);
+        assert!(!choker.is_unchoked(&optimistic));
+    }
+}
@@ -4,6 +4,9 @@ pub const BYTE_FILLED_W_ONES: u8 = 255;
 pub const TWO_MINUTES: u64 = 120;
 
 pub const HTTPS_TRACKER_PORT: &str = "443";
+pub const UDP_TRACKER_PROTOCOL_ID: u64 = 0x41727101980;
+pub const UDP_TRACKER_TIMEOUT: u64 = 15;
+pub const UDP_TRACKER_MAX_RETRIES: u32 = 2;
 
 pub const DOWNLOADING: u8 = 1;
 pub const NOT_DOWNLOADING: u8 = 0;
@@ -12,8 +15,21 @@ pub const UNCHOKED: u8 = 0;
 pub const INTERESTED: u8 = 1;
 pub const NOT_INTERESTED: u8 = 0;
 
+pub const UPLOAD_SLOTS: usize = 4;
+pub const CHOKE_INTERVAL: u64 = 10;
+pub const OPTIMISTIC_UNCHOKE_INTERVAL: u64 = 30;
+pub const SERVER_READ_TIMEOUT: u64 = 5;
+
 pub const ALL_PIECES_DOWNLOADED: u8 = 1;
 pub const MAX_CONCURRENT_TORRENTS: u32 = 3;
+pub const TORRENT_QUEUE_POLL_INTERVAL: u64 = 1;
+pub const CLIENT_STOP_POLL_MILLIS: u64 = 100;
+pub const WATCH_ADDED_DIR: &str = "added";
+pub const HASH_CHECK_THREADS: usize = 4;
+pub const RESUME_DIR: &str = ".resume";
+pub const RESUME_SAVE_INTERVAL: u64 = 30;
+pub const MIN_PIECE_LENGTH: u32 = 16384;
+pub const DEFAULT_PIECE_LENGTH_EXP: u32 = 18;
 
 pub const START_LOG_TYPE: u8 = 0;
 pub const END_LOG_TYPE: u8 = 1;
@@ -22,3 +38,48 @@ pub const GENERIC_LOG_TYPE: u8 = 3;
 
 pub const CLIENT_MODE_LOG: u8 = 0;
 pub const SERVER_MODE_LOG: u8 = 1;
+
+pub const EXTENSION_PROTOCOL_BYTE: usize = 5;
+pub const EXTENSION_PROTOCOL_MASK: u8 = 0x10;
+pub const FAST_EXTENSION_BYTE: usize = 7;
+pub const FAST_EXTENSION_MASK: u8 = 0x04;
+pub const ALLOWED_FAST_SET_SIZE: u32 = 10;
+pub const METADATA_PIECE_LENGTH: usize = 16384;
+pub const MAX_METADATA_SIZE: usize = 8 * 1024 * 1024;
+pub const MAX_EXTENDED_MSG_SIZE: u32 = 1024 * 1024;
+pub const METADATA_TIMEOUT: u64 = 30;
+pub const CLIENT_VERSION: &str = "Albatros Rustico 0.1";
+pub const REQUEST_QUEUE_LENGTH: u32 = 250;
+pub const MIN_REQUEST_WINDOW: u32 = 4;
+pub const REQUEST_QUEUE_TIME: u64 = 3;
+pub const BLOCK_REQUEST_TIMEOUT: u64 = 20;
+pub const PEX_INTERVAL: u64 = 60;
+pub const PEX_MAX_PEERS: usize = 50;
+pub const PEX_MAX_DISCOVERED: usize = 200;
+pub const MAX_ACTIVE_CONNECTIONS: usize = 50;
+
+pub const DHT_K: usize = 8;
+pub const DHT_ALPHA: usize = 3;
+pub const DHT_QUERY_TIMEOUT: u64 = 2;
+pub const DHT_TOKEN_ROTATION: u64 = 300;
+pub const DHT_QUESTIONABLE_NODE: u64 = 15 * 60;
+pub const DHT_MAX_FAILED_QUERIES: u32 = 2;
+pub const DHT_MAX_VALUES: usize = 50;
+pub const DHT_NODES_FILE: &str = ".dht_nodes";
+pub const DHT_BOOTSTRAP_NODES: [&str; 3] = [
+    "router.bittorrent.com:6881",
+    "dht.transmissionbt.com:6881",
+    "router.utorrent.com:6881",
+];
+
+pub const PROGRESS_REFRESH_INTERVAL: u64 = 1;
+pub const PROGRESS_LOG_INTERVAL: u64 = 10;
+pub const PROGRESS_BAR_WIDTH: usize = 20;
+pub const EXIT_DOWNLOAD_INCOMPLETE: i32 = 1;
+
+pub const CONTROL_DEFAULT_ADDRESS: &str = "127.0.0.1:7070";
+pub const CONTROL_UNIX_PREFIX: &str = "unix:";
+pub const CONTROL_READ_TIMEOUT: u64 = 5;
+pub const CONTROL_MAX_REQUEST_SIZE: usize = 1024 * 1024;
+pub const CONTROL_MAX_HEADER_SIZE: usize = 8 * 1024;
+pub const CONTROL_MAX_HEADERS: usize = 32;
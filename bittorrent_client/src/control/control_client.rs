@@ -0,0 +1,84 @@
+use crate::constants::CONTROL_READ_TIMEOUT;
+use crate::control::http::{self, HttpMessage};
+use crate::control::rpc::{RpcRequest, RpcResponse};
+use crate::control::transport::{ControlAddress, ControlStream};
+use crate::errors::ControlError;
+
+use serde_json::Value;
+use std::time::Duration;
+
+/// # struct ControlClient
+/// Sends requests to the control API of a running client.
+///     - address
+///     - next_id -> id of the next request
+pub struct ControlClient {
+    address: ControlAddress,
+    next_id: u64,
+}
+
+impl ControlClient {
+    /// Receives the address of the control API ("host:port" or "unix:<socket path>").
+    pub fn new(address: &str) -> Result<ControlClient, ControlError> {
+        Ok(ControlClient {
+            address: ControlAddress::parse(address)?,
+            next_id: 1,
+        })
+    }
+
+    /// Calls a method of the API, using a new connection.
+    /// On success, returns the JSON-RPC response (which can be an error of the method).
+    /// Otherwise (cannot connect, or invalid response), returns ControlError.
+    pub fn call(&mut self, method: &str, params: Value) -> Result<RpcResponse, ControlError> {
+        let request = RpcRequest::new(method, params, self.next_id);
+        self.next_id += 1;
+        let body = serde_json::to_vec(&request).map_err(|_| ControlError::InvalidResponse)?;
+
+        let mut stream = ControlStream::connect(&self.address)?;
+        let _ = stream.set_read_timeout(Duration::from_secs(CONTROL_READ_TIMEOUT));
+        http::write_request(&mut stream, &body).map_err(ControlError::CannotConnect)?;
+
+        let response = HttpMessage::read(&mut stream).map_err(|_| ControlError::InvalidResponse)?;
+        if response.get_status_code() != 200 {
+            return Err(ControlError::InvalidResponse);
+        }
+        serde_json::from_slice(&response.get_body()).map_err(|_| ControlError::InvalidResponse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::control_server::ControlServer;
+    use crate::control::rpc::{METHOD_NOT_FOUND, TORRENT_LIST};
+    use crate::session::Session;
+    use serde_json::json;
+    use std::thread;
+
+    #[test]
+    fn requests_to_a_control_server() {
+        let address = "unix:files_for_testing/control_test.sock";
+        let session = Session::new("files_for_testing/settings_files_testing/settings.txt")
+            .expect("It shouldn't fail");
+        let server = ControlServer::new(address, session).expect("It shouldn't fail");
+        thread::spawn(move || server.run());
+
+        let mut client = ControlClient::new(address).expect("It shouldn't fail");
+        let list = client.call(TORRENT_LIST, Value::Null);
+        let unknown = client.call("session.shutdown", Value::Null);
+        let _ = std::fs::remove_file("files_for_testing/control_test.sock");
+
+        let list = list.expect("It shouldn't fail");
+        assert_eq!(list.result, Some(json!([])));
+        assert_eq!(list.id, json!(1));
+        let unknown = unknown.expect("It shouldn't fail");
+        assert_eq!(unknown.error.map(|e| e.code), Some(METHOD_NOT_FOUND));
+        assert_eq!(unknown.id, json!(2));
+    }
+
+    #[test]
+    fn cannot_connect_without_server() {
+        let mut client = ControlClient::new("unix:files_for_testing/no_control.sock")
+            .expect("It shouldn't fail");
+        assert!(client.call(TORRENT_LIST, Value::Null).is_err());
+    }
+}
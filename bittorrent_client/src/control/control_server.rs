@@ -0,0 +1,120 @@
+use crate::constants::CONTROL_READ_TIMEOUT;
+use crate::control::http::{self, HttpMessage};
+use crate::control::rpc;
+use crate::control::transport::{ControlAddress, ControlListener, ControlStream};
+use crate::errors::ControlError;
+use crate::session::Session;
+
+use std::thread;
+use std::time::Duration;
+
+/// # struct ControlServer
+/// Local control API of a session: JSON-RPC 2.0 requests sent in the body of
+/// HTTP POST requests, over TCP (only on loopback addresses) or a Unix socket.
+///     - listener
+///     - session -> session whose torrents are managed
+pub struct ControlServer {
+    listener: ControlListener,
+    session: Session,
+}
+
+impl ControlServer {
+    /// Binds the address ("host:port" or "unix:<socket path>").
+    /// On error, returns ControlError.
+    pub fn new(address: &str, session: Session) -> Result<ControlServer, ControlError> {
+        let listener = ControlListener::bind(&ControlAddress::parse(address)?)?;
+        Ok(ControlServer { listener, session })
+    }
+
+    /// Handles the connections (one request each) until the listener fails.
+    pub fn run(&self) {
+        while let Ok(stream) = self.listener.accept() {
+            let session = self.session.clone();
+            thread::spawn(move || ControlServer::handle_connection(stream, &session));
+        }
+    }
+
+    /// Reads a request and responds to it:
+    ///     - A POST request with a JSON body gets the JSON-RPC response of its body
+    ///     - Other methods are not allowed
+    ///     - Requests with an Origin header are forbidden: they are sent by web pages
+    ///       (which could reach a local address), and our clients never send it
+    ///     - Bodies that are not JSON are not supported, so web pages cannot send
+    ///       requests without a CORS preflight
+    ///     - Invalid requests get a Bad Request response
+    fn handle_connection(mut stream: ControlStream, session: &Session) {
+        let _ = stream.set_read_timeout(Duration::from_secs(CONTROL_READ_TIMEOUT));
+
+        let _ = match HttpMessage::read(&mut stream) {
+            Ok(request) if !request.is_post() => {
+                http::write_response(&mut stream, "405 Method Not Allowed", b"")
+            }
+            Ok(request) if request.get_header("origin").is_some() => {
+                http::write_response(&mut stream, "403 Forbidden", b"")
+            }
+            Ok(request) if !request.has_json_body() => {
+                http::write_response(&mut stream, "415 Unsupported Media Type", b"")
+            }
+            Ok(request) => {
+                let response = rpc::handle_request(session, &request.get_body());
+                match serde_json::to_vec(&response) {
+                    Ok(body) => http::write_response(&mut stream, "200 OK", &body),
+                    Err(_) => http::write_response(&mut stream, "500 Internal Server Error", b""),
+                }
+            }
+            Err(_) => http::write_response(&mut stream, "400 Bad Request", b""),
+        };
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    fn send_raw(address: &str, request: &str) -> u32 {
+        let path = address.trim_start_matches("unix:");
+        let mut stream = UnixStream::connect(path).expect("It shouldn't fail");
+        stream
+            .write_all(request.as_bytes())
+            .expect("It shouldn't fail");
+        match HttpMessage::read(&mut stream) {
+            Ok(response) => response.get_status_code(),
+            Err(_) => 0,
+        }
+    }
+
+    #[test]
+    fn requests_of_web_pages_are_rejected() {
+        let address = "unix:files_for_testing/control_server_test.sock";
+        let session = Session::new("files_for_testing/settings_files_testing/settings.txt")
+            .expect("It shouldn't fail");
+        let server = ControlServer::new(address, session).expect("It shouldn't fail");
+        thread::spawn(move || server.run());
+
+        let body = r#"{"jsonrpc":"2.0","method":"torrent.list","id":1}"#;
+        let request = |headers: &str| {
+            format!(
+                "POST / HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+                headers,
+                body.len(),
+                body
+            )
+        };
+        let with_origin = send_raw(
+            address,
+            &request("Content-Type: application/json\r\nOrigin: http://example.com\r\n"),
+        );
+        let form = send_raw(
+            address,
+            &request("Content-Type: application/x-www-form-urlencoded\r\n"),
+        );
+        let json = send_raw(address, &request("Content-Type: application/json\r\n"));
+        let _ = std::fs::remove_file("files_for_testing/control_server_test.sock");
+
+        assert_eq!(with_origin, 403);
+        assert_eq!(form, 415);
+        assert_eq!(json, 200);
+    }
+}
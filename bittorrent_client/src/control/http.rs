@@ -0,0 +1,214 @@
+use crate::constants::{CONTROL_MAX_HEADERS, CONTROL_MAX_HEADER_SIZE, CONTROL_MAX_REQUEST_SIZE};
+use crate::errors::ControlError;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// # struct HttpMessage
+/// Minimal HTTP/1.1 message used by the control API:
+///     - start_line -> request line (e.g. "POST / HTTP/1.1") or status line
+///     - headers -> values by name (in lowercase)
+///     - body -> its length is given by the Content-Length header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpMessage {
+    start_line: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl HttpMessage {
+    /// Reads a message from the stream.
+    /// On error (invalid headers, a head larger than CONTROL_MAX_HEADER_SIZE, more than
+    /// CONTROL_MAX_HEADERS headers, or a body larger than CONTROL_MAX_REQUEST_SIZE),
+    /// returns ControlError::InvalidHttpMessage.
+    pub fn read<R: Read>(stream: &mut R) -> Result<HttpMessage, ControlError> {
+        // The start line and the headers are read up to the limit: a line cut by it
+        // is followed by the end of the stream, so the message is invalid.
+        let mut reader = BufReader::new(stream.take(CONTROL_MAX_HEADER_SIZE as u64));
+        let start_line = HttpMessage::read_line(&mut reader)?;
+        if start_line.is_empty() {
+            return Err(ControlError::InvalidHttpMessage);
+        }
+
+        let mut headers = HashMap::new();
+        for n_headers in 0.. {
+            let header = HttpMessage::read_line(&mut reader)?;
+            if header.is_empty() {
+                break;
+            }
+            if n_headers == CONTROL_MAX_HEADERS {
+                return Err(ControlError::InvalidHttpMessage);
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+            }
+        }
+        let content_length: usize = match headers.get("content-length") {
+            Some(length) => length
+                .parse()
+                .map_err(|_| ControlError::InvalidHttpMessage)?,
+            None => 0,
+        };
+        if content_length > CONTROL_MAX_REQUEST_SIZE {
+            return Err(ControlError::InvalidHttpMessage);
+        }
+
+        reader.get_mut().set_limit(content_length as u64);
+        let mut body = vec![0u8; content_length];
+        reader
+            .read_exact(&mut body)
+            .map_err(|_| ControlError::InvalidHttpMessage)?;
+        Ok(HttpMessage {
+            start_line,
+            headers,
+            body,
+        })
+    }
+
+    fn read_line<R: BufRead>(reader: &mut R) -> Result<String, ControlError> {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => Err(ControlError::InvalidHttpMessage),
+            Ok(_) => Ok(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+
+    pub fn get_start_line(&self) -> String {
+        self.start_line.clone()
+    }
+
+    pub fn get_body(&self) -> Vec<u8> {
+        self.body.clone()
+    }
+
+    /// Returns the value of a header (the name is not case sensitive).
+    pub fn get_header(&self, name: &str) -> Option<String> {
+        self.headers.get(&name.to_ascii_lowercase()).cloned()
+    }
+
+    /// Checks if the Content-Type of the body is JSON.
+    pub fn has_json_body(&self) -> bool {
+        match self.get_header("content-type") {
+            Some(content_type) => {
+                let media_type = content_type.split(';').next().unwrap_or_default();
+                media_type.trim().eq_ignore_ascii_case("application/json")
+            }
+            None => false,
+        }
+    }
+
+    /// Checks if it is a POST request.
+    pub fn is_post(&self) -> bool {
+        self.start_line.starts_with("POST ")
+    }
+
+    /// Returns the status code of a response (0 if it is not a valid status line).
+    pub fn get_status_code(&self) -> u32 {
+        self.start_line
+            .split(' ')
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Sends a POST request with a JSON body.
+pub fn write_request<W: Write>(stream: &mut W, body: &[u8]) -> std::io::Result<()> {
+    let head = format!(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(&[head.as_bytes(), body].concat())?;
+    stream.flush()
+}
+
+/// Sends a response with a JSON body. The status is the code and its reason (e.g. "200 OK").
+pub fn write_response<W: Write>(stream: &mut W, status: &str, body: &[u8]) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    stream.write_all(&[head.as_bytes(), body].concat())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_written_request() {
+        let mut request = vec![];
+        write_request(&mut request, b"{\"jsonrpc\":\"2.0\"}").expect("It shouldn't fail");
+
+        let message = HttpMessage::read(&mut request.as_slice()).expect("It shouldn't fail");
+        assert!(message.is_post());
+        assert!(message.has_json_body());
+        assert_eq!(message.get_header("host"), Some("localhost".to_string()));
+        assert_eq!(message.get_header("Origin"), None);
+        assert_eq!(message.get_start_line(), "POST / HTTP/1.1");
+        assert_eq!(message.get_body(), b"{\"jsonrpc\":\"2.0\"}".to_vec());
+    }
+
+    #[test]
+    fn read_response_with_status() {
+        let mut response = vec![];
+        write_response(&mut response, "405 Method Not Allowed", b"").expect("It shouldn't fail");
+
+        let message = HttpMessage::read(&mut response.as_slice()).expect("It shouldn't fail");
+        assert!(!message.is_post());
+        assert_eq!(message.get_status_code(), 405);
+        assert!(message.get_body().is_empty());
+    }
+
+    #[test]
+    fn content_type_of_the_body() {
+        let form = b"POST / HTTP/1.1\r\nContent-Type: text/plain\r\n\r\n".to_vec();
+        let message = HttpMessage::read(&mut form.as_slice()).expect("It shouldn't fail");
+        assert!(!message.has_json_body());
+
+        let json = b"POST / HTTP/1.1\r\ncontent-type: Application/JSON; charset=utf-8\r\n\r\n";
+        let message = HttpMessage::read(&mut json.as_slice()).expect("It shouldn't fail");
+        assert!(message.has_json_body());
+    }
+
+    #[test]
+    fn truncated_and_too_large_messages_are_invalid() {
+        let truncated = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}".to_vec();
+        assert!(HttpMessage::read(&mut truncated.as_slice()).is_err());
+
+        let too_large = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            CONTROL_MAX_REQUEST_SIZE + 1
+        );
+        assert!(HttpMessage::read(&mut too_large.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn too_large_heads_are_invalid() {
+        let long_header = format!(
+            "POST / HTTP/1.1\r\nX-Long: {}\r\n\r\n",
+            "a".repeat(CONTROL_MAX_HEADER_SIZE)
+        );
+        assert!(HttpMessage::read(&mut long_header.as_bytes()).is_err());
+
+        let many_headers = format!(
+            "POST / HTTP/1.1\r\n{}\r\n",
+            "X-Header: a\r\n".repeat(CONTROL_MAX_HEADERS + 1)
+        );
+        assert!(HttpMessage::read(&mut many_headers.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn body_is_read_after_the_head() {
+        let body = "a".repeat(2 * CONTROL_MAX_HEADER_SIZE);
+        let request = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let message = HttpMessage::read(&mut request.as_bytes()).expect("It shouldn't fail");
+        assert_eq!(message.get_body(), body.into_bytes());
+    }
+}
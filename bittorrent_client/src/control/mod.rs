@@ -0,0 +1,5 @@
+pub mod control_client;
+pub mod control_server;
+pub mod http;
+pub mod rpc;
+pub mod transport;
@@ -0,0 +1,322 @@
+use crate::errors::SessionError;
+use crate::session::Session;
+use crate::torrent_info::TorrentId;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+pub const TORRENT_ADD: &str = "torrent.add";
+pub const TORRENT_REMOVE: &str = "torrent.remove";
+pub const TORRENT_PAUSE: &str = "torrent.pause";
+pub const TORRENT_RESUME: &str = "torrent.resume";
+pub const TORRENT_RECHECK: &str = "torrent.recheck";
+pub const TORRENT_LIST: &str = "torrent.list";
+pub const SESSION_STATS: &str = "session.stats";
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const UNKNOWN_TORRENT: i64 = -32001;
+pub const INVALID_TORRENT: i64 = -32002;
+pub const TORRENT_ALREADY_ADDED: i64 = -32003;
+pub const CANNOT_REMOVE_DATA: i64 = -32004;
+pub const MISSING_METADATA: i64 = -32005;
+
+/// # struct RpcRequest
+/// JSON-RPC 2.0 request of the control API:
+///     - jsonrpc -> "2.0"
+///     - method -> one of the methods of the API (e.g. "torrent.add")
+///     - params -> object with the arguments of the method
+///     - id -> echoed in the response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default)]
+    pub id: Value,
+}
+
+impl RpcRequest {
+    pub fn new(method: &str, params: Value, id: u64) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            method: method.to_string(),
+            params,
+            id: json!(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: &str) -> RpcError {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<SessionError> for RpcError {
+    fn from(error: SessionError) -> RpcError {
+        let code = match error {
+            SessionError::UnknownTorrent => UNKNOWN_TORRENT,
+            SessionError::InvalidTorrent => INVALID_TORRENT,
+            SessionError::TorrentAlreadyAdded => TORRENT_ALREADY_ADDED,
+            SessionError::CannotRemoveData(_) => CANNOT_REMOVE_DATA,
+            SessionError::MissingMetadata => MISSING_METADATA,
+        };
+        RpcError::new(code, &error.to_string())
+    }
+}
+
+/// # struct RpcResponse
+/// JSON-RPC 2.0 response: it has either a result or an error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcResponse {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    #[serde(default)]
+    pub id: Value,
+}
+
+impl RpcResponse {
+    fn new(id: Value, outcome: Result<Value, RpcError>) -> RpcResponse {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        RpcResponse {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            result,
+            error,
+            id,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AddParams {
+    source: String,
+}
+
+#[derive(Deserialize)]
+struct TorrentParams {
+    id: String,
+    #[serde(default)]
+    delete_data: bool,
+}
+
+/// Handles the body of a request, calling the method on the session.
+/// Methods:
+///     - torrent.add {source} -> adds a torrent file (path in the host of the session)
+///       or a magnet link, returns {id}
+///     - torrent.remove {id, delete_data} -> removes a torrent (and its data, if delete_data is set)
+///     - torrent.pause {id} / torrent.resume {id}
+///     - torrent.recheck {id} -> hashes the data of a torrent again, returns {valid_pieces}.
+///       The corrupted pieces are downloaded again.
+///     - torrent.list -> status of every torrent: progress, peers and connections, among others
+///     - session.stats -> totals of the session
+pub fn handle_request(session: &Session, body: &[u8]) -> RpcResponse {
+    let request: RpcRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(error) if error.is_data() => {
+            let error = RpcError::new(INVALID_REQUEST, "Invalid request");
+            return RpcResponse::new(Value::Null, Err(error));
+        }
+        Err(_) => {
+            let error = RpcError::new(PARSE_ERROR, "Parse error");
+            return RpcResponse::new(Value::Null, Err(error));
+        }
+    };
+    if request.jsonrpc != JSONRPC_VERSION {
+        let error = RpcError::new(INVALID_REQUEST, "Invalid request");
+        return RpcResponse::new(request.id, Err(error));
+    }
+
+    let outcome = call(session, &request.method, request.params);
+    RpcResponse::new(request.id, outcome)
+}
+
+fn call(session: &Session, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        TORRENT_ADD => {
+            let params: AddParams = parse_params(params)?;
+            let torrent_id = session.add_torrent(&params.source)?;
+            Ok(json!({ "id": torrent_id.to_string() }))
+        }
+        TORRENT_REMOVE => {
+            let params: TorrentParams = parse_params(params)?;
+            session.remove_torrent(parse_id(&params.id)?, params.delete_data)?;
+            Ok(Value::Null)
+        }
+        TORRENT_PAUSE => {
+            let params: TorrentParams = parse_params(params)?;
+            session.pause_torrent(parse_id(&params.id)?)?;
+            Ok(Value::Null)
+        }
+        TORRENT_RESUME => {
+            let params: TorrentParams = parse_params(params)?;
+            session.resume_torrent(parse_id(&params.id)?)?;
+            Ok(Value::Null)
+        }
+        TORRENT_RECHECK => {
+            let params: TorrentParams = parse_params(params)?;
+            let n_valid_pieces = session.recheck_torrent(parse_id(&params.id)?)?;
+            Ok(json!({ "valid_pieces": n_valid_pieces }))
+        }
+        TORRENT_LIST => Ok(json!(session.list_torrents())),
+        SESSION_STATS => Ok(json!(session.get_stats())),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid params"))
+}
+
+fn parse_id(id: &str) -> Result<TorrentId, RpcError> {
+    id.parse()
+        .map_err(|_| RpcError::new(INVALID_PARAMS, "Invalid torrent id"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TORRENT_PATH: &str =
+        "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+
+    fn get_session() -> Session {
+        Session::new("files_for_testing/settings_files_testing/settings.txt")
+            .expect("It shouldn't fail")
+    }
+
+    fn send(session: &Session, method: &str, params: Value) -> RpcResponse {
+        let request = RpcRequest::new(method, params, 7);
+        let body = serde_json::to_vec(&request).expect("It shouldn't fail");
+        handle_request(session, &body)
+    }
+
+    fn error_code(response: &RpcResponse) -> i64 {
+        response.error.as_ref().map(|e| e.code).unwrap_or(0)
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let session = get_session();
+        assert_eq!(
+            error_code(&handle_request(&session, b"{\"jsonrpc\":")),
+            PARSE_ERROR
+        );
+        assert_eq!(
+            error_code(&handle_request(&session, b"{\"jsonrpc\":\"2.0\"}")),
+            INVALID_REQUEST
+        );
+
+        let response = send(&session, "torrent.start", Value::Null);
+        assert_eq!(error_code(&response), METHOD_NOT_FOUND);
+        assert_eq!(response.id, json!(7));
+
+        let response = send(&session, TORRENT_PAUSE, json!({ "id": "not an id" }));
+        assert_eq!(error_code(&response), INVALID_PARAMS);
+        let response = send(&session, TORRENT_ADD, json!({}));
+        assert_eq!(error_code(&response), INVALID_PARAMS);
+    }
+
+    #[test]
+    fn add_pause_resume_and_remove_a_torrent() {
+        let session = get_session();
+        let response = send(&session, TORRENT_ADD, json!({ "source": TORRENT_PATH }));
+        let id = match response.result {
+            Some(result) => result["id"].as_str().unwrap_or_default().to_string(),
+            None => String::new(),
+        };
+        assert_eq!(id, "3d374e5a6ee6641280499abe5ce8098b2cdff064");
+
+        let response = send(&session, TORRENT_ADD, json!({ "source": TORRENT_PATH }));
+        assert_eq!(error_code(&response), TORRENT_ALREADY_ADDED);
+
+        let response = send(&session, TORRENT_PAUSE, json!({ "id": id }));
+        assert_eq!(response.result, Some(Value::Null));
+        let list = send(&session, TORRENT_LIST, Value::Null).result;
+        assert_eq!(
+            list.as_ref().map(|l| l[0]["state"].clone()),
+            Some(json!("paused"))
+        );
+        assert_eq!(
+            list.map(|l| l[0]["name"].clone()),
+            Some(json!("multi_file_test"))
+        );
+
+        let response = send(&session, TORRENT_RESUME, json!({ "id": id }));
+        assert!(response.error.is_none());
+        let stats = send(&session, SESSION_STATS, Value::Null).result;
+        assert_eq!(
+            stats.as_ref().map(|s| s["torrents"].clone()),
+            Some(json!(1))
+        );
+        assert_eq!(stats.map(|s| s["queued"].clone()), Some(json!(1)));
+
+        let response = send(&session, TORRENT_REMOVE, json!({ "id": id }));
+        assert!(response.error.is_none());
+        let response = send(&session, TORRENT_REMOVE, json!({ "id": id }));
+        assert_eq!(error_code(&response), UNKNOWN_TORRENT);
+        let list = send(&session, TORRENT_LIST, Value::Null).result;
+        assert_eq!(list, Some(json!([])));
+    }
+
+    #[test]
+    fn recheck_a_torrent() {
+        let session = get_session();
+        let response = send(&session, TORRENT_ADD, json!({ "source": TORRENT_PATH }));
+        let id = response.result.map(|result| result["id"].clone());
+
+        let response = send(&session, TORRENT_RECHECK, json!({ "id": id }));
+        assert_eq!(response.result, Some(json!({ "valid_pieces": 0 })));
+        let response = send(
+            &session,
+            TORRENT_RECHECK,
+            json!({ "id": "0000000000000000000000000000000000000000" }),
+        );
+        assert_eq!(error_code(&response), UNKNOWN_TORRENT);
+
+        let magnet = "magnet:?xt=urn:btih:1111111111111111111111111111111111111111&dn=no_metadata";
+        let response = send(&session, TORRENT_ADD, json!({ "source": magnet }));
+        let id = response.result.map(|result| result["id"].clone());
+        let response = send(&session, TORRENT_RECHECK, json!({ "id": id }));
+        assert_eq!(error_code(&response), MISSING_METADATA);
+    }
+
+    #[test]
+    fn add_magnet_and_invalid_torrents() {
+        let session = get_session();
+        let magnet =
+            "magnet:?xt=urn:btih:3d374e5a6ee6641280499abe5ce8098b2cdff064&dn=multi_file_test";
+        let response = send(&session, TORRENT_ADD, json!({ "source": magnet }));
+        assert!(response.error.is_none());
+        let list = send(&session, TORRENT_LIST, Value::Null).result;
+        assert_eq!(list.map(|l| l[0]["pieces"].clone()), Some(json!(0)));
+
+        let response = send(
+            &session,
+            TORRENT_ADD,
+            json!({ "source": "no_such_file.torrent" }),
+        );
+        assert_eq!(error_code(&response), INVALID_TORRENT);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::constants::CONTROL_UNIX_PREFIX;
+use crate::errors::ControlError;
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// # enum ControlAddress
+/// Address of the control API:
+///     - Tcp -> "host:port"
+///     - Unix -> "unix:<socket path>" (only on Unix systems)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlAddress {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl ControlAddress {
+    /// Parses an address. On error (empty address, or a Unix socket
+    /// on a system without them), returns ControlError::InvalidAddress.
+    pub fn parse(address: &str) -> Result<ControlAddress, ControlError> {
+        let address = address.trim();
+        match address.strip_prefix(CONTROL_UNIX_PREFIX) {
+            Some("") => Err(ControlError::InvalidAddress),
+            #[cfg(unix)]
+            Some(path) => Ok(ControlAddress::Unix(PathBuf::from(path))),
+            #[cfg(not(unix))]
+            Some(_) => Err(ControlError::InvalidAddress),
+            None if address.is_empty() => Err(ControlError::InvalidAddress),
+            None => Ok(ControlAddress::Tcp(address.to_string())),
+        }
+    }
+}
+
+/// # enum ControlStream
+/// Connection with the control API, over TCP or a Unix socket.
+#[derive(Debug)]
+pub enum ControlStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl ControlStream {
+    pub fn connect(address: &ControlAddress) -> Result<ControlStream, ControlError> {
+        match address {
+            ControlAddress::Tcp(address) => TcpStream::connect(address).map(ControlStream::Tcp),
+            #[cfg(unix)]
+            ControlAddress::Unix(path) => UnixStream::connect(path).map(ControlStream::Unix),
+        }
+        .map_err(ControlError::CannotConnect)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        match self {
+            ControlStream::Tcp(stream) => stream.set_read_timeout(Some(timeout)),
+            #[cfg(unix)]
+            ControlStream::Unix(stream) => stream.set_read_timeout(Some(timeout)),
+        }
+    }
+}
+
+impl Read for ControlStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ControlStream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            ControlStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ControlStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ControlStream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            ControlStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ControlStream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            ControlStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// # enum ControlListener
+/// Listens for connections with the control API.
+#[derive(Debug)]
+pub enum ControlListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl ControlListener {
+    /// Binds the address. A socket file left by a previous run is replaced.
+    /// The API has no authentication, so TCP addresses have to be loopback addresses
+    /// (other hosts cannot connect); otherwise, returns ControlError::NotLoopbackAddress.
+    pub fn bind(address: &ControlAddress) -> Result<ControlListener, ControlError> {
+        if let ControlAddress::Tcp(address) = address {
+            let mut addrs = address
+                .to_socket_addrs()
+                .map_err(|_| ControlError::InvalidAddress)?;
+            if !addrs.all(|addr| addr.ip().is_loopback()) {
+                return Err(ControlError::NotLoopbackAddress);
+            }
+        }
+        match address {
+            ControlAddress::Tcp(address) => TcpListener::bind(address).map(ControlListener::Tcp),
+            #[cfg(unix)]
+            ControlAddress::Unix(path) => {
+                if let Ok(metadata) = std::fs::metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        let _ = std::fs::remove_file(path);
+                    }
+                }
+                UnixListener::bind(path).map(ControlListener::Unix)
+            }
+        }
+        .map_err(ControlError::CannotBind)
+    }
+
+    pub fn accept(&self) -> io::Result<ControlStream> {
+        match self {
+            ControlListener::Tcp(listener) => listener.accept().map(|(s, _)| ControlStream::Tcp(s)),
+            #[cfg(unix)]
+            ControlListener::Unix(listener) => {
+                listener.accept().map(|(s, _)| ControlStream::Unix(s))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_control_addresses() {
+        assert_eq!(
+            ControlAddress::parse("127.0.0.1:7070").ok(),
+            Some(ControlAddress::Tcp("127.0.0.1:7070".to_string()))
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            ControlAddress::parse("unix:/tmp/albatros.sock").ok(),
+            Some(ControlAddress::Unix(PathBuf::from("/tmp/albatros.sock")))
+        );
+        assert!(ControlAddress::parse("unix:").is_err());
+        assert!(ControlAddress::parse(" ").is_err());
+    }
+
+    #[test]
+    fn only_loopback_addresses_are_bound() {
+        for address in ["0.0.0.0:0", "192.168.0.1:7070", "[::]:0"] {
+            let address = ControlAddress::Tcp(address.to_string());
+            match ControlListener::bind(&address) {
+                Err(ControlError::NotLoopbackAddress) => assert!(true),
+                _ => assert!(false),
+            }
+        }
+        let address = ControlAddress::Tcp("127.0.0.1:0".to_string());
+        assert!(ControlListener::bind(&address).is_ok());
+    }
+}
@@ -0,0 +1,617 @@
+use crate::constants::*;
+use crate::dht::krpc::*;
+use crate::dht::node_info::*;
+use crate::dht::routing_table::RoutingTable;
+use crate::dht::token_manager::TokenManager;
+use crate::errors::DhtError;
+use bencode::BencodeEncoder;
+use bencode::BencodeParser;
+use bencode::BencodeType;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Error;
+use std::net::{SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const MAX_PACKET_SIZE: usize = 65535;
+const INVALID_ARGUMENTS: &str = "Invalid arguments";
+
+type Args = HashMap<Vec<u8>, BencodeType>;
+type QueryError = (i64, &'static str);
+type PendingQueries = HashMap<Vec<u8>, (SocketAddrV4, Sender<KrpcMessage>)>;
+
+/// Result of an iterative lookup:
+///     - peers -> peers found for an info hash (only get_peers lookups)
+///     - tokens -> nodes that answered a get_peers query, with the token they gave us
+struct LookupResult {
+    peers: Vec<SocketAddrV4>,
+    tokens: Vec<(NodeInfo, Vec<u8>)>,
+}
+
+/// # struct DhtNode
+/// Our node of the Mainline DHT (BEP 5), used to find peers without a tracker.
+/// A thread listens on the UDP socket: it answers the queries of other nodes and
+/// passes the responses to the queries we are waiting for.
+///     - id -> our node id
+///     - socket
+///     - routing_table
+///     - tokens -> tokens given to the nodes that sent us a get_peers query
+///     - peer_store -> peers announced to us, by info hash
+///     - pending -> queries waiting for a response, by transaction id
+///     - next_transaction
+///     - bootstrap_nodes -> used to join the DHT when we know few nodes
+///     - cache_path -> file where our id and the known nodes are saved
+///     - running
+#[derive(Debug)]
+pub struct DhtNode {
+    id: Vec<u8>,
+    socket: UdpSocket,
+    routing_table: Mutex<RoutingTable>,
+    tokens: Mutex<TokenManager>,
+    peer_store: Mutex<HashMap<Vec<u8>, Vec<SocketAddrV4>>>,
+    pending: Mutex<PendingQueries>,
+    next_transaction: AtomicU16,
+    bootstrap_nodes: Vec<String>,
+    cache_path: Option<String>,
+    running: AtomicBool,
+}
+
+impl DhtNode {
+    /// Binds the UDP socket and starts listening.
+    /// If there is a node cache, our previous id and the cached nodes are loaded.
+    pub fn start(
+        bind_addr: &str,
+        bootstrap_nodes: Vec<String>,
+        cache_path: Option<String>,
+    ) -> Result<Arc<DhtNode>, DhtError> {
+        let socket = UdpSocket::bind(bind_addr).map_err(DhtError::CannotBindSocket)?;
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .map_err(DhtError::CannotBindSocket)?;
+
+        let (cached_id, cached_nodes) = match &cache_path {
+            Some(path) => DhtNode::load_cache(path),
+            None => (None, vec![]),
+        };
+        let id = cached_id.unwrap_or_else(random_id);
+        let mut routing_table = RoutingTable::new(id.clone());
+        for node in cached_nodes {
+            routing_table.insert(node);
+        }
+
+        let node = Arc::new(DhtNode {
+            id,
+            socket,
+            routing_table: Mutex::new(routing_table),
+            tokens: Mutex::new(TokenManager::new()),
+            peer_store: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            next_transaction: AtomicU16::new(0),
+            bootstrap_nodes,
+            cache_path,
+            running: AtomicBool::new(true),
+        });
+        let listener = node.clone();
+        thread::spawn(move || listener.listen());
+        Ok(node)
+    }
+
+    /// Looks for peers of a torrent in the DHT.
+    /// If we have a port, we announce ourselves to the closest nodes, so other peers can find us.
+    /// The known nodes are saved in the cache afterwards.
+    pub fn get_peers(&self, info_hash: &[u8], port: Option<u16>) -> Vec<SocketAddrV4> {
+        if self.number_of_nodes() < DHT_K {
+            self.bootstrap();
+        }
+
+        let mut lookup = self.lookup(info_hash, GET_PEERS);
+        if let Some(port) = port {
+            lookup
+                .tokens
+                .sort_by_key(|(node, _)| distance(&node.get_id(), info_hash));
+            let announces = lookup
+                .tokens
+                .iter()
+                .take(DHT_K)
+                .map(|(node, token)| {
+                    let mut args = HashMap::new();
+                    args.insert(
+                        b"info_hash".to_vec(),
+                        BencodeType::String(info_hash.to_vec()),
+                    );
+                    args.insert(b"port".to_vec(), BencodeType::Integer(port as i64));
+                    args.insert(b"token".to_vec(), BencodeType::String(token.clone()));
+                    args.insert(b"implied_port".to_vec(), BencodeType::Integer(0));
+                    (node.get_addr(), args)
+                })
+                .collect();
+            self.query_all(ANNOUNCE_PEER, announces);
+        }
+
+        let _ = self.save_nodes();
+        lookup.peers
+    }
+
+    /// Joins the DHT: the bootstrap nodes are pinged, and then we look for the nodes closest to us.
+    pub fn bootstrap(&self) {
+        let addrs: Vec<SocketAddrV4> = self
+            .bootstrap_nodes
+            .iter()
+            .flat_map(|node| node.to_socket_addrs())
+            .flatten()
+            .filter_map(|addr| match addr {
+                SocketAddr::V4(addr) => Some(addr),
+                SocketAddr::V6(_) => None,
+            })
+            .collect();
+        self.query_all(
+            PING,
+            addrs.into_iter().map(|a| (a, HashMap::new())).collect(),
+        );
+        self.lookup(&self.id, FIND_NODE);
+    }
+
+    /// Saves our id and the nodes of the routing table, so the next time
+    /// we can join the DHT without the bootstrap nodes.
+    pub fn save_nodes(&self) -> Result<(), Error> {
+        if let Some(path) = &self.cache_path {
+            let mut cache = HashMap::new();
+            cache.insert(b"id".to_vec(), BencodeType::String(self.id.clone()));
+            if let Ok(routing_table) = self.routing_table.lock() {
+                let nodes = NodeInfo::to_compact(&routing_table.get_nodes());
+                cache.insert(b"nodes".to_vec(), BencodeType::String(nodes));
+            }
+            fs::write(
+                path,
+                BencodeEncoder.bencode(&BencodeType::Dictionary(cache)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Stops the thread that listens on the socket.
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn get_id(&self) -> Vec<u8> {
+        self.id.clone()
+    }
+
+    pub fn get_addr(&self) -> Result<SocketAddr, Error> {
+        self.socket.local_addr()
+    }
+
+    pub fn number_of_nodes(&self) -> usize {
+        match self.routing_table.lock() {
+            Ok(routing_table) => routing_table.len(),
+            Err(_) => 0,
+        }
+    }
+
+    fn load_cache(path: &str) -> (Option<Vec<u8>>, Vec<NodeInfo>) {
+        let cache = match BencodeParser.parse_file(path) {
+            Ok(cache) => cache,
+            Err(_) => return (None, vec![]),
+        };
+        let id = cache
+            .get_value_from_dict("id")
+            .and_then(|id| id.get_string())
+            .ok()
+            .filter(|id| id.len() == ID_LENGTH);
+        let nodes = cache
+            .get_value_from_dict("nodes")
+            .and_then(|nodes| nodes.get_string())
+            .map(|nodes| NodeInfo::from_compact(&nodes))
+            .unwrap_or_default();
+        (id, nodes)
+    }
+
+    /// Iterative lookup: the closest nodes we know are asked for nodes closer to the target,
+    /// until the K closest nodes found have answered (or failed).
+    /// At most ALPHA queries are sent at the same time.
+    fn lookup(&self, target: &[u8], method: &str) -> LookupResult {
+        let key = match method {
+            GET_PEERS => "info_hash",
+            _ => "target",
+        };
+        let mut candidates = match self.routing_table.lock() {
+            Ok(routing_table) => routing_table.closest(target, DHT_K),
+            Err(_) => vec![],
+        };
+        let mut queried = HashSet::new();
+        let mut result = LookupResult {
+            peers: vec![],
+            tokens: vec![],
+        };
+
+        loop {
+            candidates.sort_by_key(|node| distance(&node.get_id(), target));
+            let batch: Vec<SocketAddrV4> = candidates
+                .iter()
+                .take(DHT_K)
+                .map(|node| node.get_addr())
+                .filter(|addr| !queried.contains(addr))
+                .take(DHT_ALPHA)
+                .collect();
+            if batch.is_empty() {
+                break;
+            }
+
+            let mut queries = vec![];
+            for addr in batch {
+                queried.insert(addr);
+                let mut args = HashMap::new();
+                args.insert(
+                    key.as_bytes().to_vec(),
+                    BencodeType::String(target.to_vec()),
+                );
+                queries.push((addr, args));
+            }
+            for (addr, values) in self.query_all(method, queries) {
+                match values {
+                    Some(values) => {
+                        self.add_lookup_values(values, addr, &mut candidates, &mut result)
+                    }
+                    None => candidates.retain(|node| node.get_addr() != addr),
+                }
+            }
+        }
+        result
+    }
+
+    /// Adds the nodes, peers and token of a response to the lookup.
+    fn add_lookup_values(
+        &self,
+        values: Args,
+        addr: SocketAddrV4,
+        candidates: &mut Vec<NodeInfo>,
+        result: &mut LookupResult,
+    ) {
+        if let Some(BencodeType::String(nodes)) = values.get("nodes".as_bytes()) {
+            for node in NodeInfo::from_compact(nodes) {
+                let known = candidates.iter().any(|c| c.get_addr() == node.get_addr());
+                if node.get_id() != self.id && !known {
+                    candidates.push(node);
+                }
+            }
+        }
+        if let Some(BencodeType::List(peers)) = values.get("values".as_bytes()) {
+            for peer in peers {
+                if let BencodeType::String(peer) = peer {
+                    match decode_compact_peer(peer) {
+                        Some(peer) if !result.peers.contains(&peer) => result.peers.push(peer),
+                        _ => (),
+                    }
+                }
+            }
+        }
+        if let (Some(BencodeType::String(id)), Some(BencodeType::String(token))) =
+            (values.get("id".as_bytes()), values.get("token".as_bytes()))
+        {
+            result
+                .tokens
+                .push((NodeInfo::new(id.clone(), addr), token.clone()));
+        }
+    }
+
+    /// Sends a query to every node and waits for the responses.
+    /// Returns the values of each response, or None if the node did not answer.
+    fn query_all(
+        &self,
+        method: &str,
+        queries: Vec<(SocketAddrV4, Args)>,
+    ) -> Vec<(SocketAddrV4, Option<Args>)> {
+        let mut waiting = vec![];
+        for (addr, mut args) in queries {
+            args.insert(b"id".to_vec(), BencodeType::String(self.id.clone()));
+            let transaction_id = self.new_transaction_id();
+            let (tx, rx) = mpsc::channel();
+            if let Ok(mut pending) = self.pending.lock() {
+                pending.insert(transaction_id.clone(), (addr, tx));
+            }
+
+            let query = KrpcMessage::Query {
+                transaction_id: transaction_id.clone(),
+                method: method.to_string(),
+                args,
+            };
+            if self.send(&query, addr).is_ok() {
+                waiting.push((addr, transaction_id, rx));
+            } else {
+                self.remove_pending(&transaction_id);
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(DHT_QUERY_TIMEOUT);
+        let mut responses = vec![];
+        for (addr, transaction_id, rx) in waiting {
+            let values = match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(KrpcMessage::Response { values, .. }) => Some(values),
+                Ok(_) => None,
+                Err(_) => {
+                    if let Ok(mut routing_table) = self.routing_table.lock() {
+                        routing_table.mark_failed(&addr);
+                    }
+                    None
+                }
+            };
+            self.remove_pending(&transaction_id);
+            responses.push((addr, values));
+        }
+        responses
+    }
+
+    fn listen(&self) {
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        while self.running.load(Ordering::Relaxed) {
+            if let Ok((size, SocketAddr::V4(addr))) = self.socket.recv_from(&mut buffer) {
+                if let Ok(msg) = KrpcMessage::from_bytes(&buffer[..size]) {
+                    self.handle_msg(msg, addr);
+                }
+            }
+        }
+    }
+
+    /// Queries are answered. Responses and errors are passed to the query that is waiting for them.
+    /// Nodes that query us or answer our queries are added to the routing table.
+    fn handle_msg(&self, msg: KrpcMessage, addr: SocketAddrV4) {
+        if let KrpcMessage::Query {
+            transaction_id,
+            method,
+            args,
+        } = msg
+        {
+            if let Some(BencodeType::String(id)) = args.get("id".as_bytes()) {
+                self.add_node(NodeInfo::new(id.clone(), addr));
+            }
+            let response = self.handle_query(transaction_id, &method, &args, addr);
+            let _ = self.send(&response, addr);
+            return;
+        }
+
+        let waiting = match self.pending.lock() {
+            Ok(mut pending) => match pending.get(&msg.get_transaction_id()) {
+                Some((query_addr, _)) if *query_addr == addr => {
+                    pending.remove(&msg.get_transaction_id())
+                }
+                _ => None,
+            },
+            Err(_) => None,
+        };
+        if let Some((_, tx)) = waiting {
+            if let Some(id) = msg.get_sender_id() {
+                self.add_node(NodeInfo::new(id, addr));
+            }
+            let _ = tx.send(msg);
+        }
+    }
+
+    fn handle_query(
+        &self,
+        transaction_id: Vec<u8>,
+        method: &str,
+        args: &Args,
+        addr: SocketAddrV4,
+    ) -> KrpcMessage {
+        let values = match method {
+            PING => Ok(HashMap::new()),
+            FIND_NODE => self.find_node_values(args),
+            GET_PEERS => self.get_peers_values(args, addr),
+            ANNOUNCE_PEER => self.announce_peer_values(args, addr),
+            _ => Err((METHOD_UNKNOWN, "Method Unknown")),
+        };
+
+        match values {
+            Ok(mut values) => {
+                values.insert(b"id".to_vec(), BencodeType::String(self.id.clone()));
+                KrpcMessage::Response {
+                    transaction_id,
+                    values,
+                }
+            }
+            Err((code, message)) => KrpcMessage::Error {
+                transaction_id,
+                code,
+                message: message.to_string(),
+            },
+        }
+    }
+
+    fn find_node_values(&self, args: &Args) -> Result<Args, QueryError> {
+        let target = get_id_arg(args, "target")?;
+        let mut values = HashMap::new();
+        values.insert(b"nodes".to_vec(), self.compact_closest(&target));
+        Ok(values)
+    }
+
+    /// Answers with the peers announced for the info hash (if any),
+    /// the closest nodes we know and a token to announce.
+    fn get_peers_values(&self, args: &Args, addr: SocketAddrV4) -> Result<Args, QueryError> {
+        let info_hash = get_id_arg(args, "info_hash")?;
+        let mut values = HashMap::new();
+        values.insert(b"nodes".to_vec(), self.compact_closest(&info_hash));
+        if let Ok(mut tokens) = self.tokens.lock() {
+            let token = tokens.generate(addr.ip());
+            values.insert(b"token".to_vec(), BencodeType::String(token));
+        }
+        if let Ok(peer_store) = self.peer_store.lock() {
+            if let Some(peers) = peer_store.get(&info_hash) {
+                let peers = peers
+                    .iter()
+                    .map(|peer| BencodeType::String(encode_compact_peer(peer)))
+                    .collect();
+                values.insert(b"values".to_vec(), BencodeType::List(peers));
+            }
+        }
+        Ok(values)
+    }
+
+    /// Stores the peer, if the token is the one we gave to its ip.
+    fn announce_peer_values(&self, args: &Args, addr: SocketAddrV4) -> Result<Args, QueryError> {
+        let info_hash = get_id_arg(args, "info_hash")?;
+        let token = match args.get("token".as_bytes()) {
+            Some(BencodeType::String(token)) => token,
+            _ => return Err((PROTOCOL_ERROR, INVALID_ARGUMENTS)),
+        };
+        let port = match (
+            args.get("implied_port".as_bytes()),
+            args.get("port".as_bytes()),
+        ) {
+            (Some(BencodeType::Integer(1)), _) => addr.port(),
+            (_, Some(BencodeType::Integer(port @ 1..=65535))) => *port as u16,
+            _ => return Err((PROTOCOL_ERROR, INVALID_ARGUMENTS)),
+        };
+        let valid_token = match self.tokens.lock() {
+            Ok(mut tokens) => tokens.is_valid(token, addr.ip()),
+            Err(_) => false,
+        };
+        if !valid_token {
+            return Err((PROTOCOL_ERROR, "Bad token"));
+        }
+
+        let peer = SocketAddrV4::new(*addr.ip(), port);
+        if let Ok(mut peer_store) = self.peer_store.lock() {
+            let peers = peer_store.entry(info_hash).or_default();
+            peers.retain(|p| *p != peer);
+            peers.push(peer);
+            if peers.len() > DHT_MAX_VALUES {
+                peers.remove(0);
+            }
+        }
+        Ok(HashMap::new())
+    }
+
+    fn compact_closest(&self, target: &[u8]) -> BencodeType {
+        let nodes = match self.routing_table.lock() {
+            Ok(routing_table) => routing_table.closest(target, DHT_K),
+            Err(_) => vec![],
+        };
+        BencodeType::String(NodeInfo::to_compact(&nodes))
+    }
+
+    fn add_node(&self, node: NodeInfo) {
+        if let Ok(mut routing_table) = self.routing_table.lock() {
+            routing_table.insert(node);
+        }
+    }
+
+    fn send(&self, msg: &KrpcMessage, addr: SocketAddrV4) -> Result<(), DhtError> {
+        self.socket
+            .send_to(&msg.to_bytes(), addr)
+            .map(|_| ())
+            .map_err(DhtError::SendingError)
+    }
+
+    fn new_transaction_id(&self) -> Vec<u8> {
+        let transaction = self.next_transaction.fetch_add(1, Ordering::Relaxed);
+        transaction.to_be_bytes().to_vec()
+    }
+
+    fn remove_pending(&self, transaction_id: &[u8]) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(transaction_id);
+        }
+    }
+}
+
+/// Gets an argument that has to be a 160-bit id (e.g. target or info_hash).
+fn get_id_arg(args: &Args, key: &str) -> Result<Vec<u8>, QueryError> {
+    match args.get(key.as_bytes()) {
+        Some(BencodeType::String(id)) if id.len() == ID_LENGTH => Ok(id.clone()),
+        _ => Err((PROTOCOL_ERROR, INVALID_ARGUMENTS)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Starts a node on loopback that joins the DHT through the bootstrap node (if any).
+    fn start_local_node(bootstrap: Option<&Arc<DhtNode>>) -> Arc<DhtNode> {
+        let bootstrap_nodes = match bootstrap {
+            Some(node) => vec![node.get_addr().expect("It shouldn't fail").to_string()],
+            None => vec![],
+        };
+        DhtNode::start("127.0.0.1:0", bootstrap_nodes, None).expect("It shouldn't fail")
+    }
+
+    fn start_local_dht(n_nodes: usize) -> Vec<Arc<DhtNode>> {
+        let first = start_local_node(None);
+        let mut nodes = vec![first.clone()];
+        for _ in 1..n_nodes {
+            let node = start_local_node(Some(&first));
+            node.bootstrap();
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    #[test]
+    fn nodes_join_the_dht() {
+        let nodes = start_local_dht(5);
+        // The first node is queried by all the others
+        assert_eq!(nodes[0].number_of_nodes(), 4);
+        assert!(nodes[4].number_of_nodes() >= 1);
+        nodes.iter().for_each(|node| node.stop());
+    }
+
+    #[test]
+    fn announced_peer_is_found_by_other_node() {
+        let nodes = start_local_dht(5);
+        let info_hash = vec![7; ID_LENGTH];
+
+        assert!(nodes[1].get_peers(&info_hash, Some(6881)).is_empty());
+        let peers = nodes[4].get_peers(&info_hash, None);
+        assert_eq!(
+            peers,
+            vec!["127.0.0.1:6881".parse().expect("It shouldn't fail")]
+        );
+        nodes.iter().for_each(|node| node.stop());
+    }
+
+    #[test]
+    fn announce_with_invalid_token_is_rejected() {
+        let node = start_local_node(None);
+        let addr = "127.0.0.1:6881".parse().expect("It shouldn't fail");
+        let mut args = HashMap::new();
+        args.insert(b"info_hash".to_vec(), BencodeType::String(vec![7; 20]));
+        args.insert(b"port".to_vec(), BencodeType::Integer(6881));
+        args.insert(b"token".to_vec(), BencodeType::String(vec![0; 8]));
+
+        assert!(node.announce_peer_values(&args, addr).is_err());
+        if let Ok(values) = node.get_peers_values(&args, addr) {
+            assert!(!values.contains_key("values".as_bytes()));
+            if let Some(BencodeType::String(token)) = values.get("token".as_bytes()) {
+                args.insert(b"token".to_vec(), BencodeType::String(token.clone()));
+            }
+        }
+        assert!(node.announce_peer_values(&args, addr).is_ok());
+        node.stop();
+    }
+
+    #[test]
+    fn node_cache_is_loaded() {
+        let path = "files_for_testing/downloaded_files/.dht_nodes_test";
+        let nodes = start_local_dht(3);
+        let bootstrap = vec![nodes[0].get_addr().expect("It shouldn't fail").to_string()];
+        let node = DhtNode::start("127.0.0.1:0", bootstrap, Some(path.to_string()))
+            .expect("It shouldn't fail");
+        assert!(node.get_peers(&[7; ID_LENGTH], None).is_empty());
+        assert_eq!(node.number_of_nodes(), 3);
+        node.stop();
+
+        let restarted = DhtNode::start("127.0.0.1:0", vec![], Some(path.to_string()))
+            .expect("It shouldn't fail");
+        assert_eq!(restarted.get_id(), node.get_id());
+        assert_eq!(restarted.number_of_nodes(), node.number_of_nodes());
+        let _ = fs::remove_file(path);
+        restarted.stop();
+        nodes.iter().for_each(|node| node.stop());
+    }
+}
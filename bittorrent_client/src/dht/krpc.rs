@@ -0,0 +1,199 @@
+use crate::errors::DhtError;
+use bencode::BencodeEncoder;
+use bencode::BencodeParser;
+use bencode::BencodeType;
+use std::collections::HashMap;
+
+pub const PING: &str = "ping";
+pub const FIND_NODE: &str = "find_node";
+pub const GET_PEERS: &str = "get_peers";
+pub const ANNOUNCE_PEER: &str = "announce_peer";
+
+pub const PROTOCOL_ERROR: i64 = 203;
+pub const METHOD_UNKNOWN: i64 = 204;
+
+/// # enum KrpcMessage
+/// Messages of the KRPC protocol used by the DHT (BEP 5).
+/// They are bencoded dictionaries sent over UDP, and every one of them has a
+/// transaction id, which is used to match a response with its query.
+///     - Query -> method name and its arguments
+///     - Response -> return values of a query
+///     - Error -> error code and message
+#[derive(Debug, Clone, PartialEq)]
+pub enum KrpcMessage {
+    Query {
+        transaction_id: Vec<u8>,
+        method: String,
+        args: HashMap<Vec<u8>, BencodeType>,
+    },
+    Response {
+        transaction_id: Vec<u8>,
+        values: HashMap<Vec<u8>, BencodeType>,
+    },
+    Error {
+        transaction_id: Vec<u8>,
+        code: i64,
+        message: String,
+    },
+}
+
+impl KrpcMessage {
+    /// Parses a bencoded KRPC message.
+    pub fn from_bytes(bytes: &[u8]) -> Result<KrpcMessage, DhtError> {
+        let msg = BencodeParser
+            .parse_vec(bytes)
+            .map_err(|_| DhtError::InvalidKrpcMessage)?;
+        let transaction_id = msg
+            .get_value_from_dict("t")
+            .and_then(|t| t.get_string())
+            .map_err(|_| DhtError::InvalidKrpcMessage)?;
+        let msg_type = msg
+            .get_value_from_dict("y")
+            .and_then(|y| y.get_string())
+            .map_err(|_| DhtError::InvalidKrpcMessage)?;
+
+        match msg_type.as_slice() {
+            b"q" => {
+                let method = msg
+                    .get_value_from_dict("q")
+                    .and_then(|q| q.get_string())
+                    .map_err(|_| DhtError::InvalidKrpcMessage)?;
+                let args = get_dict(&msg, "a")?;
+                Ok(KrpcMessage::Query {
+                    transaction_id,
+                    method: String::from_utf8_lossy(&method).to_string(),
+                    args,
+                })
+            }
+            b"r" => Ok(KrpcMessage::Response {
+                transaction_id,
+                values: get_dict(&msg, "r")?,
+            }),
+            b"e" => {
+                let error = msg
+                    .get_value_from_dict("e")
+                    .and_then(|e| e.get_list())
+                    .map_err(|_| DhtError::InvalidKrpcMessage)?;
+                match error.as_slice() {
+                    [BencodeType::Integer(code), BencodeType::String(message)] => {
+                        Ok(KrpcMessage::Error {
+                            transaction_id,
+                            code: *code,
+                            message: String::from_utf8_lossy(message).to_string(),
+                        })
+                    }
+                    _ => Err(DhtError::InvalidKrpcMessage),
+                }
+            }
+            _ => Err(DhtError::InvalidKrpcMessage),
+        }
+    }
+
+    /// Returns the bencoded message.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut dict = HashMap::new();
+        dict.insert(
+            b"t".to_vec(),
+            BencodeType::String(self.get_transaction_id()),
+        );
+        match self {
+            KrpcMessage::Query { method, args, .. } => {
+                dict.insert(b"y".to_vec(), BencodeType::String(b"q".to_vec()));
+                dict.insert(
+                    b"q".to_vec(),
+                    BencodeType::String(method.as_bytes().to_vec()),
+                );
+                dict.insert(b"a".to_vec(), BencodeType::Dictionary(args.clone()));
+            }
+            KrpcMessage::Response { values, .. } => {
+                dict.insert(b"y".to_vec(), BencodeType::String(b"r".to_vec()));
+                dict.insert(b"r".to_vec(), BencodeType::Dictionary(values.clone()));
+            }
+            KrpcMessage::Error { code, message, .. } => {
+                dict.insert(b"y".to_vec(), BencodeType::String(b"e".to_vec()));
+                let error = vec![
+                    BencodeType::Integer(*code),
+                    BencodeType::String(message.as_bytes().to_vec()),
+                ];
+                dict.insert(b"e".to_vec(), BencodeType::List(error));
+            }
+        }
+        BencodeEncoder.bencode(&BencodeType::Dictionary(dict))
+    }
+
+    pub fn get_transaction_id(&self) -> Vec<u8> {
+        match self {
+            KrpcMessage::Query { transaction_id, .. }
+            | KrpcMessage::Response { transaction_id, .. }
+            | KrpcMessage::Error { transaction_id, .. } => transaction_id.clone(),
+        }
+    }
+
+    /// Returns the node id of the sender (the 'id' key of queries and responses).
+    pub fn get_sender_id(&self) -> Option<Vec<u8>> {
+        let dict = match self {
+            KrpcMessage::Query { args, .. } => args,
+            KrpcMessage::Response { values, .. } => values,
+            KrpcMessage::Error { .. } => return None,
+        };
+        match dict.get("id".as_bytes()) {
+            Some(BencodeType::String(id)) => Some(id.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn get_dict(msg: &BencodeType, key: &str) -> Result<HashMap<Vec<u8>, BencodeType>, DhtError> {
+    match msg.get_value_from_dict(key) {
+        Ok(BencodeType::Dictionary(dict)) => Ok(dict),
+        _ => Err(DhtError::InvalidKrpcMessage),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ping_query() {
+        let bytes = "d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe".as_bytes();
+        if let Ok(msg) = KrpcMessage::from_bytes(bytes) {
+            if let KrpcMessage::Query { method, .. } = &msg {
+                assert_eq!(method, PING);
+            } else {
+                assert!(false);
+            }
+            assert_eq!(msg.get_transaction_id(), b"aa".to_vec());
+            assert_eq!(msg.get_sender_id(), Some(b"abcdefghij0123456789".to_vec()));
+            assert_eq!(msg.to_bytes(), bytes);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn error_to_bytes_and_back() {
+        let error = KrpcMessage::Error {
+            transaction_id: vec![0, 1],
+            code: PROTOCOL_ERROR,
+            message: "Bad token".to_string(),
+        };
+        assert_eq!(
+            error.to_bytes(),
+            "d1:eli203e9:Bad tokene1:t2:\x00\x011:y1:ee".as_bytes()
+        );
+        if let Ok(parsed) = KrpcMessage::from_bytes(&error.to_bytes()) {
+            assert_eq!(parsed, error);
+            assert_eq!(parsed.get_sender_id(), None);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn error_invalid_messages() {
+        assert!(KrpcMessage::from_bytes("d1:t2:aa1:y1:qe".as_bytes()).is_err());
+        assert!(KrpcMessage::from_bytes("d1:rde1:y1:re".as_bytes()).is_err());
+        assert!(KrpcMessage::from_bytes("li1ee".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod dht_node;
+pub mod krpc;
+pub mod node_info;
+pub mod routing_table;
+pub mod token_manager;
@@ -0,0 +1,162 @@
+use crate::constants::{DHT_MAX_FAILED_QUERIES, DHT_QUESTIONABLE_NODE};
+use sha1::{Digest, Sha1};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub const ID_LENGTH: usize = 20;
+pub const COMPACT_NODE_LENGTH: usize = 26;
+pub const COMPACT_PEER_LENGTH: usize = 6;
+
+static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// # struct NodeInfo
+/// Represents another node of the DHT.
+///     - id -> 160-bit node id
+///     - addr -> UDP address of the node
+///     - last_seen -> last time the node answered us or sent us a query
+///     - failed_queries -> number of consecutive queries the node did not answer
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    id: Vec<u8>,
+    addr: SocketAddrV4,
+    last_seen: Instant,
+    failed_queries: u32,
+}
+
+impl NodeInfo {
+    pub fn new(id: Vec<u8>, addr: SocketAddrV4) -> NodeInfo {
+        NodeInfo {
+            id,
+            addr,
+            last_seen: Instant::now(),
+            failed_queries: 0,
+        }
+    }
+
+    /// Parses a list of nodes in compact format (20 bytes of id, 4 of ip and 2 of port).
+    pub fn from_compact(nodes: &[u8]) -> Vec<NodeInfo> {
+        nodes
+            .chunks_exact(COMPACT_NODE_LENGTH)
+            .flat_map(|node| {
+                decode_compact_peer(&node[ID_LENGTH..])
+                    .map(|addr| NodeInfo::new(node[..ID_LENGTH].to_vec(), addr))
+            })
+            .collect()
+    }
+
+    /// Returns the nodes in compact format.
+    pub fn to_compact(nodes: &[NodeInfo]) -> Vec<u8> {
+        let mut compact = vec![];
+        for node in nodes {
+            compact.extend(&node.id);
+            compact.extend(encode_compact_peer(&node.addr));
+        }
+        compact
+    }
+
+    /// A node is good while it answers and has been seen in the last 15 minutes.
+    pub fn is_good(&self) -> bool {
+        self.failed_queries < DHT_MAX_FAILED_QUERIES
+            && self.last_seen.elapsed().as_secs() < DHT_QUESTIONABLE_NODE
+    }
+
+    /// The node answered us, so it is good again.
+    pub fn refresh(&mut self, addr: SocketAddrV4) {
+        self.addr = addr;
+        self.last_seen = Instant::now();
+        self.failed_queries = 0;
+    }
+
+    pub fn add_failed_query(&mut self) {
+        self.failed_queries += 1;
+    }
+
+    pub fn get_id(&self) -> Vec<u8> {
+        self.id.clone()
+    }
+
+    pub fn get_addr(&self) -> SocketAddrV4 {
+        self.addr
+    }
+
+    pub fn get_failed_queries(&self) -> u32 {
+        self.failed_queries
+    }
+}
+
+/// Returns the XOR distance between two ids.
+/// Distances can be compared as big endian numbers (i.e. lexicographically).
+pub fn distance(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b.iter()).map(|(x, y)| x ^ y).collect()
+}
+
+/// Generates a random 160-bit id, used for our node id and for token secrets.
+pub fn random_id() -> Vec<u8> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or_default();
+    let mut hasher = Sha1::new();
+    hasher.update(nanos.to_be_bytes());
+    hasher.update(std::process::id().to_be_bytes());
+    hasher.update(ID_COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// Returns the address in compact format (4 bytes of ip and 2 of port).
+pub fn encode_compact_peer(addr: &SocketAddrV4) -> Vec<u8> {
+    let mut compact = addr.ip().octets().to_vec();
+    compact.extend(addr.port().to_be_bytes());
+    compact
+}
+
+/// Parses an address in compact format.
+pub fn decode_compact_peer(compact: &[u8]) -> Option<SocketAddrV4> {
+    if compact.len() != COMPACT_PEER_LENGTH {
+        return None;
+    }
+    let ip = Ipv4Addr::new(compact[0], compact[1], compact[2], compact[3]);
+    let port = u16::from_be_bytes([compact[4], compact[5]]);
+    Some(SocketAddrV4::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nodes_to_compact_and_back() {
+        let nodes = vec![
+            NodeInfo::new(
+                vec![1; 20],
+                "127.0.0.1:6881".parse().expect("It shouldn't fail"),
+            ),
+            NodeInfo::new(
+                vec![2; 20],
+                "10.0.0.2:51413".parse().expect("It shouldn't fail"),
+            ),
+        ];
+        let compact = NodeInfo::to_compact(&nodes);
+        assert_eq!(compact.len(), 2 * COMPACT_NODE_LENGTH);
+
+        let parsed = NodeInfo::from_compact(&compact);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].get_id(), vec![2; 20]);
+        assert_eq!(parsed[1].get_addr(), nodes[1].get_addr());
+    }
+
+    #[test]
+    fn distance_is_xor() {
+        let a = vec![0b1010, 0xff];
+        let b = vec![0b0110, 0xff];
+        assert_eq!(distance(&a, &b), vec![0b1100, 0]);
+        assert_eq!(distance(&a, &a), vec![0, 0]);
+    }
+
+    #[test]
+    fn random_ids_are_different() {
+        assert_ne!(random_id(), random_id());
+        assert_eq!(random_id().len(), ID_LENGTH);
+    }
+}
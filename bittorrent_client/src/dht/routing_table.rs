@@ -0,0 +1,152 @@
+use crate::constants::DHT_K;
+use crate::dht::node_info::{distance, NodeInfo, ID_LENGTH};
+use std::net::SocketAddrV4;
+
+/// # struct RoutingTable
+/// Kademlia routing table of our DHT node.
+/// There is one bucket per bit of the id: the bucket i contains the nodes whose
+/// id shares exactly i leading bits with ours, so we know many nodes close to us
+/// and only a few far away. Every bucket holds at most K nodes.
+///     - own_id -> id of our node
+///     - buckets
+#[derive(Debug)]
+pub struct RoutingTable {
+    own_id: Vec<u8>,
+    buckets: Vec<Vec<NodeInfo>>,
+}
+
+impl RoutingTable {
+    pub fn new(own_id: Vec<u8>) -> RoutingTable {
+        RoutingTable {
+            own_id,
+            buckets: vec![vec![]; ID_LENGTH * 8],
+        }
+    }
+
+    /// Adds a node that answered us (or queried us).
+    /// If the node is already in the table, it is refreshed.
+    /// If its bucket is full, it replaces a bad node. If there is not any, the new node is discarded.
+    /// Returns true if the node is in the table.
+    pub fn insert(&mut self, node: NodeInfo) -> bool {
+        let idx = match self.bucket_idx(&node.get_id()) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let bucket = &mut self.buckets[idx];
+
+        if let Some(pos) = bucket.iter().position(|n| n.get_id() == node.get_id()) {
+            let mut known = bucket.remove(pos);
+            known.refresh(node.get_addr());
+            bucket.push(known);
+            return true;
+        }
+        if bucket.len() >= DHT_K {
+            match bucket.iter().position(|n| !n.is_good()) {
+                Some(pos) => {
+                    bucket.remove(pos);
+                }
+                None => return false,
+            }
+        }
+        bucket.push(node);
+        true
+    }
+
+    /// The node at the address did not answer a query.
+    /// After too many failures, it is removed.
+    pub fn mark_failed(&mut self, addr: &SocketAddrV4) {
+        for bucket in self.buckets.iter_mut() {
+            if let Some(node) = bucket.iter_mut().find(|n| n.get_addr() == *addr) {
+                node.add_failed_query();
+            }
+            bucket.retain(|n| n.get_addr() != *addr || n.is_good());
+        }
+    }
+
+    /// Returns the n nodes closest to the target, sorted by distance.
+    pub fn closest(&self, target: &[u8], n: usize) -> Vec<NodeInfo> {
+        let mut nodes = self.get_nodes();
+        nodes.sort_by_key(|node| distance(&node.get_id(), target));
+        nodes.truncate(n);
+        nodes
+    }
+
+    pub fn get_nodes(&self) -> Vec<NodeInfo> {
+        self.buckets.iter().flatten().cloned().collect()
+    }
+
+    pub fn get_own_id(&self) -> Vec<u8> {
+        self.own_id.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of leading bits that the id shares with ours.
+    /// Our own id and invalid ids have not any bucket.
+    fn bucket_idx(&self, id: &[u8]) -> Option<usize> {
+        if id.len() != ID_LENGTH {
+            return None;
+        }
+        let dist = distance(&self.own_id, id);
+        let byte = dist.iter().position(|b| *b != 0)?;
+        Some(byte * 8 + dist[byte].leading_zeros() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::DHT_MAX_FAILED_QUERIES;
+
+    fn addr(port: u16) -> SocketAddrV4 {
+        SocketAddrV4::new("127.0.0.1".parse().expect("It shouldn't fail"), port)
+    }
+
+    fn id_with_first_byte(byte: u8, last: u8) -> Vec<u8> {
+        let mut id = vec![0; ID_LENGTH];
+        id[0] = byte;
+        id[ID_LENGTH - 1] = last;
+        id
+    }
+
+    #[test]
+    fn closest_nodes_are_sorted_by_distance() {
+        let mut table = RoutingTable::new(vec![0; ID_LENGTH]);
+        assert!(table.insert(NodeInfo::new(id_with_first_byte(0x80, 0), addr(1))));
+        assert!(table.insert(NodeInfo::new(id_with_first_byte(0x10, 0), addr(2))));
+        assert!(table.insert(NodeInfo::new(id_with_first_byte(0x01, 0), addr(3))));
+        // Our own id is not added
+        assert!(!table.insert(NodeInfo::new(vec![0; ID_LENGTH], addr(4))));
+
+        let closest = table.closest(&id_with_first_byte(0x11, 0), 2);
+        assert_eq!(closest.len(), 2);
+        assert_eq!(closest[0].get_addr(), addr(2));
+        assert_eq!(closest[1].get_addr(), addr(3));
+    }
+
+    #[test]
+    fn full_bucket_only_accepts_nodes_replacing_bad_ones() {
+        let mut table = RoutingTable::new(vec![0; ID_LENGTH]);
+        // All of them share 0 bits with our id
+        for i in 0..DHT_K {
+            assert!(table.insert(NodeInfo::new(
+                id_with_first_byte(0x80, i as u8),
+                addr(i as u16)
+            )));
+        }
+        assert!(!table.insert(NodeInfo::new(id_with_first_byte(0x80, 100), addr(100))));
+
+        for _ in 0..DHT_MAX_FAILED_QUERIES {
+            table.mark_failed(&addr(0));
+        }
+        assert_eq!(table.len(), DHT_K - 1);
+        assert!(table.insert(NodeInfo::new(id_with_first_byte(0x80, 100), addr(100))));
+        assert_eq!(table.len(), DHT_K);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::constants::DHT_TOKEN_ROTATION;
+use crate::dht::node_info::random_id;
+use sha1::{Digest, Sha1};
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+const TOKEN_LENGTH: usize = 8;
+
+/// # struct TokenManager
+/// Generates the tokens sent in the get_peers responses.
+/// A token is the hash of the ip of the querying node and a secret, so only that node
+/// can use it to announce itself. The secret changes every five minutes, and tokens
+/// made with the previous secret are still accepted.
+///     - secret
+///     - previous_secret
+///     - last_rotation
+#[derive(Debug)]
+pub struct TokenManager {
+    secret: Vec<u8>,
+    previous_secret: Vec<u8>,
+    last_rotation: Instant,
+}
+
+impl TokenManager {
+    pub fn new() -> TokenManager {
+        let secret = random_id();
+        TokenManager {
+            previous_secret: secret.clone(),
+            secret,
+            last_rotation: Instant::now(),
+        }
+    }
+
+    /// Returns the token for a node.
+    pub fn generate(&mut self, ip: &Ipv4Addr) -> Vec<u8> {
+        self.rotate();
+        TokenManager::hash(ip, &self.secret)
+    }
+
+    /// Checks if the token was given by us to a node with this ip.
+    pub fn is_valid(&mut self, token: &[u8], ip: &Ipv4Addr) -> bool {
+        self.rotate();
+        token == TokenManager::hash(ip, &self.secret)
+            || token == TokenManager::hash(ip, &self.previous_secret)
+    }
+
+    fn rotate(&mut self) {
+        if self.last_rotation.elapsed().as_secs() >= DHT_TOKEN_ROTATION {
+            self.previous_secret = std::mem::replace(&mut self.secret, random_id());
+            self.last_rotation = Instant::now();
+        }
+    }
+
+    fn hash(ip: &Ipv4Addr, secret: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        hasher.update(ip.octets());
+        hasher.update(secret);
+        hasher.finalize()[..TOKEN_LENGTH].to_vec()
+    }
+}
+
+impl Default for TokenManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_is_only_valid_for_its_ip() {
+        let mut tokens = TokenManager::new();
+        let ip = Ipv4Addr::new(10, 0, 0, 1);
+        let token = tokens.generate(&ip);
+
+        assert!(tokens.is_valid(&token, &ip));
+        assert!(!tokens.is_valid(&token, &Ipv4Addr::new(10, 0, 0, 2)));
+        assert!(!tokens.is_valid(&[0; TOKEN_LENGTH], &ip));
+    }
+}
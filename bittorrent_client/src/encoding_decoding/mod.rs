@@ -1,2 +1 @@
-pub mod bencode_parser;
 pub mod encoder;
@@ -1,7 +1,7 @@
+pub use bencode::ParseError;
+use std::fmt;
 use std::io::Error;
-use std::num::ParseIntError;
 use std::process::exit;
-use std::string::FromUtf8Error;
 
 pub trait ErrorMessage {
     fn print_error(&self);
@@ -15,17 +15,6 @@ pub enum TypeError {
     IsNotList,
 }
 
-#[derive(Debug)]
-pub enum ParseError {
-    EmptyFilePath,
-    NoSuchFile(Error),
-    InvalidFormat,
-    EmptyVector,
-    ReadingFileError(Error),
-    IntConvertionError(ParseIntError),
-    StrConvertionError(FromUtf8Error),
-}
-
 #[derive(Debug)]
 pub enum ArgsError {
     InvalidNumberOfArguments,
@@ -33,7 +22,6 @@ pub enum ArgsError {
     NoSuchSettingsFile,
     InvalidSettings,
     NoTorrentDir,
-    CannotConnectToGUI,
 }
 impl ErrorMessage for ArgsError {
     fn print_error(&self) {
@@ -46,7 +34,6 @@ impl ErrorMessage for ArgsError {
                 println!("ERROR: Settings file is in invalid format!")
             }
             ArgsError::NoTorrentDir => println!("ERROR: Cannot find the torrents directory!"),
-            ArgsError::CannotConnectToGUI => println!("ERROR: Cannot connect to GUI!"),
             ArgsError::InvalidNumberOfArguments => println!("ERROR: Invalid number of arguments!"),
         }
     }
@@ -64,6 +51,7 @@ pub enum MessageError {
     SendingError(Error),
     CreationError,
     UnknownMessage,
+    InvalidExtensionMsg,
 }
 
 #[derive(Debug)]
@@ -86,10 +74,13 @@ pub enum DownloadError {
     PeerHasNotThePiece,
     PeerChokedUs,
     InvalidPiece,
+    PieceAlreadyDownloaded,
     CannotReadPeerMessage,
     ConnectionFinished,
     NoPeers,
     HandshakeError,
+    MetadataNotSupported,
+    InvalidMetadata,
 }
 
 #[derive(Debug)]
@@ -108,6 +99,8 @@ pub enum ClientError {
     StoringPieceError,
     DownloadError,
     JoiningThreadsError,
+    CannotFetchMetadata,
+    TorrentStopped,
 }
 
 impl ClientError {
@@ -131,17 +124,120 @@ impl ClientError {
             ClientError::JoiningThreadsError => {
                 println!("ERROR: Cannot join all connection threads successfully")
             }
+            ClientError::CannotFetchMetadata => {
+                println!("ERROR: Cannot get the torrent metadata from any peer!")
+            }
             _ => (),
         }
     }
 }
 
+#[derive(Debug)]
+pub enum DhtError {
+    CannotBindSocket(Error),
+    SendingError(Error),
+    InvalidKrpcMessage,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    CannotCreateFile(Error),
+    CannotWritePiece(Error),
+    CannotReadPiece(Error),
+    CannotRemoveFile(Error),
+    InvalidPieceLength,
+}
+
+#[derive(Debug)]
+pub enum CreatorError {
+    NoSuchPath,
+    InvalidPieceLength,
+    EmptyTorrent,
+    CannotReadFile(Error),
+    CannotWriteTorrent(Error),
+}
+
+impl ErrorMessage for CreatorError {
+    fn print_error(&self) {
+        match self {
+            CreatorError::NoSuchPath => println!("ERROR: No such file or directory!"),
+            CreatorError::InvalidPieceLength => {
+                println!("ERROR: The piece length must be a power of two of at least 16 KiB!")
+            }
+            CreatorError::EmptyTorrent => println!("ERROR: There is not any data to share!"),
+            CreatorError::CannotReadFile(_) => println!("ERROR: Cannot read the files to share!"),
+            CreatorError::CannotWriteTorrent(_) => {
+                println!("ERROR: Cannot write the torrent file!")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    InvalidTorrent,
+    TorrentAlreadyAdded,
+    UnknownTorrent,
+    CannotRemoveData(StorageError),
+    MissingMetadata,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::InvalidTorrent => write!(f, "Invalid torrent file or magnet link"),
+            SessionError::TorrentAlreadyAdded => write!(f, "The torrent was already added"),
+            SessionError::UnknownTorrent => write!(f, "There is not any torrent with that id"),
+            SessionError::CannotRemoveData(_) => write!(f, "Cannot remove the downloaded data"),
+            SessionError::MissingMetadata => {
+                write!(f, "The metadata of the torrent was not received yet")
+            }
+        }
+    }
+}
+
+impl ErrorMessage for SessionError {
+    fn print_error(&self) {
+        println!("ERROR: {}!", self);
+    }
+}
+
+#[derive(Debug)]
+pub enum ControlError {
+    InvalidAddress,
+    NotLoopbackAddress,
+    CannotBind(Error),
+    CannotConnect(Error),
+    InvalidHttpMessage,
+    InvalidResponse,
+}
+
+impl ErrorMessage for ControlError {
+    fn print_error(&self) {
+        match self {
+            ControlError::InvalidAddress => println!("ERROR: Invalid control address!"),
+            ControlError::NotLoopbackAddress => {
+                println!("ERROR: The control API only listens on loopback addresses!")
+            }
+            ControlError::CannotBind(_) => println!("ERROR: Cannot listen on the control address!"),
+            ControlError::CannotConnect(_) => {
+                println!("ERROR: Cannot connect to the client, is it running?")
+            }
+            ControlError::InvalidHttpMessage => println!("ERROR: Invalid HTTP message!"),
+            ControlError::InvalidResponse => {
+                println!("ERROR: The response of the client is invalid!")
+            }
+        }
+    }
+}
+
 pub enum ServerError {
     HandshakeError,
     CannotFindTorrent,
     CannotReadPeerMessage,
     NoSuchDirectory,
     PieceError,
+    ProtocolError,
 }
 
 pub trait HandleError<T> {
@@ -1,15 +1,150 @@
 use crate::bt_client::peer::Peer;
-use crate::piece::Piece;
-use crate::torrent_info::TorrentInfo;
+use crate::torrent_info::{TorrentId, TorrentInfo};
 
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// # enum NewEvent
+/// Events of the torrents of a session. Every event has the id of its torrent.
+#[derive(Debug, Clone)]
 pub enum NewEvent {
-    NewTorrent(TorrentInfo, u32, String),
-    DownloadingTorrent(String),
-    TorrentDownloadFailed(String),
-    NewConnection(String, Peer),
-    ConnectionDropped(String, Peer),
-    NewDownloadedPiece(String, Piece, Peer),
-    CannotDownloadPiece(Piece),
-    NumberOfPeers(String, u32),
-    OurStatus(String, Peer),
+    /// A torrent was found, with its number of downloaded pieces
+    NewTorrent(TorrentId, TorrentInfo, u32),
+    /// The metadata of a torrent created from a magnet link was received
+    MetadataReceived(TorrentId, TorrentInfo, u32),
+    DownloadingTorrent(TorrentId),
+    TorrentDownloaded(TorrentId),
+    TorrentDownloadFailed(TorrentId),
+    /// The torrent was paused, so it is neither downloaded nor shared
+    TorrentPaused(TorrentId),
+    TorrentRemoved(TorrentId),
+    NewConnection(TorrentId, Peer),
+    ConnectionDropped(TorrentId, Peer),
+    /// A piece was downloaded from a peer: index and length of the piece
+    NewDownloadedPiece(TorrentId, u32, u32, Peer),
+    /// Number of peers found for the torrent
+    NumberOfPeers(TorrentId, u32),
+    /// Our status (choked / interested) in the connection with a peer
+    OurStatus(TorrentId, String, Peer),
+}
+
+impl NewEvent {
+    pub fn get_torrent_id(&self) -> TorrentId {
+        match self {
+            NewEvent::NewTorrent(id, ..)
+            | NewEvent::MetadataReceived(id, ..)
+            | NewEvent::DownloadingTorrent(id)
+            | NewEvent::TorrentDownloaded(id)
+            | NewEvent::TorrentDownloadFailed(id)
+            | NewEvent::TorrentPaused(id)
+            | NewEvent::TorrentRemoved(id)
+            | NewEvent::NewConnection(id, _)
+            | NewEvent::ConnectionDropped(id, _)
+            | NewEvent::NewDownloadedPiece(id, ..)
+            | NewEvent::NumberOfPeers(id, _)
+            | NewEvent::OurStatus(id, ..) => *id,
+        }
+    }
+}
+
+/// # trait EventSubscriber
+/// Consumer of the events of a session (e.g. a frontend).
+pub trait EventSubscriber: Send {
+    /// Receives an event. Returns false if the subscriber doesn't want more events,
+    /// so it is removed.
+    fn notify(&self, event: NewEvent) -> bool;
+}
+
+impl EventSubscriber for Sender<NewEvent> {
+    fn notify(&self, event: NewEvent) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+/// # struct EventBus
+/// Publishes the events of a session to all its subscribers.
+/// It can be cloned, and all clones share the subscribers.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Box<dyn EventSubscriber>>>>,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    /// Subscribes a new channel, which receives all events published from now on.
+    pub fn subscribe(&self) -> Receiver<NewEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.add_subscriber(Box::new(tx));
+        rx
+    }
+
+    pub fn add_subscriber(&self, subscriber: Box<dyn EventSubscriber>) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(subscriber);
+        }
+    }
+
+    /// Sends the event to every subscriber. The subscribers that are closed are removed.
+    pub fn publish(&self, event: NewEvent) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| subscriber.notify(event.clone()));
+        }
+    }
+
+    pub fn number_of_subscribers(&self) -> usize {
+        match self.subscribers.lock() {
+            Ok(subscribers) => subscribers.len(),
+            Err(_) => 0,
+        }
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &self.number_of_subscribers())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_torrent_id() -> TorrentId {
+        TorrentId::from_info_hash(&[7u8; 20])
+    }
+
+    #[test]
+    fn all_subscribers_receive_the_events() {
+        let bus = EventBus::new();
+        let rx_1 = bus.subscribe();
+        let rx_2 = bus.clone().subscribe();
+
+        bus.publish(NewEvent::DownloadingTorrent(get_torrent_id()));
+
+        for rx in [rx_1, rx_2] {
+            if let Ok(NewEvent::DownloadingTorrent(id)) = rx.try_recv() {
+                assert_eq!(id, get_torrent_id());
+            } else {
+                assert!(false);
+            }
+        }
+    }
+
+    #[test]
+    fn closed_subscribers_are_removed() {
+        let bus = EventBus::new();
+        let rx_1 = bus.subscribe();
+        let rx_2 = bus.subscribe();
+        drop(rx_2);
+
+        bus.publish(NewEvent::NumberOfPeers(get_torrent_id(), 5));
+        assert_eq!(bus.number_of_subscribers(), 1);
+        assert_eq!(rx_1.try_iter().count(), 1);
+    }
 }
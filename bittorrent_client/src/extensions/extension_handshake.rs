@@ -0,0 +1,179 @@
+use crate::constants::{CLIENT_VERSION, REQUEST_QUEUE_LENGTH};
+use crate::errors::MessageError;
+use bencode::BencodeEncoder;
+use bencode::BencodeParser;
+use bencode::BencodeType;
+
+use std::collections::HashMap;
+
+/// # struct ExtensionHandshake
+/// Payload of the extension handshake (BEP 10). It is sent as an extended message with id 0.
+///     - m -> extension names and the ids the sender assigned to them
+///     - v -> client name and version
+///     - reqq -> number of outstanding requests the sender supports
+///     - p -> listening port of the sender
+///     - others -> other keys, defined by the extensions (e.g. metadata_size)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtensionHandshake {
+    m: HashMap<String, u8>,
+    v: Option<String>,
+    reqq: Option<u32>,
+    p: Option<u16>,
+    others: HashMap<Vec<u8>, BencodeType>,
+}
+
+impl ExtensionHandshake {
+    /// Creates our extension handshake, without any extension.
+    pub fn new(port: Option<u16>) -> ExtensionHandshake {
+        ExtensionHandshake {
+            m: HashMap::new(),
+            v: Some(CLIENT_VERSION.to_string()),
+            reqq: Some(REQUEST_QUEUE_LENGTH),
+            p: port,
+            others: HashMap::new(),
+        }
+    }
+
+    /// Parses the bencoded payload of an extension handshake.
+    /// Extensions with id 0 are disabled by the sender, so they are not added.
+    pub fn from_bytes(payload: &[u8]) -> Result<ExtensionHandshake, MessageError> {
+        let dict = match BencodeParser.parse_vec(payload) {
+            Ok(BencodeType::Dictionary(dict)) => dict,
+            _ => return Err(MessageError::CreationError),
+        };
+
+        let mut handshake = ExtensionHandshake {
+            m: HashMap::new(),
+            v: None,
+            reqq: None,
+            p: None,
+            others: HashMap::new(),
+        };
+        for (key, value) in dict {
+            match (key.as_slice(), value) {
+                (b"m", BencodeType::Dictionary(m)) => {
+                    for (name, id) in m {
+                        if let BencodeType::Integer(id @ 1..=255) = id {
+                            handshake
+                                .m
+                                .insert(String::from_utf8_lossy(&name).to_string(), id as u8);
+                        }
+                    }
+                }
+                (b"v", BencodeType::String(v)) => {
+                    handshake.v = Some(String::from_utf8_lossy(&v).to_string())
+                }
+                (b"reqq", BencodeType::Integer(reqq)) if reqq > 0 => {
+                    handshake.reqq = Some(u32::try_from(reqq).unwrap_or(u32::MAX))
+                }
+                (b"p", BencodeType::Integer(p @ 1..=65535)) => handshake.p = Some(p as u16),
+                (_, value) => {
+                    handshake.others.insert(key, value);
+                }
+            }
+        }
+        Ok(handshake)
+    }
+
+    /// Returns the bencoded handshake.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut m = HashMap::new();
+        for (name, id) in &self.m {
+            m.insert(name.as_bytes().to_vec(), BencodeType::Integer(*id as i64));
+        }
+
+        let mut dict = self.others.clone();
+        dict.insert(b"m".to_vec(), BencodeType::Dictionary(m));
+        if let Some(v) = &self.v {
+            dict.insert(b"v".to_vec(), BencodeType::String(v.as_bytes().to_vec()));
+        }
+        if let Some(reqq) = self.reqq {
+            dict.insert(b"reqq".to_vec(), BencodeType::Integer(reqq as i64));
+        }
+        if let Some(p) = self.p {
+            dict.insert(b"p".to_vec(), BencodeType::Integer(p as i64));
+        }
+        BencodeEncoder.bencode(&BencodeType::Dictionary(dict))
+    }
+
+    /// Adds an extension with the id we assigned to it.
+    pub fn add_extension(&mut self, name: &str, id: u8) {
+        self.m.insert(name.to_string(), id);
+    }
+
+    /// Returns the id that the sender assigned to an extension, if it supports it.
+    pub fn get_extension_id(&self, name: &str) -> Option<u8> {
+        self.m.get(name).copied()
+    }
+
+    pub fn get_version(&self) -> Option<String> {
+        self.v.clone()
+    }
+
+    pub fn get_reqq(&self) -> Option<u32> {
+        self.reqq
+    }
+
+    pub fn get_port(&self) -> Option<u16> {
+        self.p
+    }
+
+    /// Returns the value of a key defined by an extension.
+    pub fn get_value(&self, key: &str) -> Option<BencodeType> {
+        self.others.get(key.as_bytes()).cloned()
+    }
+
+    /// Adds a key defined by an extension.
+    pub fn insert_value(&mut self, key: &str, value: BencodeType) {
+        self.others.insert(key.as_bytes().to_vec(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extension_handshake_to_bytes_and_back() {
+        let mut handshake = ExtensionHandshake::new(Some(6881));
+        handshake.add_extension("ut_metadata", 1);
+        handshake.add_extension("ut_pex", 2);
+        handshake.insert_value("metadata_size", BencodeType::Integer(31235));
+
+        if let Ok(parsed) = ExtensionHandshake::from_bytes(&handshake.to_bytes()) {
+            assert_eq!(parsed, handshake);
+            assert_eq!(parsed.get_extension_id("ut_pex"), Some(2));
+            assert_eq!(parsed.get_port(), Some(6881));
+            assert_eq!(parsed.get_reqq(), Some(REQUEST_QUEUE_LENGTH));
+            assert_eq!(parsed.get_version(), Some(CLIENT_VERSION.to_string()));
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn disabled_extensions_are_ignored() {
+        let payload = "d1:md11:ut_metadatai0e6:ut_pexi3ee1:v4:teste".as_bytes();
+        if let Ok(handshake) = ExtensionHandshake::from_bytes(payload) {
+            assert_eq!(handshake.get_extension_id("ut_metadata"), None);
+            assert_eq!(handshake.get_extension_id("ut_pex"), Some(3));
+            assert_eq!(handshake.get_version(), Some("test".to_string()));
+            assert_eq!(handshake.get_reqq(), None);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn huge_reqq_is_saturated() {
+        let payload = format!("d4:reqqi{}ee", i64::MAX);
+        let handshake =
+            ExtensionHandshake::from_bytes(payload.as_bytes()).expect("It shouldn't fail");
+        assert_eq!(handshake.get_reqq(), Some(u32::MAX));
+    }
+
+    #[test]
+    fn error_extension_handshake_is_not_a_dictionary() {
+        assert!(ExtensionHandshake::from_bytes("li1ei2ee".as_bytes()).is_err());
+    }
+}
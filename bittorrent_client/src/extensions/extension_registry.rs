@@ -0,0 +1,186 @@
+use crate::errors::MessageError;
+use crate::extensions::extension_handshake::ExtensionHandshake;
+use crate::extensions::extension_trait::Extension;
+use crate::p2p_messages::extended::ExtendedMsg;
+
+const HANDSHAKE_ID: u8 = 0;
+
+/// # struct ExtensionRegistry
+/// Contains the extensions enabled in a connection.
+/// Our id for each extension is its position in the registry plus one,
+/// and the ids of the peer are taken from its extension handshake.
+///     - extensions
+///     - peer_handshake -> extension handshake of the peer (if it was received)
+///     - port -> our listening port, announced in the handshake
+#[derive(Debug)]
+pub struct ExtensionRegistry {
+    extensions: Vec<Box<dyn Extension>>,
+    peer_handshake: Option<ExtensionHandshake>,
+    port: Option<u16>,
+}
+
+impl ExtensionRegistry {
+    pub fn new(port: Option<u16>) -> ExtensionRegistry {
+        ExtensionRegistry {
+            extensions: vec![],
+            peer_handshake: None,
+            port,
+        }
+    }
+
+    pub fn register(&mut self, extension: Box<dyn Extension>) {
+        self.extensions.push(extension);
+    }
+
+    /// Returns our extension handshake as an extended message.
+    pub fn handshake_msg(&self) -> ExtendedMsg {
+        let mut handshake = ExtensionHandshake::new(self.port);
+        for (idx, extension) in self.extensions.iter().enumerate() {
+            handshake.add_extension(extension.name(), (idx + 1) as u8);
+            extension.extend_handshake(&mut handshake);
+        }
+        ExtendedMsg::new(HANDSHAKE_ID, handshake.to_bytes())
+    }
+
+    /// Handles an extended message sent by the peer.
+    /// The extension handshake is stored and every extension is notified.
+    /// Other messages are passed to the extension they belong to.
+    /// Returns the messages that have to be sent as response.
+    pub fn handle_msg(&mut self, msg: ExtendedMsg) -> Result<Vec<ExtendedMsg>, MessageError> {
+        if msg.get_ext_id() == HANDSHAKE_ID {
+            let handshake = ExtensionHandshake::from_bytes(&msg.get_payload())?;
+            for extension in self.extensions.iter_mut() {
+                extension.on_handshake(&handshake);
+            }
+            self.peer_handshake = Some(handshake);
+            return Ok(vec![]);
+        }
+
+        let idx = (msg.get_ext_id() - 1) as usize;
+        if let Some(extension) = self.extensions.get_mut(idx) {
+            let responses = extension.handle_msg(&msg.get_payload())?;
+            let name = extension.name().to_string();
+            return Ok(self.build_msgs(&name, responses));
+        }
+        Ok(vec![])
+    }
+
+    /// Returns the messages that the extensions want to send.
+    /// Nothing is sent before the extension handshake of the peer is received.
+    pub fn poll(&mut self) -> Vec<ExtendedMsg> {
+        let mut msgs = vec![];
+        if self.peer_handshake.is_none() {
+            return msgs;
+        }
+
+        for idx in 0..self.extensions.len() {
+            let name = self.extensions[idx].name().to_string();
+            if self.peer_supports(&name) {
+                let payloads = self.extensions[idx].poll();
+                msgs.extend(self.build_msgs(&name, payloads));
+            }
+        }
+        msgs
+    }
+
+    /// Checks if the peer enabled an extension in its handshake.
+    pub fn peer_supports(&self, name: &str) -> bool {
+        self.peer_extension_id(name).is_some()
+    }
+
+    pub fn get_peer_handshake(&self) -> Option<ExtensionHandshake> {
+        self.peer_handshake.clone()
+    }
+
+    fn peer_extension_id(&self, name: &str) -> Option<u8> {
+        self.peer_handshake
+            .as_ref()
+            .and_then(|handshake| handshake.get_extension_id(name))
+    }
+
+    /// Builds the messages of an extension using the id the peer assigned to it.
+    fn build_msgs(&self, name: &str, payloads: Vec<Vec<u8>>) -> Vec<ExtendedMsg> {
+        match self.peer_extension_id(name) {
+            Some(ext_id) => payloads
+                .into_iter()
+                .map(|payload| ExtendedMsg::new(ext_id, payload))
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every message with the same payload.
+    #[derive(Debug)]
+    struct EchoExtension {
+        pending: Vec<Vec<u8>>,
+    }
+
+    impl Extension for EchoExtension {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn handle_msg(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, MessageError> {
+            Ok(vec![payload.to_vec()])
+        }
+
+        fn poll(&mut self) -> Vec<Vec<u8>> {
+            self.pending.drain(..).collect()
+        }
+    }
+
+    fn registry_with_echo(pending: Vec<Vec<u8>>) -> ExtensionRegistry {
+        let mut registry = ExtensionRegistry::new(Some(6881));
+        registry.register(Box::new(EchoExtension { pending }));
+        registry
+    }
+
+    #[test]
+    fn messages_use_the_id_of_the_peer() {
+        let mut ours = registry_with_echo(vec![vec![1, 2, 3]]);
+        // The peer gives the id 7 to the extension
+        let peer_handshake = ExtendedMsg::new(0, "d1:md4:echoi7eee".as_bytes().to_vec());
+
+        assert!(ours.poll().is_empty());
+        assert!(ours.handle_msg(peer_handshake).is_ok());
+        assert!(ours.peer_supports("echo"));
+        assert!(!ours.peer_supports("ut_pex"));
+        assert_eq!(ours.poll(), vec![ExtendedMsg::new(7, vec![1, 2, 3])]);
+
+        // The peer uses our id (1) when it sends a message of the extension
+        if let Ok(responses) = ours.handle_msg(ExtendedMsg::new(1, vec![9])) {
+            assert_eq!(responses, vec![ExtendedMsg::new(7, vec![9])]);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn handshake_between_registries() {
+        let ours = registry_with_echo(vec![]);
+        let mut theirs = ExtensionRegistry::new(None);
+
+        assert!(theirs.handle_msg(ours.handshake_msg()).is_ok());
+        if let Some(handshake) = theirs.get_peer_handshake() {
+            assert_eq!(handshake.get_extension_id("echo"), Some(1));
+            assert_eq!(handshake.get_port(), Some(6881));
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn unknown_extension_messages_are_ignored() {
+        let mut ours = registry_with_echo(vec![]);
+        if let Ok(responses) = ours.handle_msg(ExtendedMsg::new(5, vec![1])) {
+            assert!(responses.is_empty());
+        } else {
+            assert!(false);
+        }
+    }
+}
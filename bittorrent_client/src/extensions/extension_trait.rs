@@ -0,0 +1,26 @@
+use crate::errors::MessageError;
+use crate::extensions::extension_handshake::ExtensionHandshake;
+use std::fmt::Debug;
+
+/// # trait Extension
+/// An extension of the extension protocol (BEP 10), like ut_metadata or ut_pex.
+/// Payloads are the content of the extended messages, without the extended message id.
+pub trait Extension: Debug + Send {
+    /// Name used in the 'm' dictionary of the extension handshake.
+    fn name(&self) -> &str;
+
+    /// Adds the keys of the extension to our extension handshake.
+    fn extend_handshake(&self, _handshake: &mut ExtensionHandshake) {}
+
+    /// It is called when the extension handshake of the peer is received.
+    fn on_handshake(&mut self, _handshake: &ExtensionHandshake) {}
+
+    /// Handles a message of the extension sent by the peer.
+    /// Returns the payloads that have to be sent as response.
+    fn handle_msg(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, MessageError>;
+
+    /// Returns the payloads the extension wants to send on its own.
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        vec![]
+    }
+}
@@ -0,0 +1,5 @@
+pub mod extension_handshake;
+pub mod extension_registry;
+pub mod extension_trait;
+pub mod ut_metadata;
+pub mod ut_pex;
@@ -0,0 +1,328 @@
+use crate::constants::{MAX_METADATA_SIZE, METADATA_PIECE_LENGTH};
+use crate::errors::MessageError;
+use crate::extensions::extension_handshake::ExtensionHandshake;
+use crate::extensions::extension_trait::Extension;
+
+use bencode::{BencodeType, Deserializer};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::sync::{Arc, RwLock};
+
+pub const UT_METADATA: &str = "ut_metadata";
+const REQUEST: i64 = 0;
+const DATA: i64 = 1;
+const REJECT: i64 = 2;
+
+/// # struct MetadataMsg
+/// Dictionary at the beginning of every ut_metadata message.
+/// Data messages have the total size of the metadata, and the piece after the dictionary.
+#[derive(Debug, Serialize, Deserialize)]
+struct MetadataMsg {
+    msg_type: i64,
+    piece: i64,
+    total_size: Option<usize>,
+}
+
+/// # struct UtMetadata
+/// Extension used to send and receive the info dictionary of a torrent (BEP 9).
+/// If we have the metadata, the requests of the peer are answered.
+/// Otherwise, the metadata is requested to the peer and verified against the info hash.
+///     - info_hash
+///     - metadata -> shared with the owner of the connection, it is set when the download ends
+///     - size -> size of the metadata announced by the peer
+///     - pieces -> received pieces of the metadata
+///     - requested -> all pieces were already requested
+#[derive(Debug)]
+pub struct UtMetadata {
+    info_hash: Vec<u8>,
+    metadata: Arc<RwLock<Option<Vec<u8>>>>,
+    size: usize,
+    pieces: Vec<Option<Vec<u8>>>,
+    requested: bool,
+}
+
+impl UtMetadata {
+    pub fn new(info_hash: Vec<u8>, metadata: Arc<RwLock<Option<Vec<u8>>>>) -> UtMetadata {
+        UtMetadata {
+            info_hash,
+            metadata,
+            size: 0,
+            pieces: vec![],
+            requested: false,
+        }
+    }
+
+    fn get_metadata(&self) -> Option<Vec<u8>> {
+        match self.metadata.read() {
+            Ok(metadata) => metadata.clone(),
+            Err(_) => None,
+        }
+    }
+
+    fn build_msg(msg_type: i64, piece: usize, data: Option<&[u8]>, total_size: usize) -> Vec<u8> {
+        let header = MetadataMsg {
+            msg_type,
+            piece: piece as i64,
+            total_size: data.map(|_| total_size),
+        };
+        let mut msg = bencode::to_bytes(&header).unwrap_or_default();
+        if let Some(data) = data {
+            msg.extend(data);
+        }
+        msg
+    }
+
+    /// Answers a request with the piece of the metadata, or rejects it if we don't have it.
+    /// The piece index is sent by the peer, so an index out of range is rejected too.
+    fn handle_request(&self, piece: usize) -> Vec<u8> {
+        if let Some(metadata) = self.get_metadata() {
+            let begin = piece.checked_mul(METADATA_PIECE_LENGTH);
+            if let Some(begin) = begin.filter(|begin| *begin < metadata.len()) {
+                let end = metadata.len().min(begin + METADATA_PIECE_LENGTH);
+                return UtMetadata::build_msg(
+                    DATA,
+                    piece,
+                    Some(&metadata[begin..end]),
+                    metadata.len(),
+                );
+            }
+        }
+        UtMetadata::build_msg(REJECT, piece, None, 0)
+    }
+
+    /// Stores a received piece. When all pieces are received, the metadata is verified.
+    /// Every piece but the last one must be of METADATA_PIECE_LENGTH bytes,
+    /// and the last one must have the rest of the announced size.
+    /// If the metadata doesn't match the info hash, the pieces are discarded to request them again.
+    fn handle_data(&mut self, piece: usize, data: &[u8]) -> Result<(), MessageError> {
+        if piece >= self.pieces.len() || self.get_metadata().is_some() {
+            return Ok(());
+        }
+        let expected_len = if piece + 1 == self.pieces.len() {
+            self.size - piece * METADATA_PIECE_LENGTH
+        } else {
+            METADATA_PIECE_LENGTH
+        };
+        if data.len() != expected_len {
+            return Err(MessageError::InvalidExtensionMsg);
+        }
+        self.pieces[piece] = Some(data.to_vec());
+        if self.pieces.iter().any(|piece| piece.is_none()) {
+            return Ok(());
+        }
+
+        let metadata: Vec<u8> = self.pieces.iter().flatten().flatten().copied().collect();
+        let mut hasher = Sha1::new();
+        hasher.update(&metadata);
+        if hasher.finalize().to_vec() != self.info_hash {
+            self.pieces = vec![None; self.pieces.len()];
+            self.requested = false;
+            return Err(MessageError::InvalidExtensionMsg);
+        }
+        if let Ok(mut lock_metadata) = self.metadata.write() {
+            *lock_metadata = Some(metadata);
+        }
+        Ok(())
+    }
+}
+
+impl Extension for UtMetadata {
+    fn name(&self) -> &str {
+        UT_METADATA
+    }
+
+    /// Announces the size of the metadata, if we have it.
+    fn extend_handshake(&self, handshake: &mut ExtensionHandshake) {
+        if let Some(metadata) = self.get_metadata() {
+            handshake.insert_value("metadata_size", BencodeType::Integer(metadata.len() as i64));
+        }
+    }
+
+    /// If we don't have the metadata, we prepare to receive as many pieces
+    /// as the metadata size announced by the peer needs.
+    fn on_handshake(&mut self, handshake: &ExtensionHandshake) {
+        if self.get_metadata().is_some() {
+            return;
+        }
+        if let Some(BencodeType::Integer(size)) = handshake.get_value("metadata_size") {
+            if size > 0 && size as usize <= MAX_METADATA_SIZE {
+                self.size = size as usize;
+                let n_pieces = self.size.div_ceil(METADATA_PIECE_LENGTH);
+                self.pieces = vec![None; n_pieces];
+            }
+        }
+    }
+
+    fn handle_msg(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, MessageError> {
+        let mut deserializer = Deserializer::new(payload);
+        let msg = MetadataMsg::deserialize(&mut deserializer)
+            .map_err(|_| MessageError::InvalidExtensionMsg)?;
+        let dict_len = deserializer.position();
+
+        match (msg.msg_type, msg.piece) {
+            (REQUEST, piece) if piece >= 0 => Ok(vec![self.handle_request(piece as usize)]),
+            (DATA, piece) if piece >= 0 => {
+                self.handle_data(piece as usize, &payload[dict_len..])?;
+                Ok(vec![])
+            }
+            // The peer won't send us the metadata
+            (REJECT, _) if self.get_metadata().is_none() => Err(MessageError::InvalidExtensionMsg),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Requests all pieces of the metadata once, if we don't have it.
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        if self.requested || self.pieces.is_empty() || self.get_metadata().is_some() {
+            return vec![];
+        }
+        self.requested = true;
+        (0..self.pieces.len())
+            .map(|piece| UtMetadata::build_msg(REQUEST, piece, None, 0))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::extension_registry::ExtensionRegistry;
+
+    fn get_metadata() -> Vec<u8> {
+        // Big enough to need three pieces
+        let info = format!("d4:name4:test6:pieces{}:{}e", 40000, "x".repeat(40000));
+        info.into_bytes()
+    }
+
+    fn info_hash(metadata: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha1::new();
+        hasher.update(metadata);
+        hasher.finalize().to_vec()
+    }
+
+    /// Sends the messages of a registry to the other one, until there is nothing to send.
+    fn exchange_msgs(a: &mut ExtensionRegistry, b: &mut ExtensionRegistry) -> bool {
+        let mut to_b = vec![a.handshake_msg()];
+        let mut to_a = vec![b.handshake_msg()];
+        while !to_a.is_empty() || !to_b.is_empty() {
+            for msg in std::mem::take(&mut to_b) {
+                match b.handle_msg(msg) {
+                    Ok(responses) => to_a.extend(responses),
+                    Err(_) => return false,
+                }
+            }
+            for msg in std::mem::take(&mut to_a) {
+                match a.handle_msg(msg) {
+                    Ok(responses) => to_b.extend(responses),
+                    Err(_) => return false,
+                }
+            }
+            to_a.extend(b.poll());
+            to_b.extend(a.poll());
+        }
+        true
+    }
+
+    #[test]
+    fn metadata_is_sent_to_the_peer() {
+        let metadata = get_metadata();
+        let hash = info_hash(&metadata);
+        let seeder_metadata = Arc::new(RwLock::new(Some(metadata.clone())));
+        let leecher_metadata = Arc::new(RwLock::new(None));
+
+        let mut seeder = ExtensionRegistry::new(None);
+        seeder.register(Box::new(UtMetadata::new(hash.clone(), seeder_metadata)));
+        let mut leecher = ExtensionRegistry::new(None);
+        leecher.register(Box::new(UtMetadata::new(hash, leecher_metadata.clone())));
+
+        assert!(exchange_msgs(&mut seeder, &mut leecher));
+        let received = leecher_metadata.read().expect("It shouldn't fail").clone();
+        assert_eq!(received, Some(metadata));
+    }
+
+    #[test]
+    fn metadata_with_other_info_hash_is_rejected() {
+        let metadata = get_metadata();
+        let seeder_metadata = Arc::new(RwLock::new(Some(metadata.clone())));
+        let leecher_metadata = Arc::new(RwLock::new(None));
+
+        let mut seeder = ExtensionRegistry::new(None);
+        seeder.register(Box::new(UtMetadata::new(
+            info_hash(&metadata),
+            seeder_metadata,
+        )));
+        let mut leecher = ExtensionRegistry::new(None);
+        leecher.register(Box::new(UtMetadata::new(
+            vec![0; 20],
+            leecher_metadata.clone(),
+        )));
+
+        assert!(!exchange_msgs(&mut seeder, &mut leecher));
+        let received = leecher_metadata.read().expect("It shouldn't fail").clone();
+        assert_eq!(received, None);
+    }
+
+    #[test]
+    fn request_of_a_piece_out_of_range_is_rejected() {
+        let mut ut_metadata =
+            UtMetadata::new(vec![0; 20], Arc::new(RwLock::new(Some(get_metadata()))));
+        let request = format!("d8:msg_typei0e5:piecei{}ee", i64::MAX);
+        if let Ok(responses) = ut_metadata.handle_msg(request.as_bytes()) {
+            let reject = format!("d8:msg_typei2e5:piecei{}ee", i64::MAX);
+            assert_eq!(responses, vec![reject.as_bytes().to_vec()]);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn request_is_rejected_without_metadata() {
+        let mut ut_metadata = UtMetadata::new(vec![0; 20], Arc::new(RwLock::new(None)));
+        let request = "d8:msg_typei0e5:piecei0ee".as_bytes();
+        if let Ok(responses) = ut_metadata.handle_msg(request) {
+            assert_eq!(
+                responses,
+                vec!["d8:msg_typei2e5:piecei0ee".as_bytes().to_vec()]
+            );
+        } else {
+            assert!(false);
+        }
+    }
+
+    fn data_msg(piece: usize, data: &[u8], total_size: usize) -> Vec<u8> {
+        UtMetadata::build_msg(DATA, piece, Some(data), total_size)
+    }
+
+    #[test]
+    fn data_pieces_with_a_wrong_length_are_rejected() {
+        let metadata = get_metadata();
+        let mut ut_metadata = UtMetadata::new(info_hash(&metadata), Arc::new(RwLock::new(None)));
+        let mut handshake = ExtensionHandshake::new(None);
+        handshake.insert_value("metadata_size", BencodeType::Integer(metadata.len() as i64));
+        ut_metadata.on_handshake(&handshake);
+
+        let short = data_msg(0, &metadata[..100], metadata.len());
+        assert!(ut_metadata.handle_msg(&short).is_err());
+        let mut long = data_msg(2, &metadata[2 * METADATA_PIECE_LENGTH..], metadata.len());
+        long.push(0);
+        assert!(ut_metadata.handle_msg(&long).is_err());
+        assert!(ut_metadata.pieces.iter().all(|piece| piece.is_none()));
+    }
+
+    #[test]
+    fn metadata_is_requested_again_after_a_wrong_hash() {
+        let metadata = get_metadata();
+        let mut ut_metadata = UtMetadata::new(vec![0; 20], Arc::new(RwLock::new(None)));
+        let mut handshake = ExtensionHandshake::new(None);
+        handshake.insert_value("metadata_size", BencodeType::Integer(metadata.len() as i64));
+        ut_metadata.on_handshake(&handshake);
+        assert_eq!(ut_metadata.poll().len(), 3);
+
+        for (piece, data) in metadata.chunks(METADATA_PIECE_LENGTH).enumerate() {
+            let result = ut_metadata.handle_msg(&data_msg(piece, data, metadata.len()));
+            assert_eq!(result.is_err(), piece == 2);
+        }
+        assert!(ut_metadata.pieces.iter().all(|piece| piece.is_none()));
+        assert_eq!(ut_metadata.poll().len(), 3);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::bt_client::peer::{decode_compact_addrs, encode_compact_addr, COMPACT_PEER6_LENGTH};
+use crate::constants::{PEX_INTERVAL, PEX_MAX_PEERS};
+use crate::dht::node_info::COMPACT_PEER_LENGTH;
+use crate::errors::MessageError;
+use crate::extensions::extension_handshake::ExtensionHandshake;
+use crate::extensions::extension_trait::Extension;
+use crate::swarm::PeerSwarm;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const UT_PEX: &str = "ut_pex";
+
+/// # struct PexMsg
+/// Peers that joined and left the swarm since the last message.
+/// The peers are in compact format, and added.f has a byte of flags for every added peer.
+/// IPv6 peers are sent in added6 and dropped6.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PexMsg {
+    #[serde(default, with = "bencode::bytes")]
+    added: Vec<u8>,
+    #[serde(default, rename = "added.f", with = "bencode::bytes")]
+    added_f: Vec<u8>,
+    #[serde(default, with = "bencode::bytes")]
+    dropped: Vec<u8>,
+    #[serde(default, with = "bencode::bytes")]
+    added6: Vec<u8>,
+    #[serde(default, rename = "added6.f", with = "bencode::bytes")]
+    added6_f: Vec<u8>,
+    #[serde(default, with = "bencode::bytes")]
+    dropped6: Vec<u8>,
+}
+
+/// # struct UtPex
+/// Extension used to exchange the peers of a torrent (BEP 11).
+/// The first message has all the peers we are connected to, and the next ones (at most one
+/// per minute) only the changes. The peers received are added to the swarm of the torrent,
+/// so the client can connect to them.
+///     - swarm -> shared by all connections of the torrent
+///     - peer_addr -> address of the peer of the connection, it is not sent to itself.
+///       Its port is replaced by the listening port of the extension handshake of the peer.
+///     - sent -> peers the peer knows from us
+///     - last_sent
+#[derive(Debug)]
+pub struct UtPex {
+    swarm: Arc<Mutex<PeerSwarm>>,
+    peer_addr: Option<SocketAddr>,
+    sent: HashSet<SocketAddr>,
+    last_sent: Option<Instant>,
+}
+
+impl UtPex {
+    pub fn new(swarm: Arc<Mutex<PeerSwarm>>, peer_addr: Option<SocketAddr>) -> UtPex {
+        UtPex {
+            swarm,
+            peer_addr,
+            sent: HashSet::new(),
+            last_sent: None,
+        }
+    }
+
+    fn get_connected(&self) -> HashSet<SocketAddr> {
+        let mut connected: HashSet<SocketAddr> = match self.swarm.lock() {
+            Ok(swarm) => swarm.get_connected().into_iter().collect(),
+            Err(_) => HashSet::new(),
+        };
+        if let Some(peer_addr) = self.peer_addr {
+            connected.remove(&peer_addr);
+        }
+        connected
+    }
+
+    /// Returns the IPv4 and the IPv6 peers in compact format.
+    fn encode_peers(peers: &[SocketAddr]) -> (Vec<u8>, Vec<u8>) {
+        let (peers, peers6): (Vec<&SocketAddr>, Vec<&SocketAddr>) =
+            peers.iter().partition(|peer| peer.is_ipv4());
+        (
+            peers.into_iter().flat_map(encode_compact_addr).collect(),
+            peers6.into_iter().flat_map(encode_compact_addr).collect(),
+        )
+    }
+
+    fn decode_peers(compact: &[u8], compact6: &[u8]) -> Vec<SocketAddr> {
+        let mut peers = decode_compact_addrs(compact, false);
+        peers.extend(decode_compact_addrs(compact6, true));
+        peers.truncate(PEX_MAX_PEERS);
+        peers
+    }
+}
+
+impl Extension for UtPex {
+    fn name(&self) -> &str {
+        UT_PEX
+    }
+
+    fn on_handshake(&mut self, handshake: &ExtensionHandshake) {
+        if let (Some(addr), Some(port)) = (self.peer_addr, handshake.get_port()) {
+            self.peer_addr = Some(SocketAddr::new(addr.ip(), port));
+        }
+    }
+
+    /// The added peers are given to the client through the swarm.
+    fn handle_msg(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, MessageError> {
+        let msg: PexMsg =
+            bencode::from_bytes(payload).map_err(|_| MessageError::InvalidExtensionMsg)?;
+        let added = UtPex::decode_peers(&msg.added, &msg.added6);
+        let dropped = UtPex::decode_peers(&msg.dropped, &msg.dropped6);
+        if let Ok(mut swarm) = self.swarm.lock() {
+            swarm.remove_discovered(&dropped);
+            swarm.add_discovered(added);
+        }
+        Ok(vec![])
+    }
+
+    /// Sends the changes of the swarm since the last message, if a minute passed.
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        if let Some(last_sent) = self.last_sent {
+            if last_sent.elapsed() < Duration::from_secs(PEX_INTERVAL) {
+                return vec![];
+            }
+        }
+        self.last_sent = Some(Instant::now());
+
+        let connected = self.get_connected();
+        let added: Vec<SocketAddr> = connected
+            .difference(&self.sent)
+            .copied()
+            .take(PEX_MAX_PEERS)
+            .collect();
+        let dropped: Vec<SocketAddr> = self
+            .sent
+            .difference(&connected)
+            .copied()
+            .take(PEX_MAX_PEERS)
+            .collect();
+        if added.is_empty() && dropped.is_empty() {
+            return vec![];
+        }
+
+        self.sent.extend(&added);
+        for peer in &dropped {
+            self.sent.remove(peer);
+        }
+        let (added, added6) = UtPex::encode_peers(&added);
+        let (dropped, dropped6) = UtPex::encode_peers(&dropped);
+        let msg = PexMsg {
+            added_f: vec![0; added.len() / COMPACT_PEER_LENGTH],
+            added,
+            dropped,
+            added6_f: vec![0; added6.len() / COMPACT_PEER6_LENGTH],
+            added6,
+            dropped6,
+        };
+        match bencode::to_bytes(&msg) {
+            Ok(payload) => vec![payload],
+            Err(_) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().expect("It shouldn't fail")
+    }
+
+    #[test]
+    fn changes_of_the_swarm_are_sent() {
+        let swarm = Arc::new(Mutex::new(PeerSwarm::new()));
+        let mut ut_pex = UtPex::new(swarm.clone(), Some(addr("10.0.0.9:6881")));
+        if let Ok(mut swarm) = swarm.lock() {
+            swarm.add_connected(addr("10.0.0.1:6881"));
+            swarm.add_connected(addr("10.0.0.9:6881"));
+        }
+
+        // The peer of the connection is not sent to itself
+        let first = ut_pex.poll();
+        assert_eq!(
+            first,
+            vec![b"d5:added6:\x0a\x00\x00\x01\x1a\xe17:added.f1:\x006:added60:8:added6.f0:7:dropped0:8:dropped60:e".to_vec()]
+        );
+        // Nothing is sent before a minute passes
+        assert!(ut_pex.poll().is_empty());
+
+        if let Ok(mut swarm) = swarm.lock() {
+            swarm.remove_connected(addr("10.0.0.1:6881"));
+        }
+        ut_pex.last_sent = None;
+        assert_eq!(
+            ut_pex.poll(),
+            vec![b"d5:added0:7:added.f0:6:added60:8:added6.f0:7:dropped6:\x0a\x00\x00\x01\x1a\xe18:dropped60:e".to_vec()]
+        );
+        ut_pex.last_sent = None;
+        assert!(ut_pex.poll().is_empty());
+    }
+
+    #[test]
+    fn received_peers_are_discovered() {
+        let swarm = Arc::new(Mutex::new(PeerSwarm::new()));
+        let mut ut_pex = UtPex::new(swarm.clone(), None);
+        let msg = b"d5:added12:\x0a\x00\x00\x01\x1a\xe1\x0a\x00\x00\x02\x1a\xe16:added618:\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1a\xe17:dropped0:e";
+
+        if let Ok(responses) = ut_pex.handle_msg(msg) {
+            assert!(responses.is_empty());
+        } else {
+            assert!(false);
+        }
+        let discovered = swarm
+            .lock()
+            .expect("It shouldn't fail")
+            .take_discovered(PEX_MAX_PEERS);
+        assert_eq!(
+            discovered,
+            vec![
+                addr("10.0.0.1:6881"),
+                addr("10.0.0.2:6881"),
+                addr("[2001:db8::1]:6881")
+            ]
+        );
+    }
+
+    #[test]
+    fn error_pex_message_is_not_a_dictionary() {
+        let mut ut_pex = UtPex::new(Arc::new(Mutex::new(PeerSwarm::new())), None);
+        assert!(ut_pex.handle_msg("li1ei2ee".as_bytes()).is_err());
+    }
+}
@@ -1,118 +1,121 @@
 use crate::bt_client::peer::Peer;
 use crate::encoding_decoding::encoder::Encoder;
-use crate::event_messages::NewEvent;
+use crate::event_messages::{EventSubscriber, NewEvent};
 use crate::gui::gui_assets::{GeneralColumns, View};
-use crate::piece::Piece;
-use crate::torrent_info::TorrentInfo;
+use crate::torrent_info::{TorrentId, TorrentInfo};
 use gio::prelude::ApplicationExtManual;
-use glib::{Continue, MainContext, PRIORITY_DEFAULT};
+use glib::Continue;
 use gtk::prelude::*;
 use gtk::Application;
 use gtk::TreeIter;
 use gtk::TreePath;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fmt::Write;
-use std::sync::mpsc::Sender;
 use std::time::Instant;
 
 use super::gui_assets::StatColumns;
 
+/// The GUI subscribes to the events of a session with the sender of the GTK main context.
+impl EventSubscriber for glib::Sender<NewEvent> {
+    fn notify(&self, event: NewEvent) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
 pub struct UserInterface {
     view: View,
-    tx: Sender<glib::Sender<NewEvent>>,
     active_connections: HashMap<String, Instant>,
 }
 
 impl UserInterface {
-    pub fn run(tx: Sender<glib::Sender<NewEvent>>) {
+    /// Runs the GUI with the receiver of the GTK main context,
+    /// whose sender is subscribed to the events of the session.
+    pub fn run(rx: glib::Receiver<NewEvent>) {
         let app = Application::new(Some("com.taller.app"), Default::default());
+        let rx = RefCell::new(Some(rx));
         app.connect_activate(move |app| {
-            let ui = UserInterface::new(app, Sender::clone(&tx));
-            ui.show();
+            if let Some(rx) = rx.borrow_mut().take() {
+                let ui = UserInterface::new(app);
+                ui.show(rx);
+            }
         });
 
         let s: [String; 0] = [];
         app.run_with_args(&s);
     }
 
-    fn new(app: &gtk::Application, tx: Sender<glib::Sender<NewEvent>>) -> Self {
+    fn new(app: &gtk::Application) -> Self {
         let view = View::new(app);
         let active_connections = HashMap::new();
 
         UserInterface {
             view,
-            tx,
             active_connections,
         }
     }
 
-    fn show(mut self) {
-        let (tx_gui, rx) = MainContext::channel(PRIORITY_DEFAULT);
-        let _ = self.tx.send(glib::Sender::clone(&tx_gui));
-
+    fn show(mut self, rx: glib::Receiver<NewEvent>) {
         self.view.window.show_all();
 
         rx.attach(None, move |msg| {
-            self.handle_msg(msg, glib::Sender::clone(&tx_gui));
+            self.handle_msg(msg);
             Continue(true)
         });
     }
 
-    fn handle_msg(&mut self, msg: NewEvent, tx_gui: glib::Sender<NewEvent>) {
+    fn handle_msg(&mut self, msg: NewEvent) {
         match msg {
-            NewEvent::NewTorrent(torrent_info, piece_count, structure) => {
-                let _ = self.tx.send(glib::Sender::clone(&tx_gui));
-                self.add_new_torrent(&torrent_info, piece_count, structure);
+            NewEvent::NewTorrent(_, torrent_info, piece_count) => {
+                self.add_new_torrent(&torrent_info, piece_count, torrent_info.get_structure());
+            }
+            NewEvent::MetadataReceived(torrent_id, torrent_info, piece_count) => {
+                self.update_torrent_metadata(torrent_id, &torrent_info, piece_count);
+            }
+            NewEvent::DownloadingTorrent(torrent_id) => {
+                self.set_status(torrent_id, "Downloading");
             }
-            NewEvent::DownloadingTorrent(torrent_name) => {
-                self.set_status(&torrent_name, "Downloading");
+            NewEvent::TorrentDownloaded(torrent_id) => {
+                self.set_status(torrent_id, "Finished");
             }
-            NewEvent::TorrentDownloadFailed(torrent_name) => {
-                let _ = self.tx.send(glib::Sender::clone(&tx_gui));
-                self.set_status(&torrent_name, "Paused");
+            NewEvent::TorrentDownloadFailed(torrent_id) | NewEvent::TorrentPaused(torrent_id) => {
+                self.set_status(torrent_id, "Paused");
             }
-            NewEvent::NewConnection(torrent_name, peer) => {
-                self.add_new_peer(&peer, &torrent_name);
+            NewEvent::TorrentRemoved(torrent_id) => {
+                self.remove_torrent(torrent_id);
             }
-            NewEvent::ConnectionDropped(torrent_name, peer) => {
-                self.delete_peer(&peer, &torrent_name);
+            NewEvent::NewConnection(torrent_id, peer) => {
+                self.add_new_peer(&peer, torrent_id);
             }
-            NewEvent::NewDownloadedPiece(torrent_name, piece, peer) => {
-                self.add_new_piece(&torrent_name, peer, piece);
+            NewEvent::ConnectionDropped(torrent_id, peer) => {
+                self.delete_peer(&peer, torrent_id);
             }
-            NewEvent::NumberOfPeers(torrent_name, no_of_peers) => {
-                self.set_number_of_peers(&torrent_name, no_of_peers);
+            NewEvent::NewDownloadedPiece(torrent_id, _, piece_length, peer) => {
+                self.add_new_piece(torrent_id, peer, piece_length);
             }
-            NewEvent::OurStatus(status, peer) => {
+            NewEvent::NumberOfPeers(torrent_id, no_of_peers) => {
+                self.set_number_of_peers(torrent_id, no_of_peers);
+            }
+            NewEvent::OurStatus(_, status, peer) => {
                 self.update_status(status, peer);
             }
-            _ => (),
         }
     }
 
     fn add_new_torrent(&mut self, torrent_info: &TorrentInfo, piece_count: u32, structure: String) {
         let model = &self.view.notebook.general_info.list_store;
 
-        let mut infohash = String::new();
-        for byte in &torrent_info.get_info_hash() {
-            let _ = write!(infohash, "%{:02x}", byte);
-        }
-
         let size = self.convert_bytes_to_gb(torrent_info.get_length());
 
         // Assembling the row
         let mut status = "Paused";
-        if piece_count == torrent_info.get_n_pieces() {
+        if torrent_info.has_metadata() && piece_count == torrent_info.get_n_pieces() {
             status = "Finished";
         }
 
         let values: [(u32, &dyn ToValue); 10] = [
             (0, &torrent_info.get_name()),
-            (
-                1,
-                &(Encoder.urldecode(&infohash)).unwrap_or_else(|_| "n/a".to_string()),
-            ),
+            (1, &torrent_info.get_id().to_string()),
             (2, &structure),
             (3, &size),
             (4, &torrent_info.get_n_pieces()),
@@ -120,16 +123,57 @@ impl UserInterface {
             (6, &0u32),
             (7, &0u32),
             (8, &status.to_string()),
-            (9, &(piece_count / torrent_info.get_n_pieces() * 100)),
+            (
+                9,
+                &self.calculate_progress(piece_count, torrent_info.get_n_pieces()),
+            ),
         ];
 
         model.set(&model.append(), &values);
     }
 
-    fn set_status(&mut self, torrent_name: &str, status: &str) {
+    /// Updates the row of a torrent that was created from a magnet link,
+    /// once its metadata has been received.
+    fn update_torrent_metadata(
+        &mut self,
+        torrent_id: TorrentId,
+        torrent_info: &TorrentInfo,
+        piece_count: u32,
+    ) {
+        if let Some(iter) = self.search_torrent(torrent_id) {
+            let model = &self.view.notebook.general_info.list_store;
+            let size = self.convert_bytes_to_gb(torrent_info.get_length());
+            let progress = self.calculate_progress(piece_count, torrent_info.get_n_pieces());
+
+            let values: [(u32, &dyn ToValue); 6] = [
+                (GeneralColumns::Name as i32 as u32, &torrent_info.get_name()),
+                (
+                    GeneralColumns::Structure as i32 as u32,
+                    &torrent_info.get_structure(),
+                ),
+                (GeneralColumns::Size as i32 as u32, &size),
+                (
+                    GeneralColumns::TotalPieces as i32 as u32,
+                    &torrent_info.get_n_pieces(),
+                ),
+                (GeneralColumns::DownloadedPieces as i32 as u32, &piece_count),
+                (GeneralColumns::Progress as i32 as u32, &progress),
+            ];
+            model.set(&iter, &values);
+        }
+    }
+
+    fn calculate_progress(&self, piece_count: u32, n_pieces: u32) -> u32 {
+        if n_pieces == 0 {
+            return 0;
+        }
+        piece_count * 100 / n_pieces
+    }
+
+    fn set_status(&mut self, torrent_id: TorrentId, status: &str) {
         let model = &self.view.notebook.general_info.list_store;
 
-        if let Some(iter) = self.search_torrent(torrent_name) {
+        if let Some(iter) = self.search_torrent(torrent_id) {
             if let Ok(current_status) = model
                 .value(&iter, GeneralColumns::Status as i32)
                 .get::<String>()
@@ -145,10 +189,18 @@ impl UserInterface {
         }
     }
 
-    fn set_number_of_peers(&mut self, torrent_name: &str, no_of_peers: u32) {
+    fn remove_torrent(&mut self, torrent_id: TorrentId) {
+        let model = &self.view.notebook.general_info.list_store;
+
+        if let Some(iter) = self.search_torrent(torrent_id) {
+            model.remove(&iter);
+        }
+    }
+
+    fn set_number_of_peers(&mut self, torrent_id: TorrentId, no_of_peers: u32) {
         let model = &self.view.notebook.general_info.list_store;
 
-        if let Some(iter) = self.search_torrent(torrent_name) {
+        if let Some(iter) = self.search_torrent(torrent_id) {
             model.set_value(
                 &iter,
                 GeneralColumns::Peers as i32 as u32,
@@ -157,15 +209,15 @@ impl UserInterface {
         }
     }
 
-    fn add_new_piece(&mut self, torrent_name: &str, peer: Peer, piece: Piece) {
-        if let Some(iter) = self.search_torrent(torrent_name) {
-            self.update_dl_pieces(&iter, torrent_name);
+    fn add_new_piece(&mut self, torrent_id: TorrentId, peer: Peer, piece_length: u32) {
+        if let Some(iter) = self.search_torrent(torrent_id) {
+            self.update_dl_pieces(&iter, torrent_id);
         }
 
         let instant = Instant::now();
         if let Some(old_instant) = self.active_connections.insert(peer.ip(), instant) {
             if let Some(duration) = instant.checked_duration_since(old_instant) {
-                let speed = piece.get_tl() as f32 / 1024.0 / duration.as_secs_f32();
+                let speed = piece_length as f32 / 1024.0 / duration.as_secs_f32();
                 if let Some(curr_peer) = self.search_peer(&peer.ip()) {
                     let model_stats = &self.view.notebook.download_stats.list_store;
                     model_stats.set_value(
@@ -178,14 +230,16 @@ impl UserInterface {
         }
     }
 
-    fn search_torrent(&self, torrent: &str) -> Option<TreeIter> {
+    /// Searches the row of a torrent by its id, shown in the hash column.
+    fn search_torrent(&self, torrent_id: TorrentId) -> Option<TreeIter> {
+        let torrent = torrent_id.to_string();
         let model = &self.view.notebook.general_info.list_store;
         let mut path = TreePath::new_first();
         let mut curr_iter = model.iter(&path);
 
         while let Some(iter) = curr_iter {
             if let Ok(current_torrent) = model
-                .value(&iter, GeneralColumns::Name as i32)
+                .value(&iter, GeneralColumns::Hash as i32)
                 .get::<String>()
             {
                 if current_torrent == torrent {
@@ -218,8 +272,8 @@ impl UserInterface {
         None
     }
 
-    fn add_new_peer(&mut self, peer: &Peer, torrent: &str) {
-        if let Some(iter) = self.search_torrent(torrent) {
+    fn add_new_peer(&mut self, peer: &Peer, torrent_id: TorrentId) {
+        if let Some(iter) = self.search_torrent(torrent_id) {
             self.update_active_connections(&iter, true);
         }
 
@@ -228,7 +282,7 @@ impl UserInterface {
         let values: [(u32, &dyn ToValue); 6] = [
             (0, &encoded_id),
             (1, &peer.ip()),
-            (2, &peer.port()),
+            (2, &(peer.port() as u32)),
             (3, &"choked / not interested"),
             (4, &"choked / not interested"),
             (5, &"0 KiB/s"),
@@ -260,8 +314,8 @@ impl UserInterface {
         }
     }
 
-    fn delete_peer(&mut self, peer: &Peer, torrent_name: &str) {
-        if let Some(iter) = self.search_torrent(torrent_name) {
+    fn delete_peer(&mut self, peer: &Peer, torrent_id: TorrentId) {
+        if let Some(iter) = self.search_torrent(torrent_id) {
             self.update_active_connections(&iter, false);
         }
 
@@ -271,7 +325,7 @@ impl UserInterface {
         }
     }
 
-    fn update_dl_pieces(&mut self, iter: &TreeIter, torrent_name: &str) {
+    fn update_dl_pieces(&mut self, iter: &TreeIter, torrent_id: TorrentId) {
         let model = &self.view.notebook.general_info.list_store;
 
         if let Ok(mut dl_pieces) = model
@@ -290,7 +344,7 @@ impl UserInterface {
                 .get::<u32>()
             {
                 if dl_pieces == tl_pieces {
-                    self.set_status(torrent_name, "Finished");
+                    self.set_status(torrent_id, "Finished");
                 }
             }
 
@@ -327,8 +381,8 @@ impl UserInterface {
     }
 
     // Converts bytes to gb formatted to a String with two decimals
-    fn convert_bytes_to_gb(&self, size: u32) -> String {
-        let size_gb = (size as f32) / 1024.0 / 1024.0 / 1024.0;
+    fn convert_bytes_to_gb(&self, size: u64) -> String {
+        let size_gb = (size as f64) / 1024.0 / 1024.0 / 1024.0;
         format!("{:.2}", size_gb)
     }
 
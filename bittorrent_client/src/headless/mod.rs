@@ -0,0 +1,2 @@
+pub mod terminal_ui;
+mod torrent_progress;
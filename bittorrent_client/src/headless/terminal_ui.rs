@@ -0,0 +1,191 @@
+use crate::constants::*;
+use crate::event_messages::NewEvent;
+use crate::headless::torrent_progress::TorrentProgress;
+use crate::torrent_info::TorrentId;
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// # struct TerminalUserInterface
+/// Headless frontend, which shows the progress of the torrents in the terminal:
+///     - torrents -> progress of the torrents, in the order they were found
+///     - seed -> keep running after all torrents are downloaded
+///     - redraw -> the output is a terminal, so the torrents are drawn again in the same lines
+///     - drawn_lines -> number of lines of the last draw
+pub struct TerminalUserInterface {
+    torrents: Vec<TorrentProgress>,
+    seed: bool,
+    redraw: bool,
+    drawn_lines: usize,
+}
+
+impl TerminalUserInterface {
+    /// Shows the progress of the torrents with the events of a session, until all
+    /// of them are downloaded (or forever, if we keep seeding), or until the session ends.
+    /// Returns the exit code of the process: 0 if all torrents were downloaded,
+    /// EXIT_DOWNLOAD_INCOMPLETE otherwise.
+    pub fn run(rx_events: Receiver<NewEvent>, session_thread: JoinHandle<()>, seed: bool) -> i32 {
+        let mut ui = TerminalUserInterface::new(seed);
+        ui.redraw = io::stdout().is_terminal();
+
+        let exit_code = ui.listen(rx_events, &session_thread);
+        ui.draw();
+        exit_code
+    }
+
+    fn new(seed: bool) -> Self {
+        TerminalUserInterface {
+            torrents: vec![],
+            seed,
+            redraw: false,
+            drawn_lines: 0,
+        }
+    }
+
+    /// Handles the events of the torrents. The torrents are drawn every
+    /// PROGRESS_REFRESH_INTERVAL seconds in a terminal, or every PROGRESS_LOG_INTERVAL
+    /// seconds when the output is redirected.
+    fn listen(&mut self, rx_events: Receiver<NewEvent>, session_thread: &JoinHandle<()>) -> i32 {
+        let refresh_interval = Duration::from_secs(PROGRESS_REFRESH_INTERVAL);
+        let log_interval = Duration::from_secs(PROGRESS_LOG_INTERVAL);
+        let mut last_refresh = Instant::now();
+        let mut last_draw = Instant::now();
+
+        loop {
+            if let Ok(event) = rx_events.recv_timeout(refresh_interval) {
+                self.handle_msg(event);
+            }
+            if session_thread.is_finished() {
+                // The last events are handled before deciding the exit code
+                while let Ok(event) = rx_events.try_recv() {
+                    self.handle_msg(event);
+                }
+                return match self.all_downloaded() {
+                    true => 0,
+                    false => EXIT_DOWNLOAD_INCOMPLETE,
+                };
+            }
+            if self.all_downloaded() && !self.seed {
+                return 0;
+            }
+
+            let elapsed = last_refresh.elapsed();
+            if elapsed >= refresh_interval {
+                last_refresh = Instant::now();
+                for torrent in self.torrents.iter_mut() {
+                    torrent.update_speed(elapsed);
+                }
+                if self.redraw || last_draw.elapsed() >= log_interval {
+                    last_draw = Instant::now();
+                    self.draw();
+                }
+            }
+        }
+    }
+
+    fn handle_msg(&mut self, msg: NewEvent) {
+        if let NewEvent::NewTorrent(_, torrent_info, piece_count) = &msg {
+            self.torrents
+                .push(TorrentProgress::new(torrent_info, *piece_count));
+            return;
+        }
+
+        if let NewEvent::TorrentRemoved(torrent_id) = &msg {
+            self.torrents
+                .retain(|torrent| torrent.get_id() != *torrent_id);
+            return;
+        }
+
+        let torrent = match self.search_torrent(msg.get_torrent_id()) {
+            Some(torrent) => torrent,
+            None => return,
+        };
+        match msg {
+            NewEvent::MetadataReceived(_, torrent_info, piece_count) => {
+                torrent.set_metadata(&torrent_info, piece_count);
+            }
+            NewEvent::DownloadingTorrent(_) => torrent.set_status("Downloading"),
+            NewEvent::TorrentDownloaded(_) => torrent.set_downloaded(),
+            NewEvent::TorrentDownloadFailed(_) | NewEvent::TorrentPaused(_) => {
+                torrent.set_status("Paused")
+            }
+            NewEvent::NewConnection(..) => torrent.add_connection(),
+            NewEvent::ConnectionDropped(..) => torrent.remove_connection(),
+            NewEvent::NewDownloadedPiece(_, _, piece_length, _) => torrent.add_piece(piece_length),
+            NewEvent::NumberOfPeers(_, no_of_peers) => torrent.set_peers(no_of_peers),
+            _ => (),
+        }
+    }
+
+    fn search_torrent(&mut self, torrent_id: TorrentId) -> Option<&mut TorrentProgress> {
+        self.torrents
+            .iter_mut()
+            .find(|torrent| torrent.get_id() == torrent_id)
+    }
+
+    fn all_downloaded(&self) -> bool {
+        !self.torrents.is_empty() && self.torrents.iter().all(|t| t.is_downloaded())
+    }
+
+    /// Prints a line per torrent. In a terminal, the previous lines are replaced.
+    fn draw(&mut self) {
+        let mut stdout = io::stdout().lock();
+        if self.redraw && self.drawn_lines > 0 {
+            let _ = write!(stdout, "\x1b[{}A", self.drawn_lines);
+        }
+        for torrent in &self.torrents {
+            if self.redraw {
+                let _ = write!(stdout, "\x1b[2K");
+            }
+            let _ = writeln!(stdout, "{}", torrent.render());
+        }
+        self.drawn_lines = self.torrents.len();
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bt_client::peer::Peer;
+    use crate::torrent_info::TorrentInfo;
+
+    fn get_torrent() -> TorrentInfo {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        TorrentInfo::new(path).expect("It shouldn't fail")
+    }
+
+    #[test]
+    fn events_of_unknown_torrents_are_ignored() {
+        let mut ui = TerminalUserInterface::new(false);
+        let torrent = get_torrent();
+        ui.handle_msg(NewEvent::TorrentDownloaded(torrent.get_id()));
+        assert!(ui.torrents.is_empty());
+        assert!(!ui.all_downloaded());
+    }
+
+    #[test]
+    fn all_torrents_are_downloaded() {
+        let mut ui = TerminalUserInterface::new(false);
+        let torrent = get_torrent();
+        let id = torrent.get_id();
+        ui.handle_msg(NewEvent::NewTorrent(id, torrent.clone(), 3));
+        ui.handle_msg(NewEvent::DownloadingTorrent(id));
+        let peer = Peer::from_socket_addr("127.0.0.1:6881".parse().expect("It shouldn't fail"));
+        ui.handle_msg(NewEvent::NewConnection(id, peer.clone()));
+        let piece_length = torrent.length_of_piece_n(3);
+        ui.handle_msg(NewEvent::NewDownloadedPiece(id, 3, piece_length, peer));
+        // The pieces are complete, but the client has not finished yet
+        assert!(!ui.all_downloaded());
+
+        ui.handle_msg(NewEvent::TorrentDownloaded(id));
+        assert!(ui.all_downloaded());
+        assert!(ui.torrents[0].render().contains("100% 53.71 KiB"));
+        assert!(ui.torrents[0].render().ends_with("peers: 1/0 Finished"));
+
+        ui.handle_msg(NewEvent::TorrentRemoved(id));
+        assert!(ui.torrents.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::constants::PROGRESS_BAR_WIDTH;
+use crate::torrent_info::{TorrentId, TorrentInfo};
+
+use std::time::Duration;
+
+/// # struct TorrentProgress
+/// State of a torrent shown by the headless frontend:
+///     - id
+///     - name
+///     - length -> total amount of bytes (0 if the metadata was not received yet)
+///     - n_pieces
+///     - dl_pieces -> number of downloaded pieces
+///     - peers -> number of peers found for the torrent
+///     - connections -> active connections with peers
+///     - status -> "Paused", "Downloading" or "Finished"
+///     - downloaded -> the client of the torrent has finished the download
+///     - window_bytes -> bytes downloaded since the last speed update
+///     - speed -> download speed in bytes per second
+#[derive(Debug, Clone, PartialEq)]
+pub struct TorrentProgress {
+    id: TorrentId,
+    name: String,
+    length: u64,
+    n_pieces: u32,
+    dl_pieces: u32,
+    peers: u32,
+    connections: u32,
+    status: String,
+    downloaded: bool,
+    window_bytes: u64,
+    speed: f64,
+}
+
+impl TorrentProgress {
+    pub fn new(torrent_info: &TorrentInfo, dl_pieces: u32) -> TorrentProgress {
+        let mut progress = TorrentProgress {
+            id: torrent_info.get_id(),
+            name: torrent_info.get_name(),
+            length: 0,
+            n_pieces: 0,
+            dl_pieces: 0,
+            peers: 0,
+            connections: 0,
+            status: "Paused".to_string(),
+            downloaded: false,
+            window_bytes: 0,
+            speed: 0.0,
+        };
+        progress.set_metadata(torrent_info, dl_pieces);
+        progress
+    }
+
+    pub fn get_id(&self) -> TorrentId {
+        self.id
+    }
+
+    /// Updates the torrent with its metadata, which is received later for magnet links.
+    pub fn set_metadata(&mut self, torrent_info: &TorrentInfo, dl_pieces: u32) {
+        self.name = torrent_info.get_name();
+        self.length = torrent_info.get_length();
+        self.n_pieces = torrent_info.get_n_pieces();
+        self.dl_pieces = dl_pieces;
+        if self.is_finished() {
+            self.status = "Finished".to_string();
+        }
+    }
+
+    /// Sets the status of the torrent. A finished torrent keeps its status.
+    pub fn set_status(&mut self, status: &str) {
+        if self.status != "Finished" {
+            self.status = status.to_string();
+        }
+    }
+
+    pub fn set_peers(&mut self, peers: u32) {
+        self.peers = peers;
+    }
+
+    pub fn add_connection(&mut self) {
+        self.connections += 1;
+    }
+
+    pub fn remove_connection(&mut self) {
+        self.connections = self.connections.saturating_sub(1);
+    }
+
+    pub fn add_piece(&mut self, piece_bytes: u32) {
+        self.dl_pieces += 1;
+        self.window_bytes += piece_bytes as u64;
+        if self.is_finished() {
+            self.status = "Finished".to_string();
+        }
+    }
+
+    /// The client of the torrent has finished, so all pieces are stored.
+    pub fn set_downloaded(&mut self) {
+        self.downloaded = true;
+        self.status = "Finished".to_string();
+    }
+
+    pub fn is_downloaded(&self) -> bool {
+        self.downloaded
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.n_pieces > 0 && self.dl_pieces >= self.n_pieces
+    }
+
+    /// Calculates the download speed with the bytes received during the elapsed time.
+    pub fn update_speed(&mut self, elapsed: Duration) {
+        if elapsed.is_zero() {
+            return;
+        }
+        self.speed = self.window_bytes as f64 / elapsed.as_secs_f64();
+        self.window_bytes = 0;
+    }
+
+    pub fn calculate_progress(&self) -> u32 {
+        if self.n_pieces == 0 {
+            return 0;
+        }
+        (self.dl_pieces.min(self.n_pieces) as u64 * 100 / self.n_pieces as u64) as u32
+    }
+
+    /// Builds the line of the torrent:
+    /// name, progress bar, percentage, size, speed, connections / peers and status.
+    pub fn render(&self) -> String {
+        let progress = self.calculate_progress();
+        let filled = progress as usize * PROGRESS_BAR_WIDTH / 100;
+        format!(
+            "{} [{}{}] {:>3}% {} {}/s peers: {}/{} {}",
+            self.name,
+            "#".repeat(filled),
+            "-".repeat(PROGRESS_BAR_WIDTH - filled),
+            progress,
+            TorrentProgress::format_bytes(self.length as f64),
+            TorrentProgress::format_bytes(self.speed),
+            self.connections,
+            self.peers,
+            self.status
+        )
+    }
+
+    // Formats an amount of bytes with the largest unit that keeps it above 1, with two decimals
+    fn format_bytes(bytes: f64) -> String {
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut value = bytes;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < units.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        format!("{:.2} {}", value, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_progress() -> TorrentProgress {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        TorrentProgress::new(&torrent, 1)
+    }
+
+    #[test]
+    fn torrent_is_finished_with_all_pieces() {
+        let mut progress = get_progress();
+        progress.set_status("Downloading");
+        assert!(!progress.is_finished());
+        assert_eq!(progress.calculate_progress(), 25);
+
+        for _ in 0..3 {
+            progress.add_piece(16384);
+        }
+        progress.set_status("Paused");
+        assert!(progress.is_finished());
+        assert_eq!(progress.calculate_progress(), 100);
+        assert!(progress.render().ends_with("Finished"));
+    }
+
+    #[test]
+    fn speed_is_calculated_with_the_elapsed_time() {
+        let mut progress = get_progress();
+        progress.add_piece(16384);
+        progress.add_piece(16384);
+        progress.update_speed(Duration::from_secs(2));
+        assert!(progress.render().contains("16.00 KiB/s"));
+
+        progress.update_speed(Duration::from_secs(1));
+        assert!(progress.render().contains("0.00 B/s"));
+    }
+
+    #[test]
+    fn render_torrent_line() {
+        let mut progress = get_progress();
+        progress.set_peers(12);
+        progress.add_connection();
+        progress.add_connection();
+        progress.remove_connection();
+        progress.set_status("Downloading");
+
+        assert_eq!(
+            progress.render(),
+            "multi_file_test [#####---------------]  25% 53.71 KiB 0.00 B/s peers: 1/12 Downloading"
+        );
+    }
+}
@@ -1,17 +1,30 @@
-pub mod bencode_type;
 pub mod bitfield;
-pub mod bittorrent;
 pub mod bt_client;
 pub mod bt_server;
+pub mod choker;
 pub mod constants;
+pub mod control;
+pub mod dht;
 pub mod encoding_decoding;
 pub mod errors;
 pub mod event_messages;
+pub mod extensions;
+#[cfg(feature = "gui")]
 pub mod gui;
+pub mod headless;
 pub mod logging;
+pub mod magnet;
 pub mod p2p_messages;
 pub mod piece;
-pub mod piece_merger;
+pub mod resume;
+pub mod session;
 pub mod settings;
+pub mod storage;
+pub mod swarm;
+#[cfg(test)]
+mod test_support;
+pub mod torrent_creator;
 pub mod torrent_finder;
 pub mod torrent_info;
+pub mod torrent_registry;
+pub mod torrent_watcher;
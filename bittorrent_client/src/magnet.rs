@@ -0,0 +1,197 @@
+use crate::encoding_decoding::encoder::Encoder;
+use std::io::{Error, ErrorKind};
+
+const MAGNET_PREFIX: &str = "magnet:?";
+const BTIH_PREFIX: &str = "urn:btih:";
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// # struct MagnetLink
+/// Contains the information of a magnet URI:
+///     - info_hash -> taken from the 'xt' parameter (hex or base32 encoded)
+///     - name -> display name taken from the 'dn' parameter (optional)
+///     - trackers -> tracker urls taken from the 'tr' parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagnetLink {
+    info_hash: Vec<u8>,
+    name: Option<String>,
+    trackers: Vec<String>,
+}
+
+impl MagnetLink {
+    /// Receives a magnet URI and parses it.
+    /// On success, returns a MagnetLink.
+    /// Otherwise (no 'xt' parameter or invalid info hash), returns error.
+    pub fn new(uri: &str) -> Result<MagnetLink, Error> {
+        let params = match uri.trim().strip_prefix(MAGNET_PREFIX) {
+            Some(params) => params,
+            None => return Err(Error::new(ErrorKind::InvalidInput, "It's not a magnet URI")),
+        };
+
+        let mut info_hash = None;
+        let mut name = None;
+        let mut trackers = vec![];
+        for param in params.split('&') {
+            if let Some((key, value)) = param.split_once('=') {
+                let value = percent_decode(value)?;
+                match key {
+                    "xt" => {
+                        if let Some(hash) = value.strip_prefix(BTIH_PREFIX) {
+                            info_hash = Some(decode_info_hash(hash)?);
+                        }
+                    }
+                    "dn" => name = Some(value),
+                    _ if (key == "tr" || key.starts_with("tr.")) && !trackers.contains(&value) => {
+                        trackers.push(value);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        match info_hash {
+            Some(info_hash) => Ok(MagnetLink {
+                info_hash,
+                name,
+                trackers,
+            }),
+            None => Err(Error::new(ErrorKind::InvalidData, "Missing info hash")),
+        }
+    }
+
+    /// Checks if the string is a magnet URI
+    pub fn is_magnet(uri: &str) -> bool {
+        uri.trim().starts_with(MAGNET_PREFIX)
+    }
+
+    pub fn get_info_hash(&self) -> Vec<u8> {
+        self.info_hash.clone()
+    }
+
+    /// Returns the display name. If the URI has not any, the hex encoded info hash is used.
+    pub fn get_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => Encoder.hexencode(&self.info_hash),
+        }
+    }
+
+    pub fn get_trackers(&self) -> Vec<String> {
+        self.trackers.clone()
+    }
+}
+
+/// Decodes an info hash encoded as 40 hex chars or 32 base32 chars.
+fn decode_info_hash(hash: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "Invalid info hash");
+    // The hash is percent-decoded, so it can have multibyte chars
+    if !hash.is_ascii() {
+        return Err(invalid());
+    }
+    match hash.len() {
+        40 => (0..40)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hash[i..i + 2], 16).map_err(|_| invalid()))
+            .collect(),
+        32 => {
+            let mut bytes = vec![];
+            let mut buffer: u64 = 0;
+            let mut bits = 0;
+            for c in hash.to_ascii_uppercase().bytes() {
+                let value = BASE32_ALPHABET
+                    .iter()
+                    .position(|b| *b == c)
+                    .ok_or_else(invalid)?;
+                buffer = (buffer << 5) | value as u64;
+                bits += 5;
+                if bits >= 8 {
+                    bits -= 8;
+                    bytes.push((buffer >> bits) as u8);
+                }
+            }
+            Ok(bytes)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Decodes a percent-encoded value of the URI ('+' is decoded as a space).
+fn percent_decode(value: &str) -> Result<String, Error> {
+    let bytes = value.as_bytes();
+    let mut decoded = vec![];
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'%' => {
+                let hex = value
+                    .get(idx + 1..idx + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => decoded.push(byte),
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Invalid URI")),
+                }
+                idx += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                idx += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+    Ok(String::from_utf8_lossy(&decoded).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_magnet_with_hex_info_hash() {
+        let uri = "magnet:?xt=urn:btih:3d374e5a6ee6641280499abe5ce8098b2cdff064&dn=multi+file%20test&tr=http%3A%2F%2F127.0.0.1%3A7878%2Fannounce&tr=udp%3A%2F%2Ftracker.example.com%3A80";
+        if let Ok(magnet) = MagnetLink::new(uri) {
+            let exp_hash = vec![
+                61, 55, 78, 90, 110, 230, 100, 18, 128, 73, 154, 190, 92, 232, 9, 139, 44, 223,
+                240, 100,
+            ];
+            assert_eq!(magnet.get_info_hash(), exp_hash);
+            assert_eq!(magnet.get_name(), "multi file test");
+            assert_eq!(
+                magnet.get_trackers(),
+                vec![
+                    "http://127.0.0.1:7878/announce".to_string(),
+                    "udp://tracker.example.com:80".to_string()
+                ]
+            );
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn parse_magnet_with_base32_info_hash() {
+        let hex_uri = "magnet:?xt=urn:btih:3d374e5a6ee6641280499abe5ce8098b2cdff064";
+        let base32_uri = "magnet:?xt=urn:btih:HU3U4WTO4ZSBFACJTK7FZ2AJRMWN74DE";
+        if let (Ok(hex), Ok(base32)) = (MagnetLink::new(hex_uri), MagnetLink::new(base32_uri)) {
+            assert_eq!(hex.get_info_hash(), base32.get_info_hash());
+            assert_eq!(
+                base32.get_name(),
+                "3d374e5a6ee6641280499abe5ce8098b2cdff064"
+            );
+            assert!(base32.get_trackers().is_empty());
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn error_magnet_without_info_hash() {
+        assert!(MagnetLink::new("magnet:?dn=no_hash&tr=http%3A%2F%2Fa.com").is_err());
+        assert!(MagnetLink::new("magnet:?xt=urn:btih:1234").is_err());
+        let multibyte = format!("magnet:?xt=urn:btih:{}%C3%A9a", "a".repeat(37));
+        assert!(MagnetLink::new(&multibyte).is_err());
+        assert!(MagnetLink::new("http://not.a.magnet").is_err());
+    }
+}
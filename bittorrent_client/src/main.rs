@@ -1,17 +1,65 @@
-use c122_albatros_rustico::bittorrent::run_bittorrent;
+use c122_albatros_rustico::errors::{ArgsError, ErrorMessage, HandleError};
+#[cfg(feature = "gui")]
 use c122_albatros_rustico::gui::gui_model::UserInterface;
-use std::sync::mpsc::channel;
-use std::thread;
+use c122_albatros_rustico::headless::terminal_ui::TerminalUserInterface;
+use c122_albatros_rustico::session::Session;
+use std::env;
+use std::process::exit;
+use std::thread::{self, JoinHandle};
 
+/// Usage: <program> [--headless] [--seed] <torrent path> <settings file>
+///     - --headless -> shows the progress in the terminal instead of the GUI
+///       (always the case when the "gui" feature is disabled)
+///     - --seed -> in headless mode, keeps seeding after all torrents are downloaded
+///       (needed to keep a daemon managed by the control API running, see torrentctl)
 fn main() {
-    let (tx_gui, rx_gui) = channel();
-    let bt_thread = thread::spawn(move || {
-        run_bittorrent(rx_gui);
-    });
+    let mut args: Vec<String> = env::args().collect();
+    let headless = take_flag(&mut args, "--headless");
+    let seed = take_flag(&mut args, "--seed");
+    if args.len() != 3 {
+        ArgsError::InvalidNumberOfArguments.print_error();
+        exit(-1);
+    }
+
+    let session = Session::new(&args[2]).handle_error();
+    exit(run_frontend(session, args[1].clone(), headless, seed));
+}
+
+#[cfg(feature = "gui")]
+fn run_frontend(session: Session, torrents_path: String, headless: bool, seed: bool) -> i32 {
+    if headless {
+        let rx_events = session.subscribe();
+        let session_thread = start_session(session, torrents_path);
+        return TerminalUserInterface::run(rx_events, session_thread, seed);
+    }
+
+    let (tx_gui, rx_gui) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    session.add_subscriber(Box::new(tx_gui));
+    let session_thread = start_session(session, torrents_path);
 
-    UserInterface::run(tx_gui);
+    UserInterface::run(rx_gui);
 
-    if bt_thread.join().is_err() {
+    if session_thread.join().is_err() {
         println!("Error during bittorrent thread joining");
     }
+    0
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_frontend(session: Session, torrents_path: String, _headless: bool, seed: bool) -> i32 {
+    let rx_events = session.subscribe();
+    let session_thread = start_session(session, torrents_path);
+    TerminalUserInterface::run(rx_events, session_thread, seed)
+}
+
+/// Runs the session in a new thread. The frontends must be subscribed before.
+fn start_session(session: Session, torrents_path: String) -> JoinHandle<()> {
+    thread::spawn(move || session.run(&torrents_path))
+}
+
+/// Removes a flag from the arguments, returning whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != len
 }
@@ -0,0 +1,95 @@
+use crate::errors::MessageError;
+use crate::p2p_messages::message_trait::Message;
+use sha1::{Digest, Sha1};
+use std::io::{Read, Write};
+use std::net::Ipv4Addr;
+
+/// # struct AllowedFastMsg
+/// Message of the Fast extension (BEP 6). The piece can be requested even while
+/// the sender is choking us.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AllowedFastMsg {
+    _length: u32,
+    id: u8,
+    piece_index: u32,
+}
+
+impl AllowedFastMsg {
+    /// Create and returns an Allowed Fast Message.
+    pub fn new(piece_index: u32) -> AllowedFastMsg {
+        AllowedFastMsg {
+            _length: 5,
+            id: 17,
+            piece_index,
+        }
+    }
+
+    /// Reads an Allowed Fast Message from a stream and returns the message.
+    pub fn read_msg(length: u32, stream: &mut dyn Read) -> Result<AllowedFastMsg, MessageError> {
+        if length != 5 {
+            return Err(MessageError::CreationError);
+        }
+
+        let mut buf = [0u8; 4];
+        stream
+            .read_exact(&mut buf)
+            .map_err(MessageError::ReadingError)?;
+
+        Ok(AllowedFastMsg::new(u32::from_be_bytes(buf)))
+    }
+
+    /// Returns the index of the piece
+    pub fn get_piece_index(&self) -> u32 {
+        self.piece_index
+    }
+}
+
+impl Message for AllowedFastMsg {
+    /// Writes the bytes of an Allowed Fast Message in the received stream.
+    fn send_msg(&self, stream: &mut dyn Write) -> Result<(), MessageError> {
+        stream
+            .write_all(&self._length.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.id.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.piece_index.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        let _ = stream.flush();
+
+        Ok(())
+    }
+}
+
+/// Returns the allowed fast set of a peer (BEP 6): up to set_size pieces generated from
+/// the hash of the peer's IP (the last byte is ignored) and the info hash.
+pub fn generate_allowed_fast_set(
+    ip: Ipv4Addr,
+    info_hash: &[u8],
+    n_pieces: u32,
+    set_size: u32,
+) -> Vec<u32> {
+    let mut allowed_fast = vec![];
+    if n_pieces == 0 {
+        return allowed_fast;
+    }
+    let set_size = set_size.min(n_pieces) as usize;
+
+    let mut x = (u32::from(ip) & 0xFFFFFF00).to_be_bytes().to_vec();
+    x.extend_from_slice(info_hash);
+    while allowed_fast.len() < set_size {
+        x = Sha1::digest(&x).to_vec();
+        for chunk in x.chunks_exact(4) {
+            if allowed_fast.len() >= set_size {
+                break;
+            }
+            let y = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let piece_index = y % n_pieces;
+            if !allowed_fast.contains(&piece_index) {
+                allowed_fast.push(piece_index);
+            }
+        }
+    }
+    allowed_fast
+}
@@ -0,0 +1,79 @@
+use crate::constants::MAX_EXTENDED_MSG_SIZE;
+use crate::errors::MessageError;
+use crate::p2p_messages::message_trait::Message;
+use std::io::{Read, Write};
+
+/// # struct ExtendedMsg
+/// Message of the extension protocol (BEP 10).
+///     - ext_id -> 0 for the extension handshake, otherwise the id that the
+///       receiver assigned to the extension
+///     - payload -> content of the message (defined by each extension)
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExtendedMsg {
+    _length: u32,
+    id: u8,
+    ext_id: u8,
+    payload: Vec<u8>,
+}
+
+impl ExtendedMsg {
+    /// Create and returns an Extended Message.
+    pub fn new(ext_id: u8, payload: Vec<u8>) -> ExtendedMsg {
+        ExtendedMsg {
+            _length: (2 + payload.len()) as u32,
+            id: 20,
+            ext_id,
+            payload,
+        }
+    }
+
+    /// Reads an Extended Message from a stream and returns the message.
+    /// The length is sent by the peer, so messages longer than MAX_EXTENDED_MSG_SIZE
+    /// are rejected before allocating the payload.
+    pub fn read_msg(length: u32, stream: &mut dyn Read) -> Result<ExtendedMsg, MessageError> {
+        if !(2..=MAX_EXTENDED_MSG_SIZE).contains(&length) {
+            return Err(MessageError::CreationError);
+        }
+
+        let mut ext_id = [0u8; 1];
+        stream
+            .read_exact(&mut ext_id)
+            .map_err(MessageError::ReadingError)?;
+
+        let mut payload = vec![0u8; (length - 2) as usize];
+        stream
+            .read_exact(&mut payload)
+            .map_err(MessageError::ReadingError)?;
+
+        Ok(ExtendedMsg::new(ext_id[0], payload))
+    }
+
+    pub fn get_ext_id(&self) -> u8 {
+        self.ext_id
+    }
+
+    pub fn get_payload(&self) -> Vec<u8> {
+        self.payload.clone()
+    }
+}
+
+impl Message for ExtendedMsg {
+    /// Writes the bytes of an Extended Message in the received stream.
+    fn send_msg(&self, stream: &mut dyn Write) -> Result<(), MessageError> {
+        stream
+            .write_all(&self._length.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.id.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.ext_id.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.payload)
+            .map_err(MessageError::SendingError)?;
+        let _ = stream.flush();
+
+        Ok(())
+    }
+}
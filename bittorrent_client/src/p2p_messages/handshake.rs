@@ -1,4 +1,7 @@
 use crate::bt_client::client::Client;
+use crate::constants::{
+    EXTENSION_PROTOCOL_BYTE, EXTENSION_PROTOCOL_MASK, FAST_EXTENSION_BYTE, FAST_EXTENSION_MASK,
+};
 use crate::errors::MessageError;
 use crate::p2p_messages::message_trait::Message;
 
@@ -15,22 +18,20 @@ pub struct Handshake {
 
 impl Handshake {
     /// Create and returns a Handshake.
+    /// We always announce support for the extension protocol and the Fast extension.
     pub fn new(sender: &Client, pstr: &str) -> Handshake {
         let torrent_info = sender.get_torrent_info();
-        Handshake {
-            pstrlen: pstr.len() as u8,
-            pstr: pstr.as_bytes().to_vec(),
-            reserved: vec![0; 8],
-            info_hash: torrent_info.get_info_hash(),
-            peer_id: sender.get_peer_id(),
-        }
+        Handshake::new_from_param(pstr, torrent_info.get_info_hash(), sender.get_peer_id())
     }
 
     pub fn new_from_param(pstr: &str, info_hash: Vec<u8>, peer_id: Vec<u8>) -> Handshake {
+        let mut reserved = vec![0; 8];
+        reserved[EXTENSION_PROTOCOL_BYTE] |= EXTENSION_PROTOCOL_MASK;
+        reserved[FAST_EXTENSION_BYTE] |= FAST_EXTENSION_MASK;
         Handshake {
             pstrlen: pstr.len() as u8,
             pstr: pstr.as_bytes().to_vec(),
-            reserved: vec![0; 8],
+            reserved,
             info_hash,
             peer_id,
         }
@@ -68,6 +69,16 @@ impl Handshake {
         })
     }
 
+    /// Checks if the sender of the handshake supports the extension protocol (BEP 10)
+    pub fn supports_extension_protocol(&self) -> bool {
+        self.reserved[EXTENSION_PROTOCOL_BYTE] & EXTENSION_PROTOCOL_MASK != 0
+    }
+
+    /// Checks if the sender of the handshake supports the Fast extension (BEP 6)
+    pub fn supports_fast_extension(&self) -> bool {
+        self.reserved[FAST_EXTENSION_BYTE] & FAST_EXTENSION_MASK != 0
+    }
+
     pub fn is_valid(&self, info_hash: Vec<u8>) -> bool {
         if self.info_hash != info_hash {
             return false;
@@ -0,0 +1,49 @@
+use crate::errors::MessageError;
+use crate::p2p_messages::message_trait::Message;
+use std::io::Write;
+
+/// # struct HaveAllMsg
+/// Message of the Fast extension (BEP 6), sent instead of a Bitfield when the
+/// sender has all the pieces.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HaveAllMsg {
+    _length: u32,
+    id: u8,
+}
+
+impl HaveAllMsg {
+    /// Create and returns a Have All Message.
+    pub fn new() -> HaveAllMsg {
+        HaveAllMsg { _length: 1, id: 14 }
+    }
+
+    /// Reads a Have All Message from a stream and returns the message.
+    pub fn read_msg(length: u32) -> Result<HaveAllMsg, MessageError> {
+        if length != 1 {
+            return Err(MessageError::CreationError);
+        }
+
+        Ok(HaveAllMsg::new())
+    }
+}
+
+impl Message for HaveAllMsg {
+    /// Writes the bytes of a Have All Message in the received stream.
+    fn send_msg(&self, stream: &mut dyn Write) -> Result<(), MessageError> {
+        stream
+            .write_all(&self._length.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.id.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+
+        let _ = stream.flush();
+        Ok(())
+    }
+}
+
+impl Default for HaveAllMsg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
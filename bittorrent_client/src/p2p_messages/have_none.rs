@@ -0,0 +1,49 @@
+use crate::errors::MessageError;
+use crate::p2p_messages::message_trait::Message;
+use std::io::Write;
+
+/// # struct HaveNoneMsg
+/// Message of the Fast extension (BEP 6), sent instead of a Bitfield when the
+/// sender has no pieces.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HaveNoneMsg {
+    _length: u32,
+    id: u8,
+}
+
+impl HaveNoneMsg {
+    /// Create and returns a Have None Message.
+    pub fn new() -> HaveNoneMsg {
+        HaveNoneMsg { _length: 1, id: 15 }
+    }
+
+    /// Reads a Have None Message from a stream and returns the message.
+    pub fn read_msg(length: u32) -> Result<HaveNoneMsg, MessageError> {
+        if length != 1 {
+            return Err(MessageError::CreationError);
+        }
+
+        Ok(HaveNoneMsg::new())
+    }
+}
+
+impl Message for HaveNoneMsg {
+    /// Writes the bytes of a Have None Message in the received stream.
+    fn send_msg(&self, stream: &mut dyn Write) -> Result<(), MessageError> {
+        stream
+            .write_all(&self._length.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.id.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+
+        let _ = stream.flush();
+        Ok(())
+    }
+}
+
+impl Default for HaveNoneMsg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,19 +1,26 @@
 use std::io::Read;
 
 use crate::errors::MessageError;
+use crate::p2p_messages::allowed_fast::AllowedFastMsg;
 use crate::p2p_messages::bitfield::BitfieldMsg;
 use crate::p2p_messages::cancel::CancelMsg;
 use crate::p2p_messages::choke::ChokeMsg;
+use crate::p2p_messages::extended::ExtendedMsg;
 use crate::p2p_messages::have::HaveMsg;
+use crate::p2p_messages::have_all::HaveAllMsg;
+use crate::p2p_messages::have_none::HaveNoneMsg;
 use crate::p2p_messages::interested::InterestedMsg;
 use crate::p2p_messages::keep_alive::KeepAliveMsg;
 use crate::p2p_messages::not_interested::NotInterestedMsg;
 use crate::p2p_messages::piece::PieceMsg;
+use crate::p2p_messages::reject_request::RejectRequestMsg;
 use crate::p2p_messages::request::RequestMsg;
+use crate::p2p_messages::suggest_piece::SuggestPieceMsg;
 use crate::p2p_messages::unchoke::UnchokeMsg;
 
 /// # enum P2PMessage
-/// Represents the different types of messages in the BitTorrent protocol
+/// Represents the different types of messages in the BitTorrent protocol,
+/// including the ones of the Fast extension (BEP 6)
 pub enum P2PMessage {
     KeepAlive(KeepAliveMsg),
     Choke(ChokeMsg),
@@ -25,6 +32,12 @@ pub enum P2PMessage {
     Request(RequestMsg),
     Piece(PieceMsg),
     Cancel(CancelMsg),
+    SuggestPiece(SuggestPieceMsg),
+    HaveAll(HaveAllMsg),
+    HaveNone(HaveNoneMsg),
+    RejectRequest(RejectRequestMsg),
+    AllowedFast(AllowedFastMsg),
+    Extended(ExtendedMsg),
 }
 
 /// # struct MessageBuilder
@@ -63,6 +76,18 @@ impl MessageBuilder {
             6 => Ok(P2PMessage::Request(RequestMsg::read_msg(length, stream)?)),
             7 => Ok(P2PMessage::Piece(PieceMsg::read_msg(length, stream)?)),
             8 => Ok(P2PMessage::Cancel(CancelMsg::read_msg(length, stream)?)),
+            13 => Ok(P2PMessage::SuggestPiece(SuggestPieceMsg::read_msg(
+                length, stream,
+            )?)),
+            14 => Ok(P2PMessage::HaveAll(HaveAllMsg::read_msg(length)?)),
+            15 => Ok(P2PMessage::HaveNone(HaveNoneMsg::read_msg(length)?)),
+            16 => Ok(P2PMessage::RejectRequest(RejectRequestMsg::read_msg(
+                length, stream,
+            )?)),
+            17 => Ok(P2PMessage::AllowedFast(AllowedFastMsg::read_msg(
+                length, stream,
+            )?)),
+            20 => Ok(P2PMessage::Extended(ExtendedMsg::read_msg(length, stream)?)),
             _ => Err(MessageError::UnknownMessage),
         }
     }
@@ -2,14 +2,20 @@ pub mod message_builder;
 pub mod message_trait;
 mod tests;
 
+pub mod allowed_fast;
 pub mod bitfield;
 pub mod cancel;
 pub mod choke;
+pub mod extended;
 pub mod handshake;
 pub mod have;
+pub mod have_all;
+pub mod have_none;
 pub mod interested;
 pub mod keep_alive;
 pub mod not_interested;
 pub mod piece;
+pub mod reject_request;
 pub mod request;
+pub mod suggest_piece;
 pub mod unchoke;
@@ -0,0 +1,61 @@
+use crate::errors::MessageError;
+use crate::p2p_messages::message_trait::Message;
+use std::io::{Read, Write};
+
+/// # struct SuggestPieceMsg
+/// Message of the Fast extension (BEP 6). The sender suggests downloading the piece
+/// (e.g. because it is in its cache).
+#[derive(Debug, PartialEq, Eq)]
+pub struct SuggestPieceMsg {
+    _length: u32,
+    id: u8,
+    piece_index: u32,
+}
+
+impl SuggestPieceMsg {
+    /// Create and returns a Suggest Piece Message.
+    pub fn new(piece_index: u32) -> SuggestPieceMsg {
+        SuggestPieceMsg {
+            _length: 5,
+            id: 13,
+            piece_index,
+        }
+    }
+
+    /// Reads a Suggest Piece Message from a stream and returns the message.
+    pub fn read_msg(length: u32, stream: &mut dyn Read) -> Result<SuggestPieceMsg, MessageError> {
+        if length != 5 {
+            return Err(MessageError::CreationError);
+        }
+
+        let mut buf = [0u8; 4];
+        stream
+            .read_exact(&mut buf)
+            .map_err(MessageError::ReadingError)?;
+
+        Ok(SuggestPieceMsg::new(u32::from_be_bytes(buf)))
+    }
+
+    /// Returns the index of the piece
+    pub fn get_piece_index(&self) -> u32 {
+        self.piece_index
+    }
+}
+
+impl Message for SuggestPieceMsg {
+    /// Writes the bytes of a Suggest Piece Message in the received stream.
+    fn send_msg(&self, stream: &mut dyn Write) -> Result<(), MessageError> {
+        stream
+            .write_all(&self._length.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.id.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        stream
+            .write_all(&self.piece_index.to_be_bytes())
+            .map_err(MessageError::SendingError)?;
+        let _ = stream.flush();
+
+        Ok(())
+    }
+}
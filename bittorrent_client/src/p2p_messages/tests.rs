@@ -5,18 +5,25 @@ mod tests {
     use crate::p2p_messages::message_builder::P2PMessage;
     use crate::p2p_messages::message_trait::Message;
 
+    use crate::p2p_messages::allowed_fast::{generate_allowed_fast_set, AllowedFastMsg};
     use crate::p2p_messages::bitfield::BitfieldMsg;
     use crate::p2p_messages::cancel::CancelMsg;
     use crate::p2p_messages::choke::ChokeMsg;
+    use crate::p2p_messages::extended::ExtendedMsg;
+    use crate::p2p_messages::handshake::Handshake;
     use crate::p2p_messages::have::HaveMsg;
+    use crate::p2p_messages::have_all::HaveAllMsg;
+    use crate::p2p_messages::have_none::HaveNoneMsg;
     use crate::p2p_messages::interested::InterestedMsg;
     use crate::p2p_messages::keep_alive::KeepAliveMsg;
     use crate::p2p_messages::not_interested::NotInterestedMsg;
     use crate::p2p_messages::piece::PieceMsg;
+    use crate::p2p_messages::reject_request::RejectRequestMsg;
     use crate::p2p_messages::request::RequestMsg;
+    use crate::p2p_messages::suggest_piece::SuggestPieceMsg;
     use crate::p2p_messages::unchoke::UnchokeMsg;
 
-    use std::net::{TcpListener, TcpStream};
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
     use std::sync::mpsc;
     use std::sync::mpsc::Sender;
     use std::thread;
@@ -35,7 +42,7 @@ mod tests {
 
     fn handle_client(tx_cl: Sender<Vec<P2PMessage>>, address: &str) -> Result<(), MessageError> {
         if let Ok(mut stream_cl) = TcpStream::connect(address) {
-            let cl_msgs = receive_msgs(&mut stream_cl, 6)?;
+            let cl_msgs = receive_msgs(&mut stream_cl, 7)?;
             let _ = tx_cl.send(cl_msgs);
 
             let interested_msg = InterestedMsg::new();
@@ -77,6 +84,9 @@ mod tests {
         let piece_msg = PieceMsg::new(5, 0, vec![10, 16, 255])?;
         piece_msg.send_msg(&mut stream_sv)?;
 
+        let extended_msg = ExtendedMsg::new(1, b"d8:msg_typei0e5:piecei0ee".to_vec());
+        extended_msg.send_msg(&mut stream_sv)?;
+
         let sv_msgs = receive_msgs(&mut stream_sv, 4)?;
         let _ = tx_sv.send(sv_msgs);
 
@@ -122,6 +132,12 @@ mod tests {
             PieceMsg::new(5, 0, vec![10, 16, 255]).expect("error message creation");
         let bitfield_expected =
             BitfieldMsg::new(vec![1, 0, 2, 99]).expect("error message creation");
+        let extended_expected = ExtendedMsg::new(1, b"d8:msg_typei0e5:piecei0ee".to_vec());
+
+        match received_cl.pop() {
+            Some(P2PMessage::Extended(m)) => assert_eq!(m, extended_expected),
+            _ => assert!(false),
+        }
 
         match received_cl.pop() {
             Some(P2PMessage::Piece(m)) => assert_eq!(m, piece_expected),
@@ -173,4 +189,83 @@ mod tests {
             _ => assert!(false),
         }
     }
+
+    #[test]
+    fn handshake_announces_extension_protocol() {
+        let handshake = Handshake::new_from_param("BitTorrent protocol", vec![1; 20], vec![2; 20]);
+        let mut bytes = vec![];
+        assert!(handshake.send_msg(&mut bytes).is_ok());
+        assert_eq!(bytes[20..28], [0, 0, 0, 0, 0, 0x10, 0, 0x04]);
+
+        if let Ok(received) = Handshake::read_msg(&mut bytes.as_slice()) {
+            assert!(received.supports_extension_protocol());
+            assert!(received.supports_fast_extension());
+            assert_eq!(received, handshake);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn extended_msg_longer_than_max_is_rejected() {
+        let mut stream: &[u8] = &[0, 0, 0, 0];
+        match ExtendedMsg::read_msg(u32::MAX, &mut stream) {
+            Err(MessageError::CreationError) => assert!(true),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn fast_extension_msgs_are_read() {
+        let mut bytes = vec![];
+        assert!(SuggestPieceMsg::new(3).send_msg(&mut bytes).is_ok());
+        assert!(HaveAllMsg::new().send_msg(&mut bytes).is_ok());
+        assert!(HaveNoneMsg::new().send_msg(&mut bytes).is_ok());
+        assert!(RejectRequestMsg::new(5, 16384, 1024)
+            .send_msg(&mut bytes)
+            .is_ok());
+        assert!(AllowedFastMsg::new(7).send_msg(&mut bytes).is_ok());
+        assert_eq!(bytes[..9], [0, 0, 0, 5, 13, 0, 0, 0, 3]);
+
+        let mut stream = bytes.as_slice();
+        match MessageBuilder::build(&mut stream) {
+            Ok(P2PMessage::SuggestPiece(m)) => assert_eq!(m, SuggestPieceMsg::new(3)),
+            _ => assert!(false),
+        }
+        match MessageBuilder::build(&mut stream) {
+            Ok(P2PMessage::HaveAll(m)) => assert_eq!(m, HaveAllMsg::new()),
+            _ => assert!(false),
+        }
+        match MessageBuilder::build(&mut stream) {
+            Ok(P2PMessage::HaveNone(m)) => assert_eq!(m, HaveNoneMsg::new()),
+            _ => assert!(false),
+        }
+        match MessageBuilder::build(&mut stream) {
+            Ok(P2PMessage::RejectRequest(m)) => {
+                assert_eq!(m, RejectRequestMsg::new(5, 16384, 1024))
+            }
+            _ => assert!(false),
+        }
+        match MessageBuilder::build(&mut stream) {
+            Ok(P2PMessage::AllowedFast(m)) => assert_eq!(m, AllowedFastMsg::new(7)),
+            _ => assert!(false),
+        }
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn allowed_fast_set_of_bep_6() {
+        let ip = Ipv4Addr::new(80, 4, 4, 200);
+        let info_hash = vec![0xaa; 20];
+
+        assert_eq!(
+            generate_allowed_fast_set(ip, &info_hash, 1313, 7),
+            vec![1059, 431, 808, 1217, 287, 376, 1188]
+        );
+        assert_eq!(
+            generate_allowed_fast_set(ip, &info_hash, 1313, 9),
+            vec![1059, 431, 808, 1217, 287, 376, 1188, 353, 508]
+        );
+        assert_eq!(generate_allowed_fast_set(ip, &info_hash, 3, 10).len(), 3);
+    }
 }
@@ -1,49 +1,139 @@
 use sha1::{Digest, Sha1};
 
+pub const BLOCK_LENGTH: u32 = 1 << 14;
+
+/// # enum BlockState
+/// State of a block of a piece:
+///     - Free -> it has to be requested
+///     - Requested -> it was requested to a peer, and it has not arrived yet
+///     - Received -> it is in the data of the piece
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockState {
+    Free,
+    Requested,
+    Received,
+}
+
 /// # struct Piece
 /// Represents a piece of the torrent file:
 ///     - idx -> piece index
 ///     - tl_piece_bytes -> the total amount of bytes of the piece
-///     - dl_piece_bytes -> the amount of bytes already downloaded of the piece
-///     - rq_piece_bytes -> the amount of bytes already requested to a peer
 ///     - expected_hash -> the hash we should end up with after completing download
-///     - data -> the piece data (blocks)
-#[derive(Debug)]
-//#[derive(Clone)]
+///     - blocks -> state of every block of the piece (all of them are BLOCK_LENGTH bytes,
+///       except the last one)
+///     - data -> the piece data. It is allocated with the size of the piece when the first
+///       block arrives, and every block is written in its place, so the blocks can arrive
+///       in any order.
+#[derive(Debug, Clone)]
 pub struct Piece {
     idx: u32,
     tl_piece_bytes: u32,
-    dl_piece_bytes: u32,
-    rq_piece_bytes: u32,
     expected_hash: Vec<u8>,
+    blocks: Vec<BlockState>,
     data: Vec<u8>,
 }
 
 impl Piece {
     pub fn new(idx: u32, tl_piece_bytes: u32, expected_hash: Vec<u8>) -> Piece {
+        let n_blocks = tl_piece_bytes.div_ceil(BLOCK_LENGTH) as usize;
         Piece {
             idx,
             tl_piece_bytes,
-            dl_piece_bytes: 0,
-            rq_piece_bytes: 0,
             expected_hash,
+            blocks: vec![BlockState::Free; n_blocks],
             data: vec![],
         }
     }
 
-    /// Adds a new block to the piece data
-    pub fn add_block(&mut self, mut block: Vec<u8>) {
-        self.data.append(&mut block)
+    /// Creates a piece whose blocks were all received (e.g. read from the files).
+    pub fn with_data(idx: u32, data: Vec<u8>, expected_hash: Vec<u8>) -> Piece {
+        let mut piece = Piece::new(idx, data.len() as u32, expected_hash);
+        piece.blocks.fill(BlockState::Received);
+        piece.data = data;
+        piece
+    }
+
+    /// Returns the size of a block (the last one can be shorter).
+    fn block_length(&self, block: usize) -> u32 {
+        let begin = block as u32 * BLOCK_LENGTH;
+        BLOCK_LENGTH.min(self.tl_piece_bytes - begin)
     }
 
-    /// Return the size of the next block
-    pub fn next_block_length(&self) -> u32 {
-        let block_length = 1 << 14;
-        let left = self.tl_piece_bytes - self.rq_piece_bytes;
-        if left < block_length {
-            return left;
+    /// Returns the block that starts at begin, if there is any.
+    fn block_at(&self, begin: u32) -> Option<usize> {
+        let block = (begin / BLOCK_LENGTH) as usize;
+        if !begin.is_multiple_of(BLOCK_LENGTH) || block >= self.blocks.len() {
+            return None;
         }
-        block_length
+        Some(block)
+    }
+
+    /// Writes a received block in its place of the piece data.
+    /// Returns false if the block is not a block of the piece, or it was already received.
+    pub fn add_block(&mut self, begin: u32, block: Vec<u8>) -> bool {
+        let idx = match self.block_at(begin) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        if self.blocks[idx] == BlockState::Received || block.len() as u32 != self.block_length(idx)
+        {
+            return false;
+        }
+        if self.data.is_empty() {
+            self.data = vec![0; self.tl_piece_bytes as usize];
+        }
+        let begin = begin as usize;
+        self.data[begin..begin + block.len()].copy_from_slice(&block);
+        self.blocks[idx] = BlockState::Received;
+        true
+    }
+
+    /// Returns the first block (begin, length) that has to be requested.
+    pub fn next_free_block(&self) -> Option<(u32, u32)> {
+        let idx = self
+            .blocks
+            .iter()
+            .position(|state| *state == BlockState::Free)?;
+        Some((idx as u32 * BLOCK_LENGTH, self.block_length(idx)))
+    }
+
+    pub fn has_free_blocks(&self) -> bool {
+        self.blocks.contains(&BlockState::Free)
+    }
+
+    /// The block was requested to a peer.
+    pub fn mark_requested(&mut self, begin: u32) {
+        if let Some(idx) = self.block_at(begin) {
+            if self.blocks[idx] == BlockState::Free {
+                self.blocks[idx] = BlockState::Requested;
+            }
+        }
+    }
+
+    /// The peer rejected the request of a block (Fast extension), so it is requested again.
+    pub fn return_block(&mut self, begin: u32) {
+        if let Some(idx) = self.block_at(begin) {
+            if self.blocks[idx] == BlockState::Requested {
+                self.blocks[idx] = BlockState::Free;
+            }
+        }
+    }
+
+    /// Returns the blocks (begin, length) that were requested but have not been received yet.
+    pub fn pending_blocks(&self) -> Vec<(u32, u32)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| **state == BlockState::Requested)
+            .map(|(idx, _)| (idx as u32 * BLOCK_LENGTH, self.block_length(idx)))
+            .collect()
+    }
+
+    /// Returns true if every block was received.
+    pub fn is_complete(&self) -> bool {
+        self.blocks
+            .iter()
+            .all(|state| *state == BlockState::Received)
     }
 
     /// Checks if the downloaded piece is valid. To do this, it compares the hash of downloaded piece
@@ -65,7 +155,12 @@ impl Piece {
 
     // Returns the amount downloaded so far in bytes
     pub fn get_dl(&self) -> u32 {
-        self.dl_piece_bytes
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| **state == BlockState::Received)
+            .map(|(idx, _)| self.block_length(idx))
+            .sum()
     }
 
     // Returns the total piece size in bytes
@@ -73,40 +168,157 @@ impl Piece {
         self.tl_piece_bytes
     }
 
-    // Returns the amount requested to peers so far in bytes
-    pub fn get_rq(&self) -> u32 {
-        self.rq_piece_bytes
-    }
-
-    /// Increases the amount downloaded
-    pub fn add_to_dl(&mut self, bytes: u32) {
-        self.dl_piece_bytes += bytes;
-    }
-
-    /// Increases the amount requested
-    pub fn add_to_rq(&mut self, bytes: u32) {
-        self.rq_piece_bytes += bytes;
-    }
-
     /// Returns the piece data
     pub fn get_data(&self) -> Vec<u8> {
         self.data.clone()
     }
 
+    /// Returns the data of the blocks received from the start of the piece until the
+    /// first block that is missing.
+    pub fn get_contiguous_data(&self) -> Vec<u8> {
+        let blocks = self
+            .blocks
+            .iter()
+            .take_while(|state| **state == BlockState::Received)
+            .count();
+        let length = (blocks as u32 * BLOCK_LENGTH).min(self.tl_piece_bytes) as usize;
+        self.data[..length.min(self.data.len())].to_vec()
+    }
+
     /// Returns the hash the piece should have to be valid
     pub fn get_hash(&mut self) -> Vec<u8> {
         self.expected_hash.clone()
     }
 
-    // Deletes the data we had so far
-    pub fn empty_data(&mut self) {
-        self.data = vec![];
+    /// Forgets the blocks that were requested but not received, so they can be
+    /// requested again. The received blocks are kept.
+    pub fn discard_requests(&mut self) {
+        for state in self.blocks.iter_mut() {
+            if *state == BlockState::Requested {
+                *state = BlockState::Free;
+            }
+        }
+    }
+
+    /// Copies the blocks that another copy of the piece received (endgame mode), and
+    /// returns where they begin. If the other copy lost blocks that this one has (the piece
+    /// was not valid, so it is downloaded again), the received blocks are discarded too.
+    pub fn sync_with(&mut self, other: &Piece) -> Vec<u32> {
+        if self.blocks.len() != other.blocks.len() {
+            return vec![];
+        }
+        let lost =
+            self.blocks.iter().zip(&other.blocks).any(|(mine, theirs)| {
+                *mine == BlockState::Received && *theirs != BlockState::Received
+            });
+        if lost {
+            for state in self.blocks.iter_mut() {
+                if *state == BlockState::Received {
+                    *state = BlockState::Free;
+                }
+            }
+            self.data = vec![];
+            return vec![];
+        }
+
+        let mut copied = vec![];
+        for idx in 0..self.blocks.len() {
+            if self.blocks[idx] == BlockState::Received || other.blocks[idx] != BlockState::Received
+            {
+                continue;
+            }
+            let begin = idx as u32 * BLOCK_LENGTH;
+            let end = (begin + self.block_length(idx)) as usize;
+            if self.add_block(begin, other.data[begin as usize..end].to_vec()) {
+                copied.push(begin);
+            }
+        }
+        copied
+    }
+
+    /// Restores the blocks received in a previous session (the data from the start of
+    /// the piece). Only the complete blocks are restored.
+    pub fn restore_blocks(&mut self, data: Vec<u8>) {
+        if data.len() as u32 >= self.tl_piece_bytes {
+            return;
+        }
+        self.reset_info();
+        for (idx, block) in data.chunks_exact(BLOCK_LENGTH as usize).enumerate() {
+            self.add_block(idx as u32 * BLOCK_LENGTH, block.to_vec());
+        }
     }
 
     // Deletes all of the piece info
     pub fn reset_info(&mut self) {
-        self.dl_piece_bytes = 0;
-        self.rq_piece_bytes = 0;
+        self.blocks.fill(BlockState::Free);
         self.data = vec![];
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_accepted_in_any_order() {
+        let data: Vec<u8> = (0..BLOCK_LENGTH * 2 + 10).map(|i| i as u8).collect();
+        let hash = Sha1::digest(&data).to_vec();
+        let mut piece = Piece::new(0, data.len() as u32, hash);
+        let blocks: Vec<Vec<u8>> = data
+            .chunks(BLOCK_LENGTH as usize)
+            .map(|block| block.to_vec())
+            .collect();
+
+        assert!(piece.add_block(2 * BLOCK_LENGTH, blocks[2].clone()));
+        assert!(piece.get_contiguous_data().is_empty());
+        assert!(piece.add_block(0, blocks[0].clone()));
+        assert!(!piece.add_block(0, blocks[0].clone()));
+        assert!(!piece.add_block(BLOCK_LENGTH, blocks[2].clone()));
+        assert_eq!(piece.get_contiguous_data(), blocks[0]);
+        assert!(!piece.is_complete());
+
+        assert!(piece.add_block(BLOCK_LENGTH, blocks[1].clone()));
+        assert!(piece.is_complete());
+        assert!(piece.piece_is_valid());
+    }
+
+    #[test]
+    fn returned_piece_keeps_the_received_blocks() {
+        let mut piece = Piece::new(0, BLOCK_LENGTH * 3, vec![0; 20]);
+        for _ in 0..3 {
+            if let Some((begin, _)) = piece.next_free_block() {
+                piece.mark_requested(begin);
+            }
+        }
+        assert!(piece.next_free_block().is_none());
+        assert!(piece.add_block(BLOCK_LENGTH, vec![1; BLOCK_LENGTH as usize]));
+
+        piece.discard_requests();
+        assert_eq!(piece.get_dl(), BLOCK_LENGTH);
+        assert_eq!(piece.next_free_block(), Some((0, BLOCK_LENGTH)));
+        assert!(piece.pending_blocks().is_empty());
+        piece.mark_requested(0);
+        assert_eq!(
+            piece.next_free_block(),
+            Some((2 * BLOCK_LENGTH, BLOCK_LENGTH))
+        );
+    }
+
+    #[test]
+    fn copies_of_a_piece_are_synced() {
+        let mut piece = Piece::new(0, BLOCK_LENGTH * 2, vec![0; 20]);
+        let mut copy = piece.clone();
+        piece.mark_requested(0);
+        piece.mark_requested(BLOCK_LENGTH);
+        assert!(copy.add_block(BLOCK_LENGTH, vec![1; BLOCK_LENGTH as usize]));
+
+        assert_eq!(piece.sync_with(&copy), vec![BLOCK_LENGTH]);
+        assert_eq!(piece.pending_blocks(), vec![(0, BLOCK_LENGTH)]);
+        assert!(piece.sync_with(&copy).is_empty());
+
+        // The copy was not valid, so it was reset
+        copy.reset_info();
+        assert!(piece.sync_with(&copy).is_empty());
+        assert_eq!(piece.get_dl(), 0);
+    }
+}
@@ -0,0 +1,296 @@
+use crate::bitfield::PieceBitfield;
+use crate::constants::RESUME_DIR;
+use crate::dht::node_info::{decode_compact_peer, encode_compact_peer, COMPACT_PEER_LENGTH};
+use crate::encoding_decoding::encoder::Encoder;
+use crate::errors::ParseError;
+use crate::torrent_info::TorrentInfo;
+use bencode::BencodeEncoder;
+use bencode::BencodeParser;
+use bencode::BencodeType;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Error;
+use std::net::SocketAddrV4;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// # struct ResumeData
+/// State of a torrent saved by the client, so the next start does not have to hash
+/// all the data again. It is stored bencoded in the resume directory (inside the
+/// downloads directory), in a file named as the info hash.
+/// The bitfield is only trusted if the files of the torrent still have the sizes and
+/// modification times they had when the state was saved.
+///     - bitfield -> our pieces
+///     - files -> (size, mtime) of every file of the torrent
+///     - uploaded -> total bytes uploaded
+///     - downloaded -> total bytes downloaded
+///     - peers -> known peers
+///     - partial_pieces -> downloaded blocks of unfinished pieces, by piece index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeData {
+    bitfield: Vec<u8>,
+    files: Vec<(u64, u64)>,
+    uploaded: u64,
+    downloaded: u64,
+    peers: Vec<SocketAddrV4>,
+    partial_pieces: HashMap<u32, Vec<u8>>,
+}
+
+impl ResumeData {
+    /// Creates the state of a torrent with the current sizes and modification times
+    /// of its files.
+    pub fn new(torrent: &TorrentInfo, dl_dir: &str, bitfield: &PieceBitfield) -> ResumeData {
+        ResumeData {
+            bitfield: bitfield.get_vec(),
+            files: file_stats(torrent, dl_dir).unwrap_or_default(),
+            uploaded: 0,
+            downloaded: 0,
+            peers: vec![],
+            partial_pieces: HashMap::new(),
+        }
+    }
+
+    /// Returns the path of the resume file of a torrent.
+    pub fn path(torrent: &TorrentInfo, dl_dir: &str) -> String {
+        format!(
+            "{}/{}/{}.resume",
+            dl_dir,
+            RESUME_DIR,
+            Encoder.hexencode(&torrent.get_info_hash())
+        )
+    }
+
+    /// Loads the resume file of a torrent.
+    /// On error (there is not any file, or it is invalid), returns ParseError.
+    pub fn load(torrent: &TorrentInfo, dl_dir: &str) -> Result<ResumeData, ParseError> {
+        let path = ResumeData::path(torrent, dl_dir);
+        if !Path::new(&path).exists() {
+            return Err(ParseError::EmptyFilePath);
+        }
+        ResumeData::from_bencode(&BencodeParser.parse_file(&path)?)
+    }
+
+    /// Saves the state in the resume file of the torrent.
+    pub fn save(&self, torrent: &TorrentInfo, dl_dir: &str) -> Result<(), Error> {
+        fs::create_dir_all(format!("{}/{}", dl_dir, RESUME_DIR))?;
+        fs::write(
+            ResumeData::path(torrent, dl_dir),
+            BencodeEncoder.bencode(&self.to_bencode()),
+        )
+    }
+
+    /// Removes the resume file of a torrent, if there is any.
+    pub fn remove(torrent: &TorrentInfo, dl_dir: &str) -> Result<(), Error> {
+        let path = ResumeData::path(torrent, dl_dir);
+        if !Path::new(&path).exists() {
+            return Ok(());
+        }
+        fs::remove_file(path)
+    }
+
+    /// Checks if the files of the torrent are the same that were saved.
+    pub fn matches_files(&self, torrent: &TorrentInfo, dl_dir: &str) -> bool {
+        match file_stats(torrent, dl_dir) {
+            Some(files) => files == self.files,
+            None => false,
+        }
+    }
+
+    pub fn get_bitfield(&self, n_pieces: u32) -> PieceBitfield {
+        PieceBitfield::new_from_vec(self.bitfield.clone(), n_pieces)
+    }
+
+    pub fn get_uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    pub fn get_downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    pub fn get_peers(&self) -> Vec<SocketAddrV4> {
+        self.peers.clone()
+    }
+
+    pub fn get_partial_pieces(&self) -> HashMap<u32, Vec<u8>> {
+        self.partial_pieces.clone()
+    }
+
+    pub fn set_totals(&mut self, uploaded: u64, downloaded: u64) {
+        self.uploaded = uploaded;
+        self.downloaded = downloaded;
+    }
+
+    pub fn set_peers(&mut self, peers: Vec<SocketAddrV4>) {
+        self.peers = peers;
+    }
+
+    pub fn set_partial_pieces(&mut self, partial_pieces: HashMap<u32, Vec<u8>>) {
+        self.partial_pieces = partial_pieces;
+    }
+
+    fn to_bencode(&self) -> BencodeType {
+        let files = self
+            .files
+            .iter()
+            .map(|(length, mtime)| {
+                let mut file = HashMap::new();
+                file.insert(b"length".to_vec(), BencodeType::Integer(*length as i64));
+                file.insert(b"mtime".to_vec(), BencodeType::Integer(*mtime as i64));
+                BencodeType::Dictionary(file)
+            })
+            .collect();
+        let peers = self.peers.iter().flat_map(encode_compact_peer).collect();
+        let partial_pieces = self
+            .partial_pieces
+            .iter()
+            .map(|(idx, data)| {
+                (
+                    idx.to_string().into_bytes(),
+                    BencodeType::String(data.clone()),
+                )
+            })
+            .collect();
+
+        let mut dict = HashMap::new();
+        dict.insert(
+            b"bitfield".to_vec(),
+            BencodeType::String(self.bitfield.clone()),
+        );
+        dict.insert(b"files".to_vec(), BencodeType::List(files));
+        dict.insert(
+            b"uploaded".to_vec(),
+            BencodeType::Integer(self.uploaded as i64),
+        );
+        dict.insert(
+            b"downloaded".to_vec(),
+            BencodeType::Integer(self.downloaded as i64),
+        );
+        dict.insert(b"peers".to_vec(), BencodeType::String(peers));
+        dict.insert(b"partial".to_vec(), BencodeType::Dictionary(partial_pieces));
+        BencodeType::Dictionary(dict)
+    }
+
+    fn from_bencode(dict: &BencodeType) -> Result<ResumeData, ParseError> {
+        let get_integer = |value: &BencodeType, key: &str| {
+            value
+                .get_value_from_dict(key)
+                .and_then(|v| v.get_integer())
+                .map(|v| v as u64)
+                .map_err(|_| ParseError::InvalidFormat)
+        };
+        let get_string = |key: &str| {
+            dict.get_value_from_dict(key)
+                .and_then(|v| v.get_string())
+                .map_err(|_| ParseError::InvalidFormat)
+        };
+
+        let mut files = vec![];
+        let file_list = dict
+            .get_value_from_dict("files")
+            .and_then(|files| files.get_list())
+            .map_err(|_| ParseError::InvalidFormat)?;
+        for file in file_list {
+            files.push((get_integer(&file, "length")?, get_integer(&file, "mtime")?));
+        }
+
+        let peers = get_string("peers")?
+            .chunks(COMPACT_PEER_LENGTH)
+            .filter_map(decode_compact_peer)
+            .collect();
+
+        let mut partial_pieces = HashMap::new();
+        if let Ok(BencodeType::Dictionary(partial)) = dict.get_value_from_dict("partial") {
+            for (idx, data) in partial {
+                if let (Ok(idx), Ok(data)) = (
+                    String::from_utf8_lossy(&idx).parse::<u32>(),
+                    data.get_string(),
+                ) {
+                    partial_pieces.insert(idx, data);
+                }
+            }
+        }
+
+        Ok(ResumeData {
+            bitfield: get_string("bitfield")?,
+            files,
+            uploaded: get_integer(dict, "uploaded")?,
+            downloaded: get_integer(dict, "downloaded")?,
+            peers,
+            partial_pieces,
+        })
+    }
+}
+
+/// Returns the size and modification time (in seconds) of every file of the torrent.
+/// If a file does not exist, returns None.
+fn file_stats(torrent: &TorrentInfo, dl_dir: &str) -> Option<Vec<(u64, u64)>> {
+    let mut stats = vec![];
+    for idx in 0..torrent.get_files().len() {
+        let metadata = fs::metadata(torrent.get_file_path(dl_dir, idx)).ok()?;
+        let mtime = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        stats.push((metadata.len(), mtime));
+    }
+    Some(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+
+    fn multi_file_torrent() -> TorrentInfo {
+        TorrentInfo::new("files_for_testing/torrents_multi_file_testing/multi_file_test.torrent")
+            .expect("It shouldn't fail")
+    }
+
+    #[test]
+    fn saving_and_loading_resume_file() {
+        let torrent = multi_file_torrent();
+        let dl_dir = "files_for_testing/resume_save_test_dir";
+        let _ = Storage::new(torrent.clone(), dl_dir).preallocate();
+
+        let mut bitfield = PieceBitfield::new(torrent.get_n_pieces());
+        bitfield.add_a_piece(1);
+        let mut resume = ResumeData::new(&torrent, dl_dir, &bitfield);
+        resume.set_totals(100, 2000);
+        resume.set_peers(vec!["127.0.0.1:6881".parse().expect("It shouldn't fail")]);
+        resume.set_partial_pieces(HashMap::from([(2, vec![1, 2, 3])]));
+        let saved = resume.save(&torrent, dl_dir);
+        let loaded = ResumeData::load(&torrent, dl_dir);
+        let _ = fs::remove_dir_all(dl_dir);
+
+        assert!(saved.is_ok());
+        let loaded = loaded.expect("It shouldn't fail");
+        assert_eq!(loaded, resume);
+        assert_eq!(loaded.get_bitfield(torrent.get_n_pieces()), bitfield);
+    }
+
+    #[test]
+    fn resume_data_does_not_match_modified_files() {
+        let torrent = multi_file_torrent();
+        let dl_dir = "files_for_testing/resume_match_test_dir";
+        let _ = Storage::new(torrent.clone(), dl_dir).preallocate();
+
+        let resume = ResumeData::new(&torrent, dl_dir, &PieceBitfield::new(1));
+        let matches = resume.matches_files(&torrent, dl_dir);
+        let _ = fs::write(torrent.get_file_path(dl_dir, 0), b"truncated");
+        let matches_after_truncation = resume.matches_files(&torrent, dl_dir);
+        let _ = fs::remove_dir_all(dl_dir);
+
+        assert!(matches);
+        assert!(!matches_after_truncation);
+    }
+
+    #[test]
+    fn missing_resume_file() {
+        let torrent = multi_file_torrent();
+        assert!(ResumeData::load(&torrent, "files_for_testing/no_resume_dir").is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use crate::bt_client::client::Client;
+use crate::bt_server::server::Server;
+use crate::choker::Choker;
+use crate::constants::*;
+use crate::control::control_server::ControlServer;
+use crate::dht::dht_node::DhtNode;
+use crate::errors::HandleError;
+use crate::errors::{ArgsError, SessionError, StorageError};
+use crate::event_messages::{EventBus, EventSubscriber, NewEvent};
+use crate::logging::logger_recv_channel::LoggerRecvChannel;
+use crate::logging::msg_coder::MsgCoder;
+use crate::magnet::MagnetLink;
+use crate::resume::ResumeData;
+use crate::settings::Settings;
+use crate::storage::Storage;
+use crate::swarm::PeerSwarm;
+use crate::torrent_finder::TorrentFinder;
+use crate::torrent_info::{TorrentId, TorrentInfo};
+use crate::torrent_registry::{SessionStats, TorrentRegistry, TorrentStatus};
+use crate::torrent_watcher::TorrentWatcher;
+
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// # struct Session
+/// Runs a set of torrents (client, server and DHT node) and publishes their events,
+/// so any frontend can subscribe to them. While it runs, torrents can be added, paused,
+/// resumed and removed (e.g. by the control API):
+///     - settings
+///     - events -> bus where the events of all torrents are published
+///     - registry -> torrents of the session and queue of torrents waiting for a client
+#[derive(Debug, Clone)]
+pub struct Session {
+    settings: Arc<Settings>,
+    events: EventBus,
+    registry: TorrentRegistry,
+}
+
+impl Session {
+    /// Creates a session with the settings file.
+    /// On error, returns ArgsError (the settings file is missing or invalid).
+    pub fn new(settings_path: &str) -> Result<Session, ArgsError> {
+        let events = EventBus::new();
+        let registry = TorrentRegistry::new();
+        events.add_subscriber(Box::new(registry.clone()));
+
+        Ok(Session {
+            settings: Arc::new(Settings::new(settings_path)?),
+            events,
+            registry,
+        })
+    }
+
+    pub fn get_settings(&self) -> Arc<Settings> {
+        self.settings.clone()
+    }
+
+    /// Subscribes a channel to the events of the session.
+    /// The events published before the subscription are not received.
+    pub fn subscribe(&self) -> Receiver<NewEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn add_subscriber(&self, subscriber: Box<dyn EventSubscriber>) {
+        self.events.add_subscriber(subscriber);
+    }
+
+    /// Adds a torrent from a torrent file or a magnet link. It is queued until
+    /// there is a free client.
+    /// On success, returns the id of the torrent.
+    /// Otherwise, returns SessionError (invalid torrent or already added).
+    pub fn add_torrent(&self, source: &str) -> Result<TorrentId, SessionError> {
+        let torrent = match MagnetLink::is_magnet(source) {
+            true => MagnetLink::new(source).map(|magnet| TorrentInfo::from_magnet(&magnet)),
+            false => TorrentInfo::new(source),
+        }
+        .map_err(|_| SessionError::InvalidTorrent)?;
+        self.add_torrent_info(torrent)
+    }
+
+    /// Adds a parsed torrent (see add_torrent()).
+    pub fn add_torrent_info(&self, torrent: TorrentInfo) -> Result<TorrentId, SessionError> {
+        let torrent_id = torrent.get_id();
+        if self.registry.get_state(torrent_id).is_some() {
+            return Err(SessionError::TorrentAlreadyAdded);
+        }
+
+        let bitfield = TorrentFinder::build_bitfield(&self.settings.get_downloads_dir(), &torrent);
+        let n_dl_pieces = bitfield.number_of_downloaded_pieces();
+        if !self
+            .registry
+            .add((torrent.clone(), Arc::new(RwLock::new(bitfield))))
+        {
+            return Err(SessionError::TorrentAlreadyAdded);
+        }
+        self.events
+            .publish(NewEvent::NewTorrent(torrent_id, torrent, n_dl_pieces));
+        Ok(torrent_id)
+    }
+
+    /// Removes a torrent, stopping its download. If delete_data is set, its files
+    /// and its resume file are deleted too, when its client has stopped.
+    pub fn remove_torrent(
+        &self,
+        torrent_id: TorrentId,
+        delete_data: bool,
+    ) -> Result<(), SessionError> {
+        let torrent = self
+            .registry
+            .remove(torrent_id)
+            .ok_or(SessionError::UnknownTorrent)?;
+        self.events.publish(NewEvent::TorrentRemoved(torrent_id));
+        forget_torrent(&torrent.get_info_hash());
+        if !delete_data {
+            return Ok(());
+        }
+        // Its client could write the files again while it stops
+        while self.registry.is_stopping(torrent_id) {
+            thread::sleep(Duration::from_millis(CLIENT_STOP_POLL_MILLIS));
+        }
+
+        let dl_dir = self.settings.get_downloads_dir();
+        Storage::new(torrent.clone(), &dl_dir)
+            .remove_files()
+            .map_err(SessionError::CannotRemoveData)?;
+        ResumeData::remove(&torrent, &dl_dir)
+            .map_err(|err| SessionError::CannotRemoveData(StorageError::CannotRemoveFile(err)))
+    }
+
+    /// Pauses a torrent: its download is stopped and the server doesn't share it.
+    pub fn pause_torrent(&self, torrent_id: TorrentId) -> Result<(), SessionError> {
+        if !self.registry.pause(torrent_id) {
+            return Err(SessionError::UnknownTorrent);
+        }
+        self.events.publish(NewEvent::TorrentPaused(torrent_id));
+        Ok(())
+    }
+
+    /// Queues a paused torrent again.
+    pub fn resume_torrent(&self, torrent_id: TorrentId) -> Result<(), SessionError> {
+        match self.registry.resume(torrent_id) {
+            true => Ok(()),
+            false => Err(SessionError::UnknownTorrent),
+        }
+    }
+
+    /// Hashes the data of a torrent again, replacing its bitfield with the valid pieces.
+    /// The corrupted pieces are downloaded again: by its client if it is running, or
+    /// queueing the torrent if it was seeding.
+    /// On success, returns the number of valid pieces.
+    pub fn recheck_torrent(&self, torrent_id: TorrentId) -> Result<u32, SessionError> {
+        let (torrent, bitfield) = self
+            .registry
+            .get_torrent(torrent_id)
+            .ok_or(SessionError::UnknownTorrent)?;
+        if !torrent.has_metadata() {
+            return Err(SessionError::MissingMetadata);
+        }
+
+        let dl_dir = self.settings.get_downloads_dir();
+        let n_valid_pieces = TorrentFinder::force_recheck(&dl_dir, &torrent, &bitfield);
+        if n_valid_pieces < torrent.get_n_pieces() {
+            self.registry.download_again(torrent_id);
+        }
+        Ok(n_valid_pieces)
+    }
+
+    pub fn list_torrents(&self) -> Vec<TorrentStatus> {
+        self.registry.list()
+    }
+
+    pub fn get_stats(&self) -> SessionStats {
+        self.registry.get_stats()
+    }
+
+    /// Runs the torrents found in the path (a torrent file, a magnet, or a directory).
+    /// It returns when all torrents are downloaded and the server ends.
+    /// If the control API or the watch mode are enabled in the settings, it keeps running,
+    /// waiting for new torrents.
+    pub fn run(&self, torrents_path: &str) {
+        let settings = self.settings.clone();
+        let torrents =
+            TorrentFinder::find(torrents_path, &settings.get_downloads_dir(), &self.events)
+                .handle_error();
+        for torrent in torrents {
+            self.registry.add(torrent);
+        }
+
+        let (tx_logger, mut logger) =
+            LoggerRecvChannel::new(&settings.get_log_dir()).handle_error();
+
+        // Handling server
+        let sv_thread = handle_server(
+            settings.clone(),
+            Sender::clone(&tx_logger),
+            self.registry.clone(),
+        );
+
+        let dht = start_dht(&settings, Sender::clone(&tx_logger));
+        let control_running = self.start_control_server(Sender::clone(&tx_logger));
+        let watcher_running = self.start_watcher(torrents_path, Sender::clone(&tx_logger));
+
+        // Handling client
+        let cl_threads = handle_client(
+            self.registry.clone(),
+            Sender::clone(&tx_logger),
+            self.events.clone(),
+            settings,
+            dht,
+            control_running || watcher_running,
+        );
+
+        let logger_thread = thread::spawn(move || {
+            while logger.continue_receiving() {
+                if logger.receive().is_err() {
+                    break;
+                }
+            }
+        });
+
+        for thread in cl_threads {
+            if thread.join().is_err() {
+                println!("Error during client threads joining");
+            }
+        }
+        if sv_thread.join().is_err() {
+            println!("Error during server thread joining");
+        }
+        if logger_thread.join().is_err() {
+            println!("Error during logger thread joining");
+        }
+    }
+
+    /// Starts watching the torrents directory for new torrent files, if the settings
+    /// have a watch interval. Returns true if it is running.
+    fn start_watcher(&self, torrents_path: &str, tx_logger: Sender<String>) -> bool {
+        let interval = match self.settings.get_watch_interval() {
+            Some(interval) => Duration::from_secs(interval),
+            None => return false,
+        };
+        if !Path::new(torrents_path).is_dir() {
+            return false;
+        }
+
+        let watcher = TorrentWatcher::new(
+            torrents_path,
+            self.settings.get_move_added_torrents(),
+            tx_logger,
+        );
+        let session = self.clone();
+        thread::spawn(move || watcher.run(session, interval));
+        true
+    }
+
+    /// Starts the control API on the address of the settings, if there is any.
+    /// Returns true if it is running.
+    fn start_control_server(&self, tx_logger: Sender<String>) -> bool {
+        let address = match self.settings.get_control_address() {
+            Some(address) => address,
+            None => return false,
+        };
+
+        match ControlServer::new(&address, self.clone()) {
+            Ok(control_server) => {
+                thread::spawn(move || control_server.run());
+                true
+            }
+            Err(_) => {
+                let _ = tx_logger.send(MsgCoder::generate_message(
+                    ERROR_LOG_TYPE,
+                    CLIENT_MODE_LOG,
+                    format!("Cannot start the control API on {}\n", address),
+                ));
+                false
+            }
+        }
+    }
+}
+
+fn handle_server(
+    settings: Arc<Settings>,
+    tx_logger: Sender<String>,
+    torrents: TorrentRegistry,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        Server::init(settings, tx_logger, torrents);
+    })
+}
+
+/// Starts our DHT node, which is shared by all clients.
+/// The known nodes are cached in the downloads directory.
+/// If the node cannot be started, the clients only use the trackers.
+fn start_dht(settings: &Settings, tx_logger: Sender<String>) -> Option<Arc<DhtNode>> {
+    let bind_addr = format!("0.0.0.0:{}", settings.get_dht_port());
+    let cache_path = format!("{}/{}", settings.get_downloads_dir(), DHT_NODES_FILE);
+    let bootstrap_nodes = DHT_BOOTSTRAP_NODES.iter().map(|n| n.to_string()).collect();
+
+    match DhtNode::start(&bind_addr, bootstrap_nodes, Some(cache_path)) {
+        Ok(dht) => Some(dht),
+        Err(_) => {
+            let _ = tx_logger.send(MsgCoder::generate_message(
+                ERROR_LOG_TYPE,
+                CLIENT_MODE_LOG,
+                format!("Cannot start the DHT node on {}\n", bind_addr),
+            ));
+            None
+        }
+    }
+}
+
+/// Runs MAX_CONCURRENT_TORRENTS clients, which take the torrents of the queue.
+/// When the queue is empty, the clients end, unless keep_waiting is set.
+fn handle_client(
+    registry: TorrentRegistry,
+    tx_logger: Sender<String>,
+    events: EventBus,
+    settings: Arc<Settings>,
+    dht: Option<Arc<DhtNode>>,
+    keep_waiting: bool,
+) -> Vec<thread::JoinHandle<()>> {
+    let mut cl_threads = vec![];
+
+    for _i in 0..MAX_CONCURRENT_TORRENTS {
+        let registry_i = registry.clone();
+        let settings_i = settings.clone();
+        let events_i = events.clone();
+        let tx_logger_i = tx_logger.clone();
+        let dht_i = dht.clone();
+        let client_thread = thread::spawn(move || loop {
+            match registry_i.next_queued() {
+                Some((curr_torrent, stop)) => {
+                    let torrent_id = curr_torrent.0.get_id();
+                    let info_hash = curr_torrent.0.get_info_hash();
+                    let result = Client::init(
+                        settings_i.clone(),
+                        curr_torrent,
+                        Sender::clone(&tx_logger_i),
+                        events_i.clone(),
+                        dht_i.clone(),
+                        stop,
+                    );
+                    registry_i.finish(torrent_id, result.is_ok());
+                    // Its connections could use the torrent while it was being removed
+                    if registry_i.get_torrent(torrent_id).is_none() {
+                        forget_torrent(&info_hash);
+                    }
+                }
+                None if keep_waiting => {
+                    thread::sleep(Duration::from_secs(TORRENT_QUEUE_POLL_INTERVAL))
+                }
+                None => break,
+            }
+        });
+        cl_threads.push(client_thread);
+    }
+
+    cl_threads
+}
+
+/// Forgets the state shared by the connections of a removed torrent.
+fn forget_torrent(info_hash: &[u8]) {
+    Choker::remove(info_hash);
+    PeerSwarm::remove(info_hash);
+}
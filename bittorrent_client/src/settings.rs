@@ -1,3 +1,4 @@
+use crate::constants::REQUEST_QUEUE_LENGTH;
 use crate::errors::ArgsError;
 use std::collections::HashMap;
 use std::fs::File;
@@ -8,11 +9,26 @@ use std::io::{BufRead, BufReader};
 /// - log_dir -> Path of the directory where the log file will be
 /// - tcp_port -> Port on which we will listen for connections
 /// - downloads_dir -> Path of the directory where the downloaded files will be
+/// - dht_port -> UDP port of the DHT node (optional, the tcp port is used by default)
+/// - control_address -> Address of the control API, "host:port" (a loopback address)
+///   or "unix:<socket path>"
+///   (optional, without it the torrents cannot be managed while the client runs)
+/// - watch_interval -> Seconds between scans of the torrents directory, looking for new
+///   torrent files (optional, without it the directory is only scanned at startup)
+/// - move_added_torrents -> "true" to move the torrent files found by the scans to the
+///   "added" subdirectory (optional, false by default)
+/// - request_queue_depth -> Maximum number of blocks requested to a peer that have not
+///   been received yet (optional, 250 by default)
 #[derive(Debug, Clone)]
 pub struct Settings {
     log_dir: String,
     tcp_port: String,
     downloads_dir: String,
+    dht_port: Option<String>,
+    control_address: Option<String>,
+    watch_interval: Option<u64>,
+    move_added_torrents: bool,
+    request_queue_depth: u32,
 }
 
 impl Settings {
@@ -29,12 +45,36 @@ impl Settings {
         let downloads = settings_dict.get(&"download_dir_path".to_string());
         let port = settings_dict.get(&"tcp_port".to_string());
         let log = settings_dict.get(&"logs_dir_path".to_string());
+        let dht_port = settings_dict.get("dht_port").cloned();
+        let control_address = settings_dict.get("control_address").cloned();
+        let watch_interval = match settings_dict.get("watch_interval") {
+            Some(interval) => match interval.parse() {
+                Ok(interval) if interval > 0 => Some(interval),
+                _ => return Err(ArgsError::InvalidSettings),
+            },
+            None => None,
+        };
+        let move_added_torrents = settings_dict
+            .get("move_added_torrents")
+            .is_some_and(|value| value == "true");
+        let request_queue_depth = match settings_dict.get("request_queue_depth") {
+            Some(depth) => match depth.parse() {
+                Ok(depth) if depth > 0 => depth,
+                _ => return Err(ArgsError::InvalidSettings),
+            },
+            None => REQUEST_QUEUE_LENGTH,
+        };
 
         if let (Some(downloads_dir), Some(tcp_port), Some(log_dir)) = (downloads, port, log) {
             return Ok(Settings {
                 log_dir: log_dir.to_string(),
                 tcp_port: tcp_port.to_string(),
                 downloads_dir: downloads_dir.to_string(),
+                dht_port,
+                control_address,
+                watch_interval,
+                move_added_torrents,
+                request_queue_depth,
             });
         }
 
@@ -72,7 +112,15 @@ impl Settings {
             let value = split_line.next();
 
             if let (Some(k), Some(v)) = (key, value) {
-                if k == "tcp_port" || k == "logs_dir_path" || k == "download_dir_path" {
+                if k == "tcp_port"
+                    || k == "logs_dir_path"
+                    || k == "download_dir_path"
+                    || k == "dht_port"
+                    || k == "control_address"
+                    || k == "watch_interval"
+                    || k == "move_added_torrents"
+                    || k == "request_queue_depth"
+                {
                     settings.insert(k.to_string(), v.to_string());
                 }
             }
@@ -91,6 +139,29 @@ impl Settings {
     pub fn get_downloads_dir(&self) -> String {
         self.downloads_dir.clone()
     }
+
+    pub fn get_dht_port(&self) -> String {
+        match &self.dht_port {
+            Some(dht_port) => dht_port.clone(),
+            None => self.tcp_port.clone(),
+        }
+    }
+
+    pub fn get_control_address(&self) -> Option<String> {
+        self.control_address.clone()
+    }
+
+    pub fn get_watch_interval(&self) -> Option<u64> {
+        self.watch_interval
+    }
+
+    pub fn get_move_added_torrents(&self) -> bool {
+        self.move_added_torrents
+    }
+
+    pub fn get_request_queue_depth(&self) -> u32 {
+        self.request_queue_depth
+    }
 }
 
 #[cfg(test)]
@@ -144,6 +215,14 @@ mod tests {
             assert_eq!(l, "log");
             assert_eq!(p, "8080");
             assert_eq!(d, "downloaded_files");
+            assert_eq!(received_settings.get_dht_port(), "8080");
+            assert_eq!(received_settings.get_control_address(), None);
+            assert_eq!(received_settings.get_watch_interval(), None);
+            assert!(!received_settings.get_move_added_torrents());
+            assert_eq!(
+                received_settings.get_request_queue_depth(),
+                REQUEST_QUEUE_LENGTH
+            );
         } else {
             assert!(false);
         }
@@ -164,4 +243,61 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn file_with_dht_port() {
+        let path = "files_for_testing/settings_files_testing/valid_format_dht_port.txt";
+        if let Ok(received_settings) = Settings::new(path) {
+            assert_eq!(received_settings.get_tcp_port(), "8080");
+            assert_eq!(received_settings.get_dht_port(), "6881");
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn file_with_control_address() {
+        let path = "files_for_testing/settings_files_testing/valid_format_control_address.txt";
+        if let Ok(received_settings) = Settings::new(path) {
+            assert_eq!(
+                received_settings.get_control_address(),
+                Some("unix:/tmp/albatros.sock".to_string())
+            );
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn file_with_watch_directory() {
+        let path = "files_for_testing/settings_files_testing/valid_format_watch.txt";
+        if let Ok(received_settings) = Settings::new(path) {
+            assert_eq!(received_settings.get_watch_interval(), Some(5));
+            assert!(received_settings.get_move_added_torrents());
+        } else {
+            assert!(false);
+        }
+
+        let path = "files_for_testing/settings_files_testing/invalid_watch_interval.txt";
+        match Settings::new(path) {
+            Err(ArgsError::InvalidSettings) => assert!(true),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn file_with_request_queue_depth() {
+        let path = "files_for_testing/settings_files_testing/valid_format_request_queue.txt";
+        if let Ok(received_settings) = Settings::new(path) {
+            assert_eq!(received_settings.get_request_queue_depth(), 64);
+        } else {
+            assert!(false);
+        }
+
+        let path = "files_for_testing/settings_files_testing/invalid_request_queue.txt";
+        match Settings::new(path) {
+            Err(ArgsError::InvalidSettings) => assert!(true),
+            _ => assert!(false),
+        }
+    }
 }
@@ -0,0 +1,303 @@
+use crate::bitfield::PieceBitfield;
+use crate::constants::HASH_CHECK_THREADS;
+use crate::errors::StorageError;
+use crate::torrent_info::TorrentInfo;
+
+use sha1::{Digest, Sha1};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+/// # struct Storage
+/// Stores the pieces of a torrent directly in its target files.
+/// The files are preallocated (sparse) with their final size, and each piece is
+/// written at its offset. A piece can contain bytes of more than one file, so every
+/// piece is split following the piece-to-file mapping of the torrent.
+///     - torrent
+///     - dl_dir -> downloads directory
+#[derive(Debug, Clone)]
+pub struct Storage {
+    torrent: TorrentInfo,
+    dl_dir: String,
+}
+
+impl Storage {
+    pub fn new(torrent: TorrentInfo, dl_dir: &str) -> Storage {
+        Storage {
+            torrent,
+            dl_dir: dl_dir.to_string(),
+        }
+    }
+
+    /// Creates every file of the torrent (and its directories) with its final size.
+    /// Existing files keep their content.
+    pub fn preallocate(&self) -> Result<(), StorageError> {
+        for (idx, file) in self.torrent.get_files().iter().enumerate() {
+            let path = self.torrent.get_file_path(&self.dl_dir, idx);
+            if let Some(parent) = path.parent() {
+                if !parent.exists() {
+                    fs::create_dir_all(parent).map_err(StorageError::CannotCreateFile)?;
+                }
+            }
+
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .and_then(|f| f.set_len(file.get_length()))
+                .map_err(StorageError::CannotCreateFile)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the files of the torrent, and the directories of a multi-file torrent
+    /// that are left empty. Missing files are ignored.
+    pub fn remove_files(&self) -> Result<(), StorageError> {
+        let mut dirs = vec![];
+        for idx in 0..self.torrent.get_files().len() {
+            let path = self.torrent.get_file_path(&self.dl_dir, idx);
+            if path.exists() {
+                fs::remove_file(&path).map_err(StorageError::CannotRemoveFile)?;
+            }
+            if self.torrent.is_multi_file() {
+                dirs.extend(path.ancestors().skip(1).map(|dir| dir.to_path_buf()));
+            }
+        }
+
+        let root = Path::new(&self.dl_dir).join(self.torrent.get_name());
+        dirs.retain(|dir| dir.starts_with(&root));
+        // The deepest directories are removed first
+        dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+        dirs.dedup();
+        for dir in dirs {
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    /// Writes the bytes of a piece in the files it belongs to.
+    pub fn write_piece(&self, piece_idx: u32, data: &[u8]) -> Result<(), StorageError> {
+        if data.len() != self.torrent.length_of_piece_n(piece_idx) as usize {
+            return Err(StorageError::InvalidPieceLength);
+        }
+        for slice in self.torrent.piece_file_slices(piece_idx) {
+            let begin = slice.piece_offset as usize;
+            let end = begin + slice.length as usize;
+
+            let path = self.torrent.get_file_path(&self.dl_dir, slice.file_idx);
+            let mut file = OpenOptions::new()
+                .write(true)
+                .open(path)
+                .map_err(StorageError::CannotWritePiece)?;
+            file.seek(SeekFrom::Start(slice.file_offset))
+                .and_then(|_| file.write_all(&data[begin..end]))
+                .map_err(StorageError::CannotWritePiece)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a piece from the files of the torrent.
+    pub fn read_piece(&self, piece_idx: u32) -> Result<Vec<u8>, StorageError> {
+        let mut buffer = vec![0u8; self.torrent.length_of_piece_n(piece_idx) as usize];
+        for slice in self.torrent.piece_file_slices(piece_idx) {
+            let path = self.torrent.get_file_path(&self.dl_dir, slice.file_idx);
+            let begin = slice.piece_offset as usize;
+            let end = begin + slice.length as usize;
+
+            let mut file = File::open(path).map_err(StorageError::CannotReadPiece)?;
+            file.seek(SeekFrom::Start(slice.file_offset))
+                .and_then(|_| file.read_exact(&mut buffer[begin..end]))
+                .map_err(StorageError::CannotReadPiece)?;
+        }
+        Ok(buffer)
+    }
+
+    /// Checks if the piece stored in the files has the hash of the torrent.
+    pub fn has_valid_piece(&self, piece_idx: u32) -> bool {
+        match self.read_piece(piece_idx) {
+            Ok(data) => {
+                let mut hasher = Sha1::new();
+                hasher.update(data);
+                hasher.finalize().to_vec() == self.torrent.get_hash(piece_idx)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Builds the bitfield of the pieces that are already stored in the files.
+    /// Every piece is read and its hash is checked, so missing or corrupted pieces
+    /// are not marked. The pieces are split among several worker threads.
+    pub fn stored_pieces(&self) -> PieceBitfield {
+        let n_workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(HASH_CHECK_THREADS);
+        self.verify_pieces(n_workers)
+    }
+
+    /// Hashes every piece using n_workers threads. Each worker takes the next
+    /// piece that nobody has checked yet.
+    pub fn verify_pieces(&self, n_workers: usize) -> PieceBitfield {
+        let n_pieces = self.torrent.get_n_pieces();
+        let next_piece = AtomicU32::new(0);
+        let mut bitfield = PieceBitfield::new(n_pieces);
+
+        let valid_pieces: Vec<u32> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..n_workers.max(1))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut valid = vec![];
+                        loop {
+                            let idx = next_piece.fetch_add(1, Ordering::Relaxed);
+                            if idx >= n_pieces {
+                                return valid;
+                            }
+                            if self.has_valid_piece(idx) {
+                                valid.push(idx);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap_or_default())
+                .collect()
+        });
+
+        for idx in valid_pieces {
+            bitfield.add_a_piece(idx);
+        }
+        bitfield
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent_data(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn writing_pieces_of_multi_file_torrent() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let dl_dir = "files_for_testing/storage_write_test_dir";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        let data = torrent_data(torrent.get_length() as usize);
+        let storage = Storage::new(torrent.clone(), dl_dir);
+
+        let preallocated = storage.preallocate();
+        let empty_bitfield = storage.stored_pieces();
+        let piece_length = torrent.get_piece_length() as usize;
+        let written = data
+            .chunks(piece_length)
+            .enumerate()
+            .all(|(i, piece)| storage.write_piece(i as u32, piece).is_ok());
+        let full_bitfield = storage.stored_pieces();
+        let file_a = fs::read(format!("{}/multi_file_test/a.txt", dl_dir));
+        let file_b = fs::read(format!("{}/multi_file_test/sub/b.bin", dl_dir));
+        let file_c = fs::read(format!("{}/multi_file_test/c.txt", dl_dir));
+        let _ = fs::remove_dir_all(dl_dir);
+
+        assert!(preallocated.is_ok());
+        assert!(written);
+        assert_eq!(empty_bitfield.number_of_downloaded_pieces(), 0);
+        assert!(full_bitfield.has_all_pieces());
+        assert_eq!(file_a.expect("It shouldn't fail"), data[..20000].to_vec());
+        assert_eq!(
+            file_b.expect("It shouldn't fail"),
+            data[20000..50000].to_vec()
+        );
+        assert_eq!(file_c.expect("It shouldn't fail"), data[50000..].to_vec());
+    }
+
+    #[test]
+    fn reading_piece_from_multiple_files() {
+        let torrent = TorrentInfo::new(
+            "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent",
+        )
+        .expect("It shouldn't fail");
+        let dl_dir = "files_for_testing/storage_read_test_dir";
+        let data = torrent_data(torrent.get_length() as usize);
+        for (idx, file) in torrent.get_files().iter().enumerate() {
+            let path = torrent.get_file_path(dl_dir, idx);
+            let begin = file.get_offset() as usize;
+            let end = begin + file.get_length() as usize;
+            let _ = fs::create_dir_all(path.parent().expect("It shouldn't fail"));
+            fs::write(path, &data[begin..end]).expect("It shouldn't fail");
+        }
+
+        let storage = Storage::new(torrent.clone(), dl_dir);
+        let piece = storage.read_piece(3);
+        let valid = storage.has_valid_piece(3);
+        let _ = fs::remove_dir_all(dl_dir);
+
+        if let Ok(piece) = piece {
+            let mut hasher = Sha1::new();
+            hasher.update(piece);
+            assert_eq!(torrent.get_hash(3), hasher.finalize().to_vec());
+            assert!(valid);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn corrupted_pieces_are_not_verified() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let dl_dir = "files_for_testing/storage_verify_test_dir";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        let data = torrent_data(torrent.get_length() as usize);
+        let storage = Storage::new(torrent.clone(), dl_dir);
+
+        let _ = storage.preallocate();
+        let piece_length = torrent.get_piece_length() as usize;
+        for (i, piece) in data.chunks(piece_length).enumerate() {
+            let _ = storage.write_piece(i as u32, piece);
+        }
+        let mut corrupted = data[..piece_length].to_vec();
+        corrupted[0] ^= 0xff;
+        let _ = storage.write_piece(0, &corrupted);
+        let bitfield = storage.verify_pieces(3);
+        let _ = fs::remove_dir_all(dl_dir);
+
+        assert!(!bitfield.has_piece(0));
+        assert_eq!(
+            bitfield.number_of_downloaded_pieces(),
+            torrent.get_n_pieces() - 1
+        );
+    }
+
+    #[test]
+    fn removing_files_of_multi_file_torrent() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let dl_dir = "files_for_testing/storage_remove_test_dir";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        let storage = Storage::new(torrent, dl_dir);
+
+        let preallocated = storage.preallocate();
+        let _ = fs::write(format!("{}/other_file.txt", dl_dir), "not in the torrent");
+        let removed = storage.remove_files();
+        let torrent_dir_exists = Path::new(dl_dir).join("multi_file_test").exists();
+        let other_file_exists = Path::new(dl_dir).join("other_file.txt").exists();
+        let _ = fs::remove_dir_all(dl_dir);
+
+        assert!(preallocated.is_ok());
+        assert!(removed.is_ok());
+        assert!(!torrent_dir_exists);
+        assert!(other_file_exists);
+    }
+
+    #[test]
+    fn pieces_with_invalid_length_are_not_written() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        let storage = Storage::new(torrent, "files_for_testing/storage_invalid_test_dir");
+
+        assert!(storage.write_piece(0, &[0u8; 10]).is_err());
+    }
+}
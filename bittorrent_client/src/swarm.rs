@@ -0,0 +1,160 @@
+use crate::constants::PEX_MAX_DISCOVERED;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+type Swarms = Mutex<HashMap<Vec<u8>, Arc<Mutex<PeerSwarm>>>>;
+
+/// Swarms of every torrent, by info hash. They are shared by the inbound (server)
+/// and outbound (client) connections of the torrent.
+static SWARMS: OnceLock<Swarms> = OnceLock::new();
+
+/// # struct PeerSwarm
+/// Peers of a torrent, exchanged with the ut_pex extension (BEP 11).
+/// Fields:
+///     - connected -> listening addresses of the peers we are connected to,
+///       with their number of connections
+///     - discovered -> peers received from other peers, waiting for the client to connect to them
+#[derive(Debug, Default)]
+pub struct PeerSwarm {
+    connected: HashMap<SocketAddr, u32>,
+    discovered: Vec<SocketAddr>,
+}
+
+impl PeerSwarm {
+    pub fn new() -> PeerSwarm {
+        PeerSwarm::default()
+    }
+
+    /// Returns the swarm of the torrent, creating it if it does not exist yet.
+    pub fn for_torrent(info_hash: &[u8]) -> Arc<Mutex<PeerSwarm>> {
+        let swarms = SWARMS.get_or_init(|| Mutex::new(HashMap::new()));
+        match swarms.lock() {
+            Ok(mut swarms) => swarms
+                .entry(info_hash.to_vec())
+                .or_insert_with(|| Arc::new(Mutex::new(PeerSwarm::new())))
+                .clone(),
+            Err(_) => Arc::new(Mutex::new(PeerSwarm::new())),
+        }
+    }
+
+    /// Forgets the swarm of a removed torrent.
+    pub fn remove(info_hash: &[u8]) {
+        if let Some(swarms) = SWARMS.get() {
+            if let Ok(mut swarms) = swarms.lock() {
+                swarms.remove(info_hash);
+            }
+        }
+    }
+
+    /// A new connection with the peer was established.
+    pub fn add_connected(&mut self, addr: SocketAddr) {
+        *self.connected.entry(addr).or_default() += 1;
+        self.discovered.retain(|discovered| *discovered != addr);
+    }
+
+    /// A connection with the peer was closed. When the peer has not any connection left,
+    /// it is not connected anymore.
+    pub fn remove_connected(&mut self, addr: SocketAddr) {
+        if let Some(connections) = self.connected.get_mut(&addr) {
+            *connections = connections.saturating_sub(1);
+            if *connections == 0 {
+                self.connected.remove(&addr);
+            }
+        }
+    }
+
+    pub fn get_connected(&self) -> Vec<SocketAddr> {
+        self.connected.keys().copied().collect()
+    }
+
+    /// Adds the peers we are not connected to yet. The oldest discovered peers
+    /// are forgotten if there are too many.
+    pub fn add_discovered(&mut self, peers: Vec<SocketAddr>) {
+        for peer in peers {
+            if peer.port() == 0
+                || self.connected.contains_key(&peer)
+                || self.discovered.contains(&peer)
+            {
+                continue;
+            }
+            self.discovered.push(peer);
+        }
+        if self.discovered.len() > PEX_MAX_DISCOVERED {
+            let excess = self.discovered.len() - PEX_MAX_DISCOVERED;
+            self.discovered.drain(..excess);
+        }
+    }
+
+    /// Forgets discovered peers that left the swarm.
+    pub fn remove_discovered(&mut self, peers: &[SocketAddr]) {
+        self.discovered.retain(|peer| !peers.contains(peer));
+    }
+
+    /// Returns up to max discovered peers (the oldest ones), so the client connects
+    /// to them. The other ones are kept.
+    pub fn take_discovered(&mut self, max: usize) -> Vec<SocketAddr> {
+        let n_peers = max.min(self.discovered.len());
+        self.discovered.drain(..n_peers).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connected_peers_are_not_discovered() {
+        let connected: SocketAddr = "10.0.0.1:6881".parse().expect("It shouldn't fail");
+        let new_peer: SocketAddr = "[2001:db8::2]:6881".parse().expect("It shouldn't fail");
+        let mut swarm = PeerSwarm::new();
+
+        swarm.add_connected(connected);
+        swarm.add_discovered(vec![connected, new_peer, new_peer]);
+
+        assert_eq!(swarm.get_connected(), vec![connected]);
+        assert_eq!(swarm.take_discovered(PEX_MAX_DISCOVERED), vec![new_peer]);
+        assert!(swarm.take_discovered(PEX_MAX_DISCOVERED).is_empty());
+    }
+
+    #[test]
+    fn removed_swarms_are_forgotten() {
+        let info_hash = vec![0x5a; 20];
+        let peer: SocketAddr = "10.0.0.1:6881".parse().expect("It shouldn't fail");
+        if let Ok(mut swarm) = PeerSwarm::for_torrent(&info_hash).lock() {
+            swarm.add_discovered(vec![peer]);
+        }
+
+        PeerSwarm::remove(&info_hash);
+        let swarm = PeerSwarm::for_torrent(&info_hash);
+        let mut swarm = swarm.lock().expect("It shouldn't fail");
+        assert!(swarm.take_discovered(PEX_MAX_DISCOVERED).is_empty());
+    }
+
+    #[test]
+    fn discovered_peers_are_taken_in_order() {
+        let peers: Vec<SocketAddr> = (1..=3)
+            .map(|i| SocketAddr::from(([10, 0, 0, i], 6881)))
+            .collect();
+        let mut swarm = PeerSwarm::new();
+        swarm.add_discovered(peers.clone());
+
+        assert!(swarm.take_discovered(0).is_empty());
+        assert_eq!(swarm.take_discovered(2), peers[..2].to_vec());
+        assert_eq!(swarm.take_discovered(2), peers[2..].to_vec());
+    }
+
+    #[test]
+    fn peer_is_connected_until_its_last_connection_is_closed() {
+        let peer: SocketAddr = "10.0.0.1:6881".parse().expect("It shouldn't fail");
+        let mut swarm = PeerSwarm::new();
+
+        swarm.add_connected(peer);
+        swarm.add_connected(peer);
+        swarm.remove_connected(peer);
+        assert_eq!(swarm.get_connected(), vec![peer]);
+        swarm.remove_connected(peer);
+        assert!(swarm.get_connected().is_empty());
+    }
+}
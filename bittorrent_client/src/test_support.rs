@@ -0,0 +1,30 @@
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+
+/// Builds the target file of the ubuntu torrent used by the tests, writing the pieces
+/// stored in files_for_testing (0 and 10) at their offsets. The file is written with
+/// another name and then renamed, so tests running at the same time see a complete file.
+pub fn build_testing_downloads() {
+    let dir = Path::new("files_for_testing/downloaded_files");
+    let name = "ubuntu-20.04.4-desktop-amd64.iso";
+    let piece_length = 262144;
+    let target = dir.join(name);
+    if target.exists() {
+        return;
+    }
+
+    let tmp = dir.join(format!("{}.{:?}.tmp", name, thread::current().id()));
+    let built = File::create(&tmp).and_then(|mut file| {
+        for idx in [0, 10] {
+            let piece = fs::read(dir.join(format!("{}_piece_{}", name, idx)))?;
+            file.seek(SeekFrom::Start(idx * piece_length))?;
+            file.write_all(&piece)?;
+        }
+        Ok(())
+    });
+    if built.and_then(|_| fs::rename(&tmp, target)).is_err() {
+        let _ = fs::remove_file(tmp);
+    }
+}
@@ -0,0 +1,355 @@
+use crate::constants::{CLIENT_VERSION, MIN_PIECE_LENGTH};
+use crate::errors::CreatorError;
+use bencode::BencodeEncoder;
+use bencode::BencodeType;
+
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// # struct TorrentCreator
+/// Creates a torrent file from a local file or directory.
+/// The data is read in order (the files of a directory are sorted by path) and
+/// split in pieces of piece_length bytes, which are hashed with SHA1.
+///     - path -> file or directory to share
+///     - piece_length -> must be a power of two, not less than 16 KiB
+///     - announce_list -> tiers of tracker urls. The first url is also the 'announce' key
+///     - comment
+///     - created_by
+///     - creation_date -> seconds since the epoch (None to not include it)
+///     - private -> peers must be obtained only from the trackers (BEP 27)
+#[derive(Debug, Clone)]
+pub struct TorrentCreator {
+    path: PathBuf,
+    piece_length: u32,
+    announce_list: Vec<Vec<String>>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+    private: bool,
+}
+
+impl TorrentCreator {
+    /// Creates a torrent creator for the file or directory, using the current time
+    /// as creation date.
+    pub fn new(path: &str, piece_length: u32) -> TorrentCreator {
+        let creation_date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs() as i64)
+            .ok();
+        TorrentCreator {
+            path: PathBuf::from(path),
+            piece_length,
+            announce_list: vec![],
+            comment: None,
+            created_by: Some(CLIENT_VERSION.to_string()),
+            creation_date,
+            private: false,
+        }
+    }
+
+    /// Adds a tier of trackers. Empty urls are ignored.
+    pub fn add_tier(&mut self, tier: Vec<String>) {
+        let tier: Vec<String> = tier.into_iter().filter(|url| !url.is_empty()).collect();
+        if !tier.is_empty() {
+            self.announce_list.push(tier);
+        }
+    }
+
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        self.comment = comment;
+    }
+
+    pub fn set_created_by(&mut self, created_by: Option<String>) {
+        self.created_by = created_by;
+    }
+
+    pub fn set_creation_date(&mut self, creation_date: Option<i64>) {
+        self.creation_date = creation_date;
+    }
+
+    pub fn set_private(&mut self, private: bool) {
+        self.private = private;
+    }
+
+    /// Hashes the data and returns the bencoded torrent.
+    /// On error (invalid piece length, no data or unreadable files), returns CreatorError.
+    pub fn create(&self) -> Result<Vec<u8>, CreatorError> {
+        if self.piece_length < MIN_PIECE_LENGTH || !self.piece_length.is_power_of_two() {
+            return Err(CreatorError::InvalidPieceLength);
+        }
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or(CreatorError::NoSuchPath)?;
+        let files = self.list_files()?;
+        if files.iter().map(|(_, length)| length).sum::<u64>() == 0 {
+            return Err(CreatorError::EmptyTorrent);
+        }
+
+        let mut info = HashMap::new();
+        info.insert(b"name".to_vec(), BencodeType::String(name.into_bytes()));
+        info.insert(
+            b"piece length".to_vec(),
+            BencodeType::Integer(self.piece_length as i64),
+        );
+        info.insert(
+            b"pieces".to_vec(),
+            BencodeType::String(self.hash_pieces(&files)?),
+        );
+        if self.path.is_dir() {
+            info.insert(b"files".to_vec(), self.files_list(&files));
+        } else {
+            info.insert(b"length".to_vec(), BencodeType::Integer(files[0].1 as i64));
+        }
+        if self.private {
+            info.insert(b"private".to_vec(), BencodeType::Integer(1));
+        }
+
+        Ok(BencodeEncoder.bencode(&self.torrent_dict(info)))
+    }
+
+    /// Creates the torrent and writes it in the output path.
+    pub fn write(&self, output_path: &str) -> Result<(), CreatorError> {
+        let torrent = self.create()?;
+        fs::write(output_path, torrent).map_err(CreatorError::CannotWriteTorrent)
+    }
+
+    /// Builds the top level dictionary of the torrent.
+    fn torrent_dict(&self, info: HashMap<Vec<u8>, BencodeType>) -> BencodeType {
+        let mut dict = HashMap::new();
+        dict.insert(b"info".to_vec(), BencodeType::Dictionary(info));
+        if let Some(announce) = self.announce_list.first().and_then(|tier| tier.first()) {
+            dict.insert(
+                b"announce".to_vec(),
+                BencodeType::String(announce.as_bytes().to_vec()),
+            );
+        }
+        if self.announce_list.len() > 1 || self.announce_list.iter().any(|tier| tier.len() > 1) {
+            let tiers = self
+                .announce_list
+                .iter()
+                .map(|tier| {
+                    BencodeType::List(
+                        tier.iter()
+                            .map(|url| BencodeType::String(url.as_bytes().to_vec()))
+                            .collect(),
+                    )
+                })
+                .collect();
+            dict.insert(b"announce-list".to_vec(), BencodeType::List(tiers));
+        }
+        if let Some(comment) = &self.comment {
+            dict.insert(
+                b"comment".to_vec(),
+                BencodeType::String(comment.as_bytes().to_vec()),
+            );
+        }
+        if let Some(created_by) = &self.created_by {
+            dict.insert(
+                b"created by".to_vec(),
+                BencodeType::String(created_by.as_bytes().to_vec()),
+            );
+        }
+        if let Some(creation_date) = self.creation_date {
+            dict.insert(
+                b"creation date".to_vec(),
+                BencodeType::Integer(creation_date),
+            );
+        }
+        BencodeType::Dictionary(dict)
+    }
+
+    /// Returns the files to share with their lengths.
+    /// A directory is walked recursively and its files are sorted by path.
+    fn list_files(&self) -> Result<Vec<(PathBuf, u64)>, CreatorError> {
+        if !self.path.exists() {
+            return Err(CreatorError::NoSuchPath);
+        }
+        let mut paths = vec![];
+        if self.path.is_dir() {
+            walk_dir(&self.path, &mut paths)?;
+        } else {
+            paths.push(self.path.clone());
+        }
+        paths.sort();
+
+        let mut files = vec![];
+        for path in paths {
+            let length = fs::metadata(&path)
+                .map_err(CreatorError::CannotReadFile)?
+                .len();
+            files.push((path, length));
+        }
+        Ok(files)
+    }
+
+    /// Builds the 'files' list of a multi-file torrent. The paths are relative to
+    /// the shared directory.
+    fn files_list(&self, files: &[(PathBuf, u64)]) -> BencodeType {
+        let list = files
+            .iter()
+            .map(|(path, length)| {
+                let components = path
+                    .strip_prefix(&self.path)
+                    .unwrap_or(path)
+                    .iter()
+                    .map(|c| BencodeType::String(c.to_string_lossy().as_bytes().to_vec()))
+                    .collect();
+                let mut file = HashMap::new();
+                file.insert(b"length".to_vec(), BencodeType::Integer(*length as i64));
+                file.insert(b"path".to_vec(), BencodeType::List(components));
+                BencodeType::Dictionary(file)
+            })
+            .collect();
+        BencodeType::List(list)
+    }
+
+    /// Reads the files as one stream of bytes and returns the concatenated hashes
+    /// of its pieces. Only one piece is kept in memory.
+    fn hash_pieces(&self, files: &[(PathBuf, u64)]) -> Result<Vec<u8>, CreatorError> {
+        let mut hashes = vec![];
+        let mut piece = vec![0u8; self.piece_length as usize];
+        let mut filled = 0;
+
+        for (path, _) in files {
+            let mut file = File::open(path).map_err(CreatorError::CannotReadFile)?;
+            loop {
+                let read = file
+                    .read(&mut piece[filled..])
+                    .map_err(CreatorError::CannotReadFile)?;
+                if read == 0 {
+                    break;
+                }
+                filled += read;
+                if filled == piece.len() {
+                    hashes.extend_from_slice(&Sha1::digest(&piece));
+                    filled = 0;
+                }
+            }
+        }
+        if filled > 0 {
+            hashes.extend_from_slice(&Sha1::digest(&piece[..filled]));
+        }
+        Ok(hashes)
+    }
+}
+
+/// Adds the paths of all the files inside the directory (and its subdirectories).
+/// Symbolic links are skipped: they could point outside the directory, or to one of
+/// its parents (an endless loop).
+fn walk_dir(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<(), CreatorError> {
+    for entry in fs::read_dir(dir).map_err(CreatorError::CannotReadFile)? {
+        let entry = entry.map_err(CreatorError::CannotReadFile)?;
+        let file_type = entry.file_type().map_err(CreatorError::CannotReadFile)?;
+        if file_type.is_symlink() {
+            continue;
+        }
+        if file_type.is_dir() {
+            walk_dir(&entry.path(), paths)?;
+        } else {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use crate::torrent_info::TorrentInfo;
+
+    fn write_test_dir(dir: &str) {
+        let data: Vec<u8> = (0..55000).map(|i| (i % 251) as u8).collect();
+        let _ = fs::create_dir_all(format!("{}/shared/sub", dir));
+        let _ = fs::write(format!("{}/shared/a.txt", dir), &data[..20000]);
+        let _ = fs::write(format!("{}/shared/sub/b.bin", dir), &data[20000..50000]);
+        let _ = fs::write(format!("{}/shared/c.txt", dir), &data[50000..]);
+    }
+
+    #[test]
+    fn creating_multi_file_torrent() {
+        let dir = "files_for_testing/creator_multi_test_dir";
+        write_test_dir(dir);
+        #[cfg(unix)]
+        {
+            // The links are not added
+            let _ = std::os::unix::fs::symlink("../a.txt", format!("{}/shared/sub/a.txt", dir));
+            let _ = std::os::unix::fs::symlink("..", format!("{}/shared/sub/parent", dir));
+        }
+        let mut creator = TorrentCreator::new(&format!("{}/shared", dir), 16384);
+        creator.add_tier(vec!["http://127.0.0.1:7878/announce".to_string()]);
+        creator.add_tier(vec!["udp://127.0.0.1:7879".to_string()]);
+        creator.set_comment(Some("test".to_string()));
+        creator.set_private(true);
+        let output = format!("{}/shared.torrent", dir);
+        let written = creator.write(&output);
+
+        let torrent = TorrentInfo::new(&output);
+        let stored = torrent
+            .as_ref()
+            .ok()
+            .map(|t| Storage::new(t.clone(), dir).stored_pieces());
+        let _ = fs::remove_dir_all(dir);
+
+        assert!(written.is_ok());
+        let torrent = torrent.expect("It shouldn't fail");
+        assert_eq!(torrent.get_name(), "shared");
+        assert!(torrent.is_private());
+        assert_eq!(torrent.get_length(), 55000);
+        assert_eq!(torrent.get_n_pieces(), 4);
+        assert_eq!(
+            torrent.get_announce_list(),
+            vec![
+                vec!["http://127.0.0.1:7878/announce".to_string()],
+                vec!["udp://127.0.0.1:7879".to_string()]
+            ]
+        );
+        let paths: Vec<Vec<String>> = torrent.get_files().iter().map(|f| f.get_path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                vec!["a.txt".to_string()],
+                vec!["c.txt".to_string()],
+                vec!["sub".to_string(), "b.bin".to_string()]
+            ]
+        );
+        assert!(stored.expect("It shouldn't fail").has_all_pieces());
+    }
+
+    #[test]
+    fn creating_single_file_torrent() {
+        let dir = "files_for_testing/creator_single_test_dir";
+        write_test_dir(dir);
+        let mut creator = TorrentCreator::new(&format!("{}/shared/a.txt", dir), 16384);
+        creator.set_creation_date(None);
+        let output = format!("{}/a.torrent", dir);
+        let written = creator.write(&output);
+        let content = fs::read(&output).unwrap_or_default();
+        let torrent = TorrentInfo::new(&output);
+        let _ = fs::remove_dir_all(dir);
+
+        assert!(written.is_ok());
+        let torrent = torrent.expect("It shouldn't fail");
+        assert!(!torrent.is_multi_file());
+        assert!(!torrent.is_private());
+        assert_eq!(torrent.get_name(), "a.txt");
+        assert_eq!(torrent.get_length(), 20000);
+        assert_eq!(torrent.get_n_pieces(), 2);
+        assert!(!String::from_utf8_lossy(&content).contains("creation date"));
+    }
+
+    #[test]
+    fn invalid_piece_length() {
+        let creator = TorrentCreator::new("files_for_testing/downloaded_files", 10000);
+        assert!(matches!(
+            creator.create(),
+            Err(CreatorError::InvalidPieceLength)
+        ));
+    }
+}
@@ -1,56 +1,80 @@
 use crate::bitfield::PieceBitfield;
 use crate::errors::ArgsError;
-use crate::event_messages::NewEvent;
+use crate::event_messages::{EventBus, NewEvent};
+use crate::magnet::MagnetLink;
+use crate::resume::ResumeData;
+use crate::storage::Storage;
 use crate::torrent_info::TorrentInfo;
 
 use std::fs::{self, read_dir};
 use std::path::Path;
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, RwLock};
 
 type TorrentCombo = (TorrentInfo, Arc<RwLock<PieceBitfield>>);
-type ReceiverOfGtkSender = Receiver<glib::Sender<NewEvent>>;
 /// # struct TorrentFinder
-
 pub struct TorrentFinder;
 
 impl TorrentFinder {
     /// It finds all .torrent files in a directory, parses them, and builds
     /// a bitfield (per torrent) with the downloaded pieces.
+    /// A NewTorrent event is published for each torrent.
     pub fn find(
         dir_path: &str,
         dl_path: &str,
-        rx_gui: Arc<Mutex<ReceiverOfGtkSender>>,
+        events: &EventBus,
     ) -> Result<Vec<TorrentCombo>, ArgsError> {
         let all_torrents = TorrentFinder::find_in(dir_path)?;
-        if let Ok(rx_gui_lock) = rx_gui.lock() {
-            if let Ok(tx_gtk) = rx_gui_lock.recv() {
-                let mut vec_torrents = vec![];
-                for torrent in all_torrents {
-                    if let Ok(new_torrent) = TorrentInfo::new(&torrent) {
-                        let name = new_torrent.get_name();
-                        let n_pieces = new_torrent.get_n_pieces();
-                        let bitfield = TorrentFinder::build_bitfield(dl_path, &name, n_pieces);
-                        let _ = tx_gtk.send(NewEvent::NewTorrent(
-                            new_torrent.clone(),
-                            bitfield.number_of_downloaded_pieces(),
-                            "Single File".to_string(),
-                        ));
-                        vec_torrents.push((new_torrent, Arc::new(RwLock::new(bitfield))));
-                    }
-                }
-                return Ok(vec_torrents);
-            }
+        let mut vec_torrents = vec![];
+        for new_torrent in all_torrents.iter().flat_map(|t| TorrentFinder::load(t)) {
+            let bitfield = TorrentFinder::build_bitfield(dl_path, &new_torrent);
+            events.publish(NewEvent::NewTorrent(
+                new_torrent.get_id(),
+                new_torrent.clone(),
+                bitfield.number_of_downloaded_pieces(),
+            ));
+            vec_torrents.push((new_torrent, Arc::new(RwLock::new(bitfield))));
+        }
+        Ok(vec_torrents)
+    }
+
+    /// Gets the torrents described by a path found by find_in():
+    ///     - a torrent file
+    ///     - a magnet URI
+    ///     - a magnet file, which contains one magnet URI per line
+    /// Torrents created from magnet links have not metadata yet.
+    pub fn load(path: &str) -> Vec<TorrentInfo> {
+        if MagnetLink::is_magnet(path) {
+            return MagnetLink::new(path)
+                .map(|magnet| vec![TorrentInfo::from_magnet(&magnet)])
+                .unwrap_or_default();
+        }
+
+        if TorrentFinder::is_magnet_file(path) {
+            return match fs::read_to_string(path) {
+                Ok(content) => content
+                    .lines()
+                    .flat_map(MagnetLink::new)
+                    .map(|magnet| TorrentInfo::from_magnet(&magnet))
+                    .collect(),
+                Err(_) => vec![],
+            };
+        }
+
+        match TorrentInfo::new(path) {
+            Ok(torrent) => vec![torrent],
+            Err(_) => vec![],
         }
-        Err(ArgsError::CannotConnectToGUI)
     }
 
-    /// In case of receiving a path of a single torrent file, returns it.
+    /// In case of receiving a path of a single torrent file (or a magnet), returns it.
     /// In the case of receiving a directory, it loops through this and its sub directories.
     /// Then, it finds torrent files and returns a vector that contains all paths of found torrent files.
     /// If the file or directory does not exist, it returns error.
-    fn find_in(dir_path: &str) -> Result<Vec<String>, ArgsError> {
-        if TorrentFinder::is_single_torrent(dir_path) {
+    pub fn find_in(dir_path: &str) -> Result<Vec<String>, ArgsError> {
+        if TorrentFinder::is_single_torrent(dir_path)
+            || TorrentFinder::is_magnet_file(dir_path)
+            || MagnetLink::is_magnet(dir_path)
+        {
             return Ok(vec![dir_path.to_string()]);
         }
 
@@ -67,7 +91,9 @@ impl TorrentFinder {
                 if let Ok(new_dir) = file.metadata() {
                     if new_dir.is_dir() {
                         TorrentFinder::loop_through_dir(&file_path, files)?;
-                    } else if TorrentFinder::is_single_torrent(&file_path) {
+                    } else if TorrentFinder::is_single_torrent(&file_path)
+                        || TorrentFinder::is_magnet_file(&file_path)
+                    {
                         files.push(file_path);
                     }
                 }
@@ -85,38 +111,63 @@ impl TorrentFinder {
         false
     }
 
-    fn build_bitfield(dl_path: &str, name: &str, n_pieces: u32) -> PieceBitfield {
+    /// Checks if the path ends with '.magnet' extension
+    fn is_magnet_file(path: &str) -> bool {
+        if let Some(extension) = Path::new(path).extension() {
+            return extension == "magnet";
+        }
+        false
+    }
+
+    /// Builds the bitfield of a torrent using the files of the downloads directory.
+    /// If the resume file of the torrent matches its files, its bitfield is trusted.
+    /// Otherwise, the pieces are checked.
+    pub fn build_bitfield(dl_path: &str, torrent: &TorrentInfo) -> PieceBitfield {
+        if let Ok(resume) = ResumeData::load(torrent, dl_path) {
+            if resume.matches_files(torrent, dl_path) {
+                return resume.get_bitfield(torrent.get_n_pieces());
+            }
+        }
+        TorrentFinder::check_pieces(dl_path, torrent)
+    }
+
+    /// Builds the bitfield with the pieces stored in the files of the torrent that verify.
+    /// A torrent without metadata gets an empty bitfield.
+    fn check_pieces(dl_path: &str, torrent: &TorrentInfo) -> PieceBitfield {
+        let n_pieces = torrent.get_n_pieces();
+        if !torrent.has_metadata() {
+            return PieceBitfield::new(n_pieces);
+        }
         if !Path::new(dl_path).exists() && fs::create_dir_all(dl_path).is_err() {
             return PieceBitfield::new(n_pieces);
         }
+        Storage::new(torrent.clone(), dl_path).stored_pieces()
+    }
 
-        if let Ok(files) = fs::read_dir(dl_path) {
-            let mut bitfield = PieceBitfield::new(n_pieces);
-            for file in files.flatten() {
-                let file_name = file.file_name().to_string_lossy().to_string();
-                let piece_name = format!("{}_piece_", name);
-
-                if file_name.contains(&piece_name) {
-                    let (_, piece_idx) = file_name.split_at(piece_name.len());
-                    if let Ok(idx) = piece_idx.parse::<u32>() {
-                        bitfield.add_a_piece(idx);
-                    }
-                } else if file_name == name {
-                    return PieceBitfield::get_completed_bitfield(n_pieces);
-                }
-            }
-            return bitfield;
+    /// Force recheck: hashes the data of the torrent again (ignoring the resume file),
+    /// and replaces the shared bitfield with the pieces that verify. Corrupted pieces
+    /// are no longer served and will be downloaded again.
+    /// Returns the number of valid pieces.
+    pub fn force_recheck(
+        dl_path: &str,
+        torrent: &TorrentInfo,
+        bitfield: &Arc<RwLock<PieceBitfield>>,
+    ) -> u32 {
+        let checked = TorrentFinder::check_pieces(dl_path, torrent);
+        let n_valid_pieces = checked.number_of_downloaded_pieces();
+        if let Ok(mut bitfield) = bitfield.write() {
+            *bitfield = checked;
         }
-        PieceBitfield::new(n_pieces)
+        n_valid_pieces
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use glib::{MainContext, PRIORITY_DEFAULT};
 
     use super::*;
-    use std::sync::mpsc::channel;
+    use crate::test_support::build_testing_downloads;
+    use crate::torrent_info::TorrentId;
 
     #[test]
     fn get_path_single_torrent() {
@@ -156,13 +207,13 @@ mod tests {
 
     #[test]
     fn get_multiple_torrent_info() {
-        let (tx, rx) = channel();
-        let (tx_gtk, _rx_gtk) = MainContext::channel(PRIORITY_DEFAULT);
-        let _ = tx.send(tx_gtk);
+        build_testing_downloads();
+        let events = EventBus::new();
+        let rx_events = events.subscribe();
         if let Ok(files) = TorrentFinder::find(
             "./files_for_testing/torrents_testing",
             "./files_for_testing/downloaded_files",
-            Arc::new(Mutex::new(rx)),
+            &events,
         ) {
             let t1p = "files_for_testing/torrents_testing/debian-11.3.0-amd64-netinst.iso.torrent";
             let torrent1 = TorrentInfo::new(t1p);
@@ -185,6 +236,9 @@ mod tests {
                     assert!(bf_vec.contains(&bitfield1));
                     assert!(bf_vec.contains(&bitfield2));
                 }
+                let ids: Vec<TorrentId> =
+                    rx_events.try_iter().map(|e| e.get_torrent_id()).collect();
+                assert_eq!(ids, vec![files[0].0.get_id(), files[1].0.get_id()]);
                 return;
             }
         }
@@ -193,12 +247,10 @@ mod tests {
 
     #[test]
     fn invalid_dl_path() {
-        let (tx, rx) = channel();
-        let (tx_gtk, _rx_gtk) = MainContext::channel(PRIORITY_DEFAULT);
-        let _ = tx.send(tx_gtk);
+        let events = EventBus::new();
 
         let torr_dir = "./files_for_testing/torrents_testing";
-        if let Ok(files) = TorrentFinder::find(torr_dir, "./no_dir", Arc::new(Mutex::new(rx))) {
+        if let Ok(files) = TorrentFinder::find(torr_dir, "./no_dir", &events) {
             let t1p = "files_for_testing/torrents_testing/debian-11.3.0-amd64-netinst.iso.torrent";
             let torrent1 = TorrentInfo::new(t1p);
 
@@ -226,4 +278,47 @@ mod tests {
         }
         assert!(false);
     }
+
+    #[test]
+    fn force_recheck_replaces_the_bitfield() {
+        build_testing_downloads();
+        let t2p = "files_for_testing/torrents_testing/ubuntu-20.04.4-desktop-amd64.iso.torrent";
+        let torrent = TorrentInfo::new(t2p).expect("It shouldn't fail");
+        let n_pieces = torrent.get_n_pieces();
+        let bitfield = Arc::new(RwLock::new(PieceBitfield::get_completed_bitfield(n_pieces)));
+
+        let n_valid_pieces = TorrentFinder::force_recheck(
+            "./files_for_testing/downloaded_files",
+            &torrent,
+            &bitfield,
+        );
+
+        let mut expected_bf = PieceBitfield::new(n_pieces);
+        expected_bf.add_a_piece(0);
+        expected_bf.add_a_piece(10);
+        assert_eq!(n_valid_pieces, 2);
+        let checked_bf = bitfield.read().expect("It shouldn't fail").clone();
+        assert_eq!(checked_bf, expected_bf);
+    }
+
+    #[test]
+    fn get_torrent_info_from_magnet() {
+        let events = EventBus::new();
+
+        let magnet =
+            "magnet:?xt=urn:btih:3d374e5a6ee6641280499abe5ce8098b2cdff064&dn=multi_file_test";
+        if let Ok(files) =
+            TorrentFinder::find(magnet, "./files_for_testing/downloaded_files", &events)
+        {
+            assert_eq!(files.len(), 1);
+            assert!(!files[0].0.has_metadata());
+            assert_eq!(files[0].0.get_name(), "multi_file_test");
+            if let Ok(bitfield) = files[0].1.read() {
+                assert_eq!(bitfield.number_of_downloaded_pieces(), 0);
+                assert!(!bitfield.has_all_pieces());
+                return;
+            }
+        }
+        assert!(false);
+    }
 }
@@ -1,25 +1,121 @@
-use crate::encoding_decoding::bencode_parser::BencodeParser;
 use crate::encoding_decoding::encoder::Encoder;
+use crate::magnet::MagnetLink;
+use bencode::BencodeParser;
+use bencode::BencodeType;
 use sha1::{Digest, Sha1};
+use std::fmt;
+use std::fs;
 use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// # struct TorrentId
+/// Identifies a torrent by its info hash, which is known even before the metadata
+/// of a magnet link is received. It is shown as an hexadecimal string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TorrentId([u8; 20]);
+
+impl TorrentId {
+    /// Builds the id from an info hash. Missing bytes are filled with zeros.
+    pub fn from_info_hash(info_hash: &[u8]) -> TorrentId {
+        let mut id = [0u8; 20];
+        let len = info_hash.len().min(id.len());
+        id[..len].copy_from_slice(&info_hash[..len]);
+        TorrentId(id)
+    }
+}
+
+/// Parses the id from its hexadecimal string (40 chars).
+impl FromStr for TorrentId {
+    type Err = Error;
+
+    fn from_str(hex: &str) -> Result<TorrentId, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidInput, "Invalid torrent id");
+        if hex.len() != 40 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut id = [0u8; 20];
+        for (idx, byte) in id.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(TorrentId(id))
+    }
+}
+
+impl fmt::Display for TorrentId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&Encoder.hexencode(&self.0))
+    }
+}
+
+/// # struct TorrentFile
+/// Represents one of the files described by the torrent:
+///     - path -> path components, relative to the torrent root
+///     - length -> size of the file in bytes
+///     - offset -> position of the first byte of the file inside the torrent data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    path: Vec<String>,
+    length: u64,
+    offset: u64,
+}
+
+impl TorrentFile {
+    pub fn get_path(&self) -> Vec<String> {
+        self.path.clone()
+    }
+
+    pub fn get_length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// # struct FileSlice
+/// Represents the part of a piece that belongs to a single file:
+///     - file_idx -> index of the file in the torrent file list
+///     - file_offset -> position inside the file where the slice starts
+///     - piece_offset -> position inside the piece where the slice starts
+///     - length -> amount of bytes of the slice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSlice {
+    pub file_idx: usize,
+    pub file_offset: u64,
+    pub piece_offset: u32,
+    pub length: u32,
+}
 
 /// # struct TorrentInformation
 /// Contains the information of torrent file:
-///     - name -> name of the torrent
-///     - announce -> announce field (tracker url)
+///     - name -> name of the torrent (file name or root directory name)
+///     - announce_list -> tiers of tracker urls (BEP 12). If the torrent has not an
+///       'announce-list', there is only one tier with the 'announce' url
 ///     - info_hash
 ///     - piece_length
+///     - length -> total amount of bytes of all files
 ///     - n_pieces -> number of pieces
 ///     - hashes_list -> list of piece hashes
+///     - files -> list of files. A single file torrent has only one file.
+///     - multi_file -> the torrent was described using the 'files' list
+///     - private -> peers must be obtained only from the trackers (BEP 27), so the
+///       DHT and ut_pex are not used
+///     - metadata -> bencoded info dictionary (it can be sent to other peers)
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TorrentInfo {
     name: String,
-    announce: String,
+    announce_list: Vec<Vec<String>>,
     info_hash: Vec<u8>,
     piece_length: u32,
-    length: u32,
+    length: u64,
     n_pieces: u32,
     hashes_list: Vec<u8>,
+    files: Vec<TorrentFile>,
+    multi_file: bool,
+    private: bool,
+    metadata: Vec<u8>,
 }
 
 impl TorrentInfo {
@@ -28,48 +124,237 @@ impl TorrentInfo {
     /// of the parsed torrent file.
     /// Otherwise, returns ClientError (NoSuchTorrentFile or TorrentInInvalidFormat)
     pub fn new(torrent_path: &str) -> Result<TorrentInfo, Error> {
-        if let Ok(benc_torrent) = BencodeParser.parse_file(torrent_path) {
-            let announce_aux = &benc_torrent.get_value_from_dict("announce")?.get_string()?;
+        let torrent_data = fs::read(torrent_path).unwrap_or_default();
+        if let Ok(benc_torrent) = BencodeParser.parse_vec(&torrent_data) {
+            let announce_list = TorrentInfo::parse_announce_list(&benc_torrent);
             let info_value = benc_torrent.get_value_from_dict("info")?;
-            let name_aux = &info_value.get_value_from_dict("name")?.get_string()?;
-            let length = info_value.get_value_from_dict("length")?.get_integer()? as u32;
-            let piece_length = info_value
-                .get_value_from_dict("piece length")?
-                .get_integer()? as u32;
-            let n_pieces = (length as f32 / piece_length as f32).ceil() as u32;
-            let hashes_list = info_value.get_value_from_dict("pieces")?.get_string()?;
-
-            let benc_info_value = Encoder.bencode(&info_value);
-            let mut hasher = Sha1::new();
-            hasher.update(benc_info_value);
-            let info_hash = hasher.finalize();
-            return Ok(TorrentInfo {
-                name: String::from_utf8_lossy(name_aux).to_string(),
-                announce: String::from_utf8_lossy(announce_aux).to_string(),
-                info_hash: info_hash.to_vec(),
-                piece_length,
-                length,
-                n_pieces,
-                hashes_list,
-            });
+
+            // The info hash is calculated over the original bytes of the info dictionary
+            if let Ok(benc_info_value) = BencodeParser.parse_raw_value(&torrent_data, "info") {
+                return TorrentInfo::from_info_dict(
+                    &info_value,
+                    announce_list,
+                    benc_info_value.to_vec(),
+                );
+            }
         }
         println!("Cannot find or parse the torrent: {}", torrent_path);
         Err(Error::new(ErrorKind::InvalidData, "Cannot parse torrent"))
     }
 
+    /// Creates a TorrentInformation from a magnet link.
+    /// The info dictionary is not known yet, so the torrent has not any piece or file
+    /// until its metadata is received (see from_metadata).
+    /// Each tracker of the magnet link is in its own tier, so all of them are used.
+    pub fn from_magnet(magnet: &MagnetLink) -> TorrentInfo {
+        let announce_list = magnet
+            .get_trackers()
+            .into_iter()
+            .map(|tracker| vec![tracker])
+            .collect();
+        TorrentInfo {
+            name: magnet.get_name(),
+            announce_list,
+            info_hash: magnet.get_info_hash(),
+            piece_length: 0,
+            length: 0,
+            n_pieces: 0,
+            hashes_list: vec![],
+            files: vec![],
+            multi_file: false,
+            private: false,
+            metadata: vec![],
+        }
+    }
+
+    /// Receives the bencoded info dictionary (the metadata of a torrent) and
+    /// the tiers of trackers, then parses it.
+    /// The info hash is calculated over the received bytes.
+    pub fn from_metadata(
+        metadata: &[u8],
+        announce_list: Vec<Vec<String>>,
+    ) -> Result<TorrentInfo, Error> {
+        if let Ok(info_value) = BencodeParser.parse_vec(metadata) {
+            return TorrentInfo::from_info_dict(&info_value, announce_list, metadata.to_vec());
+        }
+        Err(Error::new(ErrorKind::InvalidData, "Cannot parse metadata"))
+    }
+
+    /// Builds a TorrentInformation using the fields of the info dictionary.
+    /// The info hash is calculated over the bencoded dictionary.
+    fn from_info_dict(
+        info_value: &BencodeType,
+        announce_list: Vec<Vec<String>>,
+        metadata: Vec<u8>,
+    ) -> Result<TorrentInfo, Error> {
+        let name_aux = &info_value.get_value_from_dict("name")?.get_string()?;
+        let name = String::from_utf8_lossy(name_aux).to_string();
+        // The name is the file or directory created inside the downloads directory
+        if !TorrentInfo::is_valid_path_component(&name) {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid torrent name"));
+        }
+        let (files, multi_file) = TorrentInfo::parse_files(info_value, &name)?;
+        let length = files.iter().map(|file| file.length).sum::<u64>();
+        if length == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Empty torrent"));
+        }
+        let piece_length: u32 =
+            TorrentInfo::parse_integer(&info_value.get_value_from_dict("piece length")?)?;
+        if piece_length == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid piece length"));
+        }
+        let n_pieces = u32::try_from(length.div_ceil(piece_length as u64))
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Too many pieces"))?;
+        let hashes_list = info_value.get_value_from_dict("pieces")?.get_string()?;
+        // There must be a hash of 20 bytes for every piece
+        if hashes_list.len() as u64 != n_pieces as u64 * 20 {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid pieces hashes"));
+        }
+
+        let private = info_value
+            .get_value_from_dict("private")
+            .and_then(|private| private.get_integer())
+            .is_ok_and(|private| private == 1);
+
+        let mut hasher = Sha1::new();
+        hasher.update(&metadata);
+        let info_hash = hasher.finalize().to_vec();
+        Ok(TorrentInfo {
+            name,
+            announce_list,
+            info_hash,
+            piece_length,
+            length,
+            n_pieces,
+            hashes_list,
+            files,
+            multi_file,
+            private,
+            metadata,
+        })
+    }
+
+    /// Gets the tiers of trackers of the torrent.
+    /// If there is a valid 'announce-list', the 'announce' url is ignored (BEP 12).
+    /// Torrents without trackers (e.g. only for the DHT) have not any tier.
+    fn parse_announce_list(torrent: &BencodeType) -> Vec<Vec<String>> {
+        let mut announce_list = vec![];
+        if let Ok(tiers) = torrent
+            .get_value_from_dict("announce-list")
+            .and_then(|list| list.get_list())
+        {
+            for tier in tiers.iter().flat_map(|tier| tier.get_list()) {
+                let urls: Vec<String> = tier
+                    .iter()
+                    .flat_map(|url| url.get_string())
+                    .map(|url| String::from_utf8_lossy(&url).to_string())
+                    .filter(|url| !url.is_empty())
+                    .collect();
+                if !urls.is_empty() {
+                    announce_list.push(urls);
+                }
+            }
+        }
+
+        if announce_list.is_empty() {
+            if let Ok(announce) = torrent
+                .get_value_from_dict("announce")
+                .and_then(|announce| announce.get_string())
+            {
+                announce_list.push(vec![String::from_utf8_lossy(&announce).to_string()]);
+            }
+        }
+        announce_list
+    }
+
+    /// Converts a length of the info dictionary, which cannot be negative or too big.
+    fn parse_integer<T: TryFrom<i64>>(value: &BencodeType) -> Result<T, Error> {
+        T::try_from(value.get_integer()?)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid length"))
+    }
+
+    /// Gets the file list of the info dictionary.
+    /// If the dictionary has a 'length' key, it describes a single file named as the torrent.
+    /// Otherwise, each element of the 'files' list is parsed and its offset is calculated.
+    fn parse_files(info: &BencodeType, name: &str) -> Result<(Vec<TorrentFile>, bool), Error> {
+        if let Ok(length) = info.get_value_from_dict("length") {
+            let single_file = TorrentFile {
+                path: vec![name.to_string()],
+                length: TorrentInfo::parse_integer(&length)?,
+                offset: 0,
+            };
+            return Ok((vec![single_file], false));
+        }
+
+        let mut files = vec![];
+        let mut offset: u64 = 0;
+        for file in info.get_value_from_dict("files")?.get_list()? {
+            let length: u64 = TorrentInfo::parse_integer(&file.get_value_from_dict("length")?)?;
+            let mut path = vec![];
+            for component in file.get_value_from_dict("path")?.get_list()? {
+                let component = String::from_utf8_lossy(&component.get_string()?).to_string();
+                // We don't want to write outside the torrent directory
+                if !TorrentInfo::is_valid_path_component(&component) {
+                    return Err(Error::new(ErrorKind::InvalidData, "Invalid file path"));
+                }
+                path.push(component);
+            }
+            if path.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid file path"));
+            }
+
+            files.push(TorrentFile {
+                path,
+                length,
+                offset,
+            });
+            offset = offset
+                .checked_add(length)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid file length"))?;
+        }
+
+        if files.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "Empty file list"));
+        }
+        Ok((files, true))
+    }
+
+    /// Checks that a name can be used as a single component of a path, so it cannot
+    /// point outside the directory it is pushed to (e.g. '..', '/etc' or 'a\\b').
+    fn is_valid_path_component(component: &str) -> bool {
+        !component.is_empty()
+            && component != "."
+            && component != ".."
+            && !component.contains(['/', '\\'])
+            && !Path::new(component).is_absolute()
+    }
+
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
 
+    /// Returns the first tracker url (empty if the torrent has not any tracker).
     pub fn get_announce(&self) -> String {
-        self.announce.clone()
+        self.announce_list
+            .first()
+            .and_then(|tier| tier.first())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn get_announce_list(&self) -> Vec<Vec<String>> {
+        self.announce_list.clone()
     }
 
     pub fn get_info_hash(&self) -> Vec<u8> {
         self.info_hash.clone()
     }
 
-    pub fn get_length(&self) -> u32 {
+    pub fn get_id(&self) -> TorrentId {
+        TorrentId::from_info_hash(&self.info_hash)
+    }
+
+    pub fn get_length(&self) -> u64 {
         self.length
     }
 
@@ -78,7 +363,8 @@ impl TorrentInfo {
     }
 
     pub fn length_of_piece_n(&self, piece_idx: u32) -> u32 {
-        let last_piece_len = self.length - (self.n_pieces - 1) * self.piece_length;
+        let last_piece_len =
+            (self.length - (self.n_pieces as u64 - 1) * self.piece_length as u64) as u32;
 
         match piece_idx {
             _ if piece_idx == self.n_pieces - 1 => last_piece_len,
@@ -97,11 +383,91 @@ impl TorrentInfo {
         let (hash, _) = vec.split_at(20_usize);
         hash.to_vec()
     }
+
+    pub fn get_metadata(&self) -> Vec<u8> {
+        self.metadata.clone()
+    }
+
+    pub fn get_files(&self) -> Vec<TorrentFile> {
+        self.files.clone()
+    }
+
+    /// Checks if the info dictionary of the torrent is known.
+    /// A torrent created from a magnet link has not metadata until it is fetched from peers.
+    pub fn has_metadata(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    pub fn is_multi_file(&self) -> bool {
+        self.multi_file
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    /// Returns a description of the torrent structure (used by the GUI)
+    pub fn get_structure(&self) -> String {
+        if !self.has_metadata() {
+            return "Unknown".to_string();
+        }
+        if self.multi_file {
+            return format!("Multiple Files ({})", self.files.len());
+        }
+        "Single File".to_string()
+    }
+
+    /// Returns the path where a file of the torrent is stored, inside the downloads directory.
+    /// Files of a multi-file torrent are stored inside a directory named as the torrent.
+    pub fn get_file_path(&self, dl_dir: &str, file_idx: usize) -> PathBuf {
+        let mut path = PathBuf::from(dl_dir);
+        if self.multi_file {
+            path.push(&self.name);
+        }
+        if let Some(file) = self.files.get(file_idx) {
+            for component in &file.path {
+                path.push(component);
+            }
+        }
+        path
+    }
+
+    /// Returns the position of the first byte of the piece inside the torrent data.
+    pub fn piece_offset(&self, piece_idx: u32) -> u64 {
+        piece_idx as u64 * self.piece_length as u64
+    }
+
+    /// Maps a piece to the files it belongs to.
+    /// Returns the slices of the piece ordered by their position in the piece.
+    /// A piece can span several files, and empty files are skipped.
+    pub fn piece_file_slices(&self, piece_idx: u32) -> Vec<FileSlice> {
+        let piece_begin = self.piece_offset(piece_idx);
+        let piece_end = piece_begin + self.length_of_piece_n(piece_idx) as u64;
+
+        let mut slices = vec![];
+        for (file_idx, file) in self.files.iter().enumerate() {
+            let file_end = file.offset + file.length;
+            if file.length == 0 || file_end <= piece_begin || file.offset >= piece_end {
+                continue;
+            }
+
+            let begin = piece_begin.max(file.offset);
+            let end = piece_end.min(file_end);
+            slices.push(FileSlice {
+                file_idx,
+                file_offset: begin - file.offset,
+                piece_offset: (begin - piece_begin) as u32,
+                length: (end - begin) as u32,
+            });
+        }
+        slices
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bencode::BencodeEncoder;
 
     #[test]
     fn get_torrent_info_with_pieces() {
@@ -119,6 +485,13 @@ mod tests {
 
             assert_eq!(torrent.get_name(), exp_name);
             assert_eq!(torrent.get_announce(), exp_announce);
+            assert_eq!(
+                torrent.get_announce_list(),
+                vec![
+                    vec![exp_announce],
+                    vec!["https://ipv6.torrent.ubuntu.com/announce".to_string()]
+                ]
+            );
             assert_eq!(torrent.get_info_hash(), exp_infohash);
             assert_eq!(torrent.get_piece_length(), exp_piece_length);
             assert_eq!(torrent.get_length(), exp_length);
@@ -152,4 +525,219 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn get_torrent_info_multi_file() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        if let Ok(torrent) = TorrentInfo::new(path) {
+            let exp_infohash = vec![
+                61, 55, 78, 90, 110, 230, 100, 18, 128, 73, 154, 190, 92, 232, 9, 139, 44, 223,
+                240, 100,
+            ];
+            let files = torrent.get_files();
+
+            assert_eq!(torrent.get_name(), "multi_file_test".to_string());
+            assert_eq!(torrent.get_info_hash(), exp_infohash);
+            assert!(torrent.is_multi_file());
+            assert_eq!(torrent.get_length(), 55000);
+            assert_eq!(torrent.get_n_pieces(), 4);
+            assert_eq!(files.len(), 3);
+            assert_eq!(
+                files[1].get_path(),
+                vec!["sub".to_string(), "b.bin".to_string()]
+            );
+            assert_eq!(files[1].get_length(), 30000);
+            assert_eq!(files[1].get_offset(), 20000);
+            assert_eq!(
+                torrent.get_file_path("downloads", 1),
+                PathBuf::from("downloads/multi_file_test/sub/b.bin")
+            );
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn piece_spanning_multiple_files() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        if let Ok(torrent) = TorrentInfo::new(path) {
+            let exp_slices = vec![
+                FileSlice {
+                    file_idx: 0,
+                    file_offset: 16384,
+                    piece_offset: 0,
+                    length: 3616,
+                },
+                FileSlice {
+                    file_idx: 1,
+                    file_offset: 0,
+                    piece_offset: 3616,
+                    length: 12768,
+                },
+            ];
+            assert_eq!(torrent.piece_file_slices(1), exp_slices);
+
+            let last_slices = torrent.piece_file_slices(3);
+            assert_eq!(last_slices.len(), 2);
+            assert_eq!(last_slices[1].file_idx, 2);
+            assert_eq!(last_slices[1].length, 5000);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn single_file_piece_maps_to_one_file() {
+        let path = "files_for_testing/torrents_testing/ubuntu-20.04.4-desktop-amd64.iso.torrent";
+        if let Ok(torrent) = TorrentInfo::new(path) {
+            let slices = torrent.piece_file_slices(10);
+            assert!(!torrent.is_multi_file());
+            assert_eq!(slices.len(), 1);
+            assert_eq!(slices[0].file_offset, torrent.piece_offset(10));
+            assert_eq!(
+                torrent.get_file_path("downloads", 0),
+                PathBuf::from("downloads/ubuntu-20.04.4-desktop-amd64.iso")
+            );
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn files_larger_than_4_gib() {
+        // Two files of 5 GiB and 3 GiB, with pieces of 4 MiB
+        let gib: u64 = 1 << 30;
+        let n_pieces = 2048;
+        let info = format!(
+            "d5:filesld6:lengthi{}e4:pathl5:a.isoeed6:lengthi{}e4:pathl5:b.isoeee4:name4:test12:piece lengthi4194304e6:pieces{}:{}e",
+            5 * gib,
+            3 * gib,
+            n_pieces * 20,
+            "x".repeat(n_pieces * 20)
+        );
+
+        if let Ok(torrent) = TorrentInfo::from_metadata(info.as_bytes(), vec![]) {
+            assert_eq!(torrent.get_length(), 8 * gib);
+            assert_eq!(torrent.get_n_pieces(), n_pieces as u32);
+            assert_eq!(torrent.get_files()[1].get_offset(), 5 * gib);
+            assert_eq!(torrent.length_of_piece_n(2047), 4194304);
+
+            let slices = torrent.piece_file_slices(2047);
+            assert_eq!(slices.len(), 1);
+            assert_eq!(slices[0].file_idx, 1);
+            assert_eq!(slices[0].file_offset, 3 * gib - 4194304);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn torrent_info_from_magnet_has_not_metadata() {
+        let uri = "magnet:?xt=urn:btih:3d374e5a6ee6641280499abe5ce8098b2cdff064&dn=multi_file_test&tr=http%3A%2F%2F127.0.0.1%3A7878%2Fannounce";
+        let magnet = MagnetLink::new(uri).expect("It shouldn't fail");
+        let torrent = TorrentInfo::from_magnet(&magnet);
+
+        assert!(!torrent.has_metadata());
+        assert_eq!(torrent.get_name(), "multi_file_test");
+        assert_eq!(torrent.get_announce(), "http://127.0.0.1:7878/announce");
+        assert_eq!(torrent.get_info_hash(), magnet.get_info_hash());
+        assert_eq!(
+            torrent.get_id().to_string(),
+            "3d374e5a6ee6641280499abe5ce8098b2cdff064"
+        );
+        let id: Result<TorrentId, _> = "3D374E5A6EE6641280499ABE5CE8098B2CDFF064".parse();
+        assert_eq!(id.ok(), Some(torrent.get_id()));
+        assert!("3d374e5a6ee6641280499abe5ce8098b2cdff0"
+            .parse::<TorrentId>()
+            .is_err());
+        assert_eq!(torrent.get_n_pieces(), 0);
+    }
+
+    #[test]
+    fn torrent_info_from_metadata() {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        let parsed = BencodeParser.parse_file(path).expect("It shouldn't fail");
+        let info = parsed
+            .get_value_from_dict("info")
+            .expect("It shouldn't fail");
+        let metadata = BencodeEncoder.bencode(&info);
+
+        if let Ok(from_metadata) =
+            TorrentInfo::from_metadata(&metadata, torrent.get_announce_list())
+        {
+            assert!(from_metadata.has_metadata());
+            assert_eq!(from_metadata, torrent);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn names_outside_the_downloads_dir_are_rejected() {
+        for name in ["..", "../../x", "/etc/x", "a\\b", ""] {
+            let info = format!(
+                "d6:lengthi10e4:name{}:{}12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae",
+                name.len(),
+                name
+            );
+            assert!(TorrentInfo::from_metadata(info.as_bytes(), vec![]).is_err());
+        }
+        let files = "d5:filesld6:lengthi10e4:pathl2:..1:xeee4:name4:test12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        assert!(TorrentInfo::from_metadata(files.as_bytes(), vec![]).is_err());
+    }
+
+    #[test]
+    fn pieces_without_a_hash_for_every_piece_are_rejected() {
+        // 3 pieces of 16384 bytes, but only 2 hashes
+        let info = format!(
+            "d6:lengthi40000e4:name4:test12:piece lengthi16384e6:pieces40:{}e",
+            "a".repeat(40)
+        );
+        assert!(TorrentInfo::from_metadata(info.as_bytes(), vec![]).is_err());
+    }
+
+    #[test]
+    fn invalid_lengths_are_rejected() {
+        let single_file = |length: &str, piece_length: &str| {
+            format!(
+                "d6:lengthi{}e4:name4:test12:piece lengthi{}e6:pieces20:{}e",
+                length,
+                piece_length,
+                "a".repeat(20)
+            )
+        };
+        for (length, piece_length) in [
+            ("-1", "16384"),
+            ("0", "16384"),
+            ("10", "-16384"),
+            ("10", "4294967296"),
+        ] {
+            let info = single_file(length, piece_length);
+            assert!(TorrentInfo::from_metadata(info.as_bytes(), vec![]).is_err());
+        }
+        assert!(TorrentInfo::from_metadata(single_file("10", "16384").as_bytes(), vec![]).is_ok());
+
+        let files = "d5:filesld6:lengthi-5e4:pathl1:aeed6:lengthi15e4:pathl1:beee4:name4:test12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        assert!(TorrentInfo::from_metadata(files.as_bytes(), vec![]).is_err());
+    }
+
+    #[test]
+    fn info_hash_uses_original_info_bytes() {
+        // The keys of this info dictionary are not sorted, so encoding it again
+        // would change its hash
+        let info = b"d6:lengthi10e12:piece lengthi16384e4:name4:test6:pieces20:aaaaaaaaaaaaaaaaaaaa6:md5sum32:0123456789abcdef0123456789abcdefe";
+        let torrent_data = [b"d4:info".as_slice(), info, b"e"].concat();
+        let path = "files_for_testing/unsorted_info.torrent";
+        let _ = fs::write(path, torrent_data);
+        let torrent = TorrentInfo::new(path);
+        let _ = fs::remove_file(path);
+
+        let torrent = torrent.expect("It shouldn't fail");
+        let mut hasher = Sha1::new();
+        hasher.update(info);
+        assert_eq!(torrent.get_info_hash(), hasher.finalize().to_vec());
+        assert_eq!(torrent.get_metadata(), info.to_vec());
+        assert_eq!(torrent.get_name(), "test");
+    }
 }
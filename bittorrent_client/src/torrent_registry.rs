@@ -0,0 +1,533 @@
+use crate::bitfield::PieceBitfield;
+use crate::choker::Choker;
+use crate::event_messages::{EventSubscriber, NewEvent};
+use crate::torrent_info::{TorrentId, TorrentInfo};
+
+use serde::Serialize;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+
+type TorrentCombo = (TorrentInfo, Arc<RwLock<PieceBitfield>>);
+
+/// # enum TorrentState
+/// State of a torrent of the session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentState {
+    /// Waiting for a free client
+    Queued,
+    Downloading,
+    /// Downloaded, it is only shared by the server
+    Seeding,
+    Paused,
+}
+
+impl fmt::Display for TorrentState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TorrentState::Queued => write!(f, "queued"),
+            TorrentState::Downloading => write!(f, "downloading"),
+            TorrentState::Seeding => write!(f, "seeding"),
+            TorrentState::Paused => write!(f, "paused"),
+        }
+    }
+}
+
+/// # enum StopSignal
+/// Signal from the session to the client of a torrent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StopSignal {
+    #[default]
+    Run,
+    /// The torrent was paused: the client stops, and saves its resume file
+    Pause,
+    /// The torrent was removed: the client stops without saving anything
+    Remove,
+}
+
+/// # struct TorrentStatus
+/// Snapshot of a torrent, as it is listed by the control API.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TorrentStatus {
+    pub id: String,
+    pub name: String,
+    pub state: String,
+    pub length: u64,
+    pub pieces: u32,
+    pub downloaded_pieces: u32,
+    /// Percentage of downloaded pieces
+    pub progress: u32,
+    pub peers: u32,
+    pub connections: u32,
+    pub uploaded: u64,
+    pub downloaded: u64,
+}
+
+/// # struct SessionStats
+/// Totals of all torrents of the session.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SessionStats {
+    pub torrents: u32,
+    pub queued: u32,
+    pub downloading: u32,
+    pub seeding: u32,
+    pub paused: u32,
+    pub connections: u32,
+    pub uploaded: u64,
+    pub downloaded: u64,
+}
+
+/// # struct TorrentEntry
+/// A torrent of the session:
+///     - torrent, bitfield -> shared with its client and the server
+///     - state
+///     - running -> a client is working on the torrent (it may be stopping)
+///     - stop -> signal to stop the client of the current run
+///     - peers -> number of peers found in the last run
+///     - connections -> active connections with peers
+#[derive(Debug)]
+struct TorrentEntry {
+    torrent: TorrentInfo,
+    bitfield: Arc<RwLock<PieceBitfield>>,
+    state: TorrentState,
+    running: bool,
+    stop: Arc<RwLock<StopSignal>>,
+    peers: u32,
+    connections: u32,
+}
+
+impl TorrentEntry {
+    fn get_status(&self) -> TorrentStatus {
+        let n_pieces = self.torrent.get_n_pieces();
+        let dl_pieces = match self.bitfield.read() {
+            Ok(bitfield) => bitfield.number_of_downloaded_pieces(),
+            Err(_) => 0,
+        };
+        let progress = match n_pieces {
+            0 => 0,
+            _ => (dl_pieces.min(n_pieces) as u64 * 100 / n_pieces as u64) as u32,
+        };
+        // Torrents without connections don't have a choker yet
+        let choker = Choker::get(&self.torrent.get_info_hash());
+        let (uploaded, downloaded) = match choker.as_ref().map(|choker| choker.lock()) {
+            Some(Ok(choker)) => choker.get_totals(),
+            _ => (0, 0),
+        };
+
+        TorrentStatus {
+            id: self.torrent.get_id().to_string(),
+            name: self.torrent.get_name(),
+            state: self.state.to_string(),
+            length: self.torrent.get_length(),
+            pieces: n_pieces,
+            downloaded_pieces: dl_pieces,
+            progress,
+            peers: self.peers,
+            connections: self.connections,
+            uploaded,
+            downloaded,
+        }
+    }
+}
+
+/// Torrents of the registry:
+///     - entries
+///     - queue -> torrents waiting for a client
+///     - stopping -> removed torrents whose client has not stopped yet
+#[derive(Debug, Default)]
+struct RegistryData {
+    entries: Vec<TorrentEntry>,
+    queue: VecDeque<TorrentId>,
+    stopping: HashSet<TorrentId>,
+}
+
+impl RegistryData {
+    fn search(&mut self, torrent_id: TorrentId) -> Option<&mut TorrentEntry> {
+        self.entries
+            .iter_mut()
+            .find(|entry| entry.torrent.get_id() == torrent_id)
+    }
+}
+
+/// # struct TorrentRegistry
+/// Torrents of a session and the queue of torrents waiting for a client.
+/// It can be cloned, and all clones share the torrents.
+/// It is subscribed to the events of the session to keep the peers and connections
+/// of every torrent.
+#[derive(Debug, Clone, Default)]
+pub struct TorrentRegistry {
+    data: Arc<Mutex<RegistryData>>,
+}
+
+impl TorrentRegistry {
+    pub fn new() -> TorrentRegistry {
+        TorrentRegistry::default()
+    }
+
+    /// Adds a torrent to the end of the queue.
+    /// Returns false if the torrent was already in the registry.
+    pub fn add(&self, torrent: TorrentCombo) -> bool {
+        let mut data = match self.data.lock() {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+        let torrent_id = torrent.0.get_id();
+        if data.search(torrent_id).is_some() {
+            return false;
+        }
+        data.entries.push(TorrentEntry {
+            torrent: torrent.0,
+            bitfield: torrent.1,
+            state: TorrentState::Queued,
+            running: false,
+            stop: Arc::new(RwLock::new(StopSignal::Run)),
+            peers: 0,
+            connections: 0,
+        });
+        data.queue.push_back(torrent_id);
+        true
+    }
+
+    /// Takes the first queued torrent, which starts downloading.
+    /// Returns the torrent and the signal to stop its client.
+    pub fn next_queued(&self) -> Option<(TorrentCombo, Arc<RwLock<StopSignal>>)> {
+        let mut data = self.data.lock().ok()?;
+        while let Some(torrent_id) = data.queue.pop_front() {
+            // It was removed and added again while its old client was stopping
+            if data.stopping.contains(&torrent_id) {
+                continue;
+            }
+            let entry = match data.search(torrent_id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // A torrent that is still running is queued again when its client stops
+            if entry.state != TorrentState::Queued || entry.running {
+                continue;
+            }
+            entry.state = TorrentState::Downloading;
+            entry.running = true;
+            entry.stop = Arc::new(RwLock::new(StopSignal::Run));
+            return Some((
+                (entry.torrent.clone(), entry.bitfield.clone()),
+                entry.stop.clone(),
+            ));
+        }
+        None
+    }
+
+    /// The client of a torrent has finished. If the download failed, the torrent
+    /// is queued again (unless it was paused).
+    /// If the torrent was removed, it has stopped (and it is queued if it was added again).
+    pub fn finish(&self, torrent_id: TorrentId, downloaded: bool) {
+        let mut data = match self.data.lock() {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        if data.stopping.remove(&torrent_id) {
+            if data.search(torrent_id).is_some() {
+                data.queue.push_back(torrent_id);
+            }
+            return;
+        }
+        let entry = match data.search(torrent_id) {
+            Some(entry) => entry,
+            None => return,
+        };
+        entry.running = false;
+        entry.connections = 0;
+        match entry.state {
+            TorrentState::Downloading if downloaded => entry.state = TorrentState::Seeding,
+            TorrentState::Downloading | TorrentState::Queued => {
+                entry.state = TorrentState::Queued;
+                data.queue.push_back(torrent_id);
+            }
+            _ => (),
+        }
+    }
+
+    /// Pauses a torrent: its client is stopped, and it is not shared anymore.
+    /// On error (unknown torrent), returns false.
+    pub fn pause(&self, torrent_id: TorrentId) -> bool {
+        let mut data = match self.data.lock() {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+        match data.search(torrent_id) {
+            Some(entry) => {
+                entry.state = TorrentState::Paused;
+                if let Ok(mut stop) = entry.stop.write() {
+                    *stop = StopSignal::Pause;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Queues a paused torrent again. Other torrents are not changed.
+    /// On error (unknown torrent), returns false.
+    pub fn resume(&self, torrent_id: TorrentId) -> bool {
+        let mut data = match self.data.lock() {
+            Ok(data) => data,
+            Err(_) => return false,
+        };
+        let entry = match data.search(torrent_id) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if entry.state == TorrentState::Paused {
+            entry.state = TorrentState::Queued;
+            if !entry.running {
+                data.queue.push_back(torrent_id);
+            }
+        }
+        true
+    }
+
+    /// Removes a torrent, stopping its client (see is_stopping()).
+    /// Returns the removed torrent, or None if it is unknown.
+    pub fn remove(&self, torrent_id: TorrentId) -> Option<TorrentInfo> {
+        let mut data = self.data.lock().ok()?;
+        let idx = data
+            .entries
+            .iter()
+            .position(|entry| entry.torrent.get_id() == torrent_id)?;
+        let entry = data.entries.remove(idx);
+        if let Ok(mut stop) = entry.stop.write() {
+            *stop = StopSignal::Remove;
+        }
+        if entry.running {
+            data.stopping.insert(torrent_id);
+        }
+        Some(entry.torrent)
+    }
+
+    /// Returns true if the torrent was removed, but its client has not stopped yet
+    /// (it may still write its files).
+    pub fn is_stopping(&self, torrent_id: TorrentId) -> bool {
+        match self.data.lock() {
+            Ok(data) => data.stopping.contains(&torrent_id),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the torrent and its bitfield, or None if it is unknown.
+    pub fn get_torrent(&self, torrent_id: TorrentId) -> Option<TorrentCombo> {
+        let mut data = self.data.lock().ok()?;
+        data.search(torrent_id)
+            .map(|entry| (entry.torrent.clone(), entry.bitfield.clone()))
+    }
+
+    /// A seeding torrent lost pieces (e.g. a force recheck found them corrupted),
+    /// so it is queued to download them again. Other torrents are not changed.
+    pub fn download_again(&self, torrent_id: TorrentId) {
+        let mut data = match self.data.lock() {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        let entry = match data.search(torrent_id) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if entry.state == TorrentState::Seeding {
+            entry.state = TorrentState::Queued;
+            if !entry.running {
+                data.queue.push_back(torrent_id);
+            }
+        }
+    }
+
+    pub fn get_state(&self, torrent_id: TorrentId) -> Option<TorrentState> {
+        let mut data = self.data.lock().ok()?;
+        data.search(torrent_id).map(|entry| entry.state)
+    }
+
+    /// Returns the torrents that the server can share (all of them but the paused ones).
+    pub fn shared_torrents(&self) -> Vec<TorrentCombo> {
+        match self.data.lock() {
+            Ok(data) => data
+                .entries
+                .iter()
+                .filter(|entry| entry.state != TorrentState::Paused)
+                .map(|entry| (entry.torrent.clone(), entry.bitfield.clone()))
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Returns the status of every torrent, in the order they were added.
+    pub fn list(&self) -> Vec<TorrentStatus> {
+        match self.data.lock() {
+            Ok(data) => data.entries.iter().map(|e| e.get_status()).collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    pub fn get_stats(&self) -> SessionStats {
+        let mut stats = SessionStats::default();
+        let states = match self.data.lock() {
+            Ok(data) => data.entries.iter().map(|e| e.state).collect(),
+            Err(_) => vec![],
+        };
+        for state in states {
+            match state {
+                TorrentState::Queued => stats.queued += 1,
+                TorrentState::Downloading => stats.downloading += 1,
+                TorrentState::Seeding => stats.seeding += 1,
+                TorrentState::Paused => stats.paused += 1,
+            }
+        }
+        for status in self.list() {
+            stats.torrents += 1;
+            stats.connections += status.connections;
+            stats.uploaded += status.uploaded;
+            stats.downloaded += status.downloaded;
+        }
+        stats
+    }
+
+    fn update(&self, event: NewEvent) {
+        let mut data = match self.data.lock() {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        let entry = match data.search(event.get_torrent_id()) {
+            Some(entry) => entry,
+            None => return,
+        };
+        match event {
+            NewEvent::MetadataReceived(_, torrent, _) => entry.torrent = torrent,
+            NewEvent::NumberOfPeers(_, peers) => entry.peers = peers,
+            NewEvent::NewConnection(..) => entry.connections += 1,
+            NewEvent::ConnectionDropped(..) => {
+                entry.connections = entry.connections.saturating_sub(1)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The registry keeps the peers, the connections and the metadata of the torrents
+/// with the events of their clients.
+impl EventSubscriber for TorrentRegistry {
+    fn notify(&self, event: NewEvent) -> bool {
+        self.update(event);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bt_client::peer::Peer;
+
+    fn get_torrent() -> TorrentCombo {
+        let path = "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+        let torrent = TorrentInfo::new(path).expect("It shouldn't fail");
+        let bitfield = PieceBitfield::new(torrent.get_n_pieces());
+        (torrent, Arc::new(RwLock::new(bitfield)))
+    }
+
+    #[test]
+    fn failed_torrents_are_queued_again() {
+        let registry = TorrentRegistry::new();
+        let torrent = get_torrent();
+        let id = torrent.0.get_id();
+        assert!(registry.add(torrent.clone()));
+        assert!(!registry.add(torrent));
+
+        assert!(registry.next_queued().is_some());
+        assert!(registry.next_queued().is_none());
+        assert_eq!(registry.get_state(id), Some(TorrentState::Downloading));
+
+        registry.finish(id, false);
+        assert_eq!(registry.get_state(id), Some(TorrentState::Queued));
+        assert!(registry.next_queued().is_some());
+        registry.finish(id, true);
+        assert_eq!(registry.get_state(id), Some(TorrentState::Seeding));
+        assert!(registry.next_queued().is_none());
+    }
+
+    #[test]
+    fn removed_torrents_are_stopping_until_their_client_finishes() {
+        let registry = TorrentRegistry::new();
+        let torrent = get_torrent();
+        let id = torrent.0.get_id();
+        registry.add(torrent.clone());
+        let (_, stop) = registry.next_queued().expect("It shouldn't fail");
+
+        assert!(registry.remove(id).is_some());
+        assert_eq!(*stop.read().expect("It shouldn't fail"), StopSignal::Remove);
+        assert!(registry.is_stopping(id));
+        // Added again, it waits for the old client
+        assert!(registry.add(torrent));
+        assert!(registry.next_queued().is_none());
+
+        registry.finish(id, false);
+        assert!(!registry.is_stopping(id));
+        assert!(registry.next_queued().is_some());
+    }
+
+    #[test]
+    fn seeding_torrents_are_downloaded_again() {
+        let registry = TorrentRegistry::new();
+        let torrent = get_torrent();
+        let id = torrent.0.get_id();
+        registry.add(torrent);
+        registry.download_again(id);
+        assert!(registry.next_queued().is_some());
+        registry.finish(id, true);
+        assert!(registry.get_torrent(id).is_some());
+
+        registry.download_again(id);
+        assert_eq!(registry.get_state(id), Some(TorrentState::Queued));
+        assert!(registry.next_queued().is_some());
+        assert!(registry.next_queued().is_none());
+    }
+
+    #[test]
+    fn paused_torrents_are_stopped_and_not_shared() {
+        let registry = TorrentRegistry::new();
+        let torrent = get_torrent();
+        let id = torrent.0.get_id();
+        registry.add(torrent);
+        let (_, stop) = registry.next_queued().expect("It shouldn't fail");
+
+        assert!(registry.pause(id));
+        assert_eq!(*stop.read().expect("It shouldn't fail"), StopSignal::Pause);
+        assert!(registry.shared_torrents().is_empty());
+
+        // It is not queued until its client stops
+        assert!(registry.resume(id));
+        assert!(registry.next_queued().is_none());
+        registry.finish(id, false);
+        if let Some((_, stop)) = registry.next_queued() {
+            assert_eq!(*stop.read().expect("It shouldn't fail"), StopSignal::Run);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn torrents_are_updated_with_the_events() {
+        let registry = TorrentRegistry::new();
+        let torrent = get_torrent();
+        let id = torrent.0.get_id();
+        registry.add(torrent);
+        registry.notify(NewEvent::NumberOfPeers(id, 12));
+        let peer = Peer::from_socket_addr("127.0.0.1:6881".parse().expect("It shouldn't fail"));
+        registry.notify(NewEvent::NewConnection(id, peer));
+
+        let list = registry.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].id, id.to_string());
+        assert_eq!(list[0].state, "queued");
+        assert_eq!((list[0].peers, list[0].connections), (12, 1));
+        assert_eq!(registry.get_stats().connections, 1);
+
+        assert!(registry.remove(id).is_some());
+        assert!(registry.remove(id).is_none());
+        assert_eq!(registry.get_stats(), SessionStats::default());
+    }
+}
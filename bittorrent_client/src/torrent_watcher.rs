@@ -0,0 +1,185 @@
+use crate::constants::*;
+use crate::logging::msg_coder::MsgCoder;
+use crate::session::Session;
+use crate::torrent_finder::TorrentFinder;
+use crate::torrent_info::TorrentId;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// # struct TorrentWatcher
+/// Scans the torrents directory periodically, adding the new torrent
+/// (and magnet) files to the session:
+///     - dir -> torrents directory
+///     - move_added -> the processed files are moved to the WATCH_ADDED_DIR subdirectory
+///     - known -> files already processed, with their modification time. A file that
+///       cannot be parsed (e.g. it is still being copied) is tried again when it changes.
+///     - tx_logger
+#[derive(Debug)]
+pub struct TorrentWatcher {
+    dir: String,
+    move_added: bool,
+    known: HashMap<String, Option<SystemTime>>,
+    tx_logger: Sender<String>,
+}
+
+impl TorrentWatcher {
+    pub fn new(dir: &str, move_added: bool, tx_logger: Sender<String>) -> TorrentWatcher {
+        TorrentWatcher {
+            dir: dir.to_string(),
+            move_added,
+            known: HashMap::new(),
+            tx_logger,
+        }
+    }
+
+    /// Scans the directory every interval, forever.
+    pub fn run(mut self, session: Session, interval: Duration) {
+        loop {
+            self.scan(&session);
+            thread::sleep(interval);
+        }
+    }
+
+    /// Adds the torrents of the files that were not processed yet.
+    /// The files of torrents that are already in the session count as processed.
+    /// Returns the ids of the added torrents.
+    pub fn scan(&mut self, session: &Session) -> Vec<TorrentId> {
+        let added_dir = self.get_added_dir();
+        let files = match TorrentFinder::find_in(&self.dir) {
+            Ok(files) => files,
+            Err(_) => return vec![],
+        };
+        self.known.retain(|path, _| files.contains(path));
+
+        let mut added = vec![];
+        for path in files {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            if Path::new(&path).starts_with(&added_dir) || self.known.get(&path) == Some(&modified)
+            {
+                continue;
+            }
+            self.known.insert(path.clone(), modified);
+
+            let torrents = TorrentFinder::load(&path);
+            if torrents.is_empty() {
+                continue;
+            }
+            for torrent in torrents {
+                if let Ok(torrent_id) = session.add_torrent_info(torrent) {
+                    self.log_added_torrent(&path);
+                    added.push(torrent_id);
+                }
+            }
+            if self.move_added {
+                self.move_to_added_dir(&path);
+            }
+        }
+        added
+    }
+
+    fn get_added_dir(&self) -> PathBuf {
+        Path::new(&self.dir).join(WATCH_ADDED_DIR)
+    }
+
+    /// Moves a processed file to the added directory.
+    fn move_to_added_dir(&mut self, path: &str) {
+        let added_dir = self.get_added_dir();
+        let file_name = match Path::new(path).file_name() {
+            Some(file_name) => file_name,
+            None => return,
+        };
+        let moved = fs::create_dir_all(&added_dir)
+            .and_then(|_| fs::rename(path, added_dir.join(file_name)));
+        if moved.is_ok() {
+            self.known.remove(path);
+        } else {
+            let _ = self.tx_logger.send(MsgCoder::generate_message(
+                ERROR_LOG_TYPE,
+                CLIENT_MODE_LOG,
+                format!("Cannot move {} to the added directory\n", path),
+            ));
+        }
+    }
+
+    fn log_added_torrent(&self, path: &str) {
+        if self
+            .tx_logger
+            .send(MsgCoder::generate_message(
+                GENERIC_LOG_TYPE,
+                CLIENT_MODE_LOG,
+                format!("New torrent found in the watch directory: {}\n", path),
+            ))
+            .is_err()
+        {
+            println!("Failed to log a new torrent of the watch directory");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_messages::NewEvent;
+    use std::sync::mpsc::channel;
+
+    const TORRENT_PATH: &str =
+        "files_for_testing/torrents_multi_file_testing/multi_file_test.torrent";
+
+    fn get_session() -> Session {
+        Session::new("files_for_testing/settings_files_testing/settings.txt")
+            .expect("It shouldn't fail")
+    }
+
+    #[test]
+    fn new_torrents_are_added_once() {
+        let dir = "files_for_testing/watch_test_dir";
+        let _ = fs::create_dir_all(dir);
+        let session = get_session();
+        let rx_events = session.subscribe();
+        let (tx_logger, _rx) = channel();
+        let mut watcher = TorrentWatcher::new(dir, false, tx_logger);
+
+        let empty_scan = watcher.scan(&session);
+        let _ = fs::copy(TORRENT_PATH, format!("{}/new.torrent", dir));
+        let _ = fs::write(format!("{}/invalid.torrent", dir), "not a torrent");
+        let first_scan = watcher.scan(&session);
+        let second_scan = watcher.scan(&session);
+        let _ = fs::remove_dir_all(dir);
+
+        assert!(empty_scan.is_empty());
+        assert_eq!(first_scan.len(), 1);
+        assert!(second_scan.is_empty());
+        assert_eq!(session.list_torrents().len(), 1);
+        if let Ok(NewEvent::NewTorrent(id, _, _)) = rx_events.try_recv() {
+            assert_eq!(id, first_scan[0]);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn processed_files_are_moved() {
+        let dir = "files_for_testing/watch_move_test_dir";
+        let _ = fs::create_dir_all(dir);
+        let session = get_session();
+        let (tx_logger, _rx) = channel();
+        let mut watcher = TorrentWatcher::new(dir, true, tx_logger);
+
+        let _ = fs::copy(TORRENT_PATH, format!("{}/new.torrent", dir));
+        let first_scan = watcher.scan(&session);
+        let second_scan = watcher.scan(&session);
+        let moved = Path::new(dir).join("added/new.torrent").exists();
+        let still_there = Path::new(dir).join("new.torrent").exists();
+        let _ = fs::remove_dir_all(dir);
+
+        assert_eq!(first_scan.len(), 1);
+        assert!(second_scan.is_empty());
+        assert!(moved);
+        assert!(!still_there);
+    }
+}
@@ -4,3 +4,4 @@ pub const THREADPOOL_SIZE: usize = 4;
 
 pub const THREE_DAYS_IN_SECS: i64 = 259200;
 pub const A_WEEK_IN_SECS: i64 = 604800;
+pub const ANNOUNCE_INTERVAL: u32 = 1800;
@@ -1,14 +1,22 @@
-use std::collections::HashMap;
-
-use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
 
 use crate::{
     constants::{A_WEEK_IN_SECS, THREE_DAYS_IN_SECS},
-    encoding::bencode_type::BencodeType,
     http_request::Event,
 };
 
+/// # struct PeerEntry
+/// Represents a peer in the announce response (bencoded dictionary).
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerEntry {
+    ip: String,
+    port: u32,
+    #[serde(rename = "peer id")]
+    peer_id: String,
+}
+
 /// # struct HostedPeer
 /// Represents a peer hosted on the tracker and contains the following:
 ///     - peer_id
@@ -29,7 +37,7 @@ pub struct HostedPeer {
 }
 
 impl HostedPeer {
-    pub fn new(peer_id: &str, peer_ip: &str, port: &u32, event: Event, left: u32) -> HostedPeer {
+    pub fn new(peer_id: &str, peer_ip: &str, port: &u32, event: Event, left: u64) -> HostedPeer {
         let timestamp = Utc::now().to_rfc3339();
 
         let mut peer = HostedPeer {
@@ -64,6 +72,14 @@ impl HostedPeer {
         self.port
     }
 
+    /// Returns the address of the peer, if its IP and port are valid.
+    /// IPv4-mapped IPv6 addresses are returned as IPv4 addresses.
+    pub fn get_socket_addr(&self) -> Option<SocketAddr> {
+        let ip = self.peer_ip.parse::<IpAddr>().ok()?;
+        let port = u16::try_from(self.port).ok()?;
+        Some(SocketAddr::new(ip.to_canonical(), port))
+    }
+
     // Returns the timestamp in DateTime format
     pub fn get_timestamp(&self) -> DateTime<FixedOffset> {
         DateTime::parse_from_rfc3339(&self.dt_connection).unwrap()
@@ -104,9 +120,10 @@ impl HostedPeer {
         if let Ok(dt_connection) = DateTime::parse_from_rfc3339(&self.dt_connection) {
             let ts_connection = dt_connection.timestamp();
 
-            let disconnection = NaiveDateTime::from_timestamp(ts_connection + A_WEEK_IN_SECS, 0);
-            let dt_disconnection = DateTime::<Utc>::from_utc(disconnection, Utc);
-            self.dt_disconnection = Some(dt_disconnection.to_rfc3339());
+            let disconnection = Utc.timestamp_opt(ts_connection + A_WEEK_IN_SECS, 0);
+            if let Some(dt_disconnection) = disconnection.single() {
+                self.dt_disconnection = Some(dt_disconnection.to_rfc3339());
+            }
         }
     }
 
@@ -127,17 +144,12 @@ impl HostedPeer {
         false
     }
 
-    /// Returns a bencoded dictionary that represents the peer
-    pub fn to_bencode_type(&self) -> BencodeType {
-        let mut peer_dict = HashMap::new();
-        let ip = BencodeType::String(self.get_peer_ip().into_bytes());
-        let port = BencodeType::Integer(self.port as i64);
-        let id = BencodeType::String(self.get_peer_id().into_bytes());
-
-        peer_dict.insert("ip".to_string(), ip);
-        peer_dict.insert("port".to_string(), port);
-        peer_dict.insert("peer id".to_string(), id);
-
-        BencodeType::Dictionary(peer_dict)
+    /// Returns the peer as it is sent in the announce responses
+    pub fn to_peer_entry(&self) -> PeerEntry {
+        PeerEntry {
+            ip: self.get_peer_ip(),
+            port: self.port,
+            peer_id: self.get_peer_id(),
+        }
     }
 }
@@ -1,7 +1,35 @@
-use std::{collections::HashMap, vec};
+use std::vec;
 
-use crate::{data::hosted_peer::HostedPeer, encoding::bencode_type::BencodeType};
+use crate::{
+    constants::ANNOUNCE_INTERVAL,
+    data::hosted_peer::{HostedPeer, PeerEntry},
+};
+use bencode::bytes::ByteBuf;
 use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// # enum AnnouncePeers
+/// Peers of the announce response: a list of dictionaries, or a string with
+/// the IPv4 peers in compact format (BEP 23).
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum AnnouncePeers {
+    List(Vec<PeerEntry>),
+    Compact(ByteBuf),
+}
+
+/// # struct AnnounceResponse
+/// Represents the response to an announce request (bencoded dictionary).
+/// In compact responses, the IPv6 peers are sent in peers6 (BEP 7).
+#[derive(Serialize, Debug)]
+pub struct AnnounceResponse {
+    complete: u32,
+    incomplete: u32,
+    interval: u32,
+    peers: AnnouncePeers,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    peers6: Option<ByteBuf>,
+}
 
 /// # struct HostedTorrent
 /// Represents a torrent hosted on the tracker and contains the following:
@@ -119,25 +147,36 @@ impl HostedTorrent {
         }
     }
 
-    /// Returns a bencoded dictionary that represents the torrent
-    pub fn to_bencode_type(&self) -> BencodeType {
-        let mut data_dict = HashMap::new();
-        let complete = BencodeType::Integer(self.seeders as i64);
-        let incomplete = BencodeType::Integer(self.leechers as i64);
-
-        let mut peer_list = vec![];
-        for peer in &self.peers {
-            if peer.is_connected() {
-                let peer_bencoded = peer.to_bencode_type();
-                peer_list.push(peer_bencoded);
+    /// Returns the announce response of the torrent, with its connected peers.
+    /// If the client asked for a compact response, the peers are sent in compact format
+    /// (4 or 16 bytes of ip and 2 of port), and the peers without a valid address are skipped.
+    pub fn to_announce_response(&self, compact: bool) -> AnnounceResponse {
+        let connected = self.peers.iter().filter(|peer| peer.is_connected());
+        let (peers, peers6) = if compact {
+            let mut peers = vec![];
+            let mut peers6 = vec![];
+            for addr in connected.filter_map(|peer| peer.get_socket_addr()) {
+                let (compact_peers, ip) = match addr.ip() {
+                    IpAddr::V4(ip) => (&mut peers, ip.octets().to_vec()),
+                    IpAddr::V6(ip) => (&mut peers6, ip.octets().to_vec()),
+                };
+                compact_peers.extend(ip);
+                compact_peers.extend(addr.port().to_be_bytes());
             }
+            let peers6 = (!peers6.is_empty()).then_some(ByteBuf(peers6));
+            (AnnouncePeers::Compact(ByteBuf(peers)), peers6)
+        } else {
+            let peers = connected.map(|peer| peer.to_peer_entry()).collect();
+            (AnnouncePeers::List(peers), None)
+        };
+
+        AnnounceResponse {
+            complete: self.seeders,
+            incomplete: self.leechers,
+            interval: ANNOUNCE_INTERVAL,
+            peers,
+            peers6,
         }
-
-        data_dict.insert("complete".to_string(), complete);
-        data_dict.insert("incomplete".to_string(), incomplete);
-        data_dict.insert("interval".to_string(), BencodeType::Integer(1800));
-        data_dict.insert("peers".to_string(), BencodeType::List(peer_list));
-        BencodeType::Dictionary(data_dict)
     }
 }
 
@@ -174,4 +213,43 @@ mod tests {
         torrent.add_peer(peer);
         assert_eq!(torrent.get_leechers(), 1);
     }
+
+    #[test]
+    fn announce_response_is_bencoded_with_connected_peers() {
+        let mut torrent = HostedTorrent::new("f07e0b0584745b7bcb35e98097488d34e68623d0");
+        let peer = HostedPeer::new(
+            "-AR1234-111111111111",
+            "127.0.0.1",
+            &8080,
+            Event::Started,
+            999,
+        );
+        torrent.add_peer(peer);
+
+        let bencoded = bencode::to_bytes(&torrent.to_announce_response(false));
+        assert_eq!(
+            bencoded,
+            Ok(b"d8:completei0e10:incompletei1e8:intervali1800e5:peersld2:ip9:127.0.0.17:peer id20:-AR1234-1111111111114:porti8080eeee".to_vec())
+        );
+    }
+
+    #[test]
+    fn compact_announce_response_has_ipv4_and_ipv6_peers() {
+        let mut torrent = HostedTorrent::new("f07e0b0584745b7bcb35e98097488d34e68623d0");
+        let peers = [
+            ("127.0.0.1", 6881),
+            ("::ffff:10.0.0.1", 6882),
+            ("2001:db8::1", 6883),
+        ];
+        for (ip, port) in peers {
+            let peer = HostedPeer::new("-AR1234-111111111111", ip, &port, Event::Started, 999);
+            torrent.add_peer(peer);
+        }
+
+        let bencoded = bencode::to_bytes(&torrent.to_announce_response(true));
+        assert_eq!(
+            bencoded,
+            Ok(b"d8:completei0e10:incompletei3e8:intervali1800e5:peers12:\x7f\x00\x00\x01\x1a\xe1\x0a\x00\x00\x01\x1a\xe26:peers618:\x20\x01\x0d\xb8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1a\xe3e".to_vec())
+        );
+    }
 }
@@ -1,6 +1,5 @@
 use crate::{
     data::{hosted_peer::HostedPeer, hosted_torrent::HostedTorrent},
-    encoding::encoder::Encoder,
     errors::TrackerError,
 };
 use serde::{Deserialize, Serialize};
@@ -50,13 +49,12 @@ impl TrackerData {
         }
     }
 
-    /// Returns a bencoded dictionary that represents the tracker data
-    pub fn bencode_data(&self, info_hash: String) -> Result<Vec<u8>, TrackerError> {
+    /// Returns the bencoded announce response of a torrent (with compact peers if asked)
+    pub fn bencode_data(&self, info_hash: String, compact: bool) -> Result<Vec<u8>, TrackerError> {
         for torrent in &self.torrents {
             if torrent.get_infohash() == info_hash {
-                let torr_benc_type = torrent.to_bencode_type();
-                let bencoded_data = Encoder.bencode(&torr_benc_type);
-                return Ok(bencoded_data);
+                return bencode::to_bytes(&torrent.to_announce_response(compact))
+                    .map_err(|_| TrackerError::InvalidRequest);
             }
         }
 
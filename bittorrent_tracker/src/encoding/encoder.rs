@@ -1,10 +1,8 @@
-use crate::encoding::bencode_type::BencodeType;
-use std::collections::HashMap;
 use std::fmt::Write;
 use std::io::{Error, ErrorKind};
 
 /// # struct Encoder
-/// Supports two formats: Bencode and URLencode.
+/// Supports URLencode and hexadecimal formats (Bencode is supported by the bencode crate).
 pub struct Encoder;
 
 impl Encoder {
@@ -62,62 +60,4 @@ impl Encoder {
 
         string
     }
-
-    /// Receives a decoded BencodeType element and encodes it with Bencode format.
-    /// Then, returns it as vec<u8>
-    pub fn bencode(&self, decoded: &BencodeType) -> Vec<u8> {
-        let mut bencoded_data = Vec::<u8>::new();
-        self.bencode_type(decoded, &mut bencoded_data);
-        bencoded_data
-    }
-
-    /// Receives a BencodeType element and a vec<u8>, and adds the encoded element to the vec
-    fn bencode_type(&self, ben_type: &BencodeType, vec: &mut Vec<u8>) {
-        match ben_type {
-            BencodeType::String(s) => self.bencode_string(s, vec),
-            BencodeType::Integer(i) => self.bencode_integer(*i, vec),
-            BencodeType::List(l) => self.bencode_list(l.as_slice(), vec),
-            BencodeType::Dictionary(d) => self.bencode_dictionary(d, vec),
-            _ => (),
-        }
-    }
-
-    /// Encodes an integer with Bencode format
-    fn bencode_integer(&self, integer: i64, vec: &mut Vec<u8>) {
-        vec.push(b'i');
-
-        let int_str = integer.to_string();
-        vec.append(&mut int_str.into_bytes());
-        vec.push(b'e');
-    }
-
-    /// Encodes a string with Bencode format
-    fn bencode_string(&self, string: &[u8], vec: &mut Vec<u8>) {
-        let mut length = string.len().to_string();
-        length.push(':');
-        *vec = [vec, length.as_bytes(), string].concat();
-    }
-
-    /// Encodes a list with Bencode format
-    fn bencode_list(&self, list: &[BencodeType], vec: &mut Vec<u8>) {
-        vec.push(b'l');
-        for item in list {
-            self.bencode_type(item, vec);
-        }
-        vec.push(b'e');
-    }
-
-    /// Encodes a dictionary with Bencode format.
-    /// This function only works for a info value dictionary!!
-    fn bencode_dictionary(&self, dic: &HashMap<String, BencodeType>, vec: &mut Vec<u8>) {
-        vec.push(b'd');
-        for key in dic.keys() {
-            let value = dic.get(key);
-            if let Some(v) = value {
-                self.bencode_string(key.as_bytes(), vec);
-                self.bencode_type(v, vec);
-            }
-        }
-        vec.push(b'e');
-    }
 }
@@ -1,2 +1 @@
-pub mod bencode_type;
 pub mod encoder;
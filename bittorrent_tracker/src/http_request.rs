@@ -14,10 +14,11 @@ pub struct AnnounceEndpoint {
     info_hash: String,
     peer_id: String,
     port: u32,
-    uploaded: u32,
-    downloaded: u32,
-    left: u32,
+    uploaded: u64,
+    downloaded: u64,
+    left: u64,
     event: Event,
+    compact: bool,
 }
 /// # Announce Endpoint
 /// Represents an Announce Request
@@ -34,13 +35,18 @@ impl AnnounceEndpoint {
         self.port
     }
 
-    pub fn get_left(&self) -> u32 {
+    pub fn get_left(&self) -> u64 {
         self.left
     }
 
     pub fn get_event(&self) -> Event {
         self.event.clone()
     }
+
+    /// Returns true if the client accepts compact peer lists (compact=1)
+    pub fn is_compact(&self) -> bool {
+        self.compact
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -91,18 +97,20 @@ impl HttpRequest {
     }
 
     /// Sends a response according to the type of request.
+    /// The content of announce responses are bytes, because compact peers are not valid UTF-8.
     pub fn respond(&self, stream: &mut TcpStream) {
         let (status_line, contents) = match self {
             HttpRequest::Announce(req) => HttpRequest::get_content_announce_req(req),
-            HttpRequest::Stats => HttpRequest::get_content_stats_req(),
-            HttpRequest::Unknown => HttpRequest::get_content_unknown_req(),
-            HttpRequest::CssFile => HttpRequest::get_content_css(),
-            HttpRequest::JsFile(file) => HttpRequest::get_content_js(file),
-            HttpRequest::Data => HttpRequest::get_content_json(),
+            HttpRequest::Stats => text_to_bytes(HttpRequest::get_content_stats_req()),
+            HttpRequest::Unknown => text_to_bytes(HttpRequest::get_content_unknown_req()),
+            HttpRequest::CssFile => text_to_bytes(HttpRequest::get_content_css()),
+            HttpRequest::JsFile(file) => text_to_bytes(HttpRequest::get_content_js(file)),
+            HttpRequest::Data => text_to_bytes(HttpRequest::get_content_json()),
         };
 
-        let response = format!("{}\r\n\r\n{}", status_line, contents);
-        let _ = stream.write_all(response.as_bytes());
+        let mut response = format!("{}\r\n\r\n", status_line).into_bytes();
+        response.extend(contents);
+        let _ = stream.write_all(&response);
         _ = stream.flush();
     }
 
@@ -122,20 +130,21 @@ impl HttpRequest {
                     let port = str::parse::<u32>(
                         param_dict.get("port").ok_or(TrackerError::InvalidRequest)?,
                     );
-                    let uploaded = str::parse::<u32>(
+                    let uploaded = str::parse::<u64>(
                         param_dict
                             .get("uploaded")
                             .ok_or(TrackerError::InvalidRequest)?,
                     );
-                    let downloaded = str::parse::<u32>(
+                    let downloaded = str::parse::<u64>(
                         param_dict
                             .get("downloaded")
                             .ok_or(TrackerError::InvalidRequest)?,
                     );
-                    let left = str::parse::<u32>(
+                    let left = str::parse::<u64>(
                         param_dict.get("left").ok_or(TrackerError::InvalidRequest)?,
                     );
 
+                    let compact = param_dict.get("compact") == Some(&"1");
                     let event = match param_dict.get("event") {
                         Some(&"started") => Event::Started,
                         Some(&"stopped") => Event::Stopped,
@@ -155,6 +164,7 @@ impl HttpRequest {
                             downloaded: dl_v,
                             left: left_v,
                             event,
+                            compact,
                         };
                         return Ok(HttpRequest::Announce(announce_req));
                     }
@@ -180,23 +190,25 @@ impl HttpRequest {
     }
 
     /// Returns the content of the response of an announce request and the status line.
-    fn get_content_announce_req(req: &AnnounceEndpoint) -> (String, String) {
+    fn get_content_announce_req(req: &AnnounceEndpoint) -> (String, Vec<u8>) {
         let status_line = "HTTP/1.1 200 OK".to_string();
         if let Ok(data_string) = fs::read_to_string(DATA_DIR) {
             let data_struct: Result<TrackerData, serde_json::Error> =
                 serde_json::from_str(&data_string);
 
             if let Ok(tracker_data) = data_struct {
-                if let Ok(bencoded_data) = tracker_data.bencode_data(req.get_info_hash()) {
-                    if let Ok(content) = String::from_utf8(bencoded_data) {
-                        return (status_line, content);
-                    }
+                let bencoded_data =
+                    tracker_data.bencode_data(req.get_info_hash(), req.is_compact());
+                if let Ok(content) = bencoded_data {
+                    return (status_line, content);
                 }
             }
         }
         (
             "HTTP/1.1 404 NOT FOUND".to_string(),
-            "Sorry! Cannot find the requested torrent :(".to_string(),
+            "Sorry! Cannot find the requested torrent :("
+                .as_bytes()
+                .to_vec(),
         )
     }
 
@@ -289,6 +301,11 @@ impl HttpRequest {
     }
 }
 
+/// Returns the status line and the content of a text response as bytes
+fn text_to_bytes((status_line, contents): (String, String)) -> (String, Vec<u8>) {
+    (status_line, contents.into_bytes())
+}
+
 // si es announce -> chequear si hay que añadir y torrent al json
 // implementar httprequest::response()
 
@@ -322,7 +339,7 @@ mod tests {
 
         let cl_thread = thread::spawn(move || {
             if let Ok(mut stream_cl) = TcpStream::connect(address) {
-                let announce = "GET /announce?info_hash=%f0%7e%0b%05%84%74%5b%7b%cb%35%e9%80%97%48%8d%34%e6%86%23%d0&peer_id=-AR1234-111111111111&port=6881&uploaded=0&downloaded=0&left=1502576640&event=started HTTP/1.1\r\n";
+                let announce = "GET /announce?info_hash=%f0%7e%0b%05%84%74%5b%7b%cb%35%e9%80%97%48%8d%34%e6%86%23%d0&peer_id=-AR1234-111111111111&port=6881&uploaded=0&downloaded=0&left=6442450944&event=started HTTP/1.1\r\n";
                 let _ = stream_cl.write(announce.as_bytes());
             }
         });
@@ -336,8 +353,9 @@ mod tests {
             port: 6881,
             uploaded: 0,
             downloaded: 0,
-            left: 1502576640,
+            left: 6442450944,
             event: Event::Started,
+            compact: false,
         };
 
         let exp_request = HttpRequest::Announce(exp_announce);
@@ -346,6 +364,25 @@ mod tests {
         assert_eq!(request, exp_request);
     }
 
+    #[test]
+    fn valid_request_announce_with_compact_peers() {
+        let address = "127.0.0.1:8083";
+        let listener = TcpListener::bind(address).unwrap();
+
+        let cl_thread = thread::spawn(move || {
+            if let Ok(mut stream_cl) = TcpStream::connect(address) {
+                let announce = "GET /announce?info_hash=%f0%7e%0b%05%84%74%5b%7b%cb%35%e9%80%97%48%8d%34%e6%86%23%d0&peer_id=-AR1234-111111111111&port=6881&uploaded=0&downloaded=0&left=0&compact=1 HTTP/1.1\r\n";
+                let _ = stream_cl.write(announce.as_bytes());
+            }
+        });
+
+        let (mut stream_sv, _socket_addr) = listener.accept().unwrap();
+        let request = HttpRequest::new(&mut stream_sv);
+        cl_thread.join().unwrap();
+
+        assert!(matches!(request, HttpRequest::Announce(announce) if announce.is_compact()));
+    }
+
     #[test]
     fn invalid_request_announce() {
         let address = "127.0.0.1:8082";
@@ -92,10 +92,7 @@ impl Tracker {
                 let data_struct: Result<TrackerData, serde_json::Error> =
                     serde_json::from_str(&data_string);
 
-                let mut tracker_data = match data_struct {
-                    Ok(data) => data,
-                    Err(_) => TrackerData::new(),
-                };
+                let mut tracker_data = data_struct.unwrap_or_default();
 
                 if let Event::Stopped = announce.get_event() {
                     tracker_data.remove_peer(&info_hash, peer);